@@ -0,0 +1,54 @@
+extern crate criterion;
+extern crate tmx;
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use tmx::{Compression, DataEncoding, Map, WriteOptions};
+
+const SIZE: usize = 256;
+
+// A map with a single layer of the given size, written with `encoding`, or
+// with one `<tile>` element per cell if there is none.
+fn layer_map(encoding: Option<DataEncoding>) -> Map {
+    let mut xml = String::new();
+    write!(xml, r#"<map width="{0}" height="{0}" tilewidth="16" tileheight="16">"#, SIZE).unwrap();
+    write!(xml, r#"<layer name="Ground" width="{0}" height="{0}"><data>"#, SIZE).unwrap();
+    for i in 0..SIZE * SIZE {
+        write!(xml, r#"<tile gid="{}"/>"#, i % 4096 + 1).unwrap();
+    }
+    xml.push_str("</data></layer></map>");
+    let map = Map::from_str(&xml).unwrap();
+    match encoding {
+        Some(encoding) => {
+            let xml = map.to_xml_string_with_options(&WriteOptions::new().with_encoding(encoding)).unwrap();
+            Map::from_str(&xml).unwrap()
+        }
+        None => map,
+    }
+}
+
+fn bench_layer(c: &mut Criterion, name: &str, map: &Map) {
+    let data = map.layers().next().unwrap().data().unwrap();
+    let mut group = c.benchmark_group(name);
+    group.bench_function("decode into new buffer", |b| {
+        b.iter(|| {
+            let mut gids = Vec::new();
+            data.decode_into(&mut gids).unwrap();
+            gids
+        })
+    });
+    let mut gids = Vec::new();
+    group.bench_function("decode into reused buffer", |b| b.iter(|| data.decode_into(&mut gids).unwrap()));
+    group.finish();
+}
+
+fn data(c: &mut Criterion) {
+    bench_layer(c, "csv layer", &layer_map(Some(DataEncoding::Csv)));
+    bench_layer(c, "base64 zlib layer", &layer_map(Some(DataEncoding::Base64 { compression: Compression::Zlib })));
+    bench_layer(c, "xml tiles layer", &layer_map(None));
+}
+
+criterion_group!(benches, data);
+criterion_main!(benches);
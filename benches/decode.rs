@@ -0,0 +1,51 @@
+extern crate criterion;
+extern crate tmx;
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use tmx::{Compression, DataEncoding, Map, WriteOptions};
+
+const LAYERS: usize = 8;
+const SIZE: usize = 512;
+
+// A map with zlib-compressed layers of scattered gids, which compress poorly.
+fn compressed_map() -> String {
+    let mut xml = String::new();
+    write!(xml, r#"<map width="{0}" height="{0}" tilewidth="16" tileheight="16">"#, SIZE).unwrap();
+    let mut seed: u32 = 12345;
+    for layer in 0..LAYERS {
+        write!(xml, r#"<layer name="Layer {0}" width="{1}" height="{1}"><data encoding="csv">"#, layer, SIZE).unwrap();
+        for i in 0..SIZE * SIZE {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            if i > 0 {
+                xml.push(',');
+            }
+            write!(xml, "{}", seed >> 20).unwrap();
+        }
+        xml.push_str("</data></layer>");
+    }
+    xml.push_str("</map>");
+    let map = Map::from_str(&xml).unwrap();
+    let options = WriteOptions::new().with_encoding(DataEncoding::Base64 { compression: Compression::Zlib });
+    map.to_xml_string_with_options(&options).unwrap()
+}
+
+// On a single core, both take about 55 ms, the parallel one a few percent
+// more: the gain of the rayon feature depends on the cores available.
+fn decode(c: &mut Criterion) {
+    let xml = compressed_map();
+    let mut group = c.benchmark_group("decode 8 zlib layers");
+    group.bench_function("sequential", |b| {
+        b.iter_batched(|| Map::from_str(&xml).unwrap(), |map| map.decode_all_layers().unwrap(), BatchSize::LargeInput)
+    });
+    group.bench_function("parallel", |b| {
+        b.iter_batched(|| Map::from_str(&xml).unwrap(), |map| map.decode_all_layers_parallel().unwrap(),
+                       BatchSize::LargeInput)
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
@@ -0,0 +1,45 @@
+extern crate criterion;
+extern crate tmx;
+
+use std::fmt::Write;
+use std::hint::black_box;
+use std::str::FromStr;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use tmx::{Map, Tileset};
+
+const TILESETS: u32 = 50;
+const TILE_COUNT: u32 = 100;
+
+fn many_tilesets_map() -> Map {
+    let mut xml = String::from(r#"<map width="1" height="1" tilewidth="16" tileheight="16">"#);
+    for i in 0..TILESETS {
+        write!(xml, r#"<tileset firstgid="{}" name="ts{}" tilewidth="16" tileheight="16" tilecount="{}"/>"#,
+               1 + i * TILE_COUNT, i, TILE_COUNT).unwrap();
+    }
+    xml.push_str("</map>");
+    Map::from_str(&xml).unwrap()
+}
+
+// The lookup as a scan over the tilesets.
+fn scan(map: &Map, gid: u32) -> Option<&Tileset> {
+    map.tilesets()
+        .filter(|tileset| tileset.first_gid() <= gid)
+        .max_by_key(|tileset| tileset.first_gid())
+}
+
+fn lookup(c: &mut Criterion) {
+    let map = many_tilesets_map();
+    let gids: Vec<u32> = (1..TILESETS * TILE_COUNT).step_by(37).collect();
+    let mut group = c.benchmark_group("tileset for gid, 50 tilesets");
+    group.bench_function("scan", |b| {
+        b.iter(|| gids.iter().filter_map(|&gid| scan(&map, black_box(gid))).count())
+    });
+    group.bench_function("indexed", |b| {
+        b.iter(|| gids.iter().filter_map(|&gid| map.tileset_for_gid(black_box(gid))).count())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, lookup);
+criterion_main!(benches);
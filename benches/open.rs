@@ -0,0 +1,47 @@
+extern crate criterion;
+extern crate tmx;
+
+use std::fmt::Write as FmtWrite;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use tmx::reader::TmxReader;
+
+const LAYERS: usize = 16;
+const SIZE: usize = 256;
+
+// Writes a map with large CSV layers to a temporary file.
+fn large_map_file() -> PathBuf {
+    let mut xml = String::new();
+    write!(xml, r#"<map width="{0}" height="{0}" tilewidth="16" tileheight="16">"#, SIZE).unwrap();
+    for layer in 0..LAYERS {
+        write!(xml, r#"<layer name="Layer {0}" width="{1}" height="{1}"><data encoding="csv">"#, layer, SIZE).unwrap();
+        let gids: Vec<String> = (0..SIZE * SIZE).map(|i| (i % 4096 + 1).to_string()).collect();
+        xml.push_str(&gids.join(","));
+        xml.push_str("</data></layer>");
+    }
+    xml.push_str("</map>");
+    let path = std::env::temp_dir().join("tmx-bench-open.tmx");
+    fs::write(&path, xml).unwrap();
+    path
+}
+
+fn open(c: &mut Criterion) {
+    let path = large_map_file();
+    let mut group = c.benchmark_group("open large map");
+    group.sample_size(20);
+    group.bench_function("unbuffered file", |b| {
+        b.iter(|| TmxReader::new(File::open(&path).unwrap()).read_map().unwrap())
+    });
+    group.bench_function("buffered file", |b| {
+        b.iter(|| TmxReader::new(BufReader::new(File::open(&path).unwrap())).read_map().unwrap())
+    });
+    group.bench_function("Map::open", |b| b.iter(|| tmx::Map::open(&path).unwrap()));
+    group.finish();
+    fs::remove_file(&path).unwrap();
+}
+
+criterion_group!(benches, open);
+criterion_main!(benches);
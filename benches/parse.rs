@@ -0,0 +1,65 @@
+extern crate criterion;
+extern crate tmx;
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const LAYERS: usize = 200;
+const SIZE: usize = 64;
+
+// A map with many CSV layers, each with a few properties, and an object group
+// with many objects.
+fn large_map() -> String {
+    let mut xml = String::new();
+    write!(xml, r#"<map version="1.10" orientation="orthogonal" width="{0}" height="{0}" tilewidth="16" tileheight="16">"#, SIZE).unwrap();
+    xml.push_str(r#"<tileset firstgid="1" name="tiles" tilewidth="16" tileheight="16" tilecount="64" columns="8">"#);
+    xml.push_str(r#"<image source="tiles.png" width="128" height="128"/></tileset>"#);
+    for layer in 0..LAYERS {
+        write!(xml, r#"<layer name="Layer {0}" width="{1}" height="{1}" opacity="0.5" visible="1">"#, layer, SIZE).unwrap();
+        xml.push_str(r#"<properties><property name="depth" type="int" value="3"/>"#);
+        xml.push_str(r#"<property name="label" value="some text"/></properties><data encoding="csv">"#);
+        let gids: Vec<String> = (0..SIZE * SIZE).map(|i| (i % 64 + 1).to_string()).collect();
+        xml.push_str(&gids.join(","));
+        xml.push_str("</data></layer>");
+    }
+    xml.push_str(r#"<objectgroup name="Objects">"#);
+    for id in 1..2000 {
+        write!(xml, r#"<object id="{0}" x="{0}.5" y="12" width="16" height="16" rotation="45"/>"#, id).unwrap();
+    }
+    xml.push_str("</objectgroup></map>");
+    xml
+}
+
+// A map with a single layer of XML-encoded tiles, one `<tile>` element each.
+fn xml_tiles_map(size: usize) -> String {
+    let mut xml = String::new();
+    write!(xml, r#"<map version="1.10" orientation="orthogonal" width="{0}" height="{0}" tilewidth="16" tileheight="16">"#, size).unwrap();
+    write!(xml, r#"<layer name="Ground" width="{0}" height="{0}"><data>"#, size).unwrap();
+    for i in 0..size * size {
+        write!(xml, r#"<tile gid="{}"/>"#, i % 64 + 1).unwrap();
+    }
+    xml.push_str("</data></layer></map>");
+    xml
+}
+
+fn parse(c: &mut Criterion) {
+    let xml = large_map();
+    c.bench_function("parse large map", |b| b.iter(|| tmx::Map::from_str(&xml).unwrap()));
+    let options = tmx::ReaderOptions::new().with_skip_layer_data(true);
+    c.bench_function("parse large map without layer data", |b| {
+        b.iter(|| tmx::Map::from_str_with(&xml, &options).unwrap())
+    });
+}
+
+fn parse_xml_tiles(c: &mut Criterion) {
+    let xml = xml_tiles_map(1024);
+    let mut group = c.benchmark_group("xml tiles");
+    group.sample_size(10);
+    group.bench_function("parse 1024x1024 map", |b| b.iter(|| tmx::Map::from_str(&xml).unwrap()));
+    group.finish();
+}
+
+criterion_group!(benches, parse, parse_xml_tiles);
+criterion_main!(benches);
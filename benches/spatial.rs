@@ -0,0 +1,44 @@
+extern crate criterion;
+extern crate tmx;
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use tmx::{Aabb, Map, ObjectIndex};
+
+const OBJECTS: u32 = 10000;
+
+// A 256x256 map of 16 pixel tiles with objects scattered all over it.
+fn scattered_objects_map() -> Map {
+    let mut xml = String::from(r#"<map width="256" height="256" tilewidth="16" tileheight="16"><objectgroup>"#);
+    let mut seed: u32 = 7;
+    for id in 1..=OBJECTS {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        let (x, y) = ((seed >> 4) % 4096, (seed >> 16) % 4096);
+        write!(xml, r#"<object id="{}" x="{}" y="{}" width="24" height="24" rotation="{}"/>"#, id, x, y, id % 90).unwrap();
+    }
+    xml.push_str("</objectgroup></map>");
+    Map::from_str(&xml).unwrap()
+}
+
+fn spatial(c: &mut Criterion) {
+    let map = scattered_objects_map();
+    let index = ObjectIndex::new(&map);
+    let player = Aabb::new(2000.0, 2000.0, 2032.0, 2032.0);
+    let mut group = c.benchmark_group("objects overlapping a 32x32 box, 10000 objects");
+    group.bench_function("brute force", |b| {
+        b.iter(|| {
+            map.object_groups()
+                .flat_map(|group| group.objects())
+                .filter(|object| object.bounding_box().intersects(&player))
+                .count()
+        })
+    });
+    group.bench_function("object index", |b| b.iter(|| index.query_rect(player).count()));
+    group.finish();
+    c.bench_function("build object index, 10000 objects", |b| b.iter(|| ObjectIndex::new(&map).len()));
+}
+
+criterion_group!(benches, spatial);
+criterion_main!(benches);
@@ -0,0 +1,13 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset name="Props" tilewidth="64" tileheight="96" tilecount="3" columns="0">
+ <grid orientation="orthogonal" width="1" height="1"/>
+ <tile id="0">
+  <image width="32" height="32" source="props/barrel.png"/>
+ </tile>
+ <tile id="3">
+  <image width="64" height="96" source="props/tree.png"/>
+ </tile>
+ <tile id="17">
+  <image width="48" height="16" source="props/bench.png"/>
+ </tile>
+</tileset>
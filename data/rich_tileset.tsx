@@ -0,0 +1,34 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.2" tiledversion="1.2.4" name="Terrain" tilewidth="32" tileheight="32" spacing="1" margin="1" tilecount="16" columns="4">
+ <tileoffset x="0" y="8"/>
+ <properties>
+  <property name="biome" value="forest"/>
+ </properties>
+ <image source="terrain.png" trans="ff00ff" width="133" height="133"/>
+ <terraintypes>
+  <terrain name="Grass" tile="0">
+   <properties>
+    <property name="walkable" type="bool" value="true"/>
+   </properties>
+  </terrain>
+  <terrain name="Water" tile="5"/>
+ </terraintypes>
+ <tile id="0" terrain="0,0,0,0" probability="0.75">
+  <properties>
+   <property name="cost" type="int" value="1"/>
+  </properties>
+ </tile>
+ <tile id="5" terrain="1,1,,1">
+  <objectgroup draworder="index">
+   <object id="1" x="0" y="16" width="32" height="16"/>
+   <object id="2" x="4" y="4">
+    <polygon points="0,0 8,0 4,6"/>
+   </object>
+  </objectgroup>
+  <animation>
+   <frame tileid="5" duration="250"/>
+   <frame tileid="6" duration="250"/>
+   <frame tileid="7" duration="500"/>
+  </animation>
+ </tile>
+</tileset>
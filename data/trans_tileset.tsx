@@ -0,0 +1,4 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset name="Keyed" tilewidth="2" tileheight="2" tilecount="2" columns="2">
+ <image source="trans_atlas.png" trans="ff00ff" width="4" height="2"/>
+</tileset>
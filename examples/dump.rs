@@ -7,7 +7,7 @@ use std::fmt::Debug;
 use tmx::{Map, Tileset};
 
 fn show_usage() {
-    println!("Usage: {0} <file>", env::args().nth(0).unwrap());
+    println!("Usage: {0} <file>", env::args().next().unwrap());
     println!("  where <file> is the path to a .tmx or .tsx file");
 }
 
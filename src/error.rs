@@ -16,23 +16,77 @@
 use std::error;
 use std::fmt;
 use std::io;
+use std::path::PathBuf;
 
+use xml::reader;
+
+/// The errors raised while reading or writing Tiled files.
+///
+/// New variants may be added as the format evolves, so matches on this type
+/// need a wildcard arm:
+///
+/// ```
+/// fn describe(err: &tmx::Error) -> &'static str {
+///     match *err {
+///         tmx::Error::Io(..) => "cannot read the file",
+///         tmx::Error::Xml(..) => "not an XML file",
+///         _ => "invalid Tiled file",
+///     }
+/// }
+/// ```
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     BadXml,
     BadAxis(String),
+    BadBoolean(String),
+    BadCompression(String),
+    BadEncoding(String),
     BadIndex(String),
+    BadOpacity(String),
     BadOrientation(String),
     BadPropertyType(String),
+    BadPropertyValue { name: String, value: String },
+    DuplicateProperty { name: String, line: u64, column: u64 },
     BadRenderOrder(String),
     BadDrawOrder(String),
+    BadObjectAlignment(String),
     BadProbability(f32),
-    UnknownAttribute(String),
+    MissingAttribute { element: String, attribute: String },
+    UnknownAttribute { element: String, attribute: String },
+    InvalidAttribute { element: String, attribute: String, cause: Box<Error> },
+    UnknownElement { parent: String, element: String },
     InvalidColor(String),
+    InvalidData(String),
     InvalidNumber(String),
     InvalidPoint(String),
     InvalidTerrain(String),
+    InvalidText(String),
+    InvalidTileset(String),
+    InvalidVersion(String),
+    /// Tilesets that cannot be merged, differing in the named setting.
+    TilesetMismatch { setting: String, into: String, from: String },
+    /// An image whose format is unknown or not supported, named by its
+    /// `format` attribute when it has one.
+    UnsupportedImage(String),
+    /// An image that cannot be decoded.
+    InvalidImage(String),
+    /// Data compressed with a method whose cargo feature is disabled, such
+    /// as `zstd`.
+    UnsupportedCompression(String),
+    /// An image whose size differs from the declared one, as `(width, height)`.
+    ImageSize { expected: (u32, u32), actual: (u32, u32) },
+    /// A cell outside of a layer of `width` by `height` tiles.
+    OutOfBounds { x: u64, y: u64, width: u32, height: u32 },
+    LimitExceeded { limit: String, max: usize },
+    TrailingContent(String),
+    TooDeep(usize),
+    /// The names of the layers whose data cannot be decoded, with the reason.
+    LayerData(Vec<(String, Error)>),
+    /// An error raised while reading the file at `path`.
+    File { path: PathBuf, source: Box<Error> },
     Io(io::Error),
+    Xml(reader::Error),
 }
 
 impl fmt::Display for Error {
@@ -44,11 +98,31 @@ impl fmt::Display for Error {
                        "Illegal value `{}` for the `staggeraxis` attribute",
                        value)
             }
+            Error::BadBoolean(ref value) => {
+                write!(f,
+                       "Illegal value `{}` for a boolean attribute",
+                       value)
+            }
+            Error::BadCompression(ref value) => {
+                write!(f,
+                       "Illegal value `{}` for the `compression` attribute",
+                       value)
+            }
+            Error::BadEncoding(ref value) => {
+                write!(f,
+                       "Illegal value `{}` for the `encoding` attribute",
+                       value)
+            }
             Error::BadIndex(ref value) => {
                 write!(f,
                        "Illegal value `{}` for the `staggerindex` attribute",
                        value)
             }
+            Error::BadOpacity(ref value) => {
+                write!(f,
+                       "Illegal value `{}` for the `opacity` attribute",
+                       value)
+            }
             Error::BadOrientation(ref value) => {
                 write!(f,
                        "Illegal value `{}` for the `orientation` attribute",
@@ -59,6 +133,15 @@ impl fmt::Display for Error {
                        "Illegal value `{}` for the `type` attribute",
                        value)
             }
+            Error::BadPropertyValue { ref name, ref value } => {
+                write!(f,
+                       "Illegal value `{}` for the property `{}`",
+                       value,
+                       name)
+            }
+            Error::DuplicateProperty { ref name, line, column } => {
+                write!(f, "Duplicate property `{}` at line {}, column {}", name, line, column)
+            }
             Error::BadRenderOrder(ref value) => {
                 write!(f,
                        "Illegal value `{}` for the `renderorder` attribute",
@@ -69,25 +152,93 @@ impl fmt::Display for Error {
                        "Illegal value `{}` for the `draworder` attribute",
                        value)
             }
+            Error::BadObjectAlignment(ref value) => {
+                write!(f,
+                       "Illegal value `{}` for the `objectalignment` attribute",
+                       value)
+            }
             Error::BadProbability(ref value) => {
                 write!(f,
                        "Illegal value `{}` for the `probability` attribute",
                        value)
             }
-            Error::UnknownAttribute(ref attr) => write!(f, "Unknown attribute: `{}`", attr),
+            Error::MissingAttribute { ref element, ref attribute } => {
+                write!(f, "Missing attribute `{}` on element `{}`", attribute, element)
+            }
+            Error::UnknownAttribute { ref element, ref attribute } => {
+                write!(f, "Unknown attribute `{}` on element `{}`", attribute, element)
+            }
+            Error::InvalidAttribute { ref element, ref attribute, ref cause } => {
+                write!(f, "Invalid `{}` attribute on element `{}`: {}", attribute, element, cause)
+            }
+            Error::UnknownElement { ref parent, ref element } => {
+                write!(f, "Unknown element `{}` in element `{}`", element, parent)
+            }
             Error::InvalidColor(ref color) => write!(f, "Invalid color: `{}`", color),
+            Error::InvalidData(ref reason) => write!(f, "Invalid layer data: {}", reason),
             Error::InvalidNumber(ref num) => write!(f, "Invalid number: `{}`", num),
             Error::InvalidPoint(ref point) => write!(f, "Invalid point: `{}`", point),
             Error::InvalidTerrain(ref terrain) => write!(f, "Invalid terrain: `{}`", terrain),
+            Error::InvalidText(ref reason) => write!(f, "Invalid text encoding: {}", reason),
+            Error::InvalidTileset(ref reason) => write!(f, "Invalid tileset: {}", reason),
+            Error::InvalidVersion(ref version) => write!(f, "Invalid version: `{}`", version),
+            Error::TilesetMismatch { ref setting, ref into, ref from } => {
+                write!(f, "Cannot merge tilesets of different {}: `{}` and `{}`", setting, into, from)
+            }
+            Error::UnsupportedImage(ref format) => write!(f, "Unsupported image format: `{}`", format),
+            Error::InvalidImage(ref reason) => write!(f, "Invalid image: {}", reason),
+            Error::UnsupportedCompression(ref compression) => {
+                write!(f, "Unsupported compression `{0}`: enable the `{0}` feature", compression)
+            }
+            Error::ImageSize { expected, actual } => {
+                write!(f,
+                       "Image of {}x{} pixels instead of the declared {}x{}",
+                       actual.0, actual.1, expected.0, expected.1)
+            }
+            Error::OutOfBounds { x, y, width, height } => {
+                write!(f, "Cell ({}, {}) outside of a layer of {}x{} tiles", x, y, width, height)
+            }
+            Error::LimitExceeded { ref limit, max } => {
+                write!(f, "Limit `{}` of {} exceeded", limit, max)
+            }
+            Error::TrailingContent(ref element) => {
+                write!(f, "Unexpected element `{}` after the root element", element)
+            }
+            Error::TooDeep(max) => write!(f, "Elements nested deeper than {} levels", max),
+            Error::LayerData(ref errors) => {
+                write!(f, "Cannot decode the data of {} layer(s)", errors.len())?;
+                for (name, err) in errors {
+                    write!(f, "; `{}`: {}", name, err)?;
+                }
+                Ok(())
+            }
+            Error::File { ref path, ref source } => write!(f, "{}: {}", path.display(), source),
             Error::Io(ref err) => write!(f, "I/O error: {}", err),
+            Error::Xml(ref err) => write!(f, "XML error: {}", err),
         }
     }
 }
 
-impl error::Error for Error {}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::InvalidAttribute { ref cause, .. } => Some(cause.as_ref()),
+            Error::File { ref source, .. } => Some(source.as_ref()),
+            Error::Io(ref err) => Some(err),
+            Error::Xml(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
 
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
         Error::Io(err)
     }
 }
+
+impl From<reader::Error> for Error {
+    fn from(err: reader::Error) -> Error {
+        Error::Xml(err)
+    }
+}
@@ -50,11 +50,47 @@
 //!
 //! use std::str::FromStr;
 //!
-//! let empty_map = tmx::Map::from_str(r#"<map version="1.0"/>"#);
+//! let xml = r#"<map version="1.0" width="10" height="10" tilewidth="16" tileheight="16"/>"#;
+//! let empty_map = tmx::Map::from_str(xml);
+//! ```
+//!
+//! # Editing
+//!
+//! Parsed maps and tilesets are edited through the `set_*` methods of the model
+//! types, reached with the `*_mut` accessors, and saved with `to_writer`:
+//!
+//! ```
+//! extern crate tmx;
+//!
+//! use std::str::FromStr;
+//!
+//! let xml = r#"<map version="1.0" width="10" height="10" tilewidth="16" tileheight="16">
+//!                <layer name="Ground"/>
+//!              </map>"#;
+//! let mut map = tmx::Map::from_str(xml).unwrap();
+//! for layer in map.layers_mut() {
+//!     layer.set_name("Floor");
+//!     layer.set_visible(false);
+//! }
+//! let xml = map.to_xml_string().unwrap();
 //! ```
 
+extern crate base64;
+extern crate flate2;
 extern crate xml;
 
+// Renamed so as not to hide the `image` module re-exported below.
+#[cfg(feature = "image")]
+extern crate image as image_crate;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "serde")]
+#[macro_use] extern crate serde;
+#[cfg(feature = "json")]
+#[macro_use] extern crate serde_json;
+#[cfg(feature = "zstd")]
+extern crate zstd;
+
 #[cfg(test)]
 #[macro_use] extern crate assert_matches;
 
@@ -13,26 +13,122 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fmt;
 use std::str::FromStr;
 
 use error::Error;
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct Color(pub u8, pub u8, pub u8, pub u8);
+/// A color with an alpha channel.
+///
+/// Tiled writes colors as `#AARRGGBB`, alpha first; the `argb` and `rgba`
+/// constructors and the `*_u32` conversions name the order they use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    /// Creates an opaque color.
+    pub const fn rgb(red: u8, green: u8, blue: u8) -> Color {
+        Color::rgba(red, green, blue, 255)
+    }
+
+    pub const fn rgba(red: u8, green: u8, blue: u8, alpha: u8) -> Color {
+        Color { r: red, g: green, b: blue, a: alpha }
+    }
+
+    pub const fn argb(alpha: u8, red: u8, green: u8, blue: u8) -> Color {
+        Color::rgba(red, green, blue, alpha)
+    }
+
+    /// Unpacks a color given as `0xRRGGBBAA`.
+    pub const fn from_rgba_u32(rgba: u32) -> Color {
+        let [r, g, b, a] = rgba.to_be_bytes();
+        Color::rgba(r, g, b, a)
+    }
+
+    /// Unpacks a color given as `0xAARRGGBB`, the order of Tiled.
+    pub const fn from_argb_u32(argb: u32) -> Color {
+        let [a, r, g, b] = argb.to_be_bytes();
+        Color::rgba(r, g, b, a)
+    }
+
+    pub const fn alpha(&self) -> u8 {
+        self.a
+    }
+
+    pub const fn red(&self) -> u8 {
+        self.r
+    }
+
+    pub const fn green(&self) -> u8 {
+        self.g
+    }
+
+    pub const fn blue(&self) -> u8 {
+        self.b
+    }
+
+    /// Returns the channels as `[red, green, blue, alpha]`, each in the `0.0..=1.0` range.
+    pub fn to_rgba_f32(&self) -> [f32; 4] {
+        let [r, g, b, a] = <[u8; 4]>::from(*self);
+        [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, a as f32 / 255.0]
+    }
+
+    /// Packs the color as `0xRRGGBBAA`.
+    pub const fn to_rgba_u32(&self) -> u32 {
+        u32::from_be_bytes([self.r, self.g, self.b, self.a])
+    }
+
+    /// Packs the color as `0xAARRGGBB`.
+    pub const fn to_argb_u32(&self) -> u32 {
+        u32::from_be_bytes([self.a, self.r, self.g, self.b])
+    }
+
+    /// Formats the color as Tiled does: `#rrggbb` when opaque, `#aarrggbb` otherwise.
+    pub fn to_hex_string(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// Converts to `[red, green, blue, alpha]`.
+impl From<Color> for [u8; 4] {
+    fn from(color: Color) -> [u8; 4] {
+        [color.red(), color.green(), color.blue(), color.alpha()]
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.alpha() == 255 {
+            write!(f, "#{:02x}{:02x}{:02x}", self.red(), self.green(), self.blue())
+        } else {
+            write!(f, "#{:02x}{:02x}{:02x}{:02x}", self.alpha(), self.red(), self.green(), self.blue())
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_as_string!(Color);
 
 impl FromStr for Color {
     type Err = Error;
 
     fn from_str(s: &str) -> ::Result<Color> {
+        // Slices are taken with `get`, which fails instead of panicking when a
+        // multi-byte character straddles the boundary.
         let color = if s.starts_with('#') {
             let (alpha, rgb_offset) = if s.len() == 9 {
-                (hex_pair_to_number(&s[1..3]), 3)
+                (s.get(1..3).and_then(hex_pair_to_number), 3)
             } else {
                 (Some(255), 1)
             };
-            alpha.and_then(|a| hex_rgb_to_rgb(&s[rgb_offset..]).and_then(|(r, g, b)| Some(Color(a, r, g, b))))
+            alpha.and_then(|a| s.get(rgb_offset..).and_then(hex_rgb_to_rgb).map(|(r, g, b)| Color::argb(a, r, g, b)))
         } else {
-            hex_rgb_to_rgb(s).and_then(|(r, g, b)| Some(Color(255, r, g, b)))
+            hex_rgb_to_rgb(s).map(|(r, g, b)| Color::rgb(r, g, b))
         };
         color.ok_or_else(|| Error::InvalidColor(s.to_string()))
     }
@@ -67,10 +163,8 @@ fn hex_pair_to_number(s: &str) -> Option<u8> {
 
 fn hex_rgb_to_rgb(s: &str) -> Option<(u8, u8, u8)> {
     if s.len() == 6 {
-        hex_pair_to_number(&s[0..2]).and_then(|r| {
-            hex_pair_to_number(&s[2..4])
-                .and_then(|g| hex_pair_to_number(&s[4..6]).and_then(|b| Some((r, g, b))))
-        })
+        let pair = |start: usize| s.get(start..start + 2).and_then(hex_pair_to_number);
+        pair(0).and_then(|r| pair(2).and_then(|g| pair(4).map(|b| (r, g, b))))
     } else {
         None
     }
@@ -112,4 +206,71 @@ mod tests {
         assert!(Color::from_str("00010204").is_err());
         assert!(Color::from_str("#00010204").is_ok());
     }
+
+    #[test]
+    fn test_multi_byte_characters_in_color() {
+        let values = ["#ffffffff", "#ffffff", "ffffff"];
+        for value in &values {
+            for offset in 0..value.len() {
+                for replacement in &["é", "€", "𝄞"] {
+                    let mut input = value.to_string();
+                    input.replace_range(offset..offset + 1, replacement);
+                    assert_matches!(Color::from_str(&input), Err(Error::InvalidColor(..)), "{}", input);
+                }
+            }
+        }
+        assert_matches!(Color::from_str("#ée0000"), Err(Error::InvalidColor(..)));
+        assert_matches!(Color::from_str("#aé00000"), Err(Error::InvalidColor(..)));
+    }
+
+    #[test]
+    fn test_random_strings_to_color_never_panic() {
+        let alphabet: Vec<char> = "#0123456789abcdefABCDEFxé€𝄞 ".chars().collect();
+        let mut seed = 0x2545_f491_u32;
+        for _ in 0..20_000 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let len = (seed % 11) as usize;
+            let input: String = (0..len)
+                .map(|i| alphabet[(seed.rotate_left(i as u32 * 5) as usize) % alphabet.len()])
+                .collect();
+            let _ = Color::from_str(&input);
+        }
+    }
+
+    #[test]
+    fn test_color_channels() {
+        let color = Color::argb(1, 2, 3, 4);
+        assert_eq!(Color { r: 2, g: 3, b: 4, a: 1 }, color);
+        assert_eq!((1, 2, 3, 4), (color.alpha(), color.red(), color.green(), color.blue()));
+        assert_eq!(color, Color::rgba(2, 3, 4, 1));
+        assert_eq!(Color { r: 2, g: 3, b: 4, a: 255 }, Color::rgb(2, 3, 4));
+
+        const TINT: Color = Color::from_argb_u32(0x8011_2233);
+        assert_eq!(Color::argb(0x80, 0x11, 0x22, 0x33), TINT);
+        assert_eq!(TINT, Color::from_rgba_u32(TINT.to_rgba_u32()));
+        assert_eq!(0x8011_2233, TINT.to_argb_u32());
+    }
+
+    #[test]
+    fn test_color_conversions() {
+        let color = Color::argb(0x11, 0x22, 0x33, 0x44);
+        assert_eq!([0x22, 0x33, 0x44, 0x11], <[u8; 4]>::from(color));
+        assert_eq!(0x2233_4411, color.to_rgba_u32());
+        assert_eq!(0x1122_3344, color.to_argb_u32());
+        assert_eq!([0x22 as f32 / 255.0, 0x33 as f32 / 255.0, 0x44 as f32 / 255.0, 0x11 as f32 / 255.0],
+                   color.to_rgba_f32());
+        assert_eq!([1.0, 0.0, 0.0, 1.0], Color::rgb(255, 0, 0).to_rgba_f32());
+    }
+
+    #[test]
+    fn test_color_to_string() {
+        assert_eq!("#0a0b0c", Color::rgb(10, 11, 12).to_string());
+        assert_eq!("#800a0bff", Color::argb(128, 10, 11, 255).to_hex_string());
+        assert_eq!("#00000000", format!("{}", Color::argb(0, 0, 0, 0)));
+        for s in &["#0a0b0c", "#800a0bff", "#00000000"] {
+            assert_eq!(*s, Color::from_str(s).unwrap().to_string());
+        }
+    }
 }
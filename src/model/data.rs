@@ -13,24 +13,77 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::Read;
+use std::io::{Read, Write};
+use std::sync::OnceLock;
+use std::vec;
 
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
 use xml::attribute::OwnedAttribute;
 
 use error::Error;
-use model::reader::{self, TmxReader, ElementReader};
+use model::geometry::TileRect;
+use model::reader::{self, ElementReader, Severity, TmxReader};
+use model::writer::{Attributes, ElementWriter, TmxWriter};
+
+// Most tiles reserved up front for the `<tile>` elements of a layer, whose
+// declared size may be wrong or hostile.
+const MAX_RESERVED_TILES: usize = 1 << 22;
+
+// Size of the chunks allocated in chunked data without any, as in Tiled.
+const DEFAULT_CHUNK_SIZE: u32 = 16;
 
 define_iterator_wrapper!(DataTiles, DataTile);
+define_iterator_wrapper!(Chunks, Chunk);
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Data {
     encoding: Option<String>,
     compression: Option<String>,
-    raw: Option<String>,
-    tiles: Vec<DataTile>,
+    content: DataContent,
+    // The tile ids decoded from content as read.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    decoded: OnceLock<Vec<u32>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    max_decoded_bytes: Option<usize>,
+    // Whether content not matching the encoding was already reported while
+    // reading, so as to report it once per element.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    ignored_content_reported: bool,
+}
+
+// The content of a `data` element, which depends on its encoding.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum DataContent {
+    // The `tile` children of data without encoding.
+    Xml(Vec<DataTile>),
+    // The text of encoded data, decoded according to the attributes.
+    Text { raw: String },
+    // The chunks of an infinite layer, decoded as they are read.
+    Chunked(Vec<Chunk>),
+    // Tile ids set in code or edited, encoded when written.
+    Decoded(Vec<u32>),
+}
+
+impl Default for DataContent {
+    fn default() -> DataContent {
+        DataContent::Xml(Vec::new())
+    }
 }
 
 impl Data {
+    // Data holding the given global tile ids, without any encoding.
+    pub(crate) fn from_gids(gids: Vec<u32>) -> Data {
+        Data {
+            content: DataContent::Decoded(gids),
+            ..Data::default()
+        }
+    }
+
     pub fn encoding(&self) -> Option<&str> {
         self.encoding.as_ref().map(String::as_str)
     }
@@ -47,40 +100,584 @@ impl Data {
         self.compression = Some(compression.into());
     }
 
+    /// Returns the text of the element as read, if any.
+    ///
+    /// Once the tile ids are edited, as by `Layer::set_tile`, there is no raw
+    /// content any more: the edits are encoded when the layer is written.
     pub fn raw_content(&self) -> Option<&str> {
-        self.raw.as_ref().map(String::as_str)
+        match self.content {
+            DataContent::Text { ref raw } => Some(raw),
+            _ => None,
+        }
+    }
+
+    // Adds a piece of the text of the element, which comes in several pieces
+    // when the parser does not coalesce them.
+    fn append_raw_content(&mut self, content: String) {
+        match self.content {
+            DataContent::Text { ref mut raw } => raw.push_str(&content),
+            _ => self.content = DataContent::Text { raw: content },
+        }
     }
 
-    fn set_raw_content<S: Into<String>>(&mut self, content: S) {
-        self.raw = Some(content.into());
+    // Returns data holding the tile ids of a layer `width` tiles wide, encoded
+    // as those of this data.
+    pub(crate) fn with_gids(&self, gids: Vec<u32>, width: u32) -> ::Result<Data> {
+        let content = match DataEncoding::from_attributes(self.encoding(), self.compression()) {
+            Some(encoding) => DataContent::Text { raw: encoding.encode(&gids, width)? },
+            None => DataContent::Xml(gids.iter().map(|&gid| DataTile { gid }).collect()),
+        };
+        Ok(Data {
+            encoding: self.encoding.clone(),
+            compression: self.compression.clone(),
+            content,
+            decoded: OnceLock::from(gids),
+            max_decoded_bytes: self.max_decoded_bytes,
+            ..Data::default()
+        })
+    }
+
+    // Returns data holding the tile ids of this data, those of a layer `width`
+    // tiles wide, replaced by `f` of them and encoded as those of this data.
+    // Chunks keep their place.
+    pub(crate) fn with_gids_mapped<F>(&self, width: u32, mut f: F) -> ::Result<Data>
+        where F: FnMut(u32) -> ::Result<u32>
+    {
+        let chunks = match self.content {
+            DataContent::Chunked(ref chunks) => chunks,
+            _ => {
+                let gids = self.decode()?.iter().map(|&gid| f(gid)).collect::<::Result<Vec<_>>>()?;
+                return self.with_gids(gids, width);
+            }
+        };
+        let mut mapped = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            mapped.push(Chunk {
+                x: chunk.x,
+                y: chunk.y,
+                width: chunk.width,
+                height: chunk.height,
+                gids: chunk.gids.iter().map(|&gid| f(gid)).collect::<::Result<Vec<_>>>()?,
+            });
+        }
+        Ok(Data {
+            encoding: self.encoding.clone(),
+            compression: self.compression.clone(),
+            content: DataContent::Chunked(mapped),
+            max_decoded_bytes: self.max_decoded_bytes,
+            ..Data::default()
+        })
+    }
+
+    // Returns the tile ids for editing, decoding them first if needed. The
+    // content read is dropped, the writers encoding the edited tile ids
+    // instead.
+    pub(crate) fn gids_mut(&mut self) -> ::Result<&mut Vec<u32>> {
+        if !self.is_edited() {
+            let gids = match self.decoded.take() {
+                Some(gids) => gids,
+                None => {
+                    let mut gids = Vec::new();
+                    self.decode_content(&mut gids)?;
+                    gids
+                }
+            };
+            self.content = DataContent::Decoded(gids);
+        }
+        match self.content {
+            DataContent::Decoded(ref mut gids) => Ok(gids),
+            _ => unreachable!("the content was just decoded"),
+        }
     }
 
+    // Tells whether the content is tile ids set in code or edited, rather
+    // than the content read.
+    pub(crate) fn is_edited(&self) -> bool {
+        matches!(self.content, DataContent::Decoded(_))
+    }
+
+    // Limits the size of the decoded content, see `Limits::max_layer_bytes`.
+    fn set_max_decoded_bytes(&mut self, max: Option<usize>) {
+        self.max_decoded_bytes = max;
+    }
+
+    /// Iterates over the `tile` elements of data without encoding, as read.
     pub fn tiles(&self) -> DataTiles {
-        DataTiles(self.tiles.iter())
+        match self.content {
+            DataContent::Xml(ref tiles) => DataTiles(tiles.iter()),
+            _ => DataTiles([].iter()),
+        }
+    }
+
+    pub fn into_tiles(self) -> vec::IntoIter<DataTile> {
+        match self.content {
+            DataContent::Xml(tiles) => tiles.into_iter(),
+            _ => Vec::new().into_iter(),
+        }
+    }
+
+    /// Iterates over the chunks of the data of an infinite layer, as read.
+    pub fn chunks(&self) -> Chunks {
+        match self.content {
+            DataContent::Chunked(ref chunks) => Chunks(chunks.iter()),
+            _ => Chunks([].iter()),
+        }
+    }
+
+    /// Tells whether the data is that of an infinite layer, held in chunks.
+    pub fn is_chunked(&self) -> bool {
+        matches!(self.content, DataContent::Chunked(_))
     }
 
-    fn add_tile(&mut self, tile: DataTile) {
-        self.tiles.push(tile);
+    /// Returns the smallest rectangle of cells holding every chunk, as the
+    /// position of its top-left cell followed by its width and height, or
+    /// `None` for data without chunks.
+    ///
+    /// These are the cells returned by `decode` for chunked data.
+    pub fn chunk_bounds(&self) -> Option<(i32, i32, u32, u32)> {
+        let chunks = match self.content {
+            DataContent::Chunked(ref chunks) if !chunks.is_empty() => chunks,
+            _ => return None,
+        };
+        let left = chunks.iter().map(|chunk| chunk.x).min()?;
+        let top = chunks.iter().map(|chunk| chunk.y).min()?;
+        let right = chunks.iter().map(|chunk| i64::from(chunk.x) + i64::from(chunk.width)).max()?;
+        let bottom = chunks.iter().map(|chunk| i64::from(chunk.y) + i64::from(chunk.height)).max()?;
+        let size = |from: i32, to: i64| (to - i64::from(from)).min(i64::from(u32::MAX)) as u32;
+        Some((left, top, size(left, right), size(top, bottom)))
+    }
+
+    // Returns the number of tiles per row of the tile ids returned by
+    // `decode` for a layer `width` tiles wide.
+    pub(crate) fn row_width(&self, width: u32) -> u32 {
+        self.chunk_bounds().map_or(width, |(_, _, width, _)| width)
+    }
+
+    // Sets the cells of `rect` of chunked data, counted from the top-left cell
+    // of `chunk_bounds`, to `gid`. A cell outside of every chunk gets a new
+    // chunk the size of the first one and aligned on it, kept inside of the
+    // bounds so that they still start at the same cell, unless the cell stays
+    // empty. Fails, leaving the data untouched, if a cell is past the
+    // largest position of a chunk.
+    pub(crate) fn fill_chunks(&mut self, rect: TileRect, gid: u32) -> ::Result<()> {
+        let (left, top) = self.chunk_bounds().map_or((0, 0), |(x, y, _, _)| (x, y));
+        if rect.w == 0 || rect.h == 0 {
+            return Ok(());
+        }
+        let (x, y) = (u64::from(rect.x) + u64::from(rect.w) - 1, u64::from(rect.y) + u64::from(rect.h) - 1);
+        // The number of cells from the left and top of the bounds to the
+        // largest position.
+        let room = |from: i32| (i64::from(i32::MAX) - i64::from(from) + 1).min(i64::from(u32::MAX)) as u32;
+        let (width, height) = (room(left), room(top));
+        if x >= u64::from(width) || y >= u64::from(height) {
+            return Err(Error::OutOfBounds { x, y, width, height });
+        }
+        self.decoded = OnceLock::new();
+        let chunks = match self.content {
+            DataContent::Chunked(ref mut chunks) => chunks,
+            _ => return Ok(()),
+        };
+        let (origin_x, origin_y, chunk_width, chunk_height) = chunks.iter()
+            .find(|chunk| chunk.width > 0 && chunk.height > 0)
+            .map_or((0, 0, DEFAULT_CHUNK_SIZE, DEFAULT_CHUNK_SIZE), |c| (c.x, c.y, c.width, c.height));
+        let align = |cell: i64, origin: i32, size: u32, min: i32| {
+            let start = i64::from(origin) + (cell - i64::from(origin)).div_euclid(i64::from(size)) * i64::from(size);
+            start.max(i64::from(min)) as i32
+        };
+        for y in u64::from(rect.y)..=y {
+            for x in u64::from(rect.x)..=x {
+                let (x, y) = (i64::from(left) + x as i64, i64::from(top) + y as i64);
+                let found = chunks.iter_mut().find_map(|chunk| chunk.index(x, y).map(|index| (chunk, index)));
+                if let Some((chunk, index)) = found {
+                    if let Some(cell) = chunk.gids.get_mut(index) {
+                        *cell = gid;
+                    }
+                } else if gid != 0 {
+                    let mut chunk = Chunk {
+                        x: align(x, origin_x, chunk_width, left),
+                        y: align(y, origin_y, chunk_height, top),
+                        width: chunk_width,
+                        height: chunk_height,
+                        gids: vec![0; chunk_width as usize * chunk_height as usize],
+                    };
+                    if let Some(index) = chunk.index(x, y) {
+                        chunk.gids[index] = gid;
+                    }
+                    chunks.push(chunk);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Empties every cell of chunked data, keeping the chunks.
+    pub(crate) fn clear_chunks(&mut self) {
+        self.decoded = OnceLock::new();
+        if let DataContent::Chunked(ref mut chunks) = self.content {
+            chunks.iter_mut().for_each(|chunk| chunk.gids.fill(0));
+        }
+    }
+
+    // Adds a chunk, dropping any other content.
+    fn add_chunk(&mut self, chunk: Chunk) {
+        match self.content {
+            DataContent::Chunked(ref mut chunks) => chunks.push(chunk),
+            _ => self.content = DataContent::Chunked(vec![chunk]),
+        }
+    }
+
+    // Adds a `tile` element, the number of tiles to expect being reserved
+    // for the first one.
+    fn add_tile(&mut self, tile: DataTile, reserved: usize) {
+        if let DataContent::Xml(ref mut tiles) = self.content {
+            if tiles.is_empty() {
+                tiles.reserve(reserved);
+            }
+            tiles.push(tile);
+        }
+    }
+
+    /// Returns the global tile ids of the layer, row by row, flip flags included.
+    ///
+    /// The content is decoded on the first call according to the `encoding` and
+    /// `compression` attributes; later calls return the cached result. The tile
+    /// ids of chunked data are those of the cells of `chunk_bounds`, the cells
+    /// outside of every chunk being empty.
+    pub fn decode(&self) -> ::Result<&[u32]> {
+        if let DataContent::Decoded(ref gids) = self.content {
+            return Ok(gids);
+        }
+        if let Some(gids) = self.decoded.get() {
+            return Ok(gids);
+        }
+        let mut gids = Vec::new();
+        self.decode_content(&mut gids)?;
+        Ok(self.decoded.get_or_init(|| gids))
+    }
+
+    /// Replaces the content of `out` with the global tile ids of the layer, as
+    /// returned by `decode`, so that a buffer can be reused across layers.
+    ///
+    /// The result is not cached. On error, `out` is left empty.
+    pub fn decode_into(&self, out: &mut Vec<u32>) -> ::Result<()> {
+        out.clear();
+        if let Some(gids) = self.decoded.get() {
+            out.extend_from_slice(gids);
+            return Ok(());
+        }
+        if let DataContent::Decoded(ref gids) = self.content {
+            out.extend_from_slice(gids);
+            return Ok(());
+        }
+        self.decode_content(out).inspect_err(|_| out.clear())
+    }
+
+    // Appends the decoded content to `out`, which is empty.
+    fn decode_content(&self, out: &mut Vec<u32>) -> ::Result<()> {
+        let raw = match self.content {
+            DataContent::Xml(ref tiles) => {
+                if self.encoding.is_none() {
+                    out.extend(tiles.iter().map(DataTile::gid));
+                    return Ok(());
+                }
+                ""
+            }
+            DataContent::Text { ref raw } => raw,
+            DataContent::Chunked(ref chunks) => return self.decode_chunks(chunks, out),
+            DataContent::Decoded(ref gids) => {
+                out.extend_from_slice(gids);
+                return Ok(());
+            }
+        };
+        match self.encoding() {
+            None => Ok(()),
+            Some("csv") => {
+                let count = raw.split(',').filter(|value| !value.trim().is_empty()).count();
+                reader::check_limit("max_layer_bytes", count * 4, self.max_decoded_bytes)?;
+                decode_csv(raw, count, out)
+            }
+            Some("base64") => decode_bytes(&self.decode_base64("max_layer_bytes", self.max_decoded_bytes)?, out),
+            Some(encoding) => Err(Error::BadEncoding(encoding.to_string())),
+        }
+    }
+
+    // Lays out the chunks on the cells of `chunk_bounds`, appending them to
+    // `out`, which is empty. Where chunks overlap, the first one wins.
+    fn decode_chunks(&self, chunks: &[Chunk], out: &mut Vec<u32>) -> ::Result<()> {
+        let (left, top, width, height) = match self.chunk_bounds() {
+            Some(bounds) => bounds,
+            None => return Ok(()),
+        };
+        let len = (width as usize).saturating_mul(height as usize);
+        reader::check_limit("max_layer_bytes", len.saturating_mul(4), self.max_decoded_bytes)?;
+        out.resize(len, 0);
+        for chunk in chunks.iter().rev() {
+            chunk.check_size()?;
+            let x = (i64::from(chunk.x) - i64::from(left)) as usize;
+            let y = (i64::from(chunk.y) - i64::from(top)) as usize;
+            for (row, gids) in chunk.gids.chunks(chunk.width.max(1) as usize).enumerate() {
+                let start = (y + row) * width as usize + x;
+                out[start..start + gids.len()].copy_from_slice(gids);
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the bytes of base64-encoded content, decompressed, as embedded
+    // in layers and images. Fails if there are more than `max`, going over the
+    // named `limit`.
+    pub(crate) fn decode_base64(&self, limit: &str, max: Option<usize>) -> ::Result<Vec<u8>> {
+        let raw = self.raw_content().unwrap_or("");
+        let bytes = STANDARD.decode(raw.trim())
+            .map_err(|err| Error::InvalidData(err.to_string()))?;
+        let bytes = match self.compression() {
+            None => bytes,
+            Some("zlib") => decompress(ZlibDecoder::new(&bytes[..]), max)?,
+            Some("gzip") => decompress(GzDecoder::new(&bytes[..]), max)?,
+            Some("zstd") => decompress_zstd(&bytes, max)?,
+            Some(compression) => return Err(Error::BadCompression(compression.to_string())),
+        };
+        reader::check_limit(limit, bytes.len(), max)?;
+        Ok(bytes)
     }
 }
 
-#[derive(Debug, Default)]
+/// Data is equal when it has the same encoding and compression and decodes to
+/// the same tile ids, whether they were read or edited since. Content that
+/// cannot be decoded is compared as read, and so are chunks.
+impl PartialEq for Data {
+    fn eq(&self, other: &Data) -> bool {
+        if self.encoding != other.encoding || self.compression != other.compression {
+            return false;
+        }
+        if self.is_chunked() || other.is_chunked() {
+            return self.content == other.content;
+        }
+        match (self.decode(), other.decode()) {
+            (Ok(gids), Ok(other_gids)) => gids == other_gids,
+            _ => self.content == other.content,
+        }
+    }
+}
+
+/// Encoding of the tile data of a layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum DataEncoding {
+    Csv,
+    Base64 { compression: Compression },
+}
+
+/// Compression applied to base64-encoded tile data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Compression {
+    None,
+    Gzip,
+    Zlib,
+    /// Needs the `zstd` feature.
+    Zstd,
+}
+
+impl DataEncoding {
+    // Returns the encoding described by the `encoding` and `compression`
+    // attributes, or `None` for XML tiles and unknown values.
+    pub(crate) fn from_attributes(encoding: Option<&str>, compression: Option<&str>) -> Option<DataEncoding> {
+        let compression = match compression {
+            None => Compression::None,
+            Some("gzip") => Compression::Gzip,
+            Some("zlib") => Compression::Zlib,
+            Some("zstd") => Compression::Zstd,
+            Some(_) => return None,
+        };
+        match encoding {
+            Some("csv") => Some(DataEncoding::Csv),
+            Some("base64") => Some(DataEncoding::Base64 { compression }),
+            _ => None,
+        }
+    }
+
+    /// Returns the values of the `encoding` and `compression` attributes.
+    pub fn attributes(&self) -> (&'static str, Option<&'static str>) {
+        match *self {
+            DataEncoding::Csv => ("csv", None),
+            DataEncoding::Base64 { compression } => ("base64", compression.as_attribute()),
+        }
+    }
+
+    // Encodes the tile ids of a layer `width` tiles wide. CSV is written one row
+    // per line, starting and ending on a new line, as Tiled does.
+    pub(crate) fn encode(&self, gids: &[u32], width: u32) -> ::Result<String> {
+        match *self {
+            DataEncoding::Csv => {
+                let width = if width == 0 { gids.len().max(1) } else { width as usize };
+                let rows: Vec<String> = gids.chunks(width)
+                    .map(|row| row.iter().map(u32::to_string).collect::<Vec<_>>().join(","))
+                    .collect();
+                Ok(format!("\n{}\n", rows.join(",\n")))
+            }
+            DataEncoding::Base64 { compression } => {
+                let bytes: Vec<u8> = gids.iter().flat_map(|gid| gid.to_le_bytes().to_vec()).collect();
+                let bytes = match compression {
+                    Compression::None => bytes,
+                    Compression::Gzip => {
+                        let mut encoder = GzEncoder::new(Vec::new(), Default::default());
+                        encoder.write_all(&bytes)?;
+                        encoder.finish()?
+                    }
+                    Compression::Zlib => {
+                        let mut encoder = ZlibEncoder::new(Vec::new(), Default::default());
+                        encoder.write_all(&bytes)?;
+                        encoder.finish()?
+                    }
+                    Compression::Zstd => compress_zstd(&bytes)?,
+                };
+                Ok(STANDARD.encode(bytes))
+            }
+        }
+    }
+}
+
+impl Compression {
+    fn as_attribute(&self) -> Option<&'static str> {
+        match *self {
+            Compression::None => None,
+            Compression::Gzip => Some("gzip"),
+            Compression::Zlib => Some("zlib"),
+            Compression::Zstd => Some("zstd"),
+        }
+    }
+}
+
+fn decode_csv(raw: &str, count: usize, out: &mut Vec<u32>) -> ::Result<()> {
+    out.reserve(count);
+    for value in raw.split(',').map(str::trim).filter(|value| !value.is_empty()) {
+        out.push(reader::read_num(value)?);
+    }
+    Ok(())
+}
+
+// Inflates at most one byte more than `max`, so that the caller can tell the
+// limit was exceeded without the whole output ever being held in memory.
+fn decompress<R: Read>(decoder: R, max: Option<usize>) -> ::Result<Vec<u8>> {
+    let cap = max.map_or(u64::MAX, |max| max as u64 + 1);
+    let mut bytes = Vec::new();
+    decoder.take(cap).read_to_end(&mut bytes)
+        .map_err(|err| Error::InvalidData(err.to_string()))?;
+    Ok(bytes)
+}
+
+#[cfg(feature = "zstd")]
+fn decompress_zstd(bytes: &[u8], max: Option<usize>) -> ::Result<Vec<u8>> {
+    decompress(zstd::Decoder::new(bytes)?, max)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress_zstd(_bytes: &[u8], _max: Option<usize>) -> ::Result<Vec<u8>> {
+    Err(Error::UnsupportedCompression("zstd".to_string()))
+}
+
+#[cfg(feature = "zstd")]
+fn compress_zstd(bytes: &[u8]) -> ::Result<Vec<u8>> {
+    Ok(zstd::encode_all(bytes, 0)?)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn compress_zstd(_bytes: &[u8]) -> ::Result<Vec<u8>> {
+    Err(Error::UnsupportedCompression("zstd".to_string()))
+}
+
+fn decode_bytes(bytes: &[u8], out: &mut Vec<u32>) -> ::Result<()> {
+    let chunks = bytes.chunks_exact(4);
+    if !chunks.remainder().is_empty() {
+        return Err(Error::InvalidData(format!("{} bytes is not a whole number of tiles", bytes.len())));
+    }
+    out.extend(chunks.map(|gid| u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]])));
+    Ok(())
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct DataTile {
-    gid: i32,
+    gid: u32,
 }
 
 impl DataTile {
-    pub fn gid(&self) -> i32 {
+    pub fn gid(&self) -> u32 {
         self.gid
     }
 
-    fn set_gid(&mut self, gid: i32) {
+    fn set_gid(&mut self, gid: u32) {
         self.gid = gid;
     }
 }
 
+/// A rectangle of cells of the data of an infinite layer.
+///
+/// The position of the top-left cell is in tiles and may be negative. The
+/// global tile ids are given row by row, flip flags included.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Chunk {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    gids: Vec<u32>,
+}
+
+impl Chunk {
+    pub fn x(&self) -> i32 {
+        self.x
+    }
+
+    pub fn y(&self) -> i32 {
+        self.y
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn gids(&self) -> &[u32] {
+        &self.gids
+    }
+
+    // Returns the position in `gids` of the cell at `(x, y)`, if it is in the chunk.
+    fn index(&self, x: i64, y: i64) -> Option<usize> {
+        let (x, y) = (x - i64::from(self.x), y - i64::from(self.y));
+        let inside = (0..i64::from(self.width)).contains(&x) && (0..i64::from(self.height)).contains(&y);
+        if inside { Some(y as usize * self.width as usize + x as usize) } else { None }
+    }
+
+    // Checks that there is a tile id for each cell.
+    fn check_size(&self) -> ::Result<()> {
+        let count = (self.width as usize).saturating_mul(self.height as usize);
+        if self.gids.len() != count {
+            return Err(Error::InvalidData(format!("expected {} tiles for a {}x{} chunk, got {}",
+                                                  count, self.width, self.height, self.gids.len())));
+        }
+        Ok(())
+    }
+}
+
+// A `chunk` element as read, before its content is decoded with the encoding
+// of the data holding it.
+#[derive(Debug, Default)]
+pub(crate) struct RawChunk {
+    chunk: Chunk,
+    tiles: Vec<u32>,
+    text: String,
+}
+
 impl<R: Read> ElementReader<Data> for TmxReader<R> {
-    fn read_attributes(&mut self, data: &mut Data, name: &str, value: &str) -> ::Result<()> {
+    fn read_attributes(&mut self, data: &mut Data, tag: &str, name: &str, value: &str) -> ::Result<()> {
         match name {
             "encoding" => {
                 data.set_encoding(value);
@@ -89,38 +686,253 @@ impl<R: Read> ElementReader<Data> for TmxReader<R> {
                 data.set_compression(value);
             }
             _ => {
-                return Err(Error::UnknownAttribute(name.to_string()));
+                return Err(reader::unknown_attribute(tag, name));
             }
         };
         Ok(())
     }
 
-    fn read_children(&mut self, data: &mut Data, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
-        if name == "tile" {
+    fn read_children(&mut self, data: &mut Data, tag: &str, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
+        if name == "chunk" {
+            let RawChunk { mut chunk, tiles, text } = self.on_chunk(attributes)?;
+            let limits = self.options().limits;
+            if data.encoding.is_some() {
+                if !tiles.is_empty() {
+                    self.report_ignored_content(data, "`tile` elements in encoded layer data ignored");
+                }
+                let encoded = Data {
+                    encoding: data.encoding.clone(),
+                    compression: data.compression.clone(),
+                    content: DataContent::Text { raw: text },
+                    max_decoded_bytes: limits.max_layer_bytes,
+                    ..Data::default()
+                };
+                encoded.decode_content(&mut chunk.gids)?;
+            } else {
+                if !text.trim().is_empty() {
+                    self.report_ignored_content(data, "Text in layer data without encoding ignored");
+                }
+                chunk.gids = tiles;
+            }
+            chunk.check_size()?;
+            let count = data.chunks().map(|chunk| chunk.gids.len()).sum::<usize>() + chunk.gids.len();
+            self.check_data_tile_count(count)?;
+            let other_content = data.tiles().next().is_some() || data.raw_content().is_some_and(|raw| !raw.trim().is_empty());
+            if other_content {
+                self.report_ignored_content(data, "Content beside the chunks of layer data ignored");
+            }
+            data.add_chunk(chunk);
+            data.set_max_decoded_bytes(limits.max_layer_bytes);
+        } else if name == "tile" && data.is_chunked() {
+            self.skip_element()?;
+            self.report_ignored_content(data, "Content beside the chunks of layer data ignored");
+        } else if name == "tile" {
+            // No more than the decoded data may hold, see `Limits::max_layer_bytes`.
+            let max = self.options().limits.max_layer_bytes.map_or(MAX_RESERVED_TILES, |max| max / 4);
+            let reserved = match data.tiles().len() {
+                0 => self.take_data_tile_count().min(max).min(MAX_RESERVED_TILES),
+                _ => 0,
+            };
             let tile = self.on_data_tile(attributes)?;
-            data.add_tile(tile);
+            if data.encoding.is_some() {
+                self.report_ignored_content(data, "`tile` elements in encoded layer data ignored");
+            } else {
+                self.check_data_tile_count(data.tiles().len() + 1)?;
+                data.add_tile(tile, reserved);
+            }
+        } else {
+            return Err(reader::unknown_element(tag, name));
         }
         Ok(())
     }
 
-    fn read_content(&mut self, data: &mut Data, content: &str) -> ::Result<()> {
-        data.set_raw_content(content);
+    fn read_content(&mut self, data: &mut Data, content: String) -> ::Result<()> {
+        if data.is_chunked() {
+            if !content.trim().is_empty() {
+                self.report_ignored_content(data, "Content beside the chunks of layer data ignored");
+            }
+            return Ok(());
+        }
+        if data.encoding.is_none() {
+            if !content.trim().is_empty() {
+                self.report_ignored_content(data, "Text in layer data without encoding ignored");
+            }
+            return Ok(());
+        }
+        let limits = self.options().limits;
+        let length = data.raw_content().map_or(0, str::len) + content.len();
+        reader::check_limit("max_data_length", length, limits.max_data_length)?;
+        data.append_raw_content(content);
+        data.set_max_decoded_bytes(limits.max_layer_bytes);
         Ok(())
     }
 }
 
+impl<R: Read> TmxReader<R> {
+    // Warns about content of `data` not matching its encoding, the first time only.
+    fn report_ignored_content(&mut self, data: &mut Data, message: &str) {
+        if !data.ignored_content_reported {
+            data.ignored_content_reported = true;
+            self.diagnose(Severity::Warning, message.to_string());
+        }
+    }
+}
+
 impl<R: Read> ElementReader<DataTile> for TmxReader<R> {
-    fn read_attributes(&mut self, tile: &mut DataTile, name: &str, value: &str) -> ::Result<()> {
+    fn read_attributes(&mut self, tile: &mut DataTile, tag: &str, name: &str, value: &str) -> ::Result<()> {
         match name {
             "gid" => {
                 let gid = reader::read_num(value)?;
                 tile.set_gid(gid);
             }
             _ => {
-                return Err(Error::UnknownAttribute(name.to_string()));
+                return Err(reader::unknown_attribute(tag, name));
             }
         };
         Ok(())
     }
 }
 
+impl<R: Read> ElementReader<RawChunk> for TmxReader<R> {
+    fn read_attributes(&mut self, raw: &mut RawChunk, tag: &str, name: &str, value: &str) -> ::Result<()> {
+        match name {
+            "x" => {
+                raw.chunk.x = reader::read_num(value)?;
+            }
+            "y" => {
+                raw.chunk.y = reader::read_num(value)?;
+            }
+            "width" => {
+                raw.chunk.width = self.read_dimension(value)?;
+            }
+            "height" => {
+                raw.chunk.height = self.read_dimension(value)?;
+            }
+            _ => {
+                return Err(reader::unknown_attribute(tag, name));
+            }
+        };
+        Ok(())
+    }
+
+    fn read_children(&mut self, raw: &mut RawChunk, tag: &str, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
+        if name == "tile" {
+            let tile = self.on_data_tile(attributes)?;
+            self.check_data_tile_count(raw.tiles.len() + 1)?;
+            raw.tiles.push(tile.gid);
+        } else {
+            return Err(reader::unknown_element(tag, name));
+        }
+        Ok(())
+    }
+
+    fn read_content(&mut self, raw: &mut RawChunk, content: String) -> ::Result<()> {
+        let max = self.options().limits.max_data_length;
+        reader::check_limit("max_data_length", raw.text.len() + content.len(), max)?;
+        raw.text.push_str(&content);
+        Ok(())
+    }
+}
+
+impl<W: Write> ElementWriter<Data> for TmxWriter<W> {
+    fn write_element(&mut self, data: &Data) -> ::Result<()> {
+        let mut attributes = Attributes::new();
+        attributes.add_option("encoding", data.encoding());
+        attributes.add_option("compression", data.compression());
+        if let DataContent::Chunked(ref chunks) = data.content {
+            let encoding = match (data.encoding(), DataEncoding::from_attributes(data.encoding(), data.compression())) {
+                (Some(_), None) => return Err(Error::BadEncoding(data.encoding().unwrap_or("").to_string())),
+                (_, encoding) => encoding,
+            };
+            return self.write_chunks(&attributes, chunks, encoding);
+        }
+        if data.encoding.is_some() {
+            let encoded;
+            let content = match (DataEncoding::from_attributes(data.encoding(), data.compression()), &data.content) {
+                (Some(encoding), DataContent::Decoded(gids)) => {
+                    // The width of the layer is unknown here: CSV is written on one line.
+                    encoded = encoding.encode(gids, 0)?;
+                    &encoded
+                }
+                _ => data.raw_content().unwrap_or(""),
+            };
+            return self.text_element("data", &attributes, content);
+        }
+        let gids = data.decode()?;
+        self.element("data", &attributes, !gids.is_empty(), |w| {
+            for &gid in gids {
+                w.write_element(&DataTile { gid })?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<W: Write> ElementWriter<DataTile> for TmxWriter<W> {
+    fn write_element(&mut self, tile: &DataTile) -> ::Result<()> {
+        let mut attributes = Attributes::new();
+        attributes.add("gid", tile.gid);
+        self.empty_element("tile", &attributes)
+    }
+}
+
+impl<W: Write> TmxWriter<W> {
+    // Writes the data of a layer `width` tiles wide with the given encoding. The
+    // data is passed through verbatim if it was read with that encoding and
+    // not edited since.
+    pub(crate) fn write_data_as(&mut self, data: &Data, width: u32, encoding: DataEncoding) -> ::Result<()> {
+        let (encoding_name, compression_name) = encoding.attributes();
+        if !data.is_edited() && data.encoding() == Some(encoding_name) && data.compression() == compression_name {
+            return self.write_element(data);
+        }
+        let mut attributes = Attributes::new();
+        attributes.add("encoding", encoding_name);
+        attributes.add_option("compression", compression_name);
+        if let DataContent::Chunked(ref chunks) = data.content {
+            return self.write_chunks(&attributes, chunks, Some(encoding));
+        }
+        let content = self.encoded_content(data.decode()?, width, encoding)?;
+        self.text_element("data", &attributes, &content)
+    }
+
+    // Writes chunked data, the chunks being encoded again with `encoding`, or
+    // written as `tile` elements without one.
+    fn write_chunks(&mut self, attributes: &Attributes, chunks: &[Chunk], encoding: Option<DataEncoding>)
+                    -> ::Result<()> {
+        self.element("data", attributes, !chunks.is_empty(), |w| {
+            for chunk in chunks {
+                let mut attributes = Attributes::new();
+                attributes.add("x", chunk.x);
+                attributes.add("y", chunk.y);
+                attributes.add("width", chunk.width);
+                attributes.add("height", chunk.height);
+                match encoding {
+                    Some(encoding) => {
+                        let content = w.encoded_content(&chunk.gids, chunk.width, encoding)?;
+                        w.text_element("chunk", &attributes, &content)?;
+                    }
+                    None => {
+                        w.element("chunk", &attributes, !chunk.gids.is_empty(), |w| {
+                            for &gid in &chunk.gids {
+                                w.write_element(&DataTile { gid })?;
+                            }
+                            Ok(())
+                        })?;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    // Encodes the tile ids of a layer `width` tiles wide as the content of an
+    // element.
+    fn encoded_content(&self, gids: &[u32], width: u32, encoding: DataEncoding) -> ::Result<String> {
+        let content = encoding.encode(gids, width)?;
+        Ok(match encoding {
+            // Tiled puts base64 content on its own line, one level deeper than the element.
+            DataEncoding::Base64 { .. } => format!("\n{}{}\n{}", self.indentation(1), content, self.indentation(0)),
+            DataEncoding::Csv => content,
+        })
+    }
+}
@@ -0,0 +1,180 @@
+// This file is part of tmx
+// Copyright 2017 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::str::FromStr;
+
+use error::Error;
+
+/// An axis-aligned rectangle in pixels.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, w: u32, h: u32) -> Rect {
+        Rect { x, y, w, h }
+    }
+}
+
+/// An axis-aligned rectangle of cells, in tiles.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TileRect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl TileRect {
+    pub fn new(x: u32, y: u32, w: u32, h: u32) -> TileRect {
+        TileRect { x, y, w, h }
+    }
+}
+
+/// A point of a rectangle: the point of a map kept in place when it is
+/// resized, or the point of a tile object at its position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    // Returns where the content of a map `old` cells wide or high goes in a map
+    // `new` cells wide or high, horizontally and vertically.
+    pub(crate) fn shift(&self, old: (u32, u32), new: (u32, u32)) -> (i64, i64) {
+        let grow = (i64::from(new.0) - i64::from(old.0), i64::from(new.1) - i64::from(old.1));
+        let (dx, dy) = match *self {
+            Anchor::TopLeft => (0, 0),
+            Anchor::Top => (grow.0 / 2, 0),
+            Anchor::TopRight => (grow.0, 0),
+            Anchor::Left => (0, grow.1 / 2),
+            Anchor::Center => (grow.0 / 2, grow.1 / 2),
+            Anchor::Right => (grow.0, grow.1 / 2),
+            Anchor::BottomLeft => (0, grow.1),
+            Anchor::Bottom => (grow.0 / 2, grow.1),
+            Anchor::BottomRight => (grow.0, grow.1),
+        };
+        (dx, dy)
+    }
+
+    // Returns where the anchor lies in a rectangle, as fractions of its width
+    // and height from its top-left corner.
+    pub(crate) fn fractions(&self) -> (f64, f64) {
+        let x = match *self {
+            Anchor::TopLeft | Anchor::Left | Anchor::BottomLeft => 0.0,
+            Anchor::Top | Anchor::Center | Anchor::Bottom => 0.5,
+            Anchor::TopRight | Anchor::Right | Anchor::BottomRight => 1.0,
+        };
+        let y = match *self {
+            Anchor::TopLeft | Anchor::Top | Anchor::TopRight => 0.0,
+            Anchor::Left | Anchor::Center | Anchor::Right => 0.5,
+            Anchor::BottomLeft | Anchor::Bottom | Anchor::BottomRight => 1.0,
+        };
+        (x, y)
+    }
+
+    /// Returns the name of the anchor in the `objectalignment` attribute of tilesets.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Anchor::TopLeft => "topleft",
+            Anchor::Top => "top",
+            Anchor::TopRight => "topright",
+            Anchor::Left => "left",
+            Anchor::Center => "center",
+            Anchor::Right => "right",
+            Anchor::BottomLeft => "bottomleft",
+            Anchor::Bottom => "bottom",
+            Anchor::BottomRight => "bottomright",
+        }
+    }
+}
+
+impl FromStr for Anchor {
+    type Err = Error;
+
+    fn from_str(s: &str) -> ::Result<Anchor> {
+        match s {
+            "topleft" => Ok(Anchor::TopLeft),
+            "top" => Ok(Anchor::Top),
+            "topright" => Ok(Anchor::TopRight),
+            "left" => Ok(Anchor::Left),
+            "center" => Ok(Anchor::Center),
+            "right" => Ok(Anchor::Right),
+            "bottomleft" => Ok(Anchor::BottomLeft),
+            "bottom" => Ok(Anchor::Bottom),
+            "bottomright" => Ok(Anchor::BottomRight),
+            _ => Err(Error::BadObjectAlignment(s.to_string())),
+        }
+    }
+}
+
+/// An axis-aligned bounding box in pixels.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Aabb {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+}
+
+impl Aabb {
+    pub fn new(min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Aabb {
+        Aabb { min_x, min_y, max_x, max_y }
+    }
+
+    /// Returns the smallest box containing all the points, or `None` if there are none.
+    pub fn from_points<I: IntoIterator<Item = (f64, f64)>>(points: I) -> Option<Aabb> {
+        let mut points = points.into_iter();
+        let (x, y) = points.next()?;
+        let mut aabb = Aabb::new(x, y, x, y);
+        for (x, y) in points {
+            aabb.min_x = aabb.min_x.min(x);
+            aabb.min_y = aabb.min_y.min(y);
+            aabb.max_x = aabb.max_x.max(x);
+            aabb.max_y = aabb.max_y.max(y);
+        }
+        Some(aabb)
+    }
+
+    pub fn width(&self) -> f64 {
+        self.max_x - self.min_x
+    }
+
+    pub fn height(&self) -> f64 {
+        self.max_y - self.min_y
+    }
+
+    /// Tells whether the two boxes overlap, touching edges included.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min_x <= other.max_x && other.min_x <= self.max_x
+            && self.min_y <= other.max_y && other.min_y <= self.max_y
+    }
+}
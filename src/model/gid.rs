@@ -0,0 +1,69 @@
+// This file is part of tmx
+// Copyright 2017 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub const FLIPPED_HORIZONTALLY_FLAG: u32 = 0x8000_0000;
+pub const FLIPPED_VERTICALLY_FLAG: u32 = 0x4000_0000;
+pub const FLIPPED_DIAGONALLY_FLAG: u32 = 0x2000_0000;
+pub const ROTATED_HEXAGONAL_120_FLAG: u32 = 0x1000_0000;
+
+const FLAGS_MASK: u32 = FLIPPED_HORIZONTALLY_FLAG | FLIPPED_VERTICALLY_FLAG |
+                        FLIPPED_DIAGONALLY_FLAG | ROTATED_HEXAGONAL_120_FLAG;
+
+/// A global tile id as stored in layer data, including the flip flags in its high bits.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Gid(pub u32);
+
+impl Gid {
+    /// Returns the tile id with the flip flags cleared.
+    pub fn id(&self) -> u32 {
+        self.0 & !FLAGS_MASK
+    }
+
+    pub fn flags(&self) -> u32 {
+        self.0 & FLAGS_MASK
+    }
+
+    /// Returns the gid with its tile id replaced by `id`, keeping the flip flags.
+    pub fn with_id(&self, id: u32) -> Gid {
+        Gid(id & !FLAGS_MASK | self.flags())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.id() == 0
+    }
+
+    pub fn flipped_horizontally(&self) -> bool {
+        self.0 & FLIPPED_HORIZONTALLY_FLAG != 0
+    }
+
+    pub fn flipped_vertically(&self) -> bool {
+        self.0 & FLIPPED_VERTICALLY_FLAG != 0
+    }
+
+    pub fn flipped_diagonally(&self) -> bool {
+        self.0 & FLIPPED_DIAGONALLY_FLAG != 0
+    }
+
+    pub fn rotated_hexagonal_120(&self) -> bool {
+        self.0 & ROTATED_HEXAGONAL_120_FLAG != 0
+    }
+}
+
+impl From<u32> for Gid {
+    fn from(gid: u32) -> Gid {
+        Gid(gid)
+    }
+}
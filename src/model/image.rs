@@ -13,84 +13,241 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::Read;
+use std::io::{Read, Write};
+#[cfg(feature = "image")]
+use std::path::Path;
 use std::str::FromStr;
 
+#[cfg(feature = "image")]
+use image_crate::{self, ImageError, ImageFormat, RgbaImage};
 use xml::attribute::OwnedAttribute;
 
+#[cfg(feature = "image")]
 use error::Error;
 use model::color::Color;
 use model::data::Data;
-use model::reader::{self, TmxReader, ElementReader};
+use model::reader::{self, ElementReader, Severity, TmxReader};
+#[cfg(feature = "image")]
+use model::reader::Limits;
+#[cfg(feature = "image")]
+use model::resource::{self, FileLoader, ResourceLoader};
+use model::writer::{Attributes, ElementWriter, TmxWriter};
 
-#[derive(Debug, Default)]
+/// Where the pixels of an image are found.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ImageSource {
+    /// A file, whose path is relative to the file of the map or tileset.
+    File(String),
+    /// Data embedded in the document, in the given format such as `png`.
+    Embedded { format: String, data: Data },
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Image {
-    format: String,
-    source: String,
+    source: Option<ImageSource>,
     trans: Option<Color>,
-    width: u32,
-    height: u32,
-    data: Option<Data>,
+    width: Option<u32>,
+    height: Option<u32>,
 }
 
 impl Image {
+    /// Creates an image referring to the file `source`, which is `width` by `height` pixels.
+    pub fn new<S: Into<String>>(source: S, width: u32, height: u32) -> Image {
+        Image {
+            source: Some(ImageSource::File(source.into())),
+            width: Some(width),
+            height: Some(height),
+            ..Image::default()
+        }
+    }
+
+    /// Returns where the pixels of the image are found, or `None` if the image
+    /// has neither a `source` nor embedded data.
+    pub fn source_kind(&self) -> Option<&ImageSource> {
+        self.source.as_ref()
+    }
+
+    pub fn set_source_kind(&mut self, source: ImageSource) {
+        self.source = Some(source);
+    }
+
+    /// Returns the format of the embedded data, empty for other images.
     pub fn format(&self) -> &str {
-        &self.format
+        match self.source {
+            Some(ImageSource::Embedded { ref format, .. }) => format,
+            _ => "",
+        }
     }
 
-    fn set_format<S: Into<String>>(&mut self, format: S) {
-        self.format = format.into();
+    /// Sets the format of the embedded data. Images referring to a file are
+    /// left as they are.
+    pub fn set_format<S: Into<String>>(&mut self, format: S) {
+        if let Some(ImageSource::Embedded { format: ref mut current, .. }) = self.source {
+            *current = format.into();
+        }
     }
 
+    /// Returns the path of the file of the image, empty for embedded images.
     pub fn source(&self) -> &str {
-        &self.source
+        match self.source {
+            Some(ImageSource::File(ref path)) => path,
+            _ => "",
+        }
     }
 
-    fn set_source<S: Into<String>>(&mut self, source: S) {
-        self.source = source.into();
+    /// Makes the image refer to the file `source`, dropping any embedded data.
+    pub fn set_source<S: Into<String>>(&mut self, source: S) {
+        self.source = Some(ImageSource::File(source.into()));
     }
 
     pub fn trans(&self) -> Option<&Color> {
         self.trans.as_ref()
     }
 
-    fn set_trans(&mut self, color: Color) {
+    pub fn set_trans(&mut self, color: Color) {
         self.trans = Some(color);
     }
 
-    pub fn width(&self) -> u32 {
+    /// Returns the width of the image in pixels, if declared.
+    ///
+    /// Tiled leaves it out of the images of some collection tilesets and older
+    /// files write no size at all.
+    pub fn width(&self) -> Option<u32> {
         self.width
     }
 
-    fn set_width(&mut self, width: u32) {
-        self.width = width;
+    pub fn set_width(&mut self, width: u32) {
+        self.width = Some(width);
     }
 
-    pub fn height(&self) -> u32 {
+    /// Returns the height of the image in pixels, if declared; see `width`.
+    pub fn height(&self) -> Option<u32> {
         self.height
     }
 
-    fn set_height(&mut self, height: u32) {
-        self.height = height;
+    pub fn set_height(&mut self, height: u32) {
+        self.height = Some(height);
     }
 
+    /// Returns the width and height of the image in pixels, if both are declared.
+    pub fn size(&self) -> Option<(u32, u32)> {
+        self.width.zip(self.height)
+    }
+
+    /// Returns the embedded data of the image, if any.
     pub fn data(&self) -> Option<&Data> {
-        self.data.as_ref()
+        match self.source {
+            Some(ImageSource::Embedded { ref data, .. }) => Some(data),
+            _ => None,
+        }
     }
 
-    fn set_data(&mut self, data: Data) {
-        self.data = Some(data);
+    pub fn into_data(self) -> Option<Data> {
+        match self.source {
+            Some(ImageSource::Embedded { data, .. }) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Decodes the embedded data of the image, or its file relative to
+    /// `base_dir`, into RGBA pixels where the `trans` color is transparent.
+    ///
+    /// Fails with `Error::UnsupportedImage` if the format is unknown or not
+    /// compiled in, `Error::InvalidImage` if the content cannot be decoded or
+    /// if the image has no source, and `Error::ImageSize` if the image does
+    /// not have the declared width or height. The errors raised while loading
+    /// a file, a missing file included, are wrapped in `Error::File`.
+    #[cfg(feature = "image")]
+    pub fn load(&self, base_dir: &Path) -> ::Result<RgbaImage> {
+        self.load_with(base_dir, &mut FileLoader, &Limits::default())
+    }
+
+    /// Same as `load`, the file being read through `loader` and its size, or
+    /// that of the embedded data once decoded, checked against
+    /// `limits.max_image_bytes`.
+    #[cfg(feature = "image")]
+    pub fn load_with<L>(&self, base_dir: &Path, loader: &mut L, limits: &Limits) -> ::Result<RgbaImage>
+        where L: ResourceLoader + ?Sized
+    {
+        let max = limits.max_image_bytes;
+        match self.source {
+            Some(ImageSource::File(ref source)) => {
+                let path = resource::resolve_path(Some(base_dir), source);
+                let path = resource::path_str(&path)?;
+                load_file(loader, path, max)
+                    .and_then(|bytes| self.decode(&bytes, "", source))
+                    .map_err(|err| resource::file_error(path, err))
+            }
+            Some(ImageSource::Embedded { ref format, ref data }) => {
+                let name = if format.is_empty() { "embedded data" } else { format };
+                self.decode(&data.decode_base64("max_image_bytes", max)?, format, name)
+            }
+            None => Err(Error::InvalidImage("the image has no source".to_string())),
+        }
+    }
+
+    // Decodes `bytes` in the given format, guessed from the content if empty.
+    // The errors name the image `name`.
+    #[cfg(feature = "image")]
+    fn decode(&self, bytes: &[u8], format: &str, name: &str) -> ::Result<RgbaImage> {
+        let format = if format.is_empty() {
+            image_crate::guess_format(bytes).ok()
+        } else {
+            ImageFormat::from_extension(format)
+        };
+        let unsupported = || Error::UnsupportedImage(name.to_string());
+        let decoded = image_crate::load_from_memory_with_format(bytes, format.ok_or_else(unsupported)?)
+            .map_err(|err| match err {
+                ImageError::Unsupported(_) => unsupported(),
+                err => Error::InvalidImage(err.to_string()),
+            })?;
+        let actual = (decoded.width(), decoded.height());
+        let expected = (self.width.unwrap_or(actual.0), self.height.unwrap_or(actual.1));
+        if expected != actual {
+            return Err(Error::ImageSize { expected, actual });
+        }
+        let mut pixels = decoded.into_rgba8();
+        if let Some(trans) = self.trans {
+            for pixel in pixels.pixels_mut() {
+                if pixel[0] == trans.red() && pixel[1] == trans.green() && pixel[2] == trans.blue() {
+                    pixel[3] = 0;
+                }
+            }
+        }
+        Ok(pixels)
+    }
+}
+
+// Reads the file at `path` through `loader`, no more than one byte past `max`
+// so that a file over the limit is never held whole in memory.
+#[cfg(feature = "image")]
+fn load_file<L: ResourceLoader + ?Sized>(loader: &mut L, path: &str, max: Option<usize>) -> ::Result<Vec<u8>> {
+    match max {
+        Some(max) => {
+            let mut bytes = Vec::new();
+            loader.load(path)?.take(max as u64 + 1).read_to_end(&mut bytes)?;
+            reader::check_limit("max_image_bytes", bytes.len(), Some(max))?;
+            Ok(bytes)
+        }
+        None => loader.load_bytes(path),
     }
 }
 
 impl<R: Read> ElementReader<Image> for TmxReader<R> {
-    fn read_attributes(&mut self, image: &mut Image, name: &str, value: &str) -> ::Result<()> {
+    fn read_attributes(&mut self, image: &mut Image, tag: &str, name: &str, value: &str) -> ::Result<()> {
         match name {
+            // The format only matters for embedded data: a `source` wins.
             "format" => {
-                image.set_format(value);
+                if image.source.is_none() {
+                    image.source = Some(ImageSource::Embedded { format: value.to_string(), data: Data::default() });
+                }
             }
             "source" => {
-                image.set_source(value);
+                if !value.is_empty() {
+                    image.set_source(value);
+                }
             }
             "trans" => {
                 let color = Color::from_str(value)?;
@@ -105,18 +262,43 @@ impl<R: Read> ElementReader<Image> for TmxReader<R> {
                 image.set_height(height);
             }
             _ => {
-                return Err(Error::UnknownAttribute(name.to_string()));
+                return Err(reader::unknown_attribute(tag, name));
             }
         };
         Ok(())
     }
 
-    fn read_children(&mut self, image: &mut Image, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
+    fn read_children(&mut self, image: &mut Image, tag: &str, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
         if let "data" = name {
             let data = self.on_data(attributes)?;
-            image.set_data(data);
+            match image.source {
+                Some(ImageSource::File(_)) => {
+                    self.diagnose(Severity::Warning,
+                                  "Image with both a `source` and embedded data; the data is ignored".to_string());
+                }
+                Some(ImageSource::Embedded { data: ref mut embedded, .. }) => *embedded = data,
+                None => image.source = Some(ImageSource::Embedded { format: String::new(), data }),
+            }
+        } else {
+            return Err(reader::unknown_element(tag, name));
         }
         Ok(())
     }
 }
 
+impl<W: Write> ElementWriter<Image> for TmxWriter<W> {
+    fn write_element(&mut self, image: &Image) -> ::Result<()> {
+        let mut attributes = Attributes::new();
+        attributes.add_if(!image.format().is_empty(), "format", image.format());
+        attributes.add_if(!image.source().is_empty(), "source", image.source());
+        attributes.add_option("trans", image.trans.map(|color| color.to_hex_string()[1..].to_string()));
+        attributes.add_option("width", image.width);
+        attributes.add_option("height", image.height);
+        self.element("image", &attributes, image.data().is_some(), |w| {
+            match image.data() {
+                Some(data) => w.write_element(data),
+                None => Ok(()),
+            }
+        })
+    }
+}
@@ -0,0 +1,369 @@
+// This file is part of tmx
+// Copyright 2017 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Writing maps in the Tiled JSON format (`.tmj`).
+//!
+//! This is export only: maps cannot be read back from JSON. The output is
+//! checked against the model it was written from instead, and its structure
+//! against a reference file written after the format documented by Tiled.
+
+use std::io::{self, Write};
+
+use serde_json::{self, Value};
+use serde_json::map::Map as JsonObject;
+
+use model::data::{Data, DataEncoding};
+use model::image::Image;
+use model::map::{ImageLayer, Layer, LayerKind, Map, Object, ObjectGroup, Opacity};
+use model::property::{Properties, Property, PropertyType, PropertyValue};
+use model::shape::{Point, Shape};
+use model::tileset::{Terrain, Tile, Tileset};
+use model::writer::WriteOptions;
+
+impl Map {
+    /// Writes the map in the Tiled JSON format.
+    pub fn to_json_writer<W: Write>(&self, writer: W) -> ::Result<()> {
+        self.to_json_writer_with_options(writer, &WriteOptions::default())
+    }
+
+    /// Writes the map in the Tiled JSON format, encoding layer data as requested by `options`.
+    ///
+    /// Data written as CSV, or read as XML tiles or CSV, becomes an array of
+    /// global tile ids; base64 data stays a string.
+    pub fn to_json_writer_with_options<W: Write>(&self, writer: W, options: &WriteOptions) -> ::Result<()> {
+        let value = map_value(self, options)?;
+        serde_json::to_writer_pretty(writer, &value).map_err(io::Error::from)?;
+        Ok(())
+    }
+
+    /// Returns the map in the Tiled JSON format.
+    pub fn to_json_string(&self) -> ::Result<String> {
+        self.to_json_string_with_options(&WriteOptions::default())
+    }
+
+    /// Returns the map in the Tiled JSON format, encoding layer data as requested by `options`.
+    pub fn to_json_string_with_options(&self, options: &WriteOptions) -> ::Result<String> {
+        let mut buffer = Vec::new();
+        self.to_json_writer_with_options(&mut buffer, options)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+fn map_value(map: &Map, options: &WriteOptions) -> ::Result<Value> {
+    let mut object = JsonObject::new();
+    object.insert("type".into(), "map".into());
+    if !map.version().is_empty() {
+        object.insert("version".into(), map.version().into());
+    }
+    if !map.tiled_version().is_empty() {
+        object.insert("tiledversion".into(), map.tiled_version().into());
+    }
+    object.insert("orientation".into(), map.orientation().as_str().into());
+    object.insert("renderorder".into(), map.render_order().as_str().into());
+    object.insert("width".into(), map.width().into());
+    object.insert("height".into(), map.height().into());
+    object.insert("tilewidth".into(), map.tile_width().into());
+    object.insert("tileheight".into(), map.tile_height().into());
+    object.insert("infinite".into(), map.is_infinite().into());
+    object.insert("nextobjectid".into(), map.next_object_id().into());
+    if let Some(hex_side_length) = map.hex_side_length() {
+        object.insert("hexsidelength".into(), hex_side_length.into());
+    }
+    if let Some(stagger_axis) = map.stagger_axis() {
+        object.insert("staggeraxis".into(), stagger_axis.as_str().into());
+    }
+    if let Some(stagger_index) = map.stagger_index() {
+        object.insert("staggerindex".into(), stagger_index.as_str().into());
+    }
+    if let Some(color) = map.background_color() {
+        object.insert("backgroundcolor".into(), color.to_hex_string().into());
+    }
+    insert_properties(&mut object, map.properties());
+    object.insert("tilesets".into(), map.tilesets().map(tileset_value).collect());
+    let mut layers = Vec::new();
+    for layer in map.layer_stack() {
+        layers.push(match layer {
+            LayerKind::Tile(layer) => tile_layer_value(layer, options)?,
+            LayerKind::Image(layer) => image_layer_value(layer),
+            LayerKind::Objects(group) => object_group_value(group),
+        });
+    }
+    object.insert("layers".into(), layers.into());
+    Ok(object.into())
+}
+
+// Attributes common to every kind of layer.
+fn layer_object(layer_type: &str, name: &str, x: i32, y: i32, opacity: Opacity, visible: bool, offset: (i32, i32))
+                -> JsonObject<String, Value> {
+    let mut object = JsonObject::new();
+    object.insert("type".into(), layer_type.into());
+    object.insert("name".into(), name.into());
+    object.insert("x".into(), x.into());
+    object.insert("y".into(), y.into());
+    object.insert("opacity".into(), opacity.value().into());
+    object.insert("visible".into(), visible.into());
+    if offset.0 != 0 {
+        object.insert("offsetx".into(), offset.0.into());
+    }
+    if offset.1 != 0 {
+        object.insert("offsety".into(), offset.1.into());
+    }
+    object
+}
+
+fn tile_layer_value(layer: &Layer, options: &WriteOptions) -> ::Result<Value> {
+    let offset = (layer.offset_x(), layer.offset_y());
+    let mut object = layer_object("tilelayer", layer.name(), layer.x(), layer.y(), layer.opacity(),
+                                  layer.is_visible(), offset);
+    object.insert("width".into(), layer.width().into());
+    object.insert("height".into(), layer.height().into());
+    if let Some(color) = layer.tint_color() {
+        object.insert("tintcolor".into(), color.to_hex_string().into());
+    }
+    if let Some(data) = layer.data() {
+        let encoding = options.layer_encoding(layer.name());
+        insert_data(&mut object, data, layer.width(), encoding)?;
+    }
+    insert_properties(&mut object, layer.properties());
+    Ok(object.into())
+}
+
+// Writes base64 data as a string, passed through when the encoding is unchanged,
+// and any other data as an array of global tile ids. Chunked data is written
+// as `chunks`, each one with its own `data`, along with the position of the
+// top-left cell of the chunks, as Tiled does for infinite maps.
+fn insert_data(object: &mut JsonObject<String, Value>, data: &Data, width: u32, encoding: Option<DataEncoding>)
+               -> ::Result<()> {
+    let original = DataEncoding::from_attributes(data.encoding(), data.compression());
+    let encoding = encoding.or(original);
+    if data.is_chunked() {
+        let mut chunks = Vec::new();
+        for chunk in data.chunks() {
+            let mut chunk_object = JsonObject::new();
+            chunk_object.insert("data".into(), gids_value(chunk.gids(), chunk.width(), encoding)?);
+            chunk_object.insert("x".into(), chunk.x().into());
+            chunk_object.insert("y".into(), chunk.y().into());
+            chunk_object.insert("width".into(), chunk.width().into());
+            chunk_object.insert("height".into(), chunk.height().into());
+            chunks.push(Value::from(chunk_object));
+        }
+        object.insert("chunks".into(), chunks.into());
+        let (start_x, start_y, _, _) = data.chunk_bounds().unwrap_or((0, 0, 0, 0));
+        object.insert("startx".into(), start_x.into());
+        object.insert("starty".into(), start_y.into());
+    } else {
+        let content = match encoding {
+            Some(DataEncoding::Base64 { .. }) if encoding == original && !data.is_edited() => {
+                data.raw_content().unwrap_or("").trim().into()
+            }
+            _ => gids_value(data.decode()?, width, encoding)?,
+        };
+        object.insert("data".into(), content);
+    }
+    if let Some(encoding @ DataEncoding::Base64 { .. }) = encoding {
+        let (encoding_name, compression_name) = encoding.attributes();
+        object.insert("encoding".into(), encoding_name.into());
+        if let Some(compression_name) = compression_name {
+            object.insert("compression".into(), compression_name.into());
+        }
+    }
+    Ok(())
+}
+
+// Returns the tile ids of a layer `width` tiles wide as a base64 string, or as
+// an array without base64 encoding.
+fn gids_value(gids: &[u32], width: u32, encoding: Option<DataEncoding>) -> ::Result<Value> {
+    match encoding {
+        Some(encoding @ DataEncoding::Base64 { .. }) => Ok(encoding.encode(gids, width)?.into()),
+        _ => Ok(gids.to_vec().into()),
+    }
+}
+
+fn image_layer_value(layer: &ImageLayer) -> Value {
+    let offset = (layer.offset_x(), layer.offset_y());
+    let mut object = layer_object("imagelayer", layer.name(), layer.x(), layer.y(), layer.opacity(),
+                                  layer.is_visible(), offset);
+    let source = layer.image().map_or("", Image::source);
+    object.insert("image".into(), source.into());
+    if let Some(color) = layer.image().and_then(Image::trans) {
+        object.insert("transparentcolor".into(), color.to_hex_string().into());
+    }
+    insert_properties(&mut object, layer.properties());
+    object.into()
+}
+
+fn object_group_value(group: &ObjectGroup) -> Value {
+    let offset = (group.offset_x(), group.offset_y());
+    let mut object = layer_object("objectgroup", group.name(), group.x(), group.y(), group.opacity(),
+                                  group.is_visible(), offset);
+    object.insert("draworder".into(), group.draw_order().as_str().into());
+    if let Some(color) = group.color() {
+        object.insert("color".into(), color.to_hex_string().into());
+    }
+    object.insert("objects".into(), group.objects().map(object_value).collect());
+    insert_properties(&mut object, group.properties());
+    object.into()
+}
+
+fn object_value(object: &Object) -> Value {
+    let mut value = JsonObject::new();
+    value.insert("id".into(), object.id().into());
+    value.insert("name".into(), object.name().into());
+    value.insert("type".into(), object.object_type().into());
+    value.insert("x".into(), object.x().into());
+    value.insert("y".into(), object.y().into());
+    value.insert("width".into(), object.width().into());
+    value.insert("height".into(), object.height().into());
+    value.insert("rotation".into(), object.rotation().into());
+    value.insert("visible".into(), object.is_visible().into());
+    if let Some(gid) = object.gid() {
+        value.insert("gid".into(), gid.into());
+    }
+    match object.shape() {
+        Some(Shape::Ellipse) => {
+            value.insert("ellipse".into(), true.into());
+        }
+        Some(Shape::Point) => {
+            value.insert("point".into(), true.into());
+        }
+        Some(Shape::Polygon(polygon)) => {
+            value.insert("polygon".into(), polygon.points().map(point_value).collect());
+        }
+        Some(Shape::Polyline(polyline)) => {
+            value.insert("polyline".into(), polyline.points().map(point_value).collect());
+        }
+        None => {}
+    }
+    insert_properties(&mut value, object.properties());
+    value.into()
+}
+
+fn point_value(point: &Point) -> Value {
+    json!({ "x": point.x, "y": point.y })
+}
+
+fn tileset_value(tileset: &Tileset) -> Value {
+    let mut object = JsonObject::new();
+    object.insert("firstgid".into(), tileset.first_gid().into());
+    if tileset.is_external() {
+        object.insert("source".into(), tileset.source().into());
+        return object.into();
+    }
+    object.insert("name".into(), tileset.name().into());
+    object.insert("tilewidth".into(), tileset.tile_width().into());
+    object.insert("tileheight".into(), tileset.tile_height().into());
+    object.insert("spacing".into(), tileset.spacing().into());
+    object.insert("margin".into(), tileset.margin().into());
+    object.insert("tilecount".into(), tileset.tile_count().into());
+    object.insert("columns".into(), tileset.columns().into());
+    if let Some(object_alignment) = tileset.object_alignment() {
+        object.insert("objectalignment".into(), object_alignment.as_str().into());
+    }
+    if let Some(image) = tileset.image() {
+        insert_image(&mut object, image);
+    }
+    if let Some(tile_offset) = tileset.tile_offset() {
+        object.insert("tileoffset".into(), json!({ "x": tile_offset.x(), "y": tile_offset.y() }));
+    }
+    insert_properties(&mut object, tileset.properties());
+    if tileset.terrain_types().len() > 0 {
+        object.insert("terrains".into(), tileset.terrain_types().map(terrain_value).collect());
+    }
+    if !tileset.tiles_slice().is_empty() {
+        object.insert("tiles".into(), tileset.tiles().map(tile_value).collect());
+    }
+    object.into()
+}
+
+fn insert_image(object: &mut JsonObject<String, Value>, image: &Image) {
+    object.insert("image".into(), image.source().into());
+    if let Some(width) = image.width() {
+        object.insert("imagewidth".into(), width.into());
+    }
+    if let Some(height) = image.height() {
+        object.insert("imageheight".into(), height.into());
+    }
+    if let Some(color) = image.trans() {
+        object.insert("transparentcolor".into(), color.to_hex_string().into());
+    }
+}
+
+fn terrain_value(terrain: &Terrain) -> Value {
+    let mut object = JsonObject::new();
+    object.insert("name".into(), terrain.name().into());
+    object.insert("tile".into(), terrain.tile().parse::<i64>().unwrap_or(-1).into());
+    insert_properties(&mut object, terrain.properties());
+    object.into()
+}
+
+fn tile_value(tile: &Tile) -> Value {
+    let mut object = JsonObject::new();
+    object.insert("id".into(), tile.id().into());
+    if let Some(corners) = tile.terrain() {
+        let terrain: Vec<i64> = corners.corners().iter().map(|corner| corner.map_or(-1, i64::from)).collect();
+        object.insert("terrain".into(), terrain.into());
+    }
+    if let Some(probability) = tile.probability() {
+        object.insert("probability".into(), probability.into());
+    }
+    if let Some(image) = tile.image() {
+        insert_image(&mut object, image);
+    }
+    if let Some(group) = tile.object_group() {
+        object.insert("objectgroup".into(), object_group_value(group));
+    }
+    if let Some(animation) = tile.animation() {
+        let frames = animation.frames()
+            .map(|frame| json!({ "tileid": frame.tile_id(), "duration": frame.duration() }))
+            .collect();
+        object.insert("animation".into(), frames);
+    }
+    insert_properties(&mut object, tile.properties());
+    object.into()
+}
+
+fn insert_properties(object: &mut JsonObject<String, Value>, properties: Properties) {
+    if properties.len() > 0 {
+        object.insert("properties".into(), properties.map(property_value).collect());
+    }
+}
+
+fn property_value(property: &Property) -> Value {
+    let mut object = JsonObject::new();
+    object.insert("name".into(), property.name().into());
+    object.insert("type".into(), property.property_type().as_str().into());
+    if !property.custom_type().is_empty() {
+        object.insert("propertytype".into(), property.custom_type().into());
+    }
+    object.insert("value".into(), typed_value(property));
+    object.into()
+}
+
+// Values that do not parse according to their type are written as strings.
+fn typed_value(property: &Property) -> Value {
+    if property.property_type() == PropertyType::Class {
+        let members = property.members()
+            .map(|member| (member.name().to_string(), typed_value(member)))
+            .collect::<JsonObject<String, Value>>();
+        return members.into();
+    }
+    match property.to_value() {
+        Ok(PropertyValue::Bool(value)) => value.into(),
+        Ok(PropertyValue::Int(value)) => value.into(),
+        Ok(PropertyValue::Float(value)) => value.into(),
+        Ok(PropertyValue::Object(value)) => value.into(),
+        _ => property.value().into(),
+    }
+}
@@ -13,21 +13,37 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::Read;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{Read, Write};
+use std::iter;
+use std::mem;
+use std::ops;
 use std::str::FromStr;
-use std::path::Path;
-use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::sync::{Arc, OnceLock};
+use std::vec;
 
+#[cfg(feature = "image")]
+use image_crate::RgbaImage;
 use xml::attribute::OwnedAttribute;
 
 use error::Error;
 use model::color::Color;
-use model::data::Data;
-use model::image::Image;
-use model::property::{PropertyCollection, Properties};
-use model::reader::{self, TmxReader, ElementReader};
-use model::shape::Shape;
-use model::tileset::{Tileset};
+use model::data::{Data, DataEncoding};
+use model::geometry::{Aabb, Anchor, Rect, TileRect};
+use model::gid::Gid;
+use model::image::{Image, ImageSource};
+use model::object_types::ObjectTypes;
+use model::property::{PropertyCollection, Properties, Property};
+use model::reader::{self, Diagnostic, ElementReader, ReaderOptions, Severity, TmxReader, UnknownAttribute, UnknownElement};
+use model::resource::{self, FileLoader, ResourceLoader};
+use model::shape::{Points, Shape, WorldShape};
+use model::tileset::{Animation, Tile, Tileset};
+use model::writer::{Attributes, ElementWriter, TmxWriter, WriteOptions};
 
 define_iterator_wrapper!(Tilesets, Tileset);
 define_iterator_wrapper!(Layers, Layer);
@@ -35,10 +51,14 @@ define_iterator_wrapper!(ImageLayers, ImageLayer);
 define_iterator_wrapper!(ObjectGroups, ObjectGroup);
 define_iterator_wrapper!(Objects, Object);
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "UncheckedMap"))]
 pub struct Map {
+    #[cfg_attr(feature = "serde", serde(rename = "background_color"))]
     bg_color: Option<Color>,
     version: String,
+    tiled_version: String,
     orientation: Orientation,
     render_order: RenderOrder,
     width: u32,
@@ -48,34 +68,270 @@ pub struct Map {
     hex_side_length: Option<u32>,
     stagger_axis: Option<Axis>,
     stagger_index: Option<Index>,
+    infinite: bool,
     next_object_id: u32,
     properties: PropertyCollection,
     tilesets: Vec<Tileset>,
     layers: Vec<Layer>,
     image_layers: Vec<ImageLayer>,
     object_groups: Vec<ObjectGroup>,
+    // The layers of all kinds in document order, as positions in the vectors
+    // above. The layers are kept in one vector per kind rather than in a
+    // single ordered one so that `layers`, `layers_mut`, `into_layers` and
+    // their counterparts for image layers and object groups keep returning
+    // exact-size slice and vector iterators. Layers are only ever appended,
+    // through `add_layer` and the like, which keeps the positions valid, and
+    // deserialized positions are checked against the layers.
+    layer_order: Vec<LayerSlot>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    object_index: OnceLock<HashMap<u32, (usize, usize)>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    gid_index: OnceLock<Vec<(u32, usize)>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    unknown_attributes: Vec<UnknownAttribute>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    unknown_elements: Vec<UnknownElement>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    source_path: Option<PathBuf>,
 }
 
 impl Map {
+    /// Reads a map and the external tilesets it references.
+    ///
+    /// An external tileset that cannot be read is kept as an unresolved
+    /// reference, as older versions kept every reference, with a warning
+    /// diagnostic. Turn on `ReaderOptions::require_tilesets` to fail with an
+    /// `Error::File` naming it instead. Paths that are not valid UTF-8 are
+    /// rejected.
     pub fn open<P: AsRef<Path>>(path: P) -> ::Result<Map> {
-        let file = File::open(path)?;
-        let mut reader = TmxReader::new(file);
-        reader.read_map()
+        Map::open_with(path, &ReaderOptions::default())
+    }
+
+    /// Reads a map and the external tilesets it references as requested by `options`.
+    pub fn open_with<P: AsRef<Path>>(path: P, options: &ReaderOptions) -> ::Result<Map> {
+        Map::load(resource::path_str(path.as_ref())?, &mut FileLoader, options).map(|(map, _)| map)
+    }
+
+    /// Reads a map and the external tilesets it references through `loader`.
+    pub fn open_with_loader<L: ResourceLoader + ?Sized>(path: &str, loader: &mut L) -> ::Result<Map> {
+        Map::load(path, loader, &ReaderOptions::default()).map(|(map, _)| map)
+    }
+
+    /// Reads a map and the external tilesets it references, along with the
+    /// problems found that did not prevent reading them.
+    pub fn open_with_diagnostics<P: AsRef<Path>>(path: P) -> ::Result<(Map, Vec<Diagnostic>)> {
+        Map::load(resource::path_str(path.as_ref())?, &mut FileLoader, &ReaderOptions::default())
+    }
+
+    /// Reads a map and the external tilesets it references through `loader`, as
+    /// requested by `options`, along with the problems found that did not
+    /// prevent reading them.
+    ///
+    /// The `open` functions are shorthands for this one.
+    pub fn load<L>(path: &str, loader: &mut L, options: &ReaderOptions) -> ::Result<(Map, Vec<Diagnostic>)>
+        where L: ResourceLoader + ?Sized
+    {
+        let (mut map, mut diagnostics) = loader.load_bytes(path)
+            .and_then(|bytes| Map::read_str(&reader::decode_text(&bytes)?, options))
+            .map_err(|err| resource::file_error(path, err))?;
+        reader::set_diagnostics_file(&mut diagnostics, path);
+        let source_path = loader.canonicalize(path);
+        for tileset in map.tilesets.iter_mut().filter(|tileset| !tileset.is_external()) {
+            tileset.set_source_path(source_path.clone());
+        }
+        // External tilesets are resolved against `base_dir`, as every other
+        // path found in the map.
+        let base_dir = source_path.parent().map(Path::to_path_buf);
+        map.source_path = Some(source_path);
+        map.load_external_tilesets(path, base_dir.as_deref(), loader, options, &mut diagnostics)?;
+        Ok((map, diagnostics))
+    }
+
+    /// Reads a map from a string as requested by `options`.
+    pub fn from_str_with(s: &str, options: &ReaderOptions) -> ::Result<Map> {
+        Map::read_str(s, options).map(|(map, _)| map)
+    }
+
+    fn read_str(s: &str, options: &ReaderOptions) -> ::Result<(Map, Vec<Diagnostic>)> {
+        let s = s.strip_prefix('\u{feff}').unwrap_or(s);
+        let mut tmx = TmxReader::with_options(s.as_bytes(), options.clone());
+        let map = tmx.read_map()?;
+        Ok((map, tmx.take_diagnostics()))
+    }
+
+    /// Reads a map from raw bytes, in UTF-8 or, if it starts with a byte order
+    /// mark, in UTF-16.
+    pub fn from_bytes(bytes: &[u8]) -> ::Result<Map> {
+        Map::from_bytes_with(bytes, &ReaderOptions::default())
+    }
+
+    /// Reads a map from raw bytes as requested by `options`.
+    pub fn from_bytes_with(bytes: &[u8], options: &ReaderOptions) -> ::Result<Map> {
+        Map::from_str_with(&reader::decode_text(bytes)?, options)
+    }
+
+    /// Reads a map from any reader, such as a file inside an archive, in
+    /// UTF-8 or, if it starts with a byte order mark, in UTF-16.
+    ///
+    /// The whole content is read first. As with `from_str`, the directory of
+    /// the map is unknown, so external tilesets are not loaded and the paths
+    /// found in the map are left relative: use `open_with_loader` with a
+    /// `ResourceLoader` reading from the same source to get the tilesets.
+    pub fn from_reader<R: Read>(reader: R) -> ::Result<Map> {
+        Map::from_reader_with(reader, &ReaderOptions::default())
+    }
+
+    /// Reads a map from any reader as requested by `options`.
+    pub fn from_reader_with<R: Read>(mut reader: R, options: &ReaderOptions) -> ::Result<Map> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Map::from_bytes_with(&bytes, options)
+    }
+
+    // Resolves the external tilesets of the map read from `path`.
+    fn load_external_tilesets<L>(&mut self, path: &str, base_dir: Option<&Path>, loader: &mut L,
+                                 options: &ReaderOptions, diagnostics: &mut Vec<Diagnostic>) -> ::Result<()>
+        where L: ResourceLoader + ?Sized
+    {
+        for tileset in &mut self.tilesets {
+            if !tileset.is_external() {
+                continue;
+            }
+            let tileset_path = resource::resolve_path(base_dir, tileset.source());
+            match resource::path_str(&tileset_path).and_then(|tileset_path| Tileset::load(tileset_path, loader, options)) {
+                Ok((external, tileset_diagnostics)) => {
+                    diagnostics.extend(tileset_diagnostics);
+                    tileset.resolve_reference(external);
+                }
+                Err(err) if !options.require_tilesets => {
+                    diagnostics.push(Diagnostic {
+                        severity: Severity::Warning,
+                        message: format!("External tileset `{}` left unresolved: {}", tileset.source(), err),
+                        file: Some(PathBuf::from(path)),
+                        line: 0,
+                        column: 0,
+                    });
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the file the map was read from, canonicalized when
+    /// read from the filesystem, or `None` for maps read from a string, bytes
+    /// or a reader.
+    pub fn source_path(&self) -> Option<&Path> {
+        self.source_path.as_deref()
+    }
+
+    /// Returns the directory of the file the map was read from, against which
+    /// the paths found in the map are resolved.
+    pub fn base_dir(&self) -> Option<&Path> {
+        self.source_path().and_then(Path::parent)
+    }
+
+    /// Returns the attributes skipped while reading the map with the
+    /// `UnknownPolicy::Collect` policy, including those of embedded tilesets.
+    pub fn unknown_attributes(&self) -> &[UnknownAttribute] {
+        &self.unknown_attributes
+    }
+
+    pub(crate) fn set_unknown_attributes(&mut self, unknown_attributes: Vec<UnknownAttribute>) {
+        self.unknown_attributes = unknown_attributes;
+    }
+
+    /// Returns the child elements skipped while reading the map with the
+    /// `UnknownPolicy::Collect` policy.
+    pub fn unknown_elements(&self) -> &[UnknownElement] {
+        &self.unknown_elements
+    }
+
+    pub(crate) fn set_unknown_elements(&mut self, unknown_elements: Vec<UnknownElement>) {
+        self.unknown_elements = unknown_elements;
+    }
+
+    /// Resolves the value of a `file` property against the directory of the map file.
+    ///
+    /// Returns `None` if the property is not a `file` property or has an empty value.
+    /// Maps read from a string have no directory, so the path is only normalized.
+    pub fn resolve_file_property(&self, property: &Property) -> Option<PathBuf> {
+        property.as_file().map(|file| resource::resolve_path(self.base_dir(), file))
+    }
+
+    /// Resolves the file of an image of the map, as that of an image layer,
+    /// against the directory of the map file.
+    ///
+    /// Returns `None` for embedded images. The images of tilesets are resolved
+    /// by `Tileset::resolve_image_path`, external tilesets having their own directory.
+    pub fn resolve_image_path(&self, image: &Image) -> Option<PathBuf> {
+        match image.source_kind() {
+            Some(ImageSource::File(source)) => Some(resource::resolve_path(self.base_dir(), source)),
+            _ => None,
+        }
+    }
+
+    /// Decodes an image of the map, as that of an image layer, its file being
+    /// resolved as `resolve_image_path` does; see `Image::load`. Maps read
+    /// from a string have no directory, so the file is looked for relative to
+    /// the current directory.
+    #[cfg(feature = "image")]
+    pub fn open_image(&self, image: &Image) -> ::Result<RgbaImage> {
+        image.load(self.base_dir().unwrap_or_else(|| Path::new("")))
+    }
+
+    /// Writes the map as a TMX document.
+    ///
+    /// Attributes holding their default value are omitted, as Tiled does, and
+    /// layer data is written with the encoding and compression it was read with.
+    pub fn to_writer<W: Write>(&self, writer: W) -> ::Result<()> {
+        self.to_writer_with_options(writer, &WriteOptions::default())
+    }
+
+    /// Writes the map as a TMX document, re-encoding layer data as requested by `options`.
+    pub fn to_writer_with_options<W: Write>(&self, writer: W, options: &WriteOptions) -> ::Result<()> {
+        TmxWriter::with_options(writer, options.clone()).write_map(self)
+    }
+
+    /// Returns the map as a TMX document.
+    pub fn to_xml_string(&self) -> ::Result<String> {
+        self.to_xml_string_with_options(&WriteOptions::default())
+    }
+
+    /// Returns the map as a TMX document, re-encoding layer data as requested by `options`.
+    pub fn to_xml_string_with_options(&self, options: &WriteOptions) -> ::Result<String> {
+        let mut buffer = Vec::new();
+        self.to_writer_with_options(&mut buffer, options)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
     }
 
     pub fn version(&self) -> &str {
         &self.version
     }
 
-    fn set_version<S: Into<String>>(&mut self, version: S) {
+    /// Returns the parsed format version, or `None` if it is absent or malformed.
+    pub fn format_version(&self) -> Option<FormatVersion> {
+        self.version.parse().ok()
+    }
+
+    pub fn set_version<S: Into<String>>(&mut self, version: S) {
         self.version = version.into();
     }
 
+    /// Returns the version of Tiled that saved the map.
+    pub fn tiled_version(&self) -> &str {
+        &self.tiled_version
+    }
+
+    pub fn set_tiled_version<S: Into<String>>(&mut self, tiled_version: S) {
+        self.tiled_version = tiled_version.into();
+    }
+
     pub fn orientation(&self) -> Orientation {
         self.orientation
     }
 
-    fn set_orientation(&mut self, orientation: Orientation) {
+    pub fn set_orientation(&mut self, orientation: Orientation) {
         self.orientation = orientation;
     }
 
@@ -83,7 +339,7 @@ impl Map {
         self.render_order
     }
 
-    fn set_render_order(&mut self, render_order: RenderOrder) {
+    pub fn set_render_order(&mut self, render_order: RenderOrder) {
         self.render_order = render_order;
     }
 
@@ -91,7 +347,7 @@ impl Map {
         self.width
     }
 
-    fn set_width(&mut self, width: u32) {
+    pub fn set_width(&mut self, width: u32) {
         self.width = width;
     }
 
@@ -99,7 +355,7 @@ impl Map {
         self.height
     }
 
-    fn set_height(&mut self, height: u32) {
+    pub fn set_height(&mut self, height: u32) {
         self.height = height;
     }
 
@@ -107,7 +363,7 @@ impl Map {
         self.tile_width
     }
 
-    fn set_tile_width(&mut self, tile_width: u32) {
+    pub fn set_tile_width(&mut self, tile_width: u32) {
         self.tile_width = tile_width;
     }
 
@@ -115,7 +371,7 @@ impl Map {
         self.tile_height
     }
 
-    fn set_tile_height(&mut self, tile_height: u32) {
+    pub fn set_tile_height(&mut self, tile_height: u32) {
         self.tile_height = tile_height;
     }
 
@@ -123,7 +379,7 @@ impl Map {
         self.hex_side_length
     }
 
-    fn set_hex_side_length(&mut self, hex_side_length: u32) {
+    pub fn set_hex_side_length(&mut self, hex_side_length: u32) {
         self.hex_side_length = Some(hex_side_length);
     }
 
@@ -131,7 +387,7 @@ impl Map {
         self.stagger_axis
     }
 
-    fn set_stagger_axis(&mut self, stagger_axis: Axis) {
+    pub fn set_stagger_axis(&mut self, stagger_axis: Axis) {
         self.stagger_axis = Some(stagger_axis);
     }
 
@@ -139,23 +395,84 @@ impl Map {
         self.stagger_index
     }
 
-    fn set_stagger_index(&mut self, stagger_index: Index) {
+    pub fn set_stagger_index(&mut self, stagger_index: Index) {
         self.stagger_index = Some(stagger_index);
     }
 
+    /// Returns how the rows or columns of a staggered or hexagonal map are
+    /// shifted, `None` for other orientations.
+    ///
+    /// Staggered maps missing the attributes get Tiled's defaults, the `y`
+    /// axis and the `odd` index. Hexagonal maps need both; see `validate`.
+    pub fn stagger_params(&self) -> Option<StaggerParams> {
+        match self.orientation {
+            Orientation::Staggered => Some(StaggerParams {
+                axis: self.stagger_axis.unwrap_or(Axis::Y),
+                index: self.stagger_index.unwrap_or(Index::Odd),
+            }),
+            Orientation::Hexagonal => self.hex_params().map(|params| params.stagger),
+            _ => None,
+        }
+    }
+
+    /// Returns the layout of a hexagonal map, `None` for other orientations
+    /// and if an attribute is missing; see `validate`.
+    pub fn hex_params(&self) -> Option<HexParams> {
+        self.checked_hex_params().ok().flatten()
+    }
+
+    // Returns the layout of a hexagonal map, failing if an attribute is missing.
+    fn checked_hex_params(&self) -> ::Result<Option<HexParams>> {
+        if self.orientation != Orientation::Hexagonal {
+            return Ok(None);
+        }
+        let side_length = self.hex_side_length.ok_or_else(|| reader::missing_attribute("map", "hexsidelength"))?;
+        let axis = self.stagger_axis.ok_or_else(|| reader::missing_attribute("map", "staggeraxis"))?;
+        let index = self.stagger_index.ok_or_else(|| reader::missing_attribute("map", "staggerindex"))?;
+        Ok(Some(HexParams { side_length, stagger: StaggerParams { axis, index } }))
+    }
+
+    // Returns the layout of a staggered or hexagonal map, staggered maps being
+    // laid out as hexagonal ones with a side length of 0. Fails as
+    // `validate` does.
+    fn stagger_layout(&self) -> ::Result<Option<HexParams>> {
+        match self.checked_hex_params()? {
+            Some(params) => Ok(Some(params)),
+            None => Ok(self.stagger_params().map(|stagger| HexParams { side_length: 0, stagger })),
+        }
+    }
+
+    /// Checks that the map has the attributes its orientation needs: the
+    /// `hexsidelength`, `staggeraxis` and `staggerindex` of hexagonal maps.
+    ///
+    /// Fails with `Error::MissingAttribute` naming the first one missing.
+    pub fn validate(&self) -> ::Result<()> {
+        self.checked_hex_params().map(|_| ())
+    }
+
     pub fn background_color(&self) -> Option<&Color> {
         self.bg_color.as_ref()
     }
 
-    fn set_background_color(&mut self, color: Color) {
+    pub fn set_background_color(&mut self, color: Color) {
         self.bg_color = Some(color);
     }
 
+    /// Tells whether the map is infinite, its tile layers holding their data in
+    /// chunks, see `Data::chunks`.
+    pub fn is_infinite(&self) -> bool {
+        self.infinite
+    }
+
+    pub fn set_infinite(&mut self, infinite: bool) {
+        self.infinite = infinite;
+    }
+
     pub fn next_object_id(&self) -> u32 {
         self.next_object_id
     }
 
-    fn set_next_object_id(&mut self, next_object_id: u32) {
+    pub fn set_next_object_id(&mut self, next_object_id: u32) {
         self.next_object_id = next_object_id;
     }
 
@@ -163,15 +480,41 @@ impl Map {
         self.properties.iter()
     }
 
-    fn set_properties(&mut self, properties: PropertyCollection) {
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.get(name)
+    }
+
+    pub fn set_properties(&mut self, properties: PropertyCollection) {
         self.properties = properties;
     }
 
+    pub fn properties_mut(&mut self) -> &mut PropertyCollection {
+        &mut self.properties
+    }
+
     pub fn tilesets(&self) -> Tilesets {
         Tilesets(self.tilesets.iter())
     }
 
+    pub fn tileset_count(&self) -> usize {
+        self.tilesets.len()
+    }
+
+    pub fn into_tilesets(self) -> vec::IntoIter<Tileset> {
+        self.tilesets.into_iter()
+    }
+
+    /// Iterates mutably over the tilesets.
+    ///
+    /// Their `firstgid` may be edited freely: the index used by
+    /// `tileset_for_gid` is rebuilt on the next lookup.
+    pub fn tilesets_mut(&mut self) -> slice::IterMut<Tileset> {
+        self.gid_index = OnceLock::new();
+        self.tilesets.iter_mut()
+    }
+
     fn add_tileset(&mut self, tileset: Tileset) {
+        self.gid_index = OnceLock::new();
         self.tilesets.push(tileset);
     }
 
@@ -179,37 +522,894 @@ impl Map {
         Layers(self.layers.iter())
     }
 
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn into_layers(self) -> vec::IntoIter<Layer> {
+        self.layers.into_iter()
+    }
+
+    pub fn layers_mut(&mut self) -> slice::IterMut<Layer> {
+        self.layers.iter_mut()
+    }
+
     fn add_layer(&mut self, layer: Layer) {
+        self.layer_order.push(LayerSlot::Tile(self.layers.len()));
         self.layers.push(layer);
     }
 
+    /// Iterates over all the layers, whatever their kind, in document order.
+    pub fn layer_stack(&self) -> LayerStack {
+        LayerStack {
+            map: self,
+            slots: self.layer_order.iter(),
+        }
+    }
+
     pub fn image_layers(&self) -> ImageLayers {
         ImageLayers(self.image_layers.iter())
     }
 
+    pub fn image_layer_count(&self) -> usize {
+        self.image_layers.len()
+    }
+
+    pub fn into_image_layers(self) -> vec::IntoIter<ImageLayer> {
+        self.image_layers.into_iter()
+    }
+
+    pub fn image_layers_mut(&mut self) -> slice::IterMut<ImageLayer> {
+        self.image_layers.iter_mut()
+    }
+
     fn add_image_layer(&mut self, image_layer: ImageLayer) {
+        self.layer_order.push(LayerSlot::Image(self.image_layers.len()));
         self.image_layers.push(image_layer);
     }
 
+    // Adds the layers of `other` on top of those of the map, in order.
+    fn append_layers(&mut self, other: Map) {
+        let mut layers = other.layers.into_iter();
+        let mut image_layers = other.image_layers.into_iter();
+        let mut object_groups = other.object_groups.into_iter();
+        for slot in other.layer_order {
+            match slot {
+                LayerSlot::Tile(_) => {
+                    if let Some(mut layer) = layers.next() {
+                        layer.default_size(self.width, self.height);
+                        self.add_layer(layer);
+                    }
+                }
+                LayerSlot::Image(_) => {
+                    if let Some(image_layer) = image_layers.next() {
+                        self.add_image_layer(image_layer);
+                    }
+                }
+                LayerSlot::Objects(_) => {
+                    if let Some(object_group) = object_groups.next() {
+                        self.add_object_group(object_group);
+                    }
+                }
+            }
+        }
+    }
+
     pub fn object_groups(&self) -> ObjectGroups {
         ObjectGroups(self.object_groups.iter())
     }
 
+    pub fn object_group_count(&self) -> usize {
+        self.object_groups.len()
+    }
+
+    pub fn into_object_groups(self) -> vec::IntoIter<ObjectGroup> {
+        self.object_groups.into_iter()
+    }
+
+    /// Iterates mutably over the object groups.
+    ///
+    /// Objects may be edited freely, including their ids: the index used by
+    /// `find_object` is rebuilt on the next lookup.
+    pub fn object_groups_mut(&mut self) -> slice::IterMut<ObjectGroup> {
+        self.object_index = OnceLock::new();
+        self.object_groups.iter_mut()
+    }
+
+    /// Converts a cell position to pixel coordinates, following Tiled's renderers.
+    ///
+    /// On orthogonal, staggered and hexagonal maps, the result is the top-left
+    /// corner of the cell's bounding box. On isometric maps, it is the top
+    /// corner of the cell's diamond, the origin being at the top center of the map.
+    ///
+    /// Fails as `validate` does on hexagonal maps missing an attribute.
+    /// Staggered maps default to Tiled's `y` axis and `odd` index.
+    pub fn tile_to_pixel(&self, x: i32, y: i32) -> ::Result<(f64, f64)> {
+        if let Some(params) = self.stagger_layout()? {
+            return Ok(self.hex_tile_to_pixel(x, y, params));
+        }
+        let (tile_width, tile_height) = (self.tile_width as f64, self.tile_height as f64);
+        let position = match self.orientation {
+            Orientation::Isometric => {
+                let origin_x = self.height as f64 * tile_width / 2.0;
+                ((x - y) as f64 * tile_width / 2.0 + origin_x, (x + y) as f64 * tile_height / 2.0)
+            }
+            _ => (x as f64 * tile_width, y as f64 * tile_height),
+        };
+        Ok(position)
+    }
+
+    /// Converts the position of an object to pixel coordinates, following
+    /// Tiled's renderers.
+    ///
+    /// Objects of isometric maps are placed in a space where a cell is
+    /// `tileheight` pixels wide and high, projected as `tile_to_pixel` does.
+    /// On other maps, the position of an object is already in pixels.
+    pub fn object_to_pixel(&self, x: f64, y: f64) -> (f64, f64) {
+        match self.orientation {
+            Orientation::Isometric if self.tile_height > 0 => {
+                let (tile_width, tile_height) = (self.tile_width as f64, self.tile_height as f64);
+                let (column, row) = (x / tile_height, y / tile_height);
+                let origin_x = self.height as f64 * tile_width / 2.0;
+                ((column - row) * tile_width / 2.0 + origin_x, (column + row) * tile_height / 2.0)
+            }
+            _ => (x, y),
+        }
+    }
+
+    // Staggered maps are laid out like hexagonal maps with a side length of 0.
+    fn hex_tile_to_pixel(&self, x: i32, y: i32, params: HexParams) -> (f64, f64) {
+        let tile_width = (self.tile_width & !1) as f64;
+        let tile_height = (self.tile_height & !1) as f64;
+        let side_length = params.side_length as f64;
+        let staggered = |n: i32| (n & 1 == 1) != (params.stagger.index == Index::Even);
+        match params.stagger.axis {
+            Axis::X => {
+                let column_width = (tile_width - side_length) / 2.0 + side_length;
+                let row_offset = if staggered(x) { tile_height / 2.0 } else { 0.0 };
+                (x as f64 * column_width, y as f64 * tile_height + row_offset)
+            }
+            Axis::Y => {
+                let row_height = (tile_height - side_length) / 2.0 + side_length;
+                let column_offset = if staggered(y) { tile_width / 2.0 } else { 0.0 };
+                (x as f64 * tile_width + column_offset, y as f64 * row_height)
+            }
+        }
+    }
+
+    /// Returns the tileset the global tile id belongs to, ignoring the flip flags.
+    ///
+    /// This is the tileset with the largest `firstgid` not greater than the id.
+    /// The tilesets sorted by `firstgid` are indexed on the first call.
+    pub fn tileset_for_gid(&self, gid: u32) -> Option<&Tileset> {
+        self.tileset_index_for_gid(gid).map(|position| &self.tilesets[position])
+    }
+
+    /// Iterates over the tiles having an animation in every tileset, with their
+    /// global ids. The frames of each animation are given by local id, to be
+    /// converted by `Tileset::gid_for` of the tileset of the tile, as found by
+    /// `tileset_for_gid`.
+    ///
+    /// External tilesets left unresolved have no tiles.
+    pub fn animated_gids(&self) -> impl Iterator<Item = (u32, &Animation)> {
+        self.tilesets.iter().flat_map(|tileset| {
+            tileset.animated_tiles().filter_map(move |(id, animation)| tileset.gid_for(id).map(|gid| (gid, animation)))
+        })
+    }
+
+    /// Returns the tileset of a tile object, along with the tile holding the
+    /// extra data of its tile, such as properties, collision shapes or an
+    /// animation, if the tileset has one for it.
+    ///
+    /// Returns `None` if the object has no gid or if its gid belongs to no tileset.
+    pub fn tile_for_object(&self, object: &Object) -> Option<(&Tileset, Option<&Tile>)> {
+        let gid = object.gid?;
+        let tileset = self.tileset_for_gid(gid)?;
+        Some((tileset, tileset.tile_by_id(Gid(gid).id() - tileset.first_gid())))
+    }
+
+    // Returns the position among the tilesets of the tileset the gid belongs to.
+    pub(crate) fn tileset_index_for_gid(&self, gid: u32) -> Option<usize> {
+        let id = Gid(gid).id();
+        if id == 0 {
+            return None;
+        }
+        let index = self.gid_index.get_or_init(|| {
+            let mut index: Vec<(u32, usize)> = self.tilesets.iter()
+                .enumerate()
+                .map(|(position, tileset)| (tileset.first_gid(), position))
+                .collect();
+            // Stable, so that the last of several tilesets with the same
+            // `firstgid` is found.
+            index.sort_by_key(|&(first_gid, _)| first_gid);
+            index
+        });
+        match index.partition_point(|&(first_gid, _)| first_gid <= id) {
+            0 => None,
+            end => Some(index[end - 1].1),
+        }
+    }
+
+    /// Returns where to find the tile with the given global id in its image,
+    /// in pixels and in texture coordinates, with its flip flags.
+    ///
+    /// The tile of an image collection covers its whole image. Returns `None`
+    /// if the gid belongs to no tileset, is out of range, or if the image does
+    /// not declare its size.
+    pub fn gid_uv(&self, gid: u32) -> Option<GidUv> {
+        let gid = Gid(gid);
+        let tileset = self.tileset_for_gid(gid.0)?;
+        let local_id = gid.id() - tileset.first_gid();
+        let (image, rect) = match tileset.image() {
+            Some(image) => (image, tileset.tile_rect(local_id)?),
+            None => {
+                let image = tileset.tile_image(local_id)?;
+                let (width, height) = image.size()?;
+                (image, Rect::new(0, 0, width, height))
+            }
+        };
+        let (width, height) = image.size().filter(|&(width, height)| width > 0 && height > 0)?;
+        let (width, height) = (width as f32, height as f32);
+        // The diagonal flip comes first, so the horizontal and vertical flips
+        // apply to the other texture axis when it is set.
+        let swap = gid.flipped_diagonally();
+        let (mirror_x, mirror_y) = (gid.flipped_horizontally(), gid.flipped_vertically());
+        Some(GidUv {
+            source: image.source(),
+            rect,
+            u0: rect.x as f32 / width,
+            v0: rect.y as f32 / height,
+            u1: (rect.x + rect.w) as f32 / width,
+            v1: (rect.y + rect.h) as f32 / height,
+            swap,
+            mirror_u: if swap { mirror_y } else { mirror_x },
+            mirror_v: if swap { mirror_x } else { mirror_y },
+        })
+    }
+
+    /// Iterates over the non-empty cells of a tile layer of this map.
+    ///
+    /// The layer data is decoded on the first call and cached in the layer.
+    /// Cells whose gid does not belong to any tileset are skipped. The cells
+    /// of chunked data are placed from the top-left cell of `Data::chunk_bounds`.
+    pub fn resolved_tiles<'a>(&'a self, layer: &'a Layer) -> ::Result<ResolvedTiles<'a>> {
+        let width = if layer.width() > 0 { layer.width() } else { self.width };
+        let (gids, width) = match layer.data() {
+            Some(data) => (data.decode()?, data.row_width(width)),
+            None => (&[][..], width),
+        };
+        Ok(ResolvedTiles {
+            map: self,
+            width,
+            cells: gids.iter().enumerate(),
+        })
+    }
+
+    /// Decodes the data of every tile layer, filling the caches read by `Data::decode`.
+    ///
+    /// Fails with `Error::LayerData` naming every layer whose data is invalid.
+    pub fn decode_all_layers(&self) -> ::Result<()> {
+        layer_data_result(self.layers.iter().filter_map(decode_layer).collect())
+    }
+
+    /// Same as `decode_all_layers`, decoding the layers in parallel.
+    #[cfg(feature = "rayon")]
+    pub fn decode_all_layers_parallel(&self) -> ::Result<()> {
+        use rayon::prelude::*;
+        layer_data_result(self.layers.par_iter().filter_map(decode_layer).collect())
+    }
+
+    /// Gives the objects of the map the properties of their type they lack,
+    /// with the default values found in `object_types`.
+    ///
+    /// The properties set on an object are kept. Only the map's object groups
+    /// are updated, not the collision groups of tiles.
+    pub fn apply_object_types(&mut self, object_types: &ObjectTypes) {
+        for object in self.object_groups.iter_mut().flat_map(|group| group.objects.iter_mut()) {
+            if let Some(object_type) = object_types.get(&object.object_type) {
+                object_type.fill_properties(&mut object.properties);
+            }
+        }
+    }
+
+    /// Replaces the tile id of every non-empty cell of the tile layers and of
+    /// every tile object by `f` of it, the flip flags being kept.
+    ///
+    /// The data of each layer is encoded again as it was read. Fails, leaving
+    /// the map untouched, if the data of a layer cannot be decoded or encoded.
+    pub fn remap_gids<F: Fn(u32) -> u32>(&mut self, f: F) -> ::Result<()> {
+        self.try_remap_gids(|id| Ok(f(id)))
+    }
+
+    // Same as `remap_gids`, failing as well, leaving the map untouched, when
+    // `f` fails for one of the tile ids.
+    fn try_remap_gids<F: Fn(u32) -> ::Result<u32>>(&mut self, f: F) -> ::Result<()> {
+        let remap = |gid: u32| {
+            let gid = Gid(gid);
+            if gid.is_empty() { Ok(gid.0) } else { f(gid.id()).map(|id| gid.with_id(id).0) }
+        };
+        // Everything is remapped and encoded before the map is changed.
+        let mut remapped = Vec::with_capacity(self.layers.len());
+        for layer in &self.layers {
+            remapped.push(match layer.data {
+                Some(ref data) => {
+                    let width = if layer.width > 0 { layer.width } else { self.width };
+                    Some(data.with_gids_mapped(width, remap)?)
+                }
+                None => None,
+            });
+        }
+        let mut object_gids = Vec::new();
+        for object in self.object_groups.iter().flat_map(|group| group.objects.iter()) {
+            object_gids.push(object.gid.map(remap).transpose()?);
+        }
+
+        for (layer, data) in self.layers.iter_mut().zip(remapped) {
+            layer.data = data;
+        }
+        let objects = self.object_groups.iter_mut().flat_map(|group| group.objects.iter_mut());
+        for (object, gid) in objects.zip(object_gids) {
+            object.gid = gid;
+        }
+        Ok(())
+    }
+
+    /// Gives the tilesets new `firstgid`s, given as `(old, new)` pairs, and
+    /// updates the tile ids of the map so that they still show the same tiles.
+    ///
+    /// The tile ids of the tilesets missing from `first_gids` are left as they
+    /// are. See `remap_gids` for the errors; it also fails with
+    /// `Error::InvalidTileset`, leaving the map untouched, if a tile id of the
+    /// map does not fit in a gid once moved.
+    pub fn change_first_gids(&mut self, first_gids: &[(u32, u32)]) -> ::Result<()> {
+        let mut old_first_gids: Vec<u32> = self.tilesets.iter().map(Tileset::first_gid).collect();
+        old_first_gids.sort_unstable();
+        let new_first_gid = |old: u32| first_gids.iter().find(|&&(from, _)| from == old).map(|&(_, to)| to);
+        self.try_remap_gids(|id| {
+            let tileset = old_first_gids.partition_point(|&first_gid| first_gid <= id);
+            let new_id = match tileset.checked_sub(1).map(|tileset| old_first_gids[tileset]) {
+                Some(old) => new_first_gid(old).map_or(Some(id), |new| (id - old).checked_add(new)),
+                None => Some(id),
+            };
+            new_id.filter(|&new_id| Gid(new_id).flags() == 0)
+                .ok_or_else(|| Error::InvalidTileset(format!("tile id {} out of range once moved", id)))
+        })?;
+        for tileset in self.tilesets_mut() {
+            if let Some(new) = new_first_gid(tileset.first_gid()) {
+                tileset.set_first_gid(new);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the tilesets showing no tile of the map, neither in the tile
+    /// layers nor in the tile objects, and returns them.
+    ///
+    /// The remaining tilesets are given consecutive `firstgid`s from 1 in the
+    /// order of their current ones, and the tile ids of the map are updated to
+    /// match, as by `change_first_gids`. Fails, leaving the map untouched, if
+    /// the data of a layer cannot be decoded.
+    pub fn strip_unused_tilesets(&mut self) -> ::Result<Vec<Tileset>> {
+        // For each used tileset, the number of local ids to keep room for.
+        let mut spans: Vec<Option<u32>> = vec![None; self.tilesets.len()];
+        {
+            let mut mark = |gid: u32| {
+                if let Some(index) = self.tileset_index_for_gid(gid) {
+                    let id = Gid(gid).id() - self.tilesets[index].first_gid();
+                    let span = spans[index].get_or_insert(0);
+                    *span = (*span).max(id + 1);
+                }
+            };
+            for layer in &self.layers {
+                if let Some(ref data) = layer.data {
+                    data.decode()?.iter().for_each(|&gid| mark(gid));
+                }
+            }
+            for object in self.object_groups.iter().flat_map(|group| group.objects.iter()) {
+                if let Some(gid) = object.gid {
+                    mark(gid);
+                }
+            }
+        }
+
+        let mut used: Vec<usize> = (0..self.tilesets.len()).filter(|&index| spans[index].is_some()).collect();
+        used.sort_by_key(|&index| self.tilesets[index].first_gid());
+        let mut first_gids = Vec::with_capacity(used.len());
+        let mut next_first_gid = 1u32;
+        for index in used {
+            let tileset = &self.tilesets[index];
+            first_gids.push((tileset.first_gid(), next_first_gid));
+            let span = tileset.id_count().max(spans[index].unwrap_or(0));
+            next_first_gid = next_first_gid.saturating_add(span);
+        }
+        self.change_first_gids(&first_gids)?;
+
+        let mut removed = Vec::new();
+        let mut spans = spans.into_iter();
+        for tileset in mem::take(&mut self.tilesets) {
+            if spans.next().flatten().is_some() {
+                self.tilesets.push(tileset);
+            } else {
+                removed.push(tileset);
+            }
+        }
+        self.gid_index = OnceLock::new();
+        Ok(removed)
+    }
+
+    /// Returns a copy of the map restricted to the cells of `rect`, which
+    /// become the cells of the new map from `(0, 0)`.
+    ///
+    /// Tile layers are cut to the size of the new map, at position `(0, 0)`;
+    /// the cells of `rect` outside of a layer are empty. Objects are moved
+    /// along and those whose bounding box misses the region are dropped, the
+    /// offsets of object groups being ignored. The offsets of image layers are
+    /// moved along too. Ids are kept, and so is `next_object_id`. On staggered
+    /// and hexagonal maps, the stagger index changes if needed so that the
+    /// cells keep their place.
+    ///
+    /// The data of the tile layers is decoded and encoded again as it was read.
+    /// The chunks of an infinite map are cut at the cells of the map they are
+    /// at, whatever the position of the layer, and the tile layers of the new
+    /// map are finite, as the map itself. Fails if the data cannot be decoded,
+    /// and as `validate` does on hexagonal maps missing an attribute.
+    pub fn crop(&self, rect: TileRect) -> ::Result<Map> {
+        let mut map = self.clone();
+        map.move_cells(rect.w, rect.h, -i64::from(rect.x), -i64::from(rect.y))?;
+        let (pitch_x, pitch_y) = map.cell_pitch(map.stagger_layout()?);
+        let region = Aabb::new(0.0, 0.0, f64::from(rect.w) * pitch_x, f64::from(rect.h) * pitch_y);
+        for group in &mut map.object_groups {
+            group.objects.retain(|object| object.bounding_box().intersects(&region));
+        }
+        Ok(map)
+    }
+
+    /// Resizes the map to `width` by `height` cells, keeping `anchor` in place.
+    ///
+    /// The content of the map moves as for `crop`, new cells being empty, but
+    /// no object is dropped.
+    pub fn resize(&mut self, width: u32, height: u32, anchor: Anchor) -> ::Result<()> {
+        let (dx, dy) = anchor.shift((self.width, self.height), (width, height));
+        self.move_cells(width, height, dx, dy)
+    }
+
+    // Gives the map `width` by `height` cells, the cell at `(x, y)` moving to
+    // `(x + dx, y + dy)`. The map is left untouched on error.
+    fn move_cells(&mut self, width: u32, height: u32, dx: i64, dy: i64) -> ::Result<()> {
+        let layout = self.stagger_layout()?;
+        let (pitch_x, pitch_y) = self.cell_pitch(layout);
+        let mut moved = Vec::with_capacity(self.layers.len());
+        for layer in &self.layers {
+            let data = match layer.data {
+                Some(ref data) => data,
+                None => {
+                    moved.push(None);
+                    continue;
+                }
+            };
+            let gids = data.decode()?;
+            // The cells of chunked data are at the cells of the map given by
+            // the position of the chunks.
+            let (left, top, layer_width, layer_height) = match data.chunk_bounds() {
+                Some((x, y, width, height)) => (x, y, width, height),
+                None => {
+                    let layer_width = if layer.width > 0 { layer.width } else { self.width };
+                    let layer_height = if layer.height > 0 { layer.height } else { self.height };
+                    (layer.x, layer.y, layer_width, layer_height)
+                }
+            };
+            let (layer_width, layer_height) = (i64::from(layer_width), i64::from(layer_height));
+            let mut cells = Vec::with_capacity(width as usize * height as usize);
+            for y in 0..i64::from(height) {
+                for x in 0..i64::from(width) {
+                    let source_x = x - dx - i64::from(left);
+                    let source_y = y - dy - i64::from(top);
+                    let inside = (0..layer_width).contains(&source_x) && (0..layer_height).contains(&source_y);
+                    let index = (source_y * layer_width + source_x) as usize;
+                    cells.push(if inside { gids.get(index).copied().unwrap_or(0) } else { 0 });
+                }
+            }
+            moved.push(Some(data.with_gids(cells, width)?));
+        }
+        for (layer, data) in self.layers.iter_mut().zip(moved) {
+            layer.x = 0;
+            layer.y = 0;
+            layer.width = width;
+            layer.height = height;
+            if data.is_some() {
+                layer.data = data;
+            }
+        }
+        self.infinite = false;
+
+        let (shift_x, shift_y) = (dx as f64 * pitch_x, dy as f64 * pitch_y);
+        for image_layer in &mut self.image_layers {
+            image_layer.offset_x = image_layer.offset_x.saturating_add(shift_x.round() as i32);
+            image_layer.offset_y = image_layer.offset_y.saturating_add(shift_y.round() as i32);
+        }
+        for object in self.object_groups.iter_mut().flat_map(|group| group.objects.iter_mut()) {
+            object.x += shift_x;
+            object.y += shift_y;
+        }
+        self.object_index = OnceLock::new();
+
+        if let Some(HexParams { stagger, .. }) = layout {
+            let shift = if stagger.axis == Axis::X { dx } else { dy };
+            if shift % 2 != 0 {
+                self.stagger_axis = Some(stagger.axis);
+                self.stagger_index = Some(match stagger.index {
+                    Index::Odd => Index::Even,
+                    Index::Even => Index::Odd,
+                });
+            }
+        }
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+
+    // Returns the distance in pixels between neighboring cells of a row and of
+    // a column, in the coordinates of objects, given the `stagger_layout`.
+    fn cell_pitch(&self, layout: Option<HexParams>) -> (f64, f64) {
+        if let Some(params) = layout {
+            let tile_width = (self.tile_width & !1) as f64;
+            let tile_height = (self.tile_height & !1) as f64;
+            let side_length = params.side_length as f64;
+            return match params.stagger.axis {
+                Axis::X => ((tile_width - side_length) / 2.0 + side_length, tile_height),
+                Axis::Y => (tile_width, (tile_height - side_length) / 2.0 + side_length),
+            };
+        }
+        let (tile_width, tile_height) = (self.tile_width as f64, self.tile_height as f64);
+        match self.orientation {
+            // Objects of isometric maps are placed in a space where a cell is
+            // `tileheight` pixels wide and high.
+            Orientation::Isometric => (tile_height, tile_height),
+            _ => (tile_width, tile_height),
+        }
+    }
+
+    /// Returns the object with the given id along with the group containing it.
+    ///
+    /// Only the map's object groups are searched, not the collision groups of tiles.
+    /// The id index is built on the first call.
+    pub fn find_object(&self, id: u32) -> Option<(&ObjectGroup, &Object)> {
+        let index = self.object_index.get_or_init(|| {
+            let mut index = HashMap::new();
+            for (group_index, group) in self.object_groups.iter().enumerate() {
+                for (object_index, object) in group.objects.iter().enumerate() {
+                    index.entry(object.id()).or_insert((group_index, object_index));
+                }
+            }
+            index
+        });
+        index.get(&id).map(|&(group_index, object_index)| {
+            let group = &self.object_groups[group_index];
+            (group, &group.objects[object_index])
+        })
+    }
+
+    /// Iterates over the objects of every object group whose type is exactly `object_type`.
+    pub fn objects_of_type<'a>(&'a self, object_type: &'a str) -> impl Iterator<Item = &'a Object> + 'a {
+        self.object_groups.iter().flat_map(move |group| group.objects_of_type(object_type))
+    }
+
+    /// Returns the first object group whose name is exactly `name`.
+    pub fn object_group_by_name(&self, name: &str) -> Option<&ObjectGroup> {
+        self.object_groups.iter().find(|group| group.name() == name)
+    }
+
     fn add_object_group(&mut self, object_group: ObjectGroup) {
+        self.layer_order.push(LayerSlot::Objects(self.object_groups.len()));
         self.object_groups.push(object_group);
     }
 }
 
+/// A layer of any kind, as yielded by `Map::layer_stack`.
+#[derive(Clone, Copy, Debug)]
+pub enum LayerKind<'a> {
+    Tile(&'a Layer),
+    Image(&'a ImageLayer),
+    Objects(&'a ObjectGroup),
+}
+
+impl<'a> LayerKind<'a> {
+    pub fn name(&self) -> &'a str {
+        match *self {
+            LayerKind::Tile(layer) => layer.name(),
+            LayerKind::Image(layer) => layer.name(),
+            LayerKind::Objects(group) => group.name(),
+        }
+    }
+}
+
+// Position of a layer in the vector of its kind, stored in document order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum LayerSlot {
+    Tile(usize),
+    Image(usize),
+    Objects(usize),
+}
+
+// A deserialized map whose layer order is not yet known to match its layers.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct UncheckedMap {
+    background_color: Option<Color>,
+    version: String,
+    tiled_version: String,
+    orientation: Orientation,
+    render_order: RenderOrder,
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    hex_side_length: Option<u32>,
+    stagger_axis: Option<Axis>,
+    stagger_index: Option<Index>,
+    infinite: bool,
+    next_object_id: u32,
+    properties: PropertyCollection,
+    tilesets: Vec<Tileset>,
+    layers: Vec<Layer>,
+    image_layers: Vec<ImageLayer>,
+    object_groups: Vec<ObjectGroup>,
+    layer_order: Vec<LayerSlot>,
+}
+
+// Checks that the layer order lists every layer once, each kind in the order
+// `add_layer` and the like give them, so that `LayerStack` cannot index out
+// of bounds.
+#[cfg(feature = "serde")]
+impl TryFrom<UncheckedMap> for Map {
+    type Error = String;
+
+    fn try_from(map: UncheckedMap) -> Result<Map, String> {
+        let (mut tile, mut image, mut objects) = (0, 0, 0);
+        for &slot in &map.layer_order {
+            let (next, index) = match slot {
+                LayerSlot::Tile(index) => (&mut tile, index),
+                LayerSlot::Image(index) => (&mut image, index),
+                LayerSlot::Objects(index) => (&mut objects, index),
+            };
+            if index != *next {
+                return Err("the layer order does not match the layers".to_string());
+            }
+            *next += 1;
+        }
+        if (tile, image, objects) != (map.layers.len(), map.image_layers.len(), map.object_groups.len()) {
+            return Err("the layer order does not match the layers".to_string());
+        }
+        Ok(Map {
+            bg_color: map.background_color,
+            version: map.version,
+            tiled_version: map.tiled_version,
+            orientation: map.orientation,
+            render_order: map.render_order,
+            width: map.width,
+            height: map.height,
+            tile_width: map.tile_width,
+            tile_height: map.tile_height,
+            hex_side_length: map.hex_side_length,
+            stagger_axis: map.stagger_axis,
+            stagger_index: map.stagger_index,
+            infinite: map.infinite,
+            next_object_id: map.next_object_id,
+            properties: map.properties,
+            tilesets: map.tilesets,
+            layers: map.layers,
+            image_layers: map.image_layers,
+            object_groups: map.object_groups,
+            layer_order: map.layer_order,
+            ..Map::default()
+        })
+    }
+}
+
+pub struct LayerStack<'a> {
+    map: &'a Map,
+    slots: ::std::slice::Iter<'a, LayerSlot>,
+}
+
+impl<'a> LayerStack<'a> {
+    fn get(&self, slot: LayerSlot) -> LayerKind<'a> {
+        match slot {
+            LayerSlot::Tile(index) => LayerKind::Tile(&self.map.layers[index]),
+            LayerSlot::Image(index) => LayerKind::Image(&self.map.image_layers[index]),
+            LayerSlot::Objects(index) => LayerKind::Objects(&self.map.object_groups[index]),
+        }
+    }
+}
+
+impl<'a> Iterator for LayerStack<'a> {
+    type Item = LayerKind<'a>;
+
+    fn next(&mut self) -> Option<LayerKind<'a>> {
+        self.slots.next().map(|&slot| self.get(slot))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.slots.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for LayerStack<'a> {
+    fn next_back(&mut self) -> Option<LayerKind<'a>> {
+        self.slots.next_back().map(|&slot| self.get(slot))
+    }
+}
+
+impl<'a> ExactSizeIterator for LayerStack<'a> {}
+
+impl<'a> ::std::iter::FusedIterator for LayerStack<'a> {}
+
+/// A non-empty cell of a tile layer, as yielded by `Map::resolved_tiles`.
+#[derive(Clone, Copy, Debug)]
+pub struct ResolvedTile<'a> {
+    pub x: u32,
+    pub y: u32,
+    pub gid: Gid,
+    pub tileset: &'a Tileset,
+    pub local_id: u32,
+    pub tile: Option<&'a Tile>,
+}
+
+/// The part of an image showing a tile, as returned by `Map::gid_uv`.
+///
+/// A point `(s, t)` of the drawn tile, from `(0, 0)` at its top-left corner to
+/// `(1, 1)` at its bottom-right corner, samples the texture as follows: swap
+/// `s` and `t` if `swap` is set, replace `s` by `1 - s` if `mirror_u` is set
+/// and `t` by `1 - t` if `mirror_v` is set, then interpolate between `u0`
+/// and `u1` with `s` and between `v0` and `v1` with `t`. The 120 degree
+/// rotation of hexagonal maps is not described.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GidUv<'a> {
+    pub source: &'a str,
+    pub rect: Rect,
+    pub u0: f32,
+    pub v0: f32,
+    pub u1: f32,
+    pub v1: f32,
+    pub swap: bool,
+    pub mirror_u: bool,
+    pub mirror_v: bool,
+}
+
+impl<'a> GidUv<'a> {
+    /// Returns the texture coordinates of the top-left, top-right, bottom-right
+    /// and bottom-left corners of the drawn tile, flips applied.
+    pub fn corners(&self) -> [(f32, f32); 4] {
+        let uv = |s: f32, t: f32| {
+            let (s, t) = if self.swap { (t, s) } else { (s, t) };
+            let s = if self.mirror_u { 1.0 - s } else { s };
+            let t = if self.mirror_v { 1.0 - t } else { t };
+            (self.u0 + s * (self.u1 - self.u0), self.v0 + t * (self.v1 - self.v0))
+        };
+        [uv(0.0, 0.0), uv(1.0, 0.0), uv(1.0, 1.0), uv(0.0, 1.0)]
+    }
+}
+
+pub struct ResolvedTiles<'a> {
+    map: &'a Map,
+    width: u32,
+    cells: iter::Enumerate<slice::Iter<'a, u32>>,
+}
+
+impl<'a> Iterator for ResolvedTiles<'a> {
+    type Item = ResolvedTile<'a>;
+
+    fn next(&mut self) -> Option<ResolvedTile<'a>> {
+        for (index, &gid) in &mut self.cells {
+            let gid = Gid(gid);
+            if let Some(tileset) = self.map.tileset_for_gid(gid.0) {
+                let local_id = gid.id() - tileset.first_gid();
+                let index = index as u32;
+                return Some(ResolvedTile {
+                    x: index % self.width.max(1),
+                    y: index / self.width.max(1),
+                    gid,
+                    tileset,
+                    local_id,
+                    tile: tileset.tile_by_id(local_id),
+                });
+            }
+        }
+        None
+    }
+}
+
+// Decodes the data of `layer`, returning its name along with the error if it fails.
+fn decode_layer(layer: &Layer) -> Option<(String, Error)> {
+    let err = layer.data()?.decode().err()?;
+    Some((layer.name().to_string(), err))
+}
+
+fn layer_data_result(errors: Vec<(String, Error)>) -> ::Result<()> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::LayerData(errors))
+    }
+}
+
+/// Maps are equal when their content is, compared field by field, floating
+/// point values exactly.
+///
+/// Where the map was read from and the unknown attributes and elements skipped
+/// while reading it are not compared.
+impl PartialEq for Map {
+    fn eq(&self, other: &Map) -> bool {
+        self.bg_color == other.bg_color
+            && self.version == other.version
+            && self.tiled_version == other.tiled_version
+            && self.orientation == other.orientation
+            && self.render_order == other.render_order
+            && self.width == other.width
+            && self.height == other.height
+            && self.tile_width == other.tile_width
+            && self.tile_height == other.tile_height
+            && self.hex_side_length == other.hex_side_length
+            && self.stagger_axis == other.stagger_axis
+            && self.stagger_index == other.stagger_index
+            && self.infinite == other.infinite
+            && self.next_object_id == other.next_object_id
+            && self.properties == other.properties
+            && self.tilesets == other.tilesets
+            && self.layers == other.layers
+            && self.image_layers == other.image_layers
+            && self.object_groups == other.object_groups
+            && self.layer_order == other.layer_order
+    }
+}
+
 impl FromStr for Map {
     type Err = Error;
 
     fn from_str(s: &str) -> ::Result<Map> {
-        let mut tmx = TmxReader::new(s.as_bytes());
-        tmx.read_map()
+        Map::from_str_with(s, &ReaderOptions::default())
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The version of the TMX format, as found in the `version` attribute of a map.
+///
+/// Versions are ordered numerically, so that `1.10` comes after `1.9`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FormatVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl FormatVersion {
+    pub fn new(major: u16, minor: u16) -> FormatVersion {
+        FormatVersion { major, minor }
+    }
+}
+
+impl FromStr for FormatVersion {
+    type Err = Error;
+
+    fn from_str(s: &str) -> ::Result<FormatVersion> {
+        let mut parts = s.split('.');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(major), Some(minor), None) => {
+                Ok(FormatVersion::new(reader::read_num(major)?, reader::read_num(minor)?))
+            }
+            _ => Err(Error::InvalidVersion(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for FormatVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_as_string!(FormatVersion);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Axis {
     X,
     Y,
@@ -227,7 +1427,25 @@ impl FromStr for Axis {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+impl Axis {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Axis::X => "x",
+            Axis::Y => "y",
+        }
+    }
+}
+
+impl fmt::Display for Axis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_as_string!(Axis);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Index {
     Even,
     Odd,
@@ -245,7 +1463,43 @@ impl FromStr for Index {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+impl Index {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Index::Even => "even",
+            Index::Odd => "odd",
+        }
+    }
+}
+
+impl fmt::Display for Index {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_as_string!(Index);
+
+/// How the rows or columns of a staggered or hexagonal map are shifted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StaggerParams {
+    /// The axis along which every other row or column is shifted.
+    pub axis: Axis,
+    /// Whether the even or the odd rows or columns are shifted.
+    pub index: Index,
+}
+
+/// The layout of a hexagonal map.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HexParams {
+    /// The length in pixels of the sides of a hexagon parallel to the stagger axis.
+    pub side_length: u32,
+    /// How the rows or columns of hexagons are shifted.
+    pub stagger: StaggerParams,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Orientation {
     Orthogonal,
     Isometric,
@@ -273,7 +1527,27 @@ impl FromStr for Orientation {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+impl Orientation {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Orientation::Orthogonal => "orthogonal",
+            Orientation::Isometric => "isometric",
+            Orientation::Staggered => "staggered",
+            Orientation::Hexagonal => "hexagonal",
+        }
+    }
+}
+
+impl fmt::Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_as_string!(Orientation);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum RenderOrder {
     RightDown,
     RightUp,
@@ -301,9 +1575,30 @@ impl FromStr for RenderOrder {
     }
 }
 
-#[derive(Debug)]
+impl RenderOrder {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            RenderOrder::RightDown => "right-down",
+            RenderOrder::RightUp => "right-up",
+            RenderOrder::LeftDown => "left-down",
+            RenderOrder::LeftUp => "left-up",
+        }
+    }
+}
+
+impl fmt::Display for RenderOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_as_string!(RenderOrder);
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Layer {
-    name: String,
+    name: Arc<str>,
     x: i32,
     y: i32,
     width: u32,
@@ -312,6 +1607,7 @@ pub struct Layer {
     visible: bool,
     offset_x: i32,
     offset_y: i32,
+    tint_color: Option<Color>,
     properties: PropertyCollection,
     data: Option<Data>,
 }
@@ -319,112 +1615,413 @@ pub struct Layer {
 impl Default for Layer {
     fn default() -> Layer {
         Layer {
-            name: String::default(),
+            name: Arc::default(),
             x: 0,
             y: 0,
             width: 0,
             height: 0,
-            opacity: 1.0,
+            opacity: Opacity::OPAQUE,
             visible: true,
             offset_x: 0,
             offset_y: 0,
+            tint_color: None,
             properties: PropertyCollection::new(),
             data: None,
         }
     }
-}
+}
+
+impl Layer {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_name<S: Into<String>>(&mut self, name: S) {
+        self.name = Arc::from(name.into());
+    }
+
+    pub fn x(&self) -> i32 {
+        self.x
+    }
+
+    pub fn set_x(&mut self, x: i32) {
+        self.x = x;
+    }
+
+    pub fn y(&self) -> i32 {
+        self.y
+    }
+
+    pub fn set_y(&mut self, y: i32) {
+        self.y = y;
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn set_width(&mut self, width: u32) {
+        self.width = width;
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn set_height(&mut self, height: u32) {
+        self.height = height;
+    }
+
+    // Gives the layer the size of the map when it was read without one, so
+    // that its cells can be edited.
+    pub(crate) fn default_size(&mut self, width: u32, height: u32) {
+        if self.width == 0 {
+            self.width = width;
+        }
+        if self.height == 0 {
+            self.height = height;
+        }
+    }
+
+    pub fn opacity(&self) -> Opacity {
+        self.opacity
+    }
+
+    pub fn set_opacity(&mut self, opacity: Opacity) {
+        self.opacity = opacity;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    pub fn offset_x(&self) -> i32 {
+        self.offset_x
+    }
+
+    pub fn set_offset_x(&mut self, offset_x: i32) {
+        self.offset_x = offset_x;
+    }
+
+    pub fn offset_y(&self) -> i32 {
+        self.offset_y
+    }
+
+    pub fn set_offset_y(&mut self, offset_y: i32) {
+        self.offset_y = offset_y;
+    }
+
+    /// Returns the color multiplied with the tiles of the layer, if any.
+    pub fn tint_color(&self) -> Option<&Color> {
+        self.tint_color.as_ref()
+    }
+
+    pub fn set_tint_color(&mut self, color: Color) {
+        self.tint_color = Some(color);
+    }
+
+    pub fn properties(&self) -> Properties {
+        self.properties.iter()
+    }
 
-impl Layer {
-    pub fn name(&self) -> &str {
-        &self.name
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.get(name)
     }
 
-    fn set_name<S: Into<String>>(&mut self, name: S) {
-        self.name = name.into();
+    pub fn set_properties(&mut self, properties: PropertyCollection) {
+        self.properties = properties;
     }
 
-    pub fn x(&self) -> i32 {
-        self.x
+    pub fn properties_mut(&mut self) -> &mut PropertyCollection {
+        &mut self.properties
     }
 
-    fn set_x(&mut self, x: i32) {
-        self.x = x;
+    /// Creates a layer `width` tiles wide and `height` tiles high holding the
+    /// given global tile ids, row by row.
+    ///
+    /// See `LayerBuilder` to set the other attributes of the layer.
+    pub fn from_gids<S: Into<String>>(name: S, width: u32, height: u32, gids: Vec<u32>) -> ::Result<Layer> {
+        LayerBuilder::new(name, width, height, gids).build()
     }
 
-    pub fn y(&self) -> i32 {
-        self.y
+    pub fn data(&self) -> Option<&Data> {
+        self.data.as_ref()
     }
 
-    fn set_y(&mut self, y: i32) {
-        self.y = y;
+    pub fn into_data(self) -> Option<Data> {
+        self.data
     }
 
-    pub fn width(&self) -> u32 {
-        self.width
+    pub fn set_data(&mut self, data: Data) {
+        self.data = Some(data);
     }
 
-    fn set_width(&mut self, width: u32) {
-        self.width = width;
+    /// Sets the global tile id of the cell at `(x, y)`, flip flags included.
+    ///
+    /// A layer without data gets empty cells first. The data is decoded and
+    /// encoded again as it was read when the layer is written. Fails with
+    /// `Error::OutOfBounds` if the cell is outside of the layer, and if the
+    /// data cannot be decoded.
+    ///
+    /// The cells of chunked data are counted from the top-left cell of
+    /// `Data::chunk_bounds`, as `Data::decode` returns them, whatever the size
+    /// of the layer. A cell outside of every chunk gets a new chunk, the size
+    /// of the first one, 16 by 16 tiles without any, and aligned on it.
+    pub fn set_tile(&mut self, x: u32, y: u32, gid: u32) -> ::Result<()> {
+        self.fill_rect(TileRect { x, y, w: 1, h: 1 }, gid)
+    }
+
+    /// Sets the global tile id of every cell of `rect`, as `set_tile` does.
+    ///
+    /// Fails, leaving the layer untouched, if `rect` is not entirely inside
+    /// of the layer, or for chunked data if a cell is past the largest
+    /// position of a chunk.
+    pub fn fill_rect(&mut self, rect: TileRect, gid: u32) -> ::Result<()> {
+        if let Some(data) = self.data.as_mut().filter(|data| data.is_chunked()) {
+            return data.fill_chunks(rect, gid);
+        }
+        let (width, height) = (self.width, self.height);
+        if rect.w == 0 || rect.h == 0 {
+            // An empty rect may start at the edge of the layer.
+            let (x, y) = (u64::from(rect.x), u64::from(rect.y));
+            if x > u64::from(width) || y > u64::from(height) {
+                return Err(Error::OutOfBounds { x, y, width, height });
+            }
+            return Ok(());
+        }
+        // The last cell of `rect`.
+        let x = u64::from(rect.x) + u64::from(rect.w) - 1;
+        let y = u64::from(rect.y) + u64::from(rect.h) - 1;
+        if x >= u64::from(width) || y >= u64::from(height) {
+            return Err(Error::OutOfBounds { x, y, width, height });
+        }
+        let cells = self.cells_mut()?;
+        for row in rect.y..rect.y + rect.h {
+            let start = row as usize * width as usize + rect.x as usize;
+            cells[start..start + rect.w as usize].fill(gid);
+        }
+        Ok(())
     }
 
-    pub fn height(&self) -> u32 {
-        self.height
+    /// Empties every cell of the layer, keeping the chunks of chunked data.
+    ///
+    /// Fails if the data cannot be decoded.
+    pub fn clear(&mut self) -> ::Result<()> {
+        if let Some(data) = self.data.as_mut().filter(|data| data.is_chunked()) {
+            data.clear_chunks();
+            return Ok(());
+        }
+        self.cells_mut()?.fill(0);
+        Ok(())
     }
 
-    fn set_height(&mut self, height: u32) {
-        self.height = height;
+    // Returns the tile ids of the layer for editing, as many as it has cells.
+    // Missing cells are empty.
+    fn cells_mut(&mut self) -> ::Result<&mut Vec<u32>> {
+        let len = self.width as usize * self.height as usize;
+        let cells = self.data.get_or_insert_with(Data::default).gids_mut()?;
+        cells.resize(len, 0);
+        Ok(cells)
+    }
+
+    /// Writes the global tile ids of the layer as CSV, one row of tiles per
+    /// line, whatever the encoding of its data.
+    ///
+    /// A layer without data is written as empty tiles. A chunked layer is
+    /// written as the cells of `Data::chunk_bounds`, those it uses.
+    pub fn export_csv<W: Write>(&self, map: &Map, mut w: W) -> ::Result<()> {
+        let (width, gids) = self.grid(map)?;
+        for row in gids.chunks(width) {
+            let row: Vec<String> = row.iter().map(u32::to_string).collect();
+            writeln!(w, "{}", row.join(","))?;
+        }
+        Ok(())
     }
 
-    pub fn opacity(&self) -> Opacity {
-        self.opacity
+    /// Writes the layer as a plain grayscale PGM image, one pixel per tile,
+    /// whose value is the tile id without the flip flags, 0 being an empty tile.
+    ///
+    /// The brightest value is the largest id of the layer, capped at 65535.
+    /// The cells written are those written by `export_csv`.
+    pub fn export_pgm<W: Write>(&self, map: &Map, mut w: W) -> ::Result<()> {
+        let (width, gids) = self.grid(map)?;
+        let values: Vec<u16> = gids.iter().map(|&gid| Gid(gid).id().min(u16::MAX as u32) as u16).collect();
+        let max_value = values.iter().copied().max().unwrap_or(0).max(1);
+        writeln!(w, "P2")?;
+        writeln!(w, "{} {}", width, values.len().div_ceil(width))?;
+        writeln!(w, "{}", max_value)?;
+        for row in values.chunks(width) {
+            // A short last row is padded with empty tiles.
+            let row: Vec<String> = row.iter()
+                .map(u16::to_string)
+                .chain(iter::repeat_n("0".to_string(), width - row.len()))
+                .collect();
+            writeln!(w, "{}", row.join(" "))?;
+        }
+        Ok(())
     }
 
-    fn set_opacity(&mut self, opacity: Opacity) {
-        self.opacity = opacity;
+    // Returns the width of the layer, that of the map if it has none, along
+    // with its global tile ids, all empty if it has no data. The width of
+    // chunked data is that of its chunk bounds.
+    fn grid(&self, map: &Map) -> ::Result<(usize, Cow<[u32]>)> {
+        let width = if self.width > 0 { self.width } else { map.width };
+        let height = if self.height > 0 { self.height } else { map.height };
+        let (width, gids) = match self.data {
+            Some(ref data) => (data.row_width(width), Cow::Borrowed(data.decode()?)),
+            None => (width, Cow::Owned(vec![0; width as usize * height as usize])),
+        };
+        Ok((width.max(1) as usize, gids))
     }
 
-    pub fn is_visible(&self) -> bool {
-        self.visible
+    /// Splits the global tile ids of the layer into buffers ready for a
+    /// tilemap shader, the tile indices being local to `tileset`.
+    ///
+    /// `tileset` is one of the tilesets of `map`, told apart by its `firstgid`.
+    /// The cells of other tilesets get `GpuLayerBuffer::EMPTY` as tile index.
+    pub fn to_gpu_buffer(&self, map: &Map, tileset: &Tileset) -> ::Result<GpuLayerBuffer> {
+        self.gpu_buffer(map, Some(tileset.first_gid()))
     }
 
-    fn set_visible(&mut self, visible: bool) {
-        self.visible = visible;
+    /// Same as `to_gpu_buffer`, each tile index being local to the tileset
+    /// given for the cell in `GpuLayerBuffer::tilesets`.
+    pub fn to_gpu_buffer_all_tilesets(&self, map: &Map) -> ::Result<GpuLayerBuffer> {
+        self.gpu_buffer(map, None)
     }
 
-    pub fn offset_x(&self) -> i32 {
-        self.offset_x
+    fn gpu_buffer(&self, map: &Map, first_gid: Option<u32>) -> ::Result<GpuLayerBuffer> {
+        let gids = match self.data {
+            Some(ref data) => data.decode()?,
+            None => &[],
+        };
+        let mut buffer = GpuLayerBuffer {
+            indices: Vec::with_capacity(gids.len()),
+            flags: Vec::with_capacity(gids.len()),
+            tilesets: Vec::with_capacity(gids.len()),
+        };
+        for &gid in gids {
+            let gid = Gid(gid);
+            let known = map.tileset_index_for_gid(gid.0).and_then(|position| {
+                let tileset = &map.tilesets[position];
+                let local_id = gid.id() - tileset.first_gid();
+                // Tilesets whose tiles are not known, as unresolved external
+                // ones, are given the benefit of the doubt.
+                let id_count = tileset.id_count();
+                if id_count > 0 && local_id >= id_count {
+                    return None;
+                }
+                Some((tileset.first_gid(), local_id, position as u32))
+            });
+            let (index, tileset) = match known {
+                Some((tileset_first_gid, local_id, position)) => {
+                    let index = match first_gid {
+                        Some(first_gid) if first_gid != tileset_first_gid => GpuLayerBuffer::EMPTY,
+                        _ => local_id,
+                    };
+                    (index, position)
+                }
+                None => (GpuLayerBuffer::EMPTY, GpuLayerBuffer::EMPTY),
+            };
+            buffer.indices.push(index);
+            buffer.flags.push((gid.flags() >> 28) as u8);
+            buffer.tilesets.push(tileset);
+        }
+        Ok(buffer)
     }
+}
 
-    fn set_offset_x(&mut self, offset_x: i32) {
-        self.offset_x = offset_x;
+/// The cells of a tile layer as parallel buffers, row by row, as returned by
+/// `Layer::to_gpu_buffer`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GpuLayerBuffer {
+    /// The tile index of each cell, local to its tileset, or `EMPTY`.
+    pub indices: Vec<u32>,
+    /// The flip flags of each cell, shifted down from the high bits of the
+    /// gid: 8 for horizontal, 4 for vertical, 2 for diagonal and 1 for the
+    /// hexagonal 120 degree rotation.
+    pub flags: Vec<u8>,
+    /// The position among the tilesets of the map of the tileset of each
+    /// cell, or `EMPTY` for the empty cells and the unknown gids.
+    pub tilesets: Vec<u32>,
+}
+
+impl GpuLayerBuffer {
+    /// Marks a cell with no tile, or no tile of the requested tileset.
+    pub const EMPTY: u32 = 0xFFFF_FFFF;
+}
+
+/// Builder of a tile layer holding given global tile ids.
+///
+/// The tile ids are not encoded until the layer is written, with the encoding
+/// chosen in the `WriteOptions`.
+#[derive(Debug)]
+pub struct LayerBuilder {
+    layer: Layer,
+    gids: Vec<u32>,
+}
+
+impl LayerBuilder {
+    /// Starts a layer `width` tiles wide and `height` tiles high. The tile ids
+    /// are given row by row.
+    pub fn new<S: Into<String>>(name: S, width: u32, height: u32, gids: Vec<u32>) -> LayerBuilder {
+        let mut layer = Layer::default();
+        layer.set_name(name);
+        layer.set_width(width);
+        layer.set_height(height);
+        LayerBuilder { layer, gids }
     }
 
-    pub fn offset_y(&self) -> i32 {
-        self.offset_y
+    pub fn with_position(mut self, x: i32, y: i32) -> LayerBuilder {
+        self.layer.set_x(x);
+        self.layer.set_y(y);
+        self
     }
 
-    fn set_offset_y(&mut self, offset_y: i32) {
-        self.offset_y = offset_y;
+    pub fn with_opacity(mut self, opacity: Opacity) -> LayerBuilder {
+        self.layer.set_opacity(opacity);
+        self
     }
 
-    pub fn properties(&self) -> Properties {
-        self.properties.iter()
+    pub fn with_visible(mut self, visible: bool) -> LayerBuilder {
+        self.layer.set_visible(visible);
+        self
     }
 
-    fn set_properties(&mut self, properties: PropertyCollection) {
-        self.properties = properties;
+    pub fn with_offset(mut self, offset_x: i32, offset_y: i32) -> LayerBuilder {
+        self.layer.set_offset_x(offset_x);
+        self.layer.set_offset_y(offset_y);
+        self
     }
 
-    pub fn data(&self) -> Option<&Data> {
-        self.data.as_ref()
+    pub fn with_tint_color(mut self, color: Color) -> LayerBuilder {
+        self.layer.set_tint_color(color);
+        self
     }
 
-    fn set_data(&mut self, data: Data) {
-        self.data = Some(data);
+    pub fn with_property(mut self, property: Property) -> LayerBuilder {
+        self.layer.properties.push(property);
+        self
+    }
+
+    /// Builds the layer, checking that there is one tile id per cell.
+    pub fn build(self) -> ::Result<Layer> {
+        let LayerBuilder { mut layer, gids } = self;
+        let expected = layer.width as u64 * layer.height as u64;
+        if gids.len() as u64 != expected {
+            return Err(Error::InvalidData(format!("expected {} tiles for a {}x{} layer, got {}",
+                                                  expected, layer.width, layer.height, gids.len())));
+        }
+        layer.set_data(Data::from_gids(gids));
+        Ok(layer)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ImageLayer {
     name: String,
     x: i32,
@@ -447,7 +2044,7 @@ impl Default for ImageLayer {
             y: 0,
             width: 0,
             height: 0,
-            opacity: 1.0,
+            opacity: Opacity::OPAQUE,
             visible: true,
             offset_x: 0,
             offset_y: 0,
@@ -462,7 +2059,7 @@ impl ImageLayer {
         &self.name
     }
 
-    fn set_name<S: Into<String>>(&mut self, name: S) {
+    pub fn set_name<S: Into<String>>(&mut self, name: S) {
         self.name = name.into();
     }
 
@@ -470,7 +2067,7 @@ impl ImageLayer {
         self.offset_x
     }
 
-    fn set_offset_x(&mut self, offset_x: i32) {
+    pub fn set_offset_x(&mut self, offset_x: i32) {
         self.offset_x = offset_x;
     }
 
@@ -478,7 +2075,7 @@ impl ImageLayer {
         self.offset_y
     }
 
-    fn set_offset_y(&mut self, offset_y: i32) {
+    pub fn set_offset_y(&mut self, offset_y: i32) {
         self.offset_y = offset_y;
     }
 
@@ -486,7 +2083,7 @@ impl ImageLayer {
         self.x
     }
 
-    fn set_x(&mut self, x: i32) {
+    pub fn set_x(&mut self, x: i32) {
         self.x = x;
     }
 
@@ -494,7 +2091,7 @@ impl ImageLayer {
         self.y
     }
 
-    fn set_y(&mut self, y: i32) {
+    pub fn set_y(&mut self, y: i32) {
         self.y = y;
     }
 
@@ -502,7 +2099,7 @@ impl ImageLayer {
         self.width
     }
 
-    fn set_width(&mut self, width: u32) {
+    pub fn set_width(&mut self, width: u32) {
         self.width = width;
     }
 
@@ -510,7 +2107,7 @@ impl ImageLayer {
         self.height
     }
 
-    fn set_height(&mut self, height: u32) {
+    pub fn set_height(&mut self, height: u32) {
         self.height = height;
     }
 
@@ -518,7 +2115,7 @@ impl ImageLayer {
         self.opacity
     }
 
-    fn set_opacity(&mut self, opacity: Opacity) {
+    pub fn set_opacity(&mut self, opacity: Opacity) {
         self.opacity = opacity;
     }
 
@@ -526,7 +2123,7 @@ impl ImageLayer {
         self.visible
     }
 
-    fn set_visible(&mut self, visible: bool) {
+    pub fn set_visible(&mut self, visible: bool) {
         self.visible = visible;
     }
 
@@ -534,22 +2131,115 @@ impl ImageLayer {
         self.properties.iter()
     }
 
-    fn set_properties(&mut self, properties: PropertyCollection) {
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.get(name)
+    }
+
+    pub fn set_properties(&mut self, properties: PropertyCollection) {
         self.properties = properties;
     }
 
+    pub fn properties_mut(&mut self) -> &mut PropertyCollection {
+        &mut self.properties
+    }
+
     pub fn image(&self) -> Option<&Image> {
         self.image.as_ref()
     }
 
-    fn set_image(&mut self, image: Image) {
+    pub fn into_image(self) -> Option<Image> {
+        self.image
+    }
+
+    pub fn set_image(&mut self, image: Image) {
         self.image = Some(image);
     }
 }
 
-pub type Opacity = f64;
+/// The opacity of a layer, from 0 (transparent) to 1 (opaque).
+///
+/// When reading, values outside that range are clamped into it, while NaN
+/// and infinite values are rejected with `Error::BadOpacity`.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "f64", into = "f64"))]
+pub struct Opacity(f64);
+
+impl Opacity {
+    pub const OPAQUE: Opacity = Opacity(1.0);
+    pub const TRANSPARENT: Opacity = Opacity(0.0);
+
+    /// Fails with `Error::BadOpacity` if `value` is not between 0 and 1.
+    pub fn new(value: f64) -> ::Result<Opacity> {
+        if (0.0..=1.0).contains(&value) {
+            Ok(Opacity(value))
+        } else {
+            Err(Error::BadOpacity(value.to_string()))
+        }
+    }
 
-#[derive(Debug)]
+    /// Brings `value` between 0 and 1, NaN becoming opaque.
+    pub fn new_clamped(value: f64) -> Opacity {
+        if value.is_nan() {
+            Opacity::OPAQUE
+        } else {
+            Opacity(value.clamp(0.0, 1.0))
+        }
+    }
+
+    pub fn value(&self) -> f64 {
+        self.0
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        self.0 == 1.0
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.0 == 0.0
+    }
+}
+
+impl Default for Opacity {
+    fn default() -> Opacity {
+        Opacity::OPAQUE
+    }
+}
+
+impl From<Opacity> for f64 {
+    fn from(opacity: Opacity) -> f64 {
+        opacity.0
+    }
+}
+
+impl TryFrom<f64> for Opacity {
+    type Error = Error;
+
+    fn try_from(value: f64) -> ::Result<Opacity> {
+        Opacity::new(value)
+    }
+}
+
+impl PartialEq<f64> for Opacity {
+    fn eq(&self, other: &f64) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<Opacity> for f64 {
+    fn eq(&self, other: &Opacity) -> bool {
+        *self == other.0
+    }
+}
+
+impl fmt::Display for Opacity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ObjectGroup {
     name: String,
     color: Option<Color>,
@@ -571,7 +2261,7 @@ impl ObjectGroup {
         &self.name
     }
 
-    fn set_name<S: Into<String>>(&mut self, name: S) {
+    pub fn set_name<S: Into<String>>(&mut self, name: S) {
         self.name = name.into();
     }
 
@@ -579,7 +2269,7 @@ impl ObjectGroup {
         self.color.as_ref()
     }
 
-    fn set_color(&mut self, color: Color) {
+    pub fn set_color(&mut self, color: Color) {
         self.color = Some(color);
     }
 
@@ -587,7 +2277,7 @@ impl ObjectGroup {
         self.x
     }
 
-    fn set_x(&mut self, x: i32) {
+    pub fn set_x(&mut self, x: i32) {
         self.x = x;
     }
 
@@ -595,7 +2285,7 @@ impl ObjectGroup {
         self.y
     }
 
-    fn set_y(&mut self, y: i32) {
+    pub fn set_y(&mut self, y: i32) {
         self.y = y;
     }
 
@@ -603,7 +2293,7 @@ impl ObjectGroup {
         self.width
     }
 
-    fn set_width(&mut self, width: u32) {
+    pub fn set_width(&mut self, width: u32) {
         self.width = width;
     }
 
@@ -611,7 +2301,7 @@ impl ObjectGroup {
         self.height
     }
 
-    fn set_height(&mut self, height: u32) {
+    pub fn set_height(&mut self, height: u32) {
         self.height = height;
     }
 
@@ -619,7 +2309,7 @@ impl ObjectGroup {
         self.opacity
     }
 
-    fn set_opacity(&mut self, opacity: Opacity) {
+    pub fn set_opacity(&mut self, opacity: Opacity) {
         self.opacity = opacity;
     }
 
@@ -627,7 +2317,7 @@ impl ObjectGroup {
         self.visible
     }
 
-    fn set_visible(&mut self, visible: bool) {
+    pub fn set_visible(&mut self, visible: bool) {
         self.visible = visible;
     }
 
@@ -635,7 +2325,7 @@ impl ObjectGroup {
         self.offset_x
     }
 
-    fn set_offset_x(&mut self, offset_x: i32) {
+    pub fn set_offset_x(&mut self, offset_x: i32) {
         self.offset_x = offset_x;
     }
 
@@ -643,7 +2333,7 @@ impl ObjectGroup {
         self.offset_y
     }
 
-    fn set_offset_y(&mut self, offset_y: i32) {
+    pub fn set_offset_y(&mut self, offset_y: i32) {
         self.offset_y = offset_y;
     }
 
@@ -651,7 +2341,7 @@ impl ObjectGroup {
         self.draw_order
     }
 
-    fn set_draw_order(&mut self, draw_order: DrawOrder) {
+    pub fn set_draw_order(&mut self, draw_order: DrawOrder) {
         self.draw_order = draw_order;
     }
 
@@ -659,14 +2349,57 @@ impl ObjectGroup {
         self.properties.iter()
     }
 
-    fn set_properties(&mut self, properties: PropertyCollection) {
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.get(name)
+    }
+
+    pub fn set_properties(&mut self, properties: PropertyCollection) {
         self.properties = properties;
     }
 
+    pub fn properties_mut(&mut self) -> &mut PropertyCollection {
+        &mut self.properties
+    }
+
     pub fn objects(&self) -> Objects {
         Objects(self.objects.iter())
     }
 
+    pub fn objects_slice(&self) -> &[Object] {
+        &self.objects
+    }
+
+    pub fn into_objects(self) -> vec::IntoIter<Object> {
+        self.objects.into_iter()
+    }
+
+    pub fn objects_mut(&mut self) -> slice::IterMut<Object> {
+        self.objects.iter_mut()
+    }
+
+    /// Returns the objects in the order they should be drawn.
+    ///
+    /// With the `topdown` draw order, objects are sorted by their `y` coordinate,
+    /// objects at the same height keeping their document order. With the
+    /// `index` draw order, the document order is kept.
+    pub fn objects_sorted(&self) -> Vec<&Object> {
+        let mut objects: Vec<_> = self.objects.iter().collect();
+        if self.draw_order == DrawOrder::TopDown {
+            objects.sort_by(|a, b| a.y().total_cmp(&b.y()));
+        }
+        objects
+    }
+
+    /// Iterates over the objects whose type is exactly `object_type`.
+    pub fn objects_of_type<'a>(&'a self, object_type: &'a str) -> impl Iterator<Item = &'a Object> + 'a {
+        self.objects.iter().filter(move |object| object.object_type() == object_type)
+    }
+
+    /// Returns the first object whose name is exactly `name`.
+    pub fn object_by_name(&self, name: &str) -> Option<&Object> {
+        self.objects.iter().find(|object| object.name() == name)
+    }
+
     fn add_object(&mut self, object: Object) {
         self.objects.push(object);
     }
@@ -681,7 +2414,7 @@ impl Default for ObjectGroup {
             y: 0,
             width: 0,
             height: 0,
-            opacity: 1.0,
+            opacity: Opacity::OPAQUE,
             visible: true,
             offset_x: 0,
             offset_y: 0,
@@ -692,11 +2425,13 @@ impl Default for ObjectGroup {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Object {
     id: u32,
-    name: String,
-    object_type: String,
+    name: Arc<str>,
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    object_type: Arc<str>,
     x: f64,
     y: f64,
     width: f64,
@@ -712,8 +2447,8 @@ impl Default for Object {
     fn default() -> Object {
         Object {
             id: 0,
-            name: String::new(),
-            object_type: String::new(),
+            name: Arc::default(),
+            object_type: Arc::default(),
             x: 0.0,
             y: 0.0,
             width: 0.0,
@@ -732,7 +2467,7 @@ impl Object {
         self.id
     }
 
-    fn set_id(&mut self, id: u32) {
+    pub fn set_id(&mut self, id: u32) {
         self.id = id;
     }
 
@@ -740,23 +2475,23 @@ impl Object {
         &self.name
     }
 
-    fn set_name<S: Into<String>>(&mut self, name: S) {
-        self.name = name.into();
+    pub fn set_name<S: Into<String>>(&mut self, name: S) {
+        self.name = Arc::from(name.into());
     }
 
     pub fn object_type(&self) -> &str {
         &self.object_type
     }
 
-    fn set_object_type<S: Into<String>>(&mut self, object_type: S) {
-        self.object_type = object_type.into();
+    pub fn set_object_type<S: Into<String>>(&mut self, object_type: S) {
+        self.object_type = Arc::from(object_type.into());
     }
 
     pub fn x(&self) -> f64 {
         self.x
     }
 
-    fn set_x(&mut self, x: f64) {
+    pub fn set_x(&mut self, x: f64) {
         self.x = x;
     }
 
@@ -764,7 +2499,7 @@ impl Object {
         self.y
     }
 
-    fn set_y(&mut self, y: f64) {
+    pub fn set_y(&mut self, y: f64) {
         self.y = y;
     }
 
@@ -772,7 +2507,7 @@ impl Object {
         self.width
     }
 
-    fn set_width(&mut self, width: f64) {
+    pub fn set_width(&mut self, width: f64) {
         self.width = width;
     }
 
@@ -780,7 +2515,7 @@ impl Object {
         self.height
     }
 
-    fn set_height(&mut self, height: f64) {
+    pub fn set_height(&mut self, height: f64) {
         self.height = height;
     }
 
@@ -788,7 +2523,7 @@ impl Object {
         self.rotation
     }
 
-    fn set_rotation(&mut self, rotation: f32) {
+    pub fn set_rotation(&mut self, rotation: f32) {
         self.rotation = rotation;
     }
 
@@ -796,7 +2531,7 @@ impl Object {
         self.gid
     }
 
-    fn set_gid(&mut self, gid: u32) {
+    pub fn set_gid(&mut self, gid: u32) {
         self.gid = Some(gid);
     }
 
@@ -804,7 +2539,7 @@ impl Object {
         self.visible
     }
 
-    fn set_visible(&mut self, visible: bool) {
+    pub fn set_visible(&mut self, visible: bool) {
         self.visible = visible;
     }
 
@@ -812,20 +2547,163 @@ impl Object {
         self.properties.iter()
     }
 
-    fn set_properties(&mut self, properties: PropertyCollection) {
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.get(name)
+    }
+
+    pub fn set_properties(&mut self, properties: PropertyCollection) {
         self.properties = properties;
     }
 
+    pub fn properties_mut(&mut self) -> &mut PropertyCollection {
+        &mut self.properties
+    }
+
+    /// Returns the properties of the object along with those of the tile of
+    /// a tile object it does not set, as Tiled shows them.
+    ///
+    /// The object's own properties come first and win over the tile's
+    /// properties of the same name.
+    pub fn resolved_properties(&self, map: &Map) -> PropertyCollection {
+        let mut properties = self.properties.clone();
+        if let Some((_, Some(tile))) = map.tile_for_object(self) {
+            for property in tile.properties() {
+                if !properties.contains(property.name()) {
+                    properties.push(property.clone());
+                }
+            }
+        }
+        properties
+    }
+
     pub fn shape(&self) -> Option<&Shape> {
         self.shape.as_ref()
     }
 
-    fn set_shape<S: Into<Shape>>(&mut self, shape: S) {
+    /// Returns the axis-aligned box enclosing the object once rotated.
+    ///
+    /// The rotation is applied around the object's position, which is the
+    /// top-left corner of shapes and the bottom-left corner of tile objects.
+    /// Point objects give an empty box at their position.
+    pub fn bounding_box(&self) -> Aabb {
+        let (width, height) = (self.width, self.height);
+        let points = match self.shape {
+            Some(Shape::Ellipse) => return self.ellipse_bounding_box(),
+            Some(Shape::Polygon(ref polygon)) => polygon.points().map(|p| (p.x, p.y)).collect(),
+            Some(Shape::Polyline(ref polyline)) => polyline.points().map(|p| (p.x, p.y)).collect(),
+            Some(Shape::Point) => vec![(0.0, 0.0)],
+            None if self.gid.is_some() => vec![(0.0, -height), (width, -height), (width, 0.0), (0.0, 0.0)],
+            None => vec![(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)],
+        };
+        let points = points.into_iter().map(|(x, y)| self.to_parent_coordinates(x, y));
+        Aabb::from_points(points).unwrap_or_else(|| Aabb::new(self.x, self.y, self.x, self.y))
+    }
+
+    fn ellipse_bounding_box(&self) -> Aabb {
+        let (rx, ry) = (self.width / 2.0, self.height / 2.0);
+        let (cx, cy) = self.to_parent_coordinates(rx, ry);
+        let (half_width, half_height) = if self.rotation == 0.0 {
+            (rx, ry)
+        } else {
+            let (sin, cos) = (self.rotation as f64).to_radians().sin_cos();
+            ((rx * rx * cos * cos + ry * ry * sin * sin).sqrt(),
+             (rx * rx * sin * sin + ry * ry * cos * cos).sqrt())
+        };
+        Aabb::new(cx - half_width, cy - half_height, cx + half_width, cy + half_height)
+    }
+
+    /// Returns the shape of the object with its position and rotation applied.
+    ///
+    /// Map objects get map coordinates in pixels; objects of a tile's collision
+    /// group get coordinates relative to the tile. Objects without a shape
+    /// element are rectangles, anchored at their bottom-left corner for tile objects.
+    pub fn shape_in_map_space(&self) -> Option<WorldShape> {
+        let rotation = self.rotation as f64;
+        let shape = match self.shape {
+            Some(Shape::Ellipse) => {
+                let (center_x, center_y) = self.to_parent_coordinates(self.width / 2.0, self.height / 2.0);
+                WorldShape::Ellipse {
+                    center_x,
+                    center_y,
+                    radius_x: self.width / 2.0,
+                    radius_y: self.height / 2.0,
+                    rotation,
+                }
+            }
+            Some(Shape::Polygon(ref polygon)) => WorldShape::Polygon(self.transform_points(polygon.points())),
+            Some(Shape::Polyline(ref polyline)) => WorldShape::Polyline(self.transform_points(polyline.points())),
+            Some(Shape::Point) => WorldShape::Point(self.x, self.y),
+            None => {
+                let top = if self.gid.is_some() { -self.height } else { 0.0 };
+                let (x, y) = self.to_parent_coordinates(0.0, top);
+                WorldShape::Rect { x, y, width: self.width, height: self.height, rotation }
+            }
+        };
+        Some(shape)
+    }
+
+    /// Returns the rectangle in pixels where the tile of a tile object of `map`
+    /// is drawn, before its rotation.
+    ///
+    /// The object's position, converted by `Map::object_to_pixel`, is the
+    /// point given by the `objectalignment` of the tileset, its bottom-left
+    /// corner by default, or its bottom center on isometric maps. The tile is
+    /// scaled to the object's size, if it has one, and so is the tile offset
+    /// of the tileset. The rotation, given by `rotation`, turns the rectangle
+    /// clockwise around the object's position.
+    ///
+    /// Returns `None` if the object has no gid, if the gid belongs to no
+    /// tileset of `map`, or if the size of the tile is unknown, as in external
+    /// tilesets left unresolved.
+    pub fn tile_draw_rect(&self, map: &Map) -> Option<Aabb> {
+        let (tileset, tile) = map.tile_for_object(self)?;
+        let tile_size = match tile.and_then(Tile::image) {
+            Some(image) => image.size()?,
+            None => (tileset.tile_width(), tileset.tile_height()),
+        };
+        if tile_size.0 == 0 || tile_size.1 == 0 {
+            return None;
+        }
+        let (tile_width, tile_height) = (tile_size.0 as f64, tile_size.1 as f64);
+        let width = if self.width > 0.0 { self.width } else { tile_width };
+        let height = if self.height > 0.0 { self.height } else { tile_height };
+
+        let alignment = tileset.object_alignment().unwrap_or(match map.orientation() {
+            Orientation::Isometric => Anchor::Bottom,
+            _ => Anchor::BottomLeft,
+        });
+        let (align_x, align_y) = alignment.fractions();
+        let (offset_x, offset_y) = tileset.tile_offset()
+            .map_or((0.0, 0.0), |offset| (offset.x() as f64 * width / tile_width, offset.y() as f64 * height / tile_height));
+        let (x, y) = map.object_to_pixel(self.x, self.y);
+        let (min_x, min_y) = (x - align_x * width + offset_x, y - align_y * height + offset_y);
+        Some(Aabb::new(min_x, min_y, min_x + width, min_y + height))
+    }
+
+    fn transform_points(&self, points: Points) -> Vec<(f64, f64)> {
+        points.map(|point| self.to_parent_coordinates(point.x, point.y)).collect()
+    }
+
+    // Rotates a point given relative to the object's position and moves it to
+    // the space of the object group.
+    fn to_parent_coordinates(&self, x: f64, y: f64) -> (f64, f64) {
+        if self.rotation == 0.0 {
+            return (self.x + x, self.y + y);
+        }
+        let (sin, cos) = (self.rotation as f64).to_radians().sin_cos();
+        (self.x + x * cos - y * sin, self.y + x * sin + y * cos)
+    }
+
+    pub fn into_shape(self) -> Option<Shape> {
+        self.shape
+    }
+
+    pub fn set_shape<S: Into<Shape>>(&mut self, shape: S) {
         self.shape = Some(shape.into());
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DrawOrder {
     TopDown,
     Index,
@@ -843,12 +2721,33 @@ impl FromStr for DrawOrder {
     }
 }
 
+impl DrawOrder {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            DrawOrder::TopDown => "topdown",
+            DrawOrder::Index => "index",
+        }
+    }
+}
+
+impl fmt::Display for DrawOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_as_string!(DrawOrder);
+
 impl<R: Read> ElementReader<Map> for TmxReader<R> {
-    fn read_attributes(&mut self, map: &mut Map, name: &str, value: &str) -> ::Result<()> {
+    fn read_attributes(&mut self, map: &mut Map, tag: &str, name: &str, value: &str) -> ::Result<()> {
         match name {
             "version" => {
                 map.set_version(value);
             }
+            "tiledversion" => {
+                map.set_tiled_version(value);
+            }
             "orientation" => {
                 let orientation = Orientation::from_str(value)?;
                 map.set_orientation(orientation);
@@ -858,11 +2757,11 @@ impl<R: Read> ElementReader<Map> for TmxReader<R> {
                 map.set_render_order(render_order);
             }
             "width" => {
-                let width = reader::read_num(value)?;
+                let width = self.read_dimension(value)?;
                 map.set_width(width);
             }
             "height" => {
-                let height = reader::read_num(value)?;
+                let height = self.read_dimension(value)?;
                 map.set_height(height);
             }
             "tilewidth" => {
@@ -889,50 +2788,149 @@ impl<R: Read> ElementReader<Map> for TmxReader<R> {
                 let color = Color::from_str(value)?;
                 map.set_background_color(color);
             }
+            "infinite" => {
+                map.set_infinite(reader::read_bool(value)?);
+            }
             "nextobjectid" => {
                 let next_object_id = reader::read_num(value)?;
                 map.set_next_object_id(next_object_id);
             }
             _ => {
-                return Err(Error::UnknownAttribute(name.to_string()));
+                return Err(reader::unknown_attribute(tag, name));
             }
         };
         Ok(())
     }
 
-    fn read_children(&mut self, map: &mut Map, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
+    fn read_children(&mut self, map: &mut Map, tag: &str, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
         match name {
             "properties" => {
                 let properties = self.on_properties(attributes)?;
                 map.set_properties(properties);
             }
             "tileset" => {
-                let ts = self.on_tileset(attributes)?;
+                let ts = self.on_map_tileset(attributes)?;
                 map.add_tileset(ts);
             }
+            _ => {
+                self.read_layer_element(map, tag, name, attributes)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// A `<group>` element. Group layers are not modeled: the layers of a group are
+// read as layers of the map, in document order, and the attributes and
+// properties of the group itself are dropped with a warning.
+#[derive(Default)]
+pub(crate) struct Group {
+    name: String,
+    dropped: Vec<String>,
+    layers: Map,
+}
+
+impl Group {
+    pub(crate) fn drop_properties(&mut self) {
+        self.dropped.push("properties".to_string());
+    }
+}
+
+impl<R: Read> ElementReader<Group> for TmxReader<R> {
+    fn read_attributes(&mut self, group: &mut Group, tag: &str, name: &str, value: &str) -> ::Result<()> {
+        match name {
+            "name" => {
+                group.name = value.to_string();
+            }
+            "offsetx" | "offsety" | "opacity" | "visible" | "tintcolor" | "parallaxx" | "parallaxy" => {
+                group.dropped.push(name.to_string());
+            }
+            _ => {
+                return Err(reader::unknown_attribute(tag, name));
+            }
+        };
+        Ok(())
+    }
+
+    fn read_children(&mut self, group: &mut Group, tag: &str, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()> {
+        if name == "properties" {
+            self.on_properties(attributes)?;
+            group.drop_properties();
+            Ok(())
+        } else {
+            self.read_layer_element(&mut group.layers, tag, name, attributes)
+        }
+    }
+}
+
+impl<R: Read> TmxReader<R> {
+    // Reads a child element of a map or group that holds a layer, adding the
+    // layer to `map`.
+    fn read_layer_element(&mut self, map: &mut Map, tag: &str, name: &str, attributes: &[OwnedAttribute])
+        -> ::Result<()>
+    {
+        match name {
             "layer" => {
-                let layer = self.on_layer(attributes)?;
+                let position = self.position();
+                let mut layer = self.on_layer(attributes)?;
+                layer.default_size(map.width, map.height);
+                if layer.data().is_none() && !self.options().skip_layer_data {
+                    self.diagnose_at(position, Severity::Info, format!("Layer `{}` has no data", layer.name()));
+                }
                 map.add_layer(layer);
             }
+            "objectgroup" if self.options().skip_objects => {
+                self.skip_element()?;
+            }
             "objectgroup" => {
                 let object_group = self.on_object_group(attributes)?;
                 map.add_object_group(object_group);
             }
+            "imagelayer" if self.options().skip_image_layers => {
+                self.skip_element()?;
+            }
             "imagelayer" => {
                 let image_layer = self.on_image_layer(attributes)?;
                 map.add_image_layer(image_layer);
             }
-            _ => {}
+            "group" => {
+                let position = self.position();
+                let group = self.on_group(attributes)?;
+                self.diagnose_group(position, &group);
+                map.append_layers(group.layers);
+            }
+            _ => {
+                return Err(reader::unknown_element(tag, name));
+            }
         }
         Ok(())
     }
+
+    // Warns about what of a group was dropped when reading its layers as
+    // layers of the map.
+    pub(crate) fn diagnose_group(&mut self, position: (u64, u64), group: &Group) {
+        if !group.dropped.is_empty() {
+            let message = format!("Group `{}` read as layers of the map, dropping its {}",
+                                  group.name, group.dropped.join(", "));
+            self.diagnose_at(position, Severity::Warning, message);
+        }
+    }
+
+    // Reads a tileset embedded in or referenced by a map.
+    pub(crate) fn on_map_tileset(&mut self, attributes: &[OwnedAttribute]) -> ::Result<Tileset> {
+        // References to external tilesets only have `firstgid` and `source`.
+        if !attributes.iter().any(|attr| attr.name.local_name == "source") {
+            self.check_required_attributes("tileset", attributes, &["tilewidth", "tileheight"])?;
+        }
+        self.on_tileset(attributes)
+    }
 }
 
 impl<R: Read> ElementReader<Layer> for TmxReader<R> {
-    fn read_attributes(&mut self, layer: &mut Layer, name: &str, value: &str) -> ::Result<()> {
+    fn read_attributes(&mut self, layer: &mut Layer, tag: &str, name: &str, value: &str) -> ::Result<()> {
         match name {
             "name" => {
-                layer.set_name(value);
+                layer.name = self.intern(value);
             }
             "x" => {
                 let x = reader::read_num(value)?;
@@ -943,22 +2941,18 @@ impl<R: Read> ElementReader<Layer> for TmxReader<R> {
                 layer.set_y(y);
             }
             "width" => {
-                let width = reader::read_num(value)?;
+                let width = self.read_dimension(value)?;
                 layer.set_width(width);
             }
             "height" => {
-                let height = reader::read_num(value)?;
+                let height = self.read_dimension(value)?;
                 layer.set_height(height);
             }
             "opacity" => {
-                let opacity = reader::read_num(value)?;
-                layer.set_opacity(opacity);
+                layer.set_opacity(reader::read_opacity(value)?);
             }
             "visible" => {
-                let visibility = reader::read_num::<u32>(value)?;
-                if visibility == 0 {
-                    layer.set_visible(false);
-                }
+                layer.set_visible(reader::read_bool(value)?);
             }
             "offsetx" => {
                 let offset_x = reader::read_num(value)?;
@@ -968,31 +2962,41 @@ impl<R: Read> ElementReader<Layer> for TmxReader<R> {
                 let offset_y = reader::read_num(value)?;
                 layer.set_offset_y(offset_y);
             }
+            "tintcolor" => {
+                let color = Color::from_str(value)?;
+                layer.set_tint_color(color);
+            }
             _ => {
-                return Err(Error::UnknownAttribute(name.to_string()));
+                return Err(reader::unknown_attribute(tag, name));
             }
         };
         Ok(())
     }
 
-    fn read_children(&mut self, layer: &mut Layer, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
+    fn read_children(&mut self, layer: &mut Layer, tag: &str, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
         match name {
             "properties" => {
                 let properties = self.on_properties(attributes)?;
                 layer.set_properties(properties);
             }
+            "data" if self.options().skip_layer_data => {
+                self.skip_element()?;
+            }
             "data" => {
+                self.expect_data_tiles(layer.width(), layer.height());
                 let data = self.on_data(attributes)?;
                 layer.set_data(data);
             }
-            _ => {}
+            _ => {
+                return Err(reader::unknown_element(tag, name));
+            }
         };
         Ok(())
     }
 }
 
 impl<R: Read> ElementReader<ImageLayer> for TmxReader<R> {
-    fn read_attributes(&mut self, image_layer: &mut ImageLayer, name: &str, value: &str) -> ::Result<()> {
+    fn read_attributes(&mut self, image_layer: &mut ImageLayer, tag: &str, name: &str, value: &str) -> ::Result<()> {
         match name {
             "name" => {
                 image_layer.set_name(value);
@@ -1022,23 +3026,19 @@ impl<R: Read> ElementReader<ImageLayer> for TmxReader<R> {
                 image_layer.set_height(height);
             }
             "opacity" => {
-                let opacity = reader::read_num(value)?;
-                image_layer.set_opacity(opacity);
+                image_layer.set_opacity(reader::read_opacity(value)?);
             }
             "visible" => {
-                let visibility = reader::read_num::<u32>(value)?;
-                if visibility == 0 {
-                    image_layer.set_visible(false);
-                }
+                image_layer.set_visible(reader::read_bool(value)?);
             }
             _ => {
-                return Err(Error::UnknownAttribute(name.to_string()));
+                return Err(reader::unknown_attribute(tag, name));
             }
         };
         Ok(())
     }
 
-    fn read_children(&mut self, image_layer: &mut ImageLayer, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
+    fn read_children(&mut self, image_layer: &mut ImageLayer, tag: &str, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
         match name {
             "properties" => {
                 let properties = self.on_properties(attributes)?;
@@ -1048,14 +3048,24 @@ impl<R: Read> ElementReader<ImageLayer> for TmxReader<R> {
                 let image = self.on_image(attributes)?;
                 image_layer.set_image(image);
             }
-            _ => {}
+            _ => {
+                return Err(reader::unknown_element(tag, name));
+            }
         };
         Ok(())
     }
 }
 
+impl ops::Index<usize> for ObjectGroup {
+    type Output = Object;
+
+    fn index(&self, index: usize) -> &Object {
+        &self.objects[index]
+    }
+}
+
 impl<R: Read> ElementReader<ObjectGroup> for TmxReader<R> {
-    fn read_attributes(&mut self, object_group: &mut ObjectGroup, name: &str, value: &str) -> ::Result<()> {
+    fn read_attributes(&mut self, object_group: &mut ObjectGroup, tag: &str, name: &str, value: &str) -> ::Result<()> {
         match name {
             "name" => {
                 object_group.set_name(value);
@@ -1081,14 +3091,10 @@ impl<R: Read> ElementReader<ObjectGroup> for TmxReader<R> {
                 object_group.set_height(height);
             }
             "opacity" => {
-                let opacity = reader::read_num(value)?;
-                object_group.set_opacity(opacity);
+                object_group.set_opacity(reader::read_opacity(value)?);
             }
             "visible" => {
-                let visibility = reader::read_num::<u32>(value)?;
-                if visibility == 0 {
-                    object_group.set_visible(false);
-                }
+                object_group.set_visible(reader::read_bool(value)?);
             }
             "offsetx" => {
                 let offset_x = reader::read_num(value)?;
@@ -1103,40 +3109,43 @@ impl<R: Read> ElementReader<ObjectGroup> for TmxReader<R> {
                 object_group.set_draw_order(draw_order);
             }
             _ => {
-                return Err(Error::UnknownAttribute(name.to_string()));
+                return Err(reader::unknown_attribute(tag, name));
             }
         };
         Ok(())
     }
 
-    fn read_children(&mut self, object_group: &mut ObjectGroup, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
+    fn read_children(&mut self, object_group: &mut ObjectGroup, tag: &str, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
         match name {
             "properties" => {
                 let properties = self.on_properties(attributes)?;
                 object_group.set_properties(properties);
             }
             "object" => {
+                self.count_object()?;
                 let object = self.on_object(attributes)?;
                 object_group.add_object(object);
             }
-            _ => {}
+            _ => {
+                return Err(reader::unknown_element(tag, name));
+            }
         };
         Ok(())
     }
 }
 
 impl<R: Read> ElementReader<Object> for TmxReader<R> {
-    fn read_attributes(&mut self, object: &mut Object, name: &str, value: &str) -> ::Result<()> {
+    fn read_attributes(&mut self, object: &mut Object, tag: &str, name: &str, value: &str) -> ::Result<()> {
         match name {
             "id" => {
                 let id = reader::read_num(value)?;
                 object.set_id(id);
             }
             "name" => {
-                object.set_name(value);
+                object.name = self.intern(value);
             }
-            "type" => {
-                object.set_object_type(value);
+            "type" | "class" => {
+                object.object_type = self.intern(value);
             }
             "x" => {
                 let x = reader::read_num(value)?;
@@ -1163,19 +3172,16 @@ impl<R: Read> ElementReader<Object> for TmxReader<R> {
                 object.set_gid(gid);
             }
             "visible" => {
-                let visibility = reader::read_num::<u32>(value)?;
-                if visibility == 0 {
-                    object.set_visible(false);
-                }
+                object.set_visible(reader::read_bool(value)?);
             }
             _ => {
-                return Err(Error::UnknownAttribute(name.to_string()));
+                return Err(reader::unknown_attribute(tag, name));
             }
         };
         Ok(())
     }
 
-    fn read_children(&mut self, object: &mut Object, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
+    fn read_children(&mut self, object: &mut Object, tag: &str, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
         match name {
             "properties" => {
                 let properties = self.on_properties(attributes)?;
@@ -1184,6 +3190,9 @@ impl<R: Read> ElementReader<Object> for TmxReader<R> {
             "ellipse" => {
                 object.set_shape(Shape::Ellipse);
             }
+            "point" => {
+                object.set_shape(Shape::Point);
+            }
             "polygon" => {
                 let polygon = self.on_polygon(attributes)?;
                 object.set_shape(polygon);
@@ -1192,9 +3201,187 @@ impl<R: Read> ElementReader<Object> for TmxReader<R> {
                 let polyline = self.on_polyline(attributes)?;
                 object.set_shape(polyline);
             }
-            _ => {}
+            _ => {
+                return Err(reader::unknown_element(tag, name));
+            }
         };
         Ok(())
     }
 }
 
+// Attributes common to every kind of layer, in the order Tiled writes them.
+struct LayerAttributes<'a> {
+    name: &'a str,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    opacity: Opacity,
+    visible: bool,
+    tint_color: Option<&'a Color>,
+    offset_x: i32,
+    offset_y: i32,
+}
+
+impl<'a> LayerAttributes<'a> {
+    fn add_to(&self, attributes: &mut Attributes) {
+        attributes.add_if(!self.name.is_empty(), "name", self.name);
+        attributes.add_if(self.x != 0, "x", self.x);
+        attributes.add_if(self.y != 0, "y", self.y);
+        attributes.add_if(self.width > 0, "width", self.width);
+        attributes.add_if(self.height > 0, "height", self.height);
+        attributes.add_if(!self.visible, "visible", 0);
+        attributes.add_if(!self.opacity.is_opaque(), "opacity", self.opacity);
+        attributes.add_option("tintcolor", self.tint_color);
+        attributes.add_if(self.offset_x != 0, "offsetx", self.offset_x);
+        attributes.add_if(self.offset_y != 0, "offsety", self.offset_y);
+    }
+}
+
+impl<W: Write> ElementWriter<Map> for TmxWriter<W> {
+    fn write_element(&mut self, map: &Map) -> ::Result<()> {
+        let mut attributes = Attributes::new();
+        attributes.add_if(!map.version.is_empty(), "version", &map.version);
+        attributes.add_if(!map.tiled_version.is_empty(), "tiledversion", &map.tiled_version);
+        attributes.add("orientation", map.orientation);
+        attributes.add("renderorder", map.render_order);
+        attributes.add("width", map.width);
+        attributes.add("height", map.height);
+        attributes.add("tilewidth", map.tile_width);
+        attributes.add("tileheight", map.tile_height);
+        attributes.add_if(map.infinite, "infinite", 1);
+        attributes.add_option("hexsidelength", map.hex_side_length);
+        attributes.add_option("staggeraxis", map.stagger_axis);
+        attributes.add_option("staggerindex", map.stagger_index);
+        attributes.add_option("backgroundcolor", map.bg_color);
+        attributes.add_if(map.next_object_id > 0, "nextobjectid", map.next_object_id);
+        let has_children = !map.properties.is_empty() || !map.tilesets.is_empty() || !map.layer_order.is_empty();
+        self.element("map", &attributes, has_children, |w| {
+            w.write_element(&map.properties)?;
+            for tileset in &map.tilesets {
+                w.write_element(tileset)?;
+            }
+            for layer in map.layer_stack() {
+                match layer {
+                    LayerKind::Tile(layer) => w.write_element(layer)?,
+                    LayerKind::Image(layer) => w.write_element(layer)?,
+                    LayerKind::Objects(group) => w.write_element(group)?,
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<W: Write> ElementWriter<Layer> for TmxWriter<W> {
+    fn write_element(&mut self, layer: &Layer) -> ::Result<()> {
+        let mut attributes = Attributes::new();
+        LayerAttributes {
+            name: &layer.name,
+            x: layer.x,
+            y: layer.y,
+            width: layer.width,
+            height: layer.height,
+            opacity: layer.opacity,
+            visible: layer.visible,
+            tint_color: layer.tint_color.as_ref(),
+            offset_x: layer.offset_x,
+            offset_y: layer.offset_y,
+        }.add_to(&mut attributes);
+        let has_children = !layer.properties.is_empty() || layer.data.is_some();
+        self.element("layer", &attributes, has_children, |w| {
+            w.write_element(&layer.properties)?;
+            if let Some(ref data) = layer.data {
+                // Edited data has no content left to pass through.
+                let edited_encoding = || {
+                    DataEncoding::from_attributes(data.encoding(), data.compression()).filter(|_| data.is_edited())
+                };
+                match w.options().layer_encoding(&layer.name).or_else(edited_encoding) {
+                    Some(encoding) => w.write_data_as(data, layer.width, encoding)?,
+                    None => w.write_element(data)?,
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<W: Write> ElementWriter<ImageLayer> for TmxWriter<W> {
+    fn write_element(&mut self, image_layer: &ImageLayer) -> ::Result<()> {
+        let mut attributes = Attributes::new();
+        LayerAttributes {
+            name: &image_layer.name,
+            x: image_layer.x,
+            y: image_layer.y,
+            width: image_layer.width,
+            height: image_layer.height,
+            opacity: image_layer.opacity,
+            visible: image_layer.visible,
+            tint_color: None,
+            offset_x: image_layer.offset_x,
+            offset_y: image_layer.offset_y,
+        }.add_to(&mut attributes);
+        let has_children = image_layer.image.is_some() || !image_layer.properties.is_empty();
+        self.element("imagelayer", &attributes, has_children, |w| {
+            if let Some(ref image) = image_layer.image {
+                w.write_element(image)?;
+            }
+            w.write_element(&image_layer.properties)
+        })
+    }
+}
+
+impl<W: Write> ElementWriter<ObjectGroup> for TmxWriter<W> {
+    fn write_element(&mut self, object_group: &ObjectGroup) -> ::Result<()> {
+        let mut attributes = Attributes::new();
+        attributes.add_option("color", object_group.color);
+        attributes.add_if(object_group.draw_order != DrawOrder::TopDown, "draworder", object_group.draw_order);
+        LayerAttributes {
+            name: &object_group.name,
+            x: object_group.x,
+            y: object_group.y,
+            width: object_group.width,
+            height: object_group.height,
+            opacity: object_group.opacity,
+            visible: object_group.visible,
+            tint_color: None,
+            offset_x: object_group.offset_x,
+            offset_y: object_group.offset_y,
+        }.add_to(&mut attributes);
+        let has_children = !object_group.properties.is_empty() || !object_group.objects.is_empty();
+        self.element("objectgroup", &attributes, has_children, |w| {
+            w.write_element(&object_group.properties)?;
+            for object in &object_group.objects {
+                w.write_element(object)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<W: Write> ElementWriter<Object> for TmxWriter<W> {
+    fn write_element(&mut self, object: &Object) -> ::Result<()> {
+        let mut attributes = Attributes::new();
+        attributes.add_if(object.id > 0, "id", object.id);
+        attributes.add_if(!object.name.is_empty(), "name", &object.name);
+        attributes.add_if(!object.object_type.is_empty(), "type", &object.object_type);
+        attributes.add_option("gid", object.gid);
+        attributes.add("x", object.x);
+        attributes.add("y", object.y);
+        attributes.add_if(object.width != 0.0, "width", object.width);
+        attributes.add_if(object.height != 0.0, "height", object.height);
+        attributes.add_if(object.rotation != 0.0, "rotation", object.rotation);
+        attributes.add_if(!object.visible, "visible", 0);
+        let has_children = !object.properties.is_empty() || object.shape.is_some();
+        self.element("object", &attributes, has_children, |w| {
+            w.write_element(&object.properties)?;
+            match object.shape {
+                Some(Shape::Ellipse) => w.empty_element("ellipse", &Attributes::new()),
+                Some(Shape::Point) => w.empty_element("point", &Attributes::new()),
+                Some(Shape::Polygon(ref polygon)) => w.write_element(polygon),
+                Some(Shape::Polyline(ref polyline)) => w.write_element(polyline),
+                None => Ok(()),
+            }
+        })
+    }
+}
@@ -23,21 +23,91 @@ macro_rules! define_iterator_wrapper {
             fn next(&mut self) -> Option<Self::Item> {
                 self.0.next()
             }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.0.size_hint()
+            }
+
+            fn nth(&mut self, n: usize) -> Option<Self::Item> {
+                self.0.nth(n)
+            }
+
+            fn count(self) -> usize {
+                self.0.count()
+            }
+        }
+
+        impl<'a> DoubleEndedIterator for $name<'a> {
+            fn next_back(&mut self) -> Option<Self::Item> {
+                self.0.next_back()
+            }
+        }
+
+        impl<'a> ExactSizeIterator for $name<'a> {
+            fn len(&self) -> usize {
+                self.0.len()
+            }
+        }
+
+        impl<'a> ::std::iter::FusedIterator for $name<'a> {}
+    }
+}
+
+// Serializes a value as its TMX spelling, given by its `Display` and `FromStr` implementations.
+#[cfg(feature = "serde")]
+macro_rules! serde_as_string {
+    ($name: ident) => {
+        impl ::serde::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+                where S: ::serde::Serializer
+            {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> ::serde::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> ::std::result::Result<$name, D::Error>
+                where D: ::serde::Deserializer<'de>
+            {
+                let value = <String as ::serde::Deserialize>::deserialize(deserializer)?;
+                value.parse().map_err(::serde::de::Error::custom)
+            }
         }
     }
 }
 
 pub mod color;
 pub mod data;
+pub mod geometry;
+pub mod gid;
 pub mod image;
+#[cfg(feature = "json")]
+pub mod json;
 pub mod map;
+pub mod object_types;
 pub mod property;
 pub mod reader;
+pub mod resource;
 pub mod shape;
+pub mod spatial;
+pub mod stats;
 pub mod tileset;
+pub mod visitor;
+pub mod writer;
 
-pub use self::map::Map;
-pub use self::tileset::Tileset;
+pub use self::data::{Compression, DataEncoding};
+pub use self::geometry::{Aabb, Anchor, Rect, TileRect};
+pub use self::gid::Gid;
+pub use self::map::{LayerBuilder, Map};
+pub use self::object_types::ObjectTypes;
+pub use self::property::HasProperties;
+pub use self::reader::{Diagnostic, DuplicatePolicy, Limits, ReaderOptions, Severity, UnknownPolicy};
+pub use self::resource::{FileLoader, ResourceLoader};
+pub use self::spatial::ObjectIndex;
+pub use self::stats::MapStats;
+pub use self::tileset::{GidRemapTable, Tileset, TilesetBuilder};
+pub use self::visitor::{Flow, MapVisitor};
+pub use self::writer::WriteOptions;
 
 #[cfg(test)]
 mod tests;
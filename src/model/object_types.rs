@@ -0,0 +1,168 @@
+// This file is part of tmx
+// Copyright 2017 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use xml::attribute::OwnedAttribute;
+
+use error::Error;
+use model::color::Color;
+use model::property::{Properties, Property, PropertyCollection};
+use model::reader::{self, ElementReader, TmxReader};
+use model::resource::{self, FileLoader, ResourceLoader};
+
+define_iterator_wrapper!(ObjectTypeIter, ObjectType);
+
+/// The object types of a Tiled project, as saved in `objecttypes.xml` by
+/// Tiled versions older than 1.8.
+///
+/// The objects of a map only store the properties whose value differs from
+/// the default of their type; `Map::apply_object_types` fills in the others.
+#[derive(Debug, Default, PartialEq)]
+pub struct ObjectTypes {
+    types: Vec<ObjectType>,
+}
+
+impl ObjectTypes {
+    pub fn open<P: AsRef<Path>>(path: P) -> ::Result<ObjectTypes> {
+        let path = resource::path_str(path.as_ref())?;
+        FileLoader.load_bytes(path)
+            .and_then(|bytes| ObjectTypes::from_str(&reader::decode_text(&bytes)?))
+            .map_err(|err| resource::file_error(path, err))
+    }
+
+    pub fn types(&self) -> ObjectTypeIter {
+        ObjectTypeIter(self.types.iter())
+    }
+
+    /// Returns the type with the given name. If several have it, the first wins.
+    pub fn get(&self, name: &str) -> Option<&ObjectType> {
+        self.types.iter().find(|object_type| object_type.name == name)
+    }
+
+    fn add_type(&mut self, object_type: ObjectType) {
+        self.types.push(object_type);
+    }
+}
+
+impl FromStr for ObjectTypes {
+    type Err = Error;
+
+    fn from_str(s: &str) -> ::Result<ObjectTypes> {
+        let s = s.strip_prefix('\u{feff}').unwrap_or(s);
+        TmxReader::new(s.as_bytes()).read_object_types()
+    }
+}
+
+/// An object type, with the default values of its properties.
+#[derive(Debug, Default, PartialEq)]
+pub struct ObjectType {
+    name: String,
+    color: Option<Color>,
+    properties: PropertyCollection,
+}
+
+impl ObjectType {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn color(&self) -> Option<&Color> {
+        self.color.as_ref()
+    }
+
+    /// Iterates over the properties of the type, holding their default values.
+    pub fn properties(&self) -> Properties {
+        self.properties.iter()
+    }
+
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.get(name)
+    }
+
+    /// Adds the properties of the type missing from `properties`.
+    pub(crate) fn fill_properties(&self, properties: &mut PropertyCollection) {
+        for property in self.properties.iter() {
+            if properties.get(property.name()).is_none() {
+                properties.push(property.clone());
+            }
+        }
+    }
+}
+
+impl<R: Read> ElementReader<ObjectTypes> for TmxReader<R> {
+    fn read_children(&mut self, object_types: &mut ObjectTypes, tag: &str, name: &str, attributes: &[OwnedAttribute])
+        -> ::Result<()>
+    {
+        if name == "objecttype" {
+            let object_type = self.on_object_type(attributes)?;
+            object_types.add_type(object_type);
+        } else {
+            return Err(reader::unknown_element(tag, name));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> ElementReader<ObjectType> for TmxReader<R> {
+    fn read_attributes(&mut self, object_type: &mut ObjectType, tag: &str, name: &str, value: &str) -> ::Result<()> {
+        match name {
+            "name" => {
+                object_type.name = value.to_string();
+            }
+            "color" => {
+                object_type.color = Some(Color::from_str(value)?);
+            }
+            _ => {
+                return Err(reader::unknown_attribute(tag, name));
+            }
+        };
+        Ok(())
+    }
+
+    fn read_children(&mut self, object_type: &mut ObjectType, tag: &str, name: &str, attributes: &[OwnedAttribute])
+        -> ::Result<()>
+    {
+        if name == "property" {
+            let ObjectTypeProperty(property) = self.on_object_type_property(attributes)?;
+            object_type.properties.push(property);
+        } else {
+            return Err(reader::unknown_element(tag, name));
+        }
+        Ok(())
+    }
+}
+
+// A `<property>` of an object type, whose value is given by its `default`
+// attribute.
+#[derive(Default)]
+pub(crate) struct ObjectTypeProperty(Property);
+
+impl<R: Read> ElementReader<ObjectTypeProperty> for TmxReader<R> {
+    fn read_attributes(&mut self, property: &mut ObjectTypeProperty, tag: &str, name: &str, value: &str)
+        -> ::Result<()>
+    {
+        let name = if name == "default" { "value" } else { name };
+        <Self as ElementReader<Property>>::read_attributes(self, &mut property.0, tag, name, value)
+    }
+
+    fn read_children(&mut self, property: &mut ObjectTypeProperty, tag: &str, name: &str, attributes: &[OwnedAttribute])
+        -> ::Result<()>
+    {
+        <Self as ElementReader<Property>>::read_children(self, &mut property.0, tag, name, attributes)
+    }
+}
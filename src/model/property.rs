@@ -13,19 +13,36 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::Read;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{Read, Write};
+use std::iter::FromIterator;
+use std::ops::Index;
+use std::slice;
 use std::str::FromStr;
+use std::sync::Arc;
+
+use xml::attribute::OwnedAttribute;
 
 use error::Error;
-use model::reader::{TmxReader, ElementReader};
+use model::color::Color;
+use model::map::{ImageLayer, Layer, Map, Object, ObjectGroup};
+use model::object_types::ObjectType;
+use model::reader::{self, DuplicatePolicy, ElementReader, Severity, TmxReader};
+use model::tileset::{Terrain, Tile, Tileset};
+use model::writer::{Attributes, ElementWriter, TmxWriter};
 
 define_iterator_wrapper!(Properties, Property);
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Property {
-    name: String,
+    name: Arc<str>,
     value: String,
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
     property_type: PropertyType,
+    custom_type: String,
+    members: PropertyCollection,
 }
 
 impl Property {
@@ -33,9 +50,11 @@ impl Property {
         where S: Into<String>
     {
         Property {
-            name: name.into(),
+            name: Arc::from(name.into()),
             value: value.into(),
-            property_type: property_type,
+            property_type,
+            custom_type: String::new(),
+            members: PropertyCollection::new(),
         }
     }
 
@@ -43,15 +62,15 @@ impl Property {
         &self.name
     }
 
-    fn set_name<S: Into<String>>(&mut self, name: S) {
-        self.name = name.into();
+    pub fn set_name<S: Into<String>>(&mut self, name: S) {
+        self.name = Arc::from(name.into());
     }
 
     pub fn property_type(&self) -> PropertyType {
         self.property_type
     }
 
-    fn set_property_type(&mut self, property_type: PropertyType) {
+    pub fn set_property_type(&mut self, property_type: PropertyType) {
         self.property_type = property_type;
     }
 
@@ -59,12 +78,167 @@ impl Property {
         &self.value
     }
 
-    fn set_value<S: Into<String>>(&mut self, value: S) {
+    pub fn set_value<S: Into<String>>(&mut self, value: S) {
         self.value = value.into();
     }
+
+    /// Returns the name of the custom type of a `class` property (or of an enum value).
+    pub fn custom_type(&self) -> &str {
+        &self.custom_type
+    }
+
+    pub fn set_custom_type<S: Into<String>>(&mut self, custom_type: S) {
+        self.custom_type = custom_type.into();
+    }
+
+    /// Iterates over the members of a `class` property.
+    pub fn members(&self) -> Properties {
+        self.members.iter()
+    }
+
+    pub fn set_members(&mut self, members: PropertyCollection) {
+        self.members = members;
+    }
+
+    /// Parses the value according to the declared type of the property.
+    ///
+    /// An empty `color` value, which Tiled writes for unset colors, gives a fully
+    /// transparent color, and an empty `object` value gives the null object id 0.
+    pub fn to_value(&self) -> ::Result<PropertyValue> {
+        let value = match self.property_type {
+            PropertyType::Bool => reader::read_bool(&self.value).ok().map(PropertyValue::Bool),
+            PropertyType::Int => self.value.parse().ok().map(PropertyValue::Int),
+            PropertyType::Float => self.value.parse().ok().map(PropertyValue::Float),
+            PropertyType::String => Some(PropertyValue::String(self.value.clone())),
+            PropertyType::File => Some(PropertyValue::File(self.value.clone())),
+            PropertyType::Color if self.value.is_empty() => Some(PropertyValue::Color(Color::argb(0, 0, 0, 0))),
+            PropertyType::Color => Color::from_str(&self.value).ok().map(PropertyValue::Color),
+            PropertyType::Object if self.value.is_empty() => Some(PropertyValue::Object(0)),
+            PropertyType::Object => self.value.parse().ok().map(PropertyValue::Object),
+            PropertyType::Class => Some(PropertyValue::Class(self.members.to_map()?)),
+        };
+        value.ok_or_else(|| self.bad_value())
+    }
+
+    // Checks that the value parses according to the declared type, like
+    // `to_value` but without building the value.
+    pub(crate) fn check_value(&self) -> ::Result<()> {
+        let valid = match self.property_type {
+            PropertyType::Bool => reader::read_bool(&self.value).is_ok(),
+            PropertyType::Int => self.value.parse::<i64>().is_ok(),
+            PropertyType::Float => self.value.parse::<f64>().is_ok(),
+            PropertyType::String | PropertyType::File => true,
+            PropertyType::Color => self.value.is_empty() || Color::from_str(&self.value).is_ok(),
+            PropertyType::Object => self.value.is_empty() || self.value.parse::<u32>().is_ok(),
+            PropertyType::Class => return self.members.iter().try_for_each(Property::check_value),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(self.bad_value())
+        }
+    }
+
+    fn bad_value(&self) -> Error {
+        Error::BadPropertyValue {
+            name: self.name.to_string(),
+            value: self.value.clone(),
+        }
+    }
+
+    /// Returns the value of an `int` property, or `None` if the property has
+    /// another type or its value is not a valid integer.
+    pub fn as_int(&self) -> Option<i64> {
+        self.typed_value(PropertyType::Int).and_then(|value| value.parse().ok())
+    }
+
+    /// Returns the value of a `float` property, or `None` if the property has
+    /// another type or its value is not a valid number.
+    pub fn as_float(&self) -> Option<f64> {
+        self.typed_value(PropertyType::Float).and_then(|value| value.parse().ok())
+    }
+
+    /// Returns the value of a `bool` property (`true`/`false` or `1`/`0`), or
+    /// `None` if the property has another type or an unexpected value.
+    pub fn as_bool(&self) -> Option<bool> {
+        self.typed_value(PropertyType::Bool).and_then(|value| reader::read_bool(value).ok())
+    }
+
+    /// Returns the value of a `color` property, or `None` if the property has
+    /// another type or is unset.
+    pub fn as_color(&self) -> Option<Color> {
+        self.typed_value(PropertyType::Color).and_then(|value| Color::from_str(value).ok())
+    }
+
+    /// Returns the value of a `string` property, or `None` if the property has another type.
+    pub fn as_string(&self) -> Option<&str> {
+        self.typed_value(PropertyType::String)
+    }
+
+    fn typed_value(&self, property_type: PropertyType) -> Option<&str> {
+        if self.property_type == property_type {
+            Some(&self.value)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the path stored in a `file` property, or `None` if the property
+    /// has another type or is unset (Tiled writes an empty value in that case).
+    pub fn as_file(&self) -> Option<&str> {
+        self.typed_value(PropertyType::File).filter(|value| !value.is_empty())
+    }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// The elements having properties: maps, layers of every kind, objects,
+/// tilesets, tiles, terrains and object types.
+///
+/// The typed shortcuts return `None` if the property is missing or if its
+/// value is not of the expected type, as the `as_*` methods of `Property` do.
+pub trait HasProperties {
+    fn properties(&self) -> Properties<'_>;
+
+    /// Returns the property named `name`, the first one if the name is repeated.
+    fn property(&self, name: &str) -> Option<&Property> {
+        self.properties().find(|property| property.name() == name)
+    }
+
+    fn property_bool(&self, name: &str) -> Option<bool> {
+        self.property(name).and_then(Property::as_bool)
+    }
+
+    fn property_int(&self, name: &str) -> Option<i64> {
+        self.property(name).and_then(Property::as_int)
+    }
+
+    fn property_float(&self, name: &str) -> Option<f64> {
+        self.property(name).and_then(Property::as_float)
+    }
+
+    fn property_str(&self, name: &str) -> Option<&str> {
+        self.property(name).and_then(Property::as_string)
+    }
+
+    fn property_color(&self, name: &str) -> Option<Color> {
+        self.property(name).and_then(Property::as_color)
+    }
+}
+
+macro_rules! impl_has_properties {
+    ($($name: ident),*) => {
+        $(
+            impl HasProperties for $name {
+                fn properties(&self) -> Properties<'_> {
+                    $name::properties(self)
+                }
+            }
+        )*
+    }
+}
+
+impl_has_properties!(Map, Layer, ImageLayer, ObjectGroup, Object, Tileset, Tile, Terrain, ObjectType);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum PropertyType {
     Bool,
     Color,
@@ -72,6 +246,21 @@ pub enum PropertyType {
     Float,
     Int,
     String,
+    Object,
+    Class,
+}
+
+/// The value of a property, parsed according to its declared type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Color(Color),
+    File(String),
+    Object(u32),
+    Class(HashMap<String, PropertyValue>),
 }
 
 impl Default for PropertyType {
@@ -80,20 +269,160 @@ impl Default for PropertyType {
     }
 }
 
-#[derive(Debug, Default)]
-pub struct PropertyCollection(Vec<Property>);
+// Most elements have no properties: an empty collection takes the size of a
+// pointer and allocates nothing. The box is what makes it smaller than a `Vec`.
+#[derive(Debug, Default, Clone)]
+#[allow(clippy::box_collection)]
+pub struct PropertyCollection(Option<Box<Vec<Property>>>);
 
 impl PropertyCollection {
     pub fn new() -> PropertyCollection {
-        PropertyCollection(Vec::new())
+        PropertyCollection(None)
     }
 
     pub fn push(&mut self, property: Property) {
-        self.0.push(property);
+        self.0.get_or_insert_with(Box::default).push(property);
     }
 
     pub fn iter(&self) -> Properties {
-        Properties(self.0.iter())
+        Properties(self.as_slice().iter())
+    }
+
+    pub fn iter_mut(&mut self) -> slice::IterMut<Property> {
+        match self.0 {
+            Some(ref mut properties) => properties.iter_mut(),
+            None => [].iter_mut(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.as_slice().is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[Property] {
+        self.0.as_ref().map_or(&[], |properties| properties.as_slice())
+    }
+
+    /// Returns the property named `name`, the first one if the name is repeated.
+    pub fn get(&self, name: &str) -> Option<&Property> {
+        self.iter().find(|property| property.name() == name)
+    }
+
+    /// Returns the property named `name` for editing, the first one if the name is repeated.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Property> {
+        self.iter_mut().find(|property| property.name() == name)
+    }
+
+    /// Removes the properties named `name`, returning the first one.
+    pub fn remove(&mut self, name: &str) -> Option<Property> {
+        let properties = self.0.as_mut()?;
+        let index = properties.iter().position(|property| property.name() == name)?;
+        let removed = properties.remove(index);
+        properties.retain(|property| property.name() != name);
+        if properties.is_empty() {
+            self.0 = None;
+        }
+        Some(removed)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Removes the properties whose name was already used by an earlier one.
+    pub fn dedup(&mut self) {
+        if let Some(ref mut properties) = self.0 {
+            let mut names = HashSet::new();
+            properties.retain(|property| names.insert(property.name.clone()));
+        }
+    }
+
+    /// Parses every property according to its declared type.
+    ///
+    /// When a name is repeated, the first property wins, as with `get`.
+    pub fn to_map(&self) -> ::Result<HashMap<String, PropertyValue>> {
+        self.iter().to_map()
+    }
+}
+
+// A `<properties>` element being read, along with the position of each name
+// in the collection so that duplicates are found in constant time.
+#[derive(Default)]
+pub(crate) struct PropertyBlock {
+    properties: PropertyCollection,
+    positions: HashMap<Arc<str>, usize>,
+}
+
+impl PropertyBlock {
+    pub(crate) fn into_properties(self) -> PropertyCollection {
+        self.properties
+    }
+}
+
+// Compared as slices, an empty collection being equal whether or not it was
+// ever allocated.
+impl PartialEq for PropertyCollection {
+    fn eq(&self, other: &PropertyCollection) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Index<usize> for PropertyCollection {
+    type Output = Property;
+
+    fn index(&self, index: usize) -> &Property {
+        &self.as_slice()[index]
+    }
+}
+
+impl<'a> Properties<'a> {
+    /// Parses the remaining properties according to their declared type.
+    ///
+    /// When a name is repeated, the first property wins and the others are
+    /// not parsed.
+    pub fn to_map(self) -> ::Result<HashMap<String, PropertyValue>> {
+        let mut map = HashMap::new();
+        for property in self {
+            if !map.contains_key(property.name()) {
+                map.insert(property.name().to_string(), property.to_value()?);
+            }
+        }
+        Ok(map)
+    }
+}
+
+impl FromIterator<Property> for PropertyCollection {
+    fn from_iter<I: IntoIterator<Item = Property>>(iter: I) -> PropertyCollection {
+        let properties: Vec<Property> = iter.into_iter().collect();
+        if properties.is_empty() {
+            PropertyCollection(None)
+        } else {
+            PropertyCollection(Some(Box::new(properties)))
+        }
+    }
+}
+
+// Serialized as a plain sequence, empty or not.
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for PropertyCollection {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+        where S: ::serde::Serializer
+    {
+        self.as_slice().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for PropertyCollection {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<PropertyCollection, D::Error>
+        where D: ::serde::Deserializer<'de>
+    {
+        let properties = <Vec<Property> as ::serde::Deserialize>::deserialize(deserializer)?;
+        Ok(properties.into_iter().collect())
     }
 }
 
@@ -108,16 +437,42 @@ impl FromStr for PropertyType {
             "float" => Ok(PropertyType::Float),
             "int" => Ok(PropertyType::Int),
             "string" => Ok(PropertyType::String),
+            "object" => Ok(PropertyType::Object),
+            "class" => Ok(PropertyType::Class),
             _ => Err(Error::BadPropertyType(s.to_string())),
         }
     }
 }
 
+impl PropertyType {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            PropertyType::Bool => "bool",
+            PropertyType::Color => "color",
+            PropertyType::File => "file",
+            PropertyType::Float => "float",
+            PropertyType::Int => "int",
+            PropertyType::String => "string",
+            PropertyType::Object => "object",
+            PropertyType::Class => "class",
+        }
+    }
+}
+
+impl fmt::Display for PropertyType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_as_string!(PropertyType);
+
 impl<R: Read> ElementReader<Property> for TmxReader<R> {
-    fn read_attributes(&mut self, property: &mut Property, name: &str, value: &str) -> ::Result<()> {
+    fn read_attributes(&mut self, property: &mut Property, tag: &str, name: &str, value: &str) -> ::Result<()> {
         match name {
             "name" => {
-                property.set_name(value);
+                property.name = self.intern(value);
             }
             "type" => {
                 property.set_property_type(PropertyType::from_str(value)?);
@@ -125,11 +480,93 @@ impl<R: Read> ElementReader<Property> for TmxReader<R> {
             "value" => {
                 property.set_value(value);
             }
+            "propertytype" => {
+                property.set_custom_type(value);
+            }
             _ => {
-                return Err(Error::UnknownAttribute(name.to_string()));
+                return Err(reader::unknown_attribute(tag, name));
             }
         };
         Ok(())
     }
+
+    fn read_children(&mut self, property: &mut Property, tag: &str, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()> {
+        if name == "properties" {
+            let members = self.on_properties(attributes)?;
+            property.set_members(members);
+        } else {
+            return Err(reader::unknown_element(tag, name));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> ElementReader<PropertyBlock> for TmxReader<R> {
+    fn read_children(&mut self, block: &mut PropertyBlock, tag: &str, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
+        if name == "property" {
+            self.count_property()?;
+            let position = self.position();
+            let property = self.on_property(attributes)?;
+            // Class properties are checked through their members.
+            if property.property_type() != PropertyType::Class {
+                if let Err(err) = property.check_value() {
+                    self.diagnose_at(position, Severity::Warning, err.to_string());
+                }
+            }
+            let index = match block.positions.get(&property.name) {
+                Some(&index) => index,
+                None => {
+                    block.positions.insert(property.name.clone(), block.properties.len());
+                    block.properties.push(property);
+                    return Ok(());
+                }
+            };
+            match self.options().duplicate_properties {
+                DuplicatePolicy::KeepFirst => {
+                    let message = format!("Duplicate property `{}` skipped", property.name());
+                    self.diagnose_at(position, Severity::Warning, message);
+                }
+                DuplicatePolicy::KeepLast => {
+                    let message = format!("Duplicate property `{}` replaces the previous one", property.name());
+                    self.diagnose_at(position, Severity::Warning, message);
+                    if let Some(previous) = block.properties.iter_mut().nth(index) {
+                        *previous = property;
+                    }
+                }
+                DuplicatePolicy::Strict => {
+                    let (line, column) = position;
+                    return Err(Error::DuplicateProperty { name: property.name().to_string(), line, column });
+                }
+            }
+        } else {
+            return Err(reader::unknown_element(tag, name));
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> ElementWriter<Property> for TmxWriter<W> {
+    fn write_element(&mut self, property: &Property) -> ::Result<()> {
+        let mut attributes = Attributes::new();
+        attributes.add("name", &property.name);
+        attributes.add_if(property.property_type != PropertyType::String, "type", property.property_type);
+        attributes.add_if(!property.custom_type.is_empty(), "propertytype", &property.custom_type);
+        attributes.add_if(property.property_type != PropertyType::Class, "value", &property.value);
+        let has_children = !property.members.is_empty();
+        self.element("property", &attributes, has_children, |w| w.write_element(&property.members))
+    }
 }
 
+impl<W: Write> ElementWriter<PropertyCollection> for TmxWriter<W> {
+    fn write_element(&mut self, properties: &PropertyCollection) -> ::Result<()> {
+        if properties.is_empty() {
+            return Ok(());
+        }
+        self.element("properties", &Attributes::new(), true, |w| {
+            for property in properties.iter() {
+                w.write_element(property)?;
+            }
+            Ok(())
+        })
+    }
+}
@@ -13,43 +13,56 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::io::Read;
+use std::mem;
+use std::path::PathBuf;
+use std::str;
 use std::str::FromStr;
+use std::sync::Arc;
 
-use xml::reader::{EventReader, XmlEvent};
+use xml::common::Position;
+use xml::reader::{EventReader, ParserConfig, XmlEvent};
 use xml::attribute::OwnedAttribute;
 
 use error::Error;
-use model::data::{Data, DataTile};
+use model::data::{Data, DataTile, RawChunk};
 use model::image::Image;
-use model::map::{ImageLayer, Layer, Map, Object, ObjectGroup};
-use model::property::{PropertyCollection, Property};
+use model::map::{Group, ImageLayer, Layer, Map, Object, ObjectGroup, Opacity};
+use model::object_types::{ObjectType, ObjectTypeProperty, ObjectTypes};
+use model::property::{PropertyBlock, PropertyCollection, Property};
 use model::shape::{Polygon, Polyline};
 use model::tileset::{Animation, Terrain, TerrainCollection, Tile, TileOffset, Tileset, Frame};
 
 macro_rules! implement_handler {
     ($handler: ident, $tag: expr, $elem_type: ty) => {
-        pub fn $handler(&mut self, attributes: &[OwnedAttribute]) -> ::Result<$elem_type> {
+        implement_handler!(pub $handler, $tag, $elem_type);
+    };
+    ($vis: vis $handler: ident, $tag: expr, $elem_type: ty) => {
+        $vis fn $handler(&mut self, attributes: &[OwnedAttribute]) -> ::Result<$elem_type> {
+            self.enter_element()?;
             let mut elem = <$elem_type>::default();
-
-            // Process attributes
-            for attr in attributes {
-                <Self as ElementReader<$elem_type>>::read_attributes(self, &mut elem, &attr.name.local_name, &attr.value)?;
-            }
+            self.read_element_attributes(&mut elem, $tag, attributes)?;
 
             // Process children elements
-            while let Ok(event) = self.reader.next() {
-                match event {
+            loop {
+                match self.reader.next()? {
                     XmlEvent::StartElement { ref name, ref attributes, .. } => {
-                        <Self as ElementReader<$elem_type>>::read_children(self, &mut elem, &name.local_name, attributes)?;
+                        match <Self as ElementReader<$elem_type>>::read_children(self, &mut elem, $tag, &name.local_name, attributes) {
+                            Err(Error::UnknownElement { parent, element }) => {
+                                self.on_unknown_element(parent, element)?;
+                            }
+                            result => result?,
+                        }
                     }
                     XmlEvent::EndElement { ref name, .. } => {
                         if name.local_name == $tag {
                             break;
                         }
                     }
-                    XmlEvent::Characters(ref content) => {
-                        <Self as ElementReader<$elem_type>>::read_content(self, &mut elem, &content)?;
+                    XmlEvent::Characters(content) => {
+                        <Self as ElementReader<$elem_type>>::read_content(self, &mut elem, content)?;
                     }
                     XmlEvent::EndDocument { .. } => {
                         break;
@@ -58,60 +71,635 @@ macro_rules! implement_handler {
                 }
             }
 
+            self.leave_element();
             Ok(elem)
         }
     }
 }
 
+/// Decodes a document given as raw bytes.
+///
+/// A UTF-8, UTF-16LE or UTF-16BE byte order mark selects the encoding and is
+/// dropped; input without one is taken as UTF-8.
+pub fn decode_text(bytes: &[u8]) -> ::Result<Cow<'_, str>> {
+    if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        decode_utf8(rest)
+    } else if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        decode_utf16(rest, u16::from_le_bytes)
+    } else if let Some(rest) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        decode_utf16(rest, u16::from_be_bytes)
+    } else {
+        decode_utf8(bytes)
+    }
+}
+
+fn decode_utf8(bytes: &[u8]) -> ::Result<Cow<'_, str>> {
+    str::from_utf8(bytes)
+        .map(Cow::Borrowed)
+        .map_err(|err| Error::InvalidText(format!("not valid UTF-8: {}", err)))
+}
+
+fn decode_utf16(bytes: &[u8], to_unit: fn([u8; 2]) -> u16) -> ::Result<Cow<'static, str>> {
+    let units = bytes.chunks_exact(2);
+    if !units.remainder().is_empty() {
+        return Err(Error::InvalidText("odd number of bytes in UTF-16 input".to_string()));
+    }
+    char::decode_utf16(units.map(|unit| to_unit([unit[0], unit[1]])))
+        .collect::<Result<String, _>>()
+        .map(Cow::Owned)
+        .map_err(|err| Error::InvalidText(format!("not valid UTF-16: {}", err)))
+}
+
 pub fn read_num<T: FromStr>(s: &str) -> ::Result<T> {
     s.parse::<T>().map_err(|_| Error::InvalidNumber(s.to_string()))
 }
 
+/// Parses an `opacity` attribute.
+///
+/// Values outside `[0, 1]` are clamped to that range; NaN and infinite values
+/// are rejected.
+pub fn read_opacity(s: &str) -> ::Result<Opacity> {
+    let opacity = read_num::<f64>(s)?;
+    if opacity.is_finite() {
+        Ok(Opacity::new_clamped(opacity))
+    } else {
+        Err(Error::BadOpacity(s.to_string()))
+    }
+}
+
+/// Parses a boolean attribute, which Tiled writes as `0`/`1` and some
+/// exporters as `false`/`true`.
+pub fn read_bool(s: &str) -> ::Result<bool> {
+    match s {
+        "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        _ => Err(Error::BadBoolean(s.to_string())),
+    }
+}
+
+pub fn check_limit(limit: &str, value: usize, max: Option<usize>) -> ::Result<()> {
+    match max {
+        Some(max) if value > max => Err(Error::LimitExceeded { limit: limit.to_string(), max }),
+        _ => Ok(()),
+    }
+}
+
+pub fn unknown_attribute(tag: &str, name: &str) -> Error {
+    Error::UnknownAttribute {
+        element: tag.to_string(),
+        attribute: name.to_string(),
+    }
+}
+
+pub fn missing_attribute(tag: &str, name: &str) -> Error {
+    Error::MissingAttribute {
+        element: tag.to_string(),
+        attribute: name.to_string(),
+    }
+}
+
+pub fn unknown_element(parent: &str, name: &str) -> Error {
+    Error::UnknownElement {
+        parent: parent.to_string(),
+        element: name.to_string(),
+    }
+}
+
+// Tells which attribute of which element held the value that could not be read.
+fn attribute_error(tag: &str, name: &str, err: Error) -> Error {
+    Error::InvalidAttribute {
+        element: tag.to_string(),
+        attribute: name.to_string(),
+        cause: Box::new(err),
+    }
+}
+
+/// What the reader does with attributes or elements it does not know, for
+/// instance those added by a newer version of Tiled.
+///
+/// Unknown elements are skipped along with everything they contain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnknownPolicy {
+    /// Fail with `Error::UnknownAttribute` or `Error::UnknownElement`.
+    Strict,
+    /// Skip them silently.
+    Ignore,
+    /// Skip them and record them in the map or tileset read, with a warning
+    /// diagnostic for each.
+    Collect,
+}
+
+/// What the reader does when a `properties` element declares the same name twice.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Keep the first property with the name, as `PropertyCollection::get` would find it.
+    KeepFirst,
+    /// Keep the last property with the name, in place of the first one.
+    KeepLast,
+    /// Fail with `Error::DuplicateProperty`.
+    Strict,
+}
+
+/// An attribute skipped by the reader in the `Collect` mode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownAttribute {
+    pub element: String,
+    pub attribute: String,
+}
+
+/// A child element skipped by the reader in the `Collect` mode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownElement {
+    pub parent: String,
+    pub element: String,
+}
+
+/// How serious a diagnostic is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Something worth knowing that does not change what was read, such as a deprecated construct.
+    Info,
+    /// Something that was skipped or cannot be used as is.
+    Warning,
+}
+
+/// A problem found while reading a document that did not prevent reading it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// The file the problem was found in, when read from a file.
+    pub file: Option<PathBuf>,
+    /// The line of the problem, counting from 1, or 0 when the problem is not
+    /// found at a place of the document, as for an unreadable external tileset.
+    pub line: u64,
+    /// The column of the problem, counting from 1, or 0 along with the line.
+    pub column: u64,
+}
+
+// Attaches the diagnostics found in a document to the file it was read from.
+pub(crate) fn set_diagnostics_file(diagnostics: &mut [Diagnostic], path: &str) {
+    for diagnostic in diagnostics {
+        diagnostic.file = Some(PathBuf::from(path));
+    }
+}
+
+/// Limits on the size of what is read, to guard against hostile files.
+///
+/// Each limit is disabled when `None`, which is the default. Going over a limit
+/// fails with `Error::LimitExceeded`, naming the field of the exceeded limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum width and height, in tiles, of maps and tile layers. Layer data
+    /// without encoding may list no more tiles than the square of this.
+    pub max_dimension: Option<u32>,
+    /// Maximum length, in bytes, of the content of a `data` element.
+    pub max_data_length: Option<usize>,
+    /// Maximum size, in bytes, of the decoded tile data of a layer, four bytes
+    /// per tile. Compressed data is never inflated past this size.
+    pub max_layer_bytes: Option<usize>,
+    /// Maximum number of objects in a document.
+    pub max_objects: Option<usize>,
+    /// Maximum number of properties in a document, members of class properties included.
+    pub max_properties: Option<usize>,
+    /// Maximum size, in bytes, of an image file loaded by `Image::load_with`,
+    /// or of the embedded data of an image once decoded.
+    pub max_image_bytes: Option<usize>,
+}
+
+/// Options controlling how maps and tilesets are read.
+#[derive(Debug, Clone)]
+pub struct ReaderOptions {
+    /// What to do with unknown attributes, on any element. Strict by default.
+    pub unknown_attributes: UnknownPolicy,
+    /// What to do with unknown child elements, in any element. Ignored by default.
+    pub unknown_elements: UnknownPolicy,
+    /// Whether to fail when a map lacks one of its `width`, `height`, `tilewidth`
+    /// and `tileheight` attributes, or an embedded tileset one of its `tilewidth`
+    /// and `tileheight` attributes. On by default; when off, they default to 0.
+    pub require_attributes: bool,
+    /// Whether to fail when an external tileset of a map opened from a file
+    /// cannot be read. Off by default, in which case the reference is kept
+    /// unresolved and a warning diagnostic tells why.
+    pub require_tilesets: bool,
+    /// What to do with properties declared twice in the same `properties`
+    /// element. The first one is kept by default.
+    pub duplicate_properties: DuplicatePolicy,
+    /// Limits on the size of the document. Unlimited by default.
+    pub limits: Limits,
+    /// Maximum nesting depth of the elements read, the root element being at
+    /// depth 1. 256 by default.
+    pub max_depth: usize,
+    /// Whether to drop whitespace-only text and trim the text of elements. Off by default.
+    pub trim_whitespace: bool,
+    /// Whether to merge the pieces of text of an element split by comments or
+    /// CDATA sections. On by default.
+    pub coalesce_characters: bool,
+    /// Whether to skip comments. On by default.
+    pub ignore_comments: bool,
+    /// Whether to read CDATA sections as ordinary text. Off by default, in
+    /// which case they are skipped.
+    pub cdata_to_characters: bool,
+    /// Whether to skip the `<data>` of tile layers, leaving the layers without
+    /// data. Off by default.
+    pub skip_layer_data: bool,
+    /// Whether to skip object groups, those of the map as well as the collision
+    /// shapes of tiles. Off by default.
+    pub skip_objects: bool,
+    /// Whether to skip image layers. Off by default.
+    pub skip_image_layers: bool,
+    /// Whether to share a single copy of the repeated names of layers,
+    /// objects and properties and of the types of objects. Off by default.
+    pub intern_strings: bool,
+}
+
+impl Default for ReaderOptions {
+    fn default() -> ReaderOptions {
+        ReaderOptions {
+            unknown_attributes: UnknownPolicy::Strict,
+            unknown_elements: UnknownPolicy::Ignore,
+            require_attributes: true,
+            require_tilesets: false,
+            duplicate_properties: DuplicatePolicy::KeepFirst,
+            limits: Limits::default(),
+            max_depth: 256,
+            trim_whitespace: false,
+            coalesce_characters: true,
+            ignore_comments: true,
+            cdata_to_characters: false,
+            skip_layer_data: false,
+            skip_objects: false,
+            skip_image_layers: false,
+            intern_strings: false,
+        }
+    }
+}
+
+impl ReaderOptions {
+    pub fn new() -> ReaderOptions {
+        ReaderOptions::default()
+    }
+
+    pub fn with_unknown_attributes(mut self, policy: UnknownPolicy) -> ReaderOptions {
+        self.unknown_attributes = policy;
+        self
+    }
+
+    pub fn with_unknown_elements(mut self, policy: UnknownPolicy) -> ReaderOptions {
+        self.unknown_elements = policy;
+        self
+    }
+
+    pub fn with_require_attributes(mut self, require_attributes: bool) -> ReaderOptions {
+        self.require_attributes = require_attributes;
+        self
+    }
+
+    pub fn with_require_tilesets(mut self, require_tilesets: bool) -> ReaderOptions {
+        self.require_tilesets = require_tilesets;
+        self
+    }
+
+    pub fn with_duplicate_properties(mut self, policy: DuplicatePolicy) -> ReaderOptions {
+        self.duplicate_properties = policy;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> ReaderOptions {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> ReaderOptions {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn with_trim_whitespace(mut self, trim_whitespace: bool) -> ReaderOptions {
+        self.trim_whitespace = trim_whitespace;
+        self
+    }
+
+    pub fn with_coalesce_characters(mut self, coalesce_characters: bool) -> ReaderOptions {
+        self.coalesce_characters = coalesce_characters;
+        self
+    }
+
+    pub fn with_ignore_comments(mut self, ignore_comments: bool) -> ReaderOptions {
+        self.ignore_comments = ignore_comments;
+        self
+    }
+
+    pub fn with_cdata_to_characters(mut self, cdata_to_characters: bool) -> ReaderOptions {
+        self.cdata_to_characters = cdata_to_characters;
+        self
+    }
+
+    pub fn with_skip_layer_data(mut self, skip_layer_data: bool) -> ReaderOptions {
+        self.skip_layer_data = skip_layer_data;
+        self
+    }
+
+    pub fn with_skip_objects(mut self, skip_objects: bool) -> ReaderOptions {
+        self.skip_objects = skip_objects;
+        self
+    }
+
+    pub fn with_skip_image_layers(mut self, skip_image_layers: bool) -> ReaderOptions {
+        self.skip_image_layers = skip_image_layers;
+        self
+    }
+
+    pub fn with_intern_strings(mut self, intern_strings: bool) -> ReaderOptions {
+        self.intern_strings = intern_strings;
+        self
+    }
+
+    // The configuration of the XML parser matching these options.
+    fn parser_config(&self) -> ParserConfig {
+        ParserConfig::new()
+            .trim_whitespace(self.trim_whitespace)
+            .coalesce_characters(self.coalesce_characters)
+            .ignore_comments(self.ignore_comments)
+            .cdata_to_characters(self.cdata_to_characters)
+    }
+}
+
+// Attributes without which a map cannot be laid out.
+pub(crate) const REQUIRED_MAP_ATTRIBUTES: &[&str] = &["width", "height", "tilewidth", "tileheight"];
+
 pub struct TmxReader<R: Read> {
     reader: EventReader<R>,
+    options: ReaderOptions,
+    unknown_attributes: Vec<UnknownAttribute>,
+    unknown_elements: Vec<UnknownElement>,
+    object_count: usize,
+    property_count: usize,
+    diagnostics: Vec<Diagnostic>,
+    depth: usize,
+    data_tile_count: usize,
+    interned: HashSet<Arc<str>>,
 }
 
 impl<R: Read> TmxReader<R> {
 
     pub fn new(source: R) -> TmxReader<R> {
+        TmxReader::with_options(source, ReaderOptions::default())
+    }
+
+    pub fn with_options(source: R, options: ReaderOptions) -> TmxReader<R> {
         TmxReader {
-            reader: EventReader::new(source),
+            reader: EventReader::new_with_config(source, options.parser_config()),
+            options,
+            unknown_attributes: Vec::new(),
+            unknown_elements: Vec::new(),
+            object_count: 0,
+            property_count: 0,
+            diagnostics: Vec::new(),
+            depth: 0,
+            data_tile_count: 0,
+            interned: HashSet::new(),
+        }
+    }
+
+    pub fn options(&self) -> &ReaderOptions {
+        &self.options
+    }
+
+    /// Returns the diagnostics collected so far, leaving none behind.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        mem::take(&mut self.diagnostics)
+    }
+
+    // Returns the line and column, counting from 1, of the last event read.
+    pub(crate) fn position(&self) -> (u64, u64) {
+        let position = self.reader.position();
+        (position.row + 1, position.column + 1)
+    }
+
+    // Records a diagnostic at the position of the last event read.
+    pub(crate) fn diagnose(&mut self, severity: Severity, message: String) {
+        let position = self.position();
+        self.diagnose_at(position, severity, message);
+    }
+
+    pub(crate) fn diagnose_at(&mut self, (line, column): (u64, u64), severity: Severity, message: String) {
+        self.diagnostics.push(Diagnostic { severity, message, file: None, line, column });
+    }
+
+    // Fails if one of the `required` attributes is absent, unless the options
+    // say not to.
+    pub(crate) fn check_required_attributes(&self, tag: &str, attributes: &[OwnedAttribute], required: &[&str])
+        -> ::Result<()>
+    {
+        if !self.options.require_attributes {
+            return Ok(());
+        }
+        match required.iter().find(|name| attributes.iter().all(|attr| attr.name.local_name != **name)) {
+            Some(name) => Err(missing_attribute(tag, name)),
+            None => Ok(()),
+        }
+    }
+
+    // Goes one level deeper in the document, as each element is read by a
+    // recursive call of the handlers.
+    pub(crate) fn enter_element(&mut self) -> ::Result<()> {
+        self.depth += 1;
+        if self.depth > self.options.max_depth {
+            return Err(Error::TooDeep(self.options.max_depth));
         }
+        Ok(())
+    }
+
+    pub(crate) fn leave_element(&mut self) {
+        self.depth -= 1;
+    }
+
+    pub(crate) fn next_event(&mut self) -> ::Result<XmlEvent> {
+        Ok(self.reader.next()?)
+    }
+
+    // Sets the fields of `elem` from the attributes of its start tag.
+    pub(crate) fn read_element_attributes<T>(&mut self, elem: &mut T, tag: &str, attributes: &[OwnedAttribute])
+        -> ::Result<()>
+        where Self: ElementReader<T>
+    {
+        for attr in attributes {
+            let name = &attr.name.local_name;
+            match <Self as ElementReader<T>>::read_attributes(self, elem, tag, name, &attr.value) {
+                Err(Error::UnknownAttribute { element, attribute }) => {
+                    self.on_unknown_attribute(element, attribute)?;
+                }
+                Err(err @ Error::LimitExceeded { .. }) => return Err(err),
+                result => result.map_err(|err| attribute_error(tag, name, err))?,
+            }
+        }
+        Ok(())
+    }
+
+    // Reads the width or height of a map or tile layer.
+    pub(crate) fn read_dimension(&self, value: &str) -> ::Result<u32> {
+        let dimension = read_num(value)?;
+        let max = self.options.limits.max_dimension.map(|max| max as usize);
+        check_limit("max_dimension", dimension as usize, max)?;
+        Ok(dimension)
+    }
+
+    // Declares the number of tiles of the layer whose data is about to be read.
+    pub(crate) fn expect_data_tiles(&mut self, width: u32, height: u32) {
+        self.data_tile_count = (width as usize).saturating_mul(height as usize);
+    }
+
+    // Checks the number of `tile` elements read in layer data without encoding
+    // against the size of the decoded data and against the area of the largest
+    // layer allowed.
+    pub(crate) fn check_data_tile_count(&self, count: usize) -> ::Result<()> {
+        let limits = self.options.limits;
+        check_limit("max_layer_bytes", count.saturating_mul(4), limits.max_layer_bytes)?;
+        let max_area = limits.max_dimension.map(|max| (max as usize).saturating_mul(max as usize));
+        check_limit("max_dimension", count, max_area)
+    }
+
+    // Returns, once, the number of tiles declared by `expect_data_tiles`.
+    pub(crate) fn take_data_tile_count(&mut self) -> usize {
+        mem::take(&mut self.data_tile_count)
+    }
+
+    // Returns `value` as a shared string, the same for equal values when
+    // interning is on.
+    pub(crate) fn intern(&mut self, value: &str) -> Arc<str> {
+        if !self.options.intern_strings {
+            return Arc::from(value);
+        }
+        if let Some(interned) = self.interned.get(value) {
+            return interned.clone();
+        }
+        let interned: Arc<str> = Arc::from(value);
+        self.interned.insert(interned.clone());
+        interned
+    }
+
+    // Counts an object about to be read against the `max_objects` limit.
+    pub(crate) fn count_object(&mut self) -> ::Result<()> {
+        self.object_count += 1;
+        check_limit("max_objects", self.object_count, self.options.limits.max_objects)
+    }
+
+    // Counts a property about to be read against the `max_properties` limit.
+    pub(crate) fn count_property(&mut self) -> ::Result<()> {
+        self.property_count += 1;
+        check_limit("max_properties", self.property_count, self.options.limits.max_properties)
+    }
+
+    fn on_unknown_attribute(&mut self, element: String, attribute: String) -> ::Result<()> {
+        let policy = self.options.unknown_attributes;
+        match policy {
+            UnknownPolicy::Strict => return Err(Error::UnknownAttribute { element, attribute }),
+            UnknownPolicy::Ignore => {}
+            UnknownPolicy::Collect => {
+                self.diagnose(Severity::Warning, format!("Unknown attribute `{}` on element `{}` skipped", attribute, element));
+                self.unknown_attributes.push(UnknownAttribute { element, attribute });
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn on_unknown_element(&mut self, parent: String, element: String) -> ::Result<()> {
+        let policy = self.options.unknown_elements;
+        match policy {
+            UnknownPolicy::Strict => return Err(Error::UnknownElement { parent, element }),
+            UnknownPolicy::Ignore => {}
+            UnknownPolicy::Collect => {
+                self.diagnose(Severity::Warning, format!("Unknown element `{}` in element `{}` skipped", element, parent));
+                self.unknown_elements.push(UnknownElement { parent, element });
+            }
+        }
+        self.skip_element()
+    }
+
+    // Consumes the events up to the end of the element just started.
+    pub(crate) fn skip_element(&mut self) -> ::Result<()> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.reader.next()? {
+                XmlEvent::StartElement { .. } => depth += 1,
+                XmlEvent::EndElement { .. } => depth -= 1,
+                XmlEvent::EndDocument => break,
+                _ => {}
+            }
+        }
+        Ok(())
     }
 
     pub fn read_map(&mut self) -> ::Result<Map> {
-        let mut result = Err(Error::BadXml);
-        while let Ok(event) = self.reader.next() {
-            match event {
-                XmlEvent::StartElement { ref name, ref attributes, .. } => {
-                    if name.local_name == "map" {
-                        result = self.on_map(attributes);
+        let mut map = self.read_document("map", |reader, attributes| {
+            reader.check_required_attributes("map", attributes, REQUIRED_MAP_ATTRIBUTES)?;
+            reader.on_map(attributes)
+        })?;
+        map.set_unknown_attributes(mem::take(&mut self.unknown_attributes));
+        map.set_unknown_elements(mem::take(&mut self.unknown_elements));
+        Ok(map)
+    }
+
+    pub fn read_tileset(&mut self) -> ::Result<Tileset> {
+        let mut tileset = self.read_document("tileset", Self::on_tileset)?;
+        tileset.set_unknown_attributes(mem::take(&mut self.unknown_attributes));
+        tileset.set_unknown_elements(mem::take(&mut self.unknown_elements));
+        Ok(tileset)
+    }
+
+    pub fn read_object_types(&mut self) -> ::Result<ObjectTypes> {
+        self.read_document("objecttypes", Self::on_object_types)
+    }
+
+    // Reads a document whose root element must be `tag`, followed by nothing
+    // but whitespace, comments and processing instructions.
+    fn read_document<T, F>(&mut self, tag: &str, handler: F) -> ::Result<T>
+        where F: FnOnce(&mut Self, &[OwnedAttribute]) -> ::Result<T>
+    {
+        let attributes = self.read_root(tag)?;
+        let root = handler(self, &attributes)?;
+        self.read_end_of_document()?;
+        Ok(root)
+    }
+
+    // Reads up to the start of the root element, which must be `tag`, and
+    // returns its attributes.
+    pub(crate) fn read_root(&mut self, tag: &str) -> ::Result<Vec<OwnedAttribute>> {
+        loop {
+            match self.reader.next()? {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    if name.local_name != tag {
+                        return Err(Error::BadXml);
                     }
+                    return Ok(attributes);
                 }
-                XmlEvent::EndDocument { .. } => {
-                    break;
+                XmlEvent::EndDocument => {
+                    return Err(Error::BadXml);
                 }
                 _ => {}
             }
         }
-        result
     }
 
-    pub fn read_tileset(&mut self) -> ::Result<Tileset> {
-        while let Ok(event) = self.reader.next() {
-            match event {
-                XmlEvent::StartElement { ref name, ref attributes, .. } => {
-                    if name.local_name == "tileset" {
-                        return self.on_tileset(attributes);
-                    }
+    // Reads what follows the root element. Stray text is already rejected by
+    // the XML parser.
+    pub(crate) fn read_end_of_document(&mut self) -> ::Result<()> {
+        loop {
+            match self.reader.next()? {
+                XmlEvent::StartElement { name, .. } => {
+                    return Err(Error::TrailingContent(name.local_name));
                 }
-                XmlEvent::EndDocument { .. } => {
-                    break;
+                XmlEvent::EndDocument => {
+                    return Ok(());
                 }
                 _ => {}
             }
         }
-        Err(Error::BadXml)
+    }
+
+    pub fn on_properties(&mut self, attributes: &[OwnedAttribute]) -> ::Result<PropertyCollection> {
+        self.on_property_block(attributes).map(PropertyBlock::into_properties)
     }
 
     implement_handler!(on_map, "map", Map);
@@ -119,12 +707,14 @@ impl<R: Read> TmxReader<R> {
     implement_handler!(on_layer, "layer", Layer);
     implement_handler!(on_image_layer, "imagelayer", ImageLayer);
     implement_handler!(on_object_group, "objectgroup", ObjectGroup);
+    implement_handler!(pub(crate) on_group, "group", Group);
     implement_handler!(on_object, "object", Object);
     implement_handler!(on_image, "image", Image);
     implement_handler!(on_tile_offset, "tileoffset", TileOffset);
-    implement_handler!(on_properties, "properties", PropertyCollection);
+    implement_handler!(pub(crate) on_property_block, "properties", PropertyBlock);
     implement_handler!(on_data, "data", Data);
     implement_handler!(on_data_tile, "tile", DataTile);
+    implement_handler!(pub(crate) on_chunk, "chunk", RawChunk);
     implement_handler!(on_terrain_types, "terraintypes", TerrainCollection);
     implement_handler!(on_tile, "tile", Tile);
     implement_handler!(on_property, "property", Property);
@@ -133,21 +723,24 @@ impl<R: Read> TmxReader<R> {
     implement_handler!(on_frame, "frame", Frame);
     implement_handler!(on_polygon, "polygon", Polygon);
     implement_handler!(on_polyline, "polyline", Polyline);
+    implement_handler!(on_object_types, "objecttypes", ObjectTypes);
+    implement_handler!(on_object_type, "objecttype", ObjectType);
+    implement_handler!(pub(crate) on_object_type_property, "property", ObjectTypeProperty);
 }
 
 pub trait ElementReader<T> {
     #[allow(unused_variables)]
-    fn read_attributes(&mut self, elem: &mut T, name: &str, value: &str) -> ::Result<()> {
+    fn read_attributes(&mut self, elem: &mut T, tag: &str, name: &str, value: &str) -> ::Result<()> {
         Ok(())
     }
 
     #[allow(unused_variables)]
-    fn read_children(&mut self, elem: &mut T, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()> {
-        Ok(())
+    fn read_children(&mut self, elem: &mut T, tag: &str, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()> {
+        Err(unknown_element(tag, name))
     }
 
     #[allow(unused_variables)]
-    fn read_content(&mut self, elem: &mut T, content: &str) -> ::Result<()> {
+    fn read_content(&mut self, elem: &mut T, content: String) -> ::Result<()> {
         Ok(())
     }
 }
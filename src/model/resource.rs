@@ -0,0 +1,135 @@
+// This file is part of tmx
+// Copyright 2017 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+use error::Error;
+
+/// Source of the files read while loading a map or a tileset.
+///
+/// Every file is requested through the loader, including secondary files such
+/// as external tilesets, and images loaded with `Image::load_with`. Paths are
+/// already joined against the directory of the referencing file.
+pub trait ResourceLoader {
+    fn load(&mut self, path: &str) -> ::Result<Box<dyn Read>>;
+
+    /// Returns the whole content of the file at `path`, read through `load`
+    /// unless the loader knows better.
+    fn load_bytes(&mut self, path: &str) -> ::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.load(path)?.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Returns the path remembered by the maps and tilesets read from `path`,
+    /// against which the paths they contain are resolved. The default keeps
+    /// `path` as it is.
+    fn canonicalize(&self, path: &str) -> PathBuf {
+        PathBuf::from(path)
+    }
+}
+
+/// Loader reading files from the filesystem.
+///
+/// Whole files are read at once, into a buffer sized from their metadata.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileLoader;
+
+impl ResourceLoader for FileLoader {
+    fn load(&mut self, path: &str) -> ::Result<Box<dyn Read>> {
+        let file = File::open(path)?;
+        Ok(Box::new(BufReader::new(file)))
+    }
+
+    fn load_bytes(&mut self, path: &str) -> ::Result<Vec<u8>> {
+        let mut file = File::open(path)?;
+        let mut bytes = Vec::with_capacity(file.metadata()?.len() as usize);
+        file.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    // Paths that cannot be canonicalized, as when the file vanished since it
+    // was read, are kept as they are.
+    fn canonicalize(&self, path: &str) -> PathBuf {
+        fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+    }
+}
+
+// Returns `path` as the string handed to loaders, failing for a path that is
+// not valid UTF-8 rather than reading a file the caller never named.
+pub(crate) fn path_str(path: &Path) -> ::Result<&str> {
+    path.to_str().ok_or_else(|| Error::File {
+        path: path.to_path_buf(),
+        source: Box::new(Error::InvalidText("the path is not valid UTF-8".to_string())),
+    })
+}
+
+// Tells which file was being read when `err` was raised.
+pub(crate) fn file_error(path: &str, err: Error) -> Error {
+    Error::File {
+        path: PathBuf::from(path),
+        source: Box::new(err),
+    }
+}
+
+/// Joins a path found in a Tiled document against the directory of that document.
+///
+/// Both `/` and `\` are accepted as separators in `relative`, and `.`/`..`
+/// components are folded so that the result uses the platform's separators.
+pub fn resolve_path(base_dir: Option<&Path>, relative: &str) -> PathBuf {
+    let mut result = match base_dir {
+        Some(dir) if !is_absolute(relative) => dir.to_path_buf(),
+        _ => PathBuf::new(),
+    };
+    if relative.starts_with('/') || relative.starts_with('\\') {
+        result.push(Component::RootDir.as_os_str());
+    }
+    for part in relative.split(&['/', '\\'][..]) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if let Some(Component::Normal(_)) = result.components().next_back() {
+                    result.pop();
+                } else {
+                    result.push("..");
+                }
+            }
+            _ => result.push(part),
+        }
+    }
+    result
+}
+
+fn is_absolute(path: &str) -> bool {
+    path.starts_with('/') || path.starts_with('\\') || Path::new(path).is_absolute()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_resolve_path() {
+        let base = Path::new("maps/level1");
+        assert_eq!(PathBuf::from("maps/level1/tiles.png"), resolve_path(Some(base), "tiles.png"));
+        assert_eq!(PathBuf::from("maps/images/tiles.png"), resolve_path(Some(base), "../images\\tiles.png"));
+        assert_eq!(PathBuf::from("a/b.png"), resolve_path(None, "./a//b.png"));
+        assert_eq!(PathBuf::from("../b.png"), resolve_path(None, "../b.png"));
+        assert_eq!(PathBuf::from("/abs/b.png"), resolve_path(Some(base), "/abs/b.png"));
+    }
+}
@@ -13,22 +13,70 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::Read;
+use std::fmt;
+use std::io::{Read, Write};
+use std::ops::{Add, Div, Index, Mul, Neg, Sub};
 use std::str::FromStr;
+use std::vec;
 
 use error::Error;
+use model::geometry::Aabb;
 use model::reader::{self, TmxReader, ElementReader};
+use model::writer::{Attributes, ElementWriter, TmxWriter};
+
+// Below this fraction of the area of the box around its points, a polygon is
+// taken as having no area, since collinear points with fractional coordinates
+// seldom give an area of exactly zero.
+const DEGENERATE_AREA_RATIO: f64 = 1e-9;
 
 define_iterator_wrapper!(Points, Point);
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum Shape {
     Ellipse,
+    Point,
     Polygon(Polygon),
     Polyline(Polyline),
 }
 
-#[derive(Debug, Default, PartialEq, Eq)]
+/// The shape of an object in the space of its object group, as returned by
+/// `Object::shape_in_map_space`.
+pub type WorldShape = CollisionShape;
+
+/// The shape of an object with its position and rotation applied, in the
+/// space of its object group: relative to the map for map objects, to the
+/// tile's top-left corner for the collision group of a tile.
+///
+/// Rotations are in degrees, clockwise.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum CollisionShape {
+    /// A rectangle whose top-left corner is at `(x, y)`, rotated around that corner.
+    Rect { x: f64, y: f64, width: f64, height: f64, rotation: f64 },
+    /// An ellipse rotated around its center.
+    Ellipse { center_x: f64, center_y: f64, radius_x: f64, radius_y: f64, rotation: f64 },
+    /// A closed polygon, rotation already applied.
+    Polygon(Vec<(f64, f64)>),
+    /// An open polyline, rotation already applied.
+    Polyline(Vec<(f64, f64)>),
+    /// A point object, never among the collision shapes of a tile.
+    Point(f64, f64),
+}
+
+/// The order in which the points of a polygon go around it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Winding {
+    Clockwise,
+    CounterClockwise,
+    /// Less than three points, or all of them collinear.
+    Degenerate,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Polygon {
     points: Vec<Point>,
 }
@@ -38,9 +86,99 @@ impl Polygon {
         Points(self.points.iter())
     }
 
+    pub fn points_slice(&self) -> &[Point] {
+        &self.points
+    }
+
+    pub fn into_points(self) -> vec::IntoIter<Point> {
+        self.points.into_iter()
+    }
+
     fn add_point(&mut self, point: Point) {
         self.points.push(point);
     }
+
+    /// Returns the area enclosed by the polygon, whatever its winding.
+    ///
+    /// Polygons with less than three points have an area of zero.
+    pub fn area(&self) -> f64 {
+        self.signed_area().abs()
+    }
+
+    /// Returns the order in which the points go around the polygon, as displayed
+    /// by Tiled, that is with the y axis pointing down.
+    ///
+    /// Polygons with less than three points, or whose points are all collinear,
+    /// are `Winding::Degenerate`. Points are taken as collinear when the area of
+    /// the polygon is negligible next to that of the box around them, so that
+    /// rounding errors do not make up a winding.
+    pub fn winding(&self) -> Winding {
+        let signed_area = self.signed_area();
+        let tolerance = self.aabb().map_or(0.0, |aabb| DEGENERATE_AREA_RATIO * aabb.width() * aabb.height());
+        if signed_area.abs() <= tolerance {
+            Winding::Degenerate
+        } else if signed_area > 0.0 {
+            Winding::Clockwise
+        } else {
+            Winding::CounterClockwise
+        }
+    }
+
+    /// Returns a copy of the polygon whose points go around it in the given order,
+    /// reversing them if needed.
+    ///
+    /// Degenerate polygons have no winding to change and are returned as they are,
+    /// as are all polygons when asked for `Winding::Degenerate`.
+    pub fn normalized(&self, winding: Winding) -> Polygon {
+        let current = self.winding();
+        if current == Winding::Degenerate || winding == Winding::Degenerate || current == winding {
+            return self.clone();
+        }
+        Polygon { points: self.points.iter().rev().cloned().collect() }
+    }
+
+    // Positive when clockwise with the y axis pointing down. The points are
+    // taken relative to the first one, which keeps the rounding errors in
+    // proportion to the size of the polygon rather than to its position.
+    fn signed_area(&self) -> f64 {
+        if self.points.len() < 3 {
+            return 0.0;
+        }
+        let (x0, y0) = (self.points[0].x, self.points[0].y);
+        let twice_area: f64 = edges(&self.points, true)
+            .map(|((x1, y1), (x2, y2))| (x1 - x0) * (y2 - y0) - (x2 - x0) * (y1 - y0))
+            .sum();
+        twice_area / 2.0
+    }
+
+    /// Tests whether a point lies inside the polygon, using the even-odd rule.
+    ///
+    /// The coordinates are relative to the object's position, like the points of
+    /// the polygon. Points lying exactly on an edge may be reported either way.
+    /// Polygons with less than three points contain nothing.
+    pub fn contains_point(&self, x: f64, y: f64) -> bool {
+        if self.points.len() < 3 {
+            return false;
+        }
+        edges(&self.points, true)
+            .filter(|&((x1, y1), (x2, y2))| {
+                (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1)
+            })
+            .count() % 2 == 1
+    }
+
+    /// Returns the box enclosing the points, or `None` if there are none.
+    pub fn aabb(&self) -> Option<Aabb> {
+        Aabb::from_points(coordinates(&self.points))
+    }
+}
+
+impl Index<usize> for Polygon {
+    type Output = Point;
+
+    fn index(&self, index: usize) -> &Point {
+        &self.points[index]
+    }
 }
 
 impl From<Polygon> for Shape {
@@ -49,7 +187,8 @@ impl From<Polygon> for Shape {
     }
 }
 
-#[derive(Debug, Default, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Polyline {
     points: Vec<Point>,
 }
@@ -59,9 +198,68 @@ impl Polyline {
         Points(self.points.iter())
     }
 
+    pub fn points_slice(&self) -> &[Point] {
+        &self.points
+    }
+
+    pub fn into_points(self) -> vec::IntoIter<Point> {
+        self.points.into_iter()
+    }
+
     fn add_point(&mut self, point: Point) {
         self.points.push(point);
     }
+
+    /// Returns the segments joining consecutive points, in order.
+    ///
+    /// Polylines with less than two points have no segments.
+    pub fn segments(&self) -> impl Iterator<Item = (Point, Point)> + '_ {
+        self.points.windows(2).map(|pair| (pair[0], pair[1]))
+    }
+
+    /// Returns the sum of the lengths of the segments.
+    pub fn length(&self) -> f64 {
+        self.segments().map(|(start, end)| start.distance_to(end)).sum()
+    }
+
+    /// Returns the point lying at the given distance from the first point, when
+    /// walking along the segments.
+    ///
+    /// Distances are clamped to the polyline, so that negative distances give the
+    /// first point and distances past the end give the last one. A polyline with a
+    /// single point always gives that point. Returns `None` if there are no points
+    /// or if the distance is NaN.
+    pub fn point_at(&self, distance: f64) -> Option<Point> {
+        let first = *self.points.first()?;
+        if distance.is_nan() {
+            return None;
+        }
+        if distance <= 0.0 {
+            return Some(first);
+        }
+        let mut remaining = distance;
+        for (start, end) in self.segments() {
+            let length = start.distance_to(end);
+            if remaining < length {
+                return Some(start.lerp(end, remaining / length));
+            }
+            remaining -= length;
+        }
+        self.points.last().cloned()
+    }
+
+    /// Returns the box enclosing the points, or `None` if there are none.
+    pub fn aabb(&self) -> Option<Aabb> {
+        Aabb::from_points(coordinates(&self.points))
+    }
+}
+
+impl Index<usize> for Polyline {
+    type Output = Point;
+
+    fn index(&self, index: usize) -> &Point {
+        &self.points[index]
+    }
 }
 
 impl From<Polyline> for Shape {
@@ -70,21 +268,121 @@ impl From<Polyline> for Shape {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// A point of a polygon or polyline, relative to the position of its object.
+///
+/// Tiled only rounds the coordinates when snapping to the grid, so they may be fractional.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Point {
-    pub x: i32,
-    pub y: i32,
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    pub fn new(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    /// Returns the Euclidean distance between the two points.
+    #[inline]
+    pub fn distance_to(&self, other: Point) -> f64 {
+        (other.x - self.x).hypot(other.y - self.y)
+    }
+
+    /// Interpolates linearly between the two points, returning `self` for a
+    /// `t` of 0 and `other` for a `t` of 1.
+    #[inline]
+    pub fn lerp(&self, other: Point, t: f64) -> Point {
+        *self + (other - *self) * t
+    }
+}
+
+impl Add for Point {
+    type Output = Point;
+
+    #[inline]
+    fn add(self, other: Point) -> Point {
+        Point { x: self.x + other.x, y: self.y + other.y }
+    }
+}
+
+impl Sub for Point {
+    type Output = Point;
+
+    #[inline]
+    fn sub(self, other: Point) -> Point {
+        Point { x: self.x - other.x, y: self.y - other.y }
+    }
+}
+
+impl Neg for Point {
+    type Output = Point;
+
+    #[inline]
+    fn neg(self) -> Point {
+        Point { x: -self.x, y: -self.y }
+    }
+}
+
+impl Mul<f64> for Point {
+    type Output = Point;
+
+    #[inline]
+    fn mul(self, factor: f64) -> Point {
+        Point { x: self.x * factor, y: self.y * factor }
+    }
+}
+
+impl Div<f64> for Point {
+    type Output = Point;
+
+    #[inline]
+    fn div(self, divisor: f64) -> Point {
+        Point { x: self.x / divisor, y: self.y / divisor }
+    }
+}
+
+impl From<(f64, f64)> for Point {
+    #[inline]
+    fn from((x, y): (f64, f64)) -> Point {
+        Point { x, y }
+    }
+}
+
+impl From<Point> for (f64, f64) {
+    #[inline]
+    fn from(point: Point) -> (f64, f64) {
+        (point.x, point.y)
+    }
+}
+
+fn coordinates(points: &[Point]) -> impl Iterator<Item = (f64, f64)> + '_ {
+    points.iter().map(|point| (point.x, point.y))
+}
+
+// Iterates over the segments joining consecutive points, including the one
+// going back to the first point if `closed` is set.
+fn edges(points: &[Point], closed: bool) -> impl Iterator<Item = ((f64, f64), (f64, f64))> + '_ {
+    let closing = if closed && points.len() > 1 { points.first() } else { None };
+    let ends = coordinates(points).skip(1).chain(closing.map(|point| (point.x, point.y)));
+    coordinates(points).zip(ends)
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.x, self.y)
+    }
 }
 
 impl FromStr for Point {
     type Err = Error;
 
     fn from_str(s: &str) -> ::Result<Point> {
-        let mut coords: Vec<_> = s.split(',').map(reader::read_num::<i32>).collect();
+        let mut coords: Vec<_> = s.split(',').map(reader::read_num::<f64>).collect();
         if coords.len() == 2 {
             let y = coords.pop().unwrap()?;
             let x = coords.pop().unwrap()?;
-            Ok(Point {x: x, y: y})
+            Ok(Point { x, y })
         } else {
             Err(Error::InvalidPoint(s.to_string()))
         }
@@ -92,7 +390,7 @@ impl FromStr for Point {
 }
 
 impl<R: Read> ElementReader<Polygon> for TmxReader<R> {
-    fn read_attributes(&mut self, polygon: &mut Polygon, name: &str, value: &str) -> ::Result<()> {
+    fn read_attributes(&mut self, polygon: &mut Polygon, tag: &str, name: &str, value: &str) -> ::Result<()> {
         match name {
             "points" => {
                 for result in value.split(' ').map(Point::from_str) {
@@ -100,7 +398,7 @@ impl<R: Read> ElementReader<Polygon> for TmxReader<R> {
                 }
             }
             _ => {
-                return Err(Error::UnknownAttribute(name.to_string()));
+                return Err(reader::unknown_attribute(tag, name));
             }
         };
         Ok(())
@@ -108,7 +406,7 @@ impl<R: Read> ElementReader<Polygon> for TmxReader<R> {
 }
 
 impl<R: Read> ElementReader<Polyline> for TmxReader<R> {
-    fn read_attributes(&mut self, polyline: &mut Polyline, name: &str, value: &str) -> ::Result<()> {
+    fn read_attributes(&mut self, polyline: &mut Polyline, tag: &str, name: &str, value: &str) -> ::Result<()> {
         match name {
             "points" => {
                 for result in value.split(' ').map(Point::from_str) {
@@ -116,10 +414,146 @@ impl<R: Read> ElementReader<Polyline> for TmxReader<R> {
                 }
             }
             _ => {
-                return Err(Error::UnknownAttribute(name.to_string()));
+                return Err(reader::unknown_attribute(tag, name));
             }
         };
         Ok(())
     }
 }
 
+
+fn points_attribute(points: &[Point]) -> Attributes {
+    let points: Vec<_> = points.iter().map(Point::to_string).collect();
+    let mut attributes = Attributes::new();
+    attributes.add("points", points.join(" "));
+    attributes
+}
+
+impl<W: Write> ElementWriter<Polygon> for TmxWriter<W> {
+    fn write_element(&mut self, polygon: &Polygon) -> ::Result<()> {
+        self.empty_element("polygon", &points_attribute(&polygon.points))
+    }
+}
+
+impl<W: Write> ElementWriter<Polyline> for TmxWriter<W> {
+    fn write_element(&mut self, polyline: &Polyline) -> ::Result<()> {
+        self.empty_element("polyline", &points_attribute(&polyline.points))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::geometry::Aabb;
+
+    fn points(coords: &[(i32, i32)]) -> Vec<Point> {
+        coords.iter().map(|&(x, y)| Point { x: f64::from(x), y: f64::from(y) }).collect()
+    }
+
+    #[test]
+    fn test_polygon_area() {
+        assert_eq!(12.0, Polygon { points: points(&[(0, 0), (4, 0), (4, 3), (0, 3)]) }.area());
+        assert_eq!(12.0, Polygon { points: points(&[(0, 3), (4, 3), (4, 0), (0, 0)]) }.area());
+        assert_eq!(6.0, Polygon { points: points(&[(0, 0), (4, 0), (4, 0), (4, 3)]) }.area());
+        assert_eq!(0.0, Polygon { points: points(&[(0, 0), (4, 0)]) }.area());
+        assert_eq!(0.0, Polygon::default().area());
+    }
+
+    #[test]
+    fn test_polygon_winding() {
+        let triangle = Polygon { points: points(&[(0, 0), (4, 0), (0, 3)]) };
+        assert_eq!(Winding::Clockwise, triangle.winding());
+        let square = Polygon { points: points(&[(0, 0), (0, 2), (2, 2), (2, 0)]) };
+        assert_eq!(Winding::CounterClockwise, square.winding());
+        let collinear = Polygon { points: points(&[(0, 0), (1, 1), (3, 3), (2, 2)]) };
+        assert_eq!(Winding::Degenerate, collinear.winding());
+        assert_eq!(Winding::Degenerate, Polygon { points: points(&[(0, 0), (4, 0)]) }.winding());
+        assert_eq!(Winding::Degenerate, Polygon::default().winding());
+
+        // Collinear points whose fractional coordinates do not give an area of
+        // exactly zero.
+        for i in 1..200 {
+            let origin = Point::new(0.1 * f64::from(i), 1000.37);
+            let direction = Point::new(0.013 * f64::from(i), -0.29);
+            let points = vec![origin, origin + direction * 0.3, origin + direction * 1.7, origin + direction * 0.9];
+            let collinear = Polygon { points };
+            assert_eq!(Winding::Degenerate, collinear.winding(), "{:?}", collinear);
+            assert_eq!(collinear, collinear.normalized(Winding::Clockwise));
+        }
+        let thin = Polygon { points: vec![Point::new(0.0, 0.0), Point::new(100.0, 0.0), Point::new(50.0, 0.001)] };
+        assert_eq!(Winding::Clockwise, thin.winding());
+    }
+
+    #[test]
+    fn test_polygon_normalized() {
+        let triangle = Polygon { points: points(&[(0, 0), (4, 0), (0, 3)]) };
+        assert_eq!(triangle, triangle.normalized(Winding::Clockwise));
+        let reversed = triangle.normalized(Winding::CounterClockwise);
+        assert_eq!(points(&[(0, 3), (4, 0), (0, 0)]), reversed.points);
+        assert_eq!(Winding::CounterClockwise, reversed.winding());
+        assert_eq!(triangle.area(), reversed.area());
+
+        let square = Polygon { points: points(&[(0, 0), (0, 2), (2, 2), (2, 0)]) };
+        assert_eq!(Winding::Clockwise, square.normalized(Winding::Clockwise).winding());
+        assert_eq!(square, square.normalized(Winding::Degenerate));
+
+        let collinear = Polygon { points: points(&[(0, 0), (1, 1), (3, 3)]) };
+        assert_eq!(collinear, collinear.normalized(Winding::CounterClockwise));
+    }
+
+    #[test]
+    fn test_polygon_contains_point() {
+        // An L shape.
+        let polygon = Polygon { points: points(&[(0, 0), (4, 0), (4, 2), (2, 2), (2, 4), (0, 4)]) };
+        assert!(polygon.contains_point(1.0, 1.0));
+        assert!(polygon.contains_point(3.0, 1.0));
+        assert!(polygon.contains_point(1.0, 3.0));
+        assert!(!polygon.contains_point(3.0, 3.0));
+        assert!(!polygon.contains_point(-1.0, 1.0));
+        assert!(!Polygon { points: points(&[(0, 0), (4, 4)]) }.contains_point(2.0, 2.0));
+        assert!(!Polygon::default().contains_point(0.0, 0.0));
+    }
+
+    #[test]
+    fn test_polyline_length() {
+        assert_eq!(8.0, Polyline { points: points(&[(0, 0), (3, 4), (3, 4), (3, 7)]) }.length());
+        assert_eq!(0.0, Polyline { points: points(&[(1, 1)]) }.length());
+        assert_eq!(0.0, Polyline::default().length());
+    }
+
+    #[test]
+    fn test_polyline_segments() {
+        let polyline = Polyline { points: points(&[(0, 0), (3, 4), (3, 7)]) };
+        let segments: Vec<_> = polyline.segments().collect();
+        assert_eq!(vec![(polyline[0], polyline[1]), (polyline[1], polyline[2])], segments);
+        assert_eq!(0, Polyline { points: points(&[(1, 1)]) }.segments().count());
+        assert_eq!(0, Polyline::default().segments().count());
+    }
+
+    #[test]
+    fn test_polyline_point_at() {
+        let polyline = Polyline { points: points(&[(0, 0), (3, 4), (3, 4), (3, 7)]) };
+        assert_eq!(Some(Point::new(0.0, 0.0)), polyline.point_at(0.0));
+        assert_eq!(Some(Point::new(1.5, 2.0)), polyline.point_at(2.5));
+        assert_eq!(Some(Point::new(3.0, 4.0)), polyline.point_at(5.0));
+        assert_eq!(Some(Point::new(3.0, 5.5)), polyline.point_at(6.5));
+        assert_eq!(Some(Point::new(3.0, 7.0)), polyline.point_at(8.0));
+        assert_eq!(Some(Point::new(3.0, 7.0)), polyline.point_at(100.0));
+        assert_eq!(Some(Point::new(0.0, 0.0)), polyline.point_at(-1.0));
+        assert_eq!(None, polyline.point_at(f64::NAN));
+
+        let single = Polyline { points: points(&[(2, 1)]) };
+        assert_eq!(Some(Point::new(2.0, 1.0)), single.point_at(0.0));
+        assert_eq!(Some(Point::new(2.0, 1.0)), single.point_at(3.0));
+        assert_eq!(None, Polyline::default().point_at(0.0));
+    }
+
+    #[test]
+    fn test_shape_aabb() {
+        let polyline = Polyline { points: points(&[(0, 0), (-3, 4), (5, -1)]) };
+        assert_eq!(Some(Aabb::new(-3.0, -1.0, 5.0, 4.0)), polyline.aabb());
+        let polygon = Polygon { points: points(&[(2, 2)]) };
+        assert_eq!(Some(Aabb::new(2.0, 2.0, 2.0, 2.0)), polygon.aabb());
+        assert_eq!(None, Polygon::default().aabb());
+    }
+}
@@ -0,0 +1,144 @@
+// This file is part of tmx
+// Copyright 2017 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use model::geometry::Aabb;
+use model::map::{Map, Object, ObjectGroup};
+
+// Objects covering more cells than this are kept apart and tested on every
+// query, so that a huge object does not fill the grid.
+const MAX_CELLS_PER_OBJECT: i64 = 64;
+
+/// Index of objects by position, answering which objects overlap a rectangle.
+///
+/// Objects are bucketed into a uniform grid according to their rotated
+/// bounding boxes. The index borrows the objects: after editing them, build
+/// a new index.
+#[derive(Debug)]
+pub struct ObjectIndex<'a> {
+    cell_size: f64,
+    objects: Vec<(&'a Object, Aabb)>,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+    oversized: Vec<usize>,
+}
+
+impl<'a> ObjectIndex<'a> {
+    /// Indexes the objects of all the object groups of `map`, with cells four
+    /// tiles wide.
+    pub fn new(map: &'a Map) -> ObjectIndex<'a> {
+        let tile_size = map.tile_width().max(map.tile_height());
+        let cell_size = if tile_size > 0 { 4.0 * tile_size as f64 } else { 64.0 };
+        ObjectIndex::with_cell_size(map.object_groups(), cell_size)
+    }
+
+    /// Indexes the objects of the given groups, with square cells `cell_size`
+    /// pixels wide.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cell_size` is not a positive number.
+    pub fn with_cell_size<I>(groups: I, cell_size: f64) -> ObjectIndex<'a>
+        where I: IntoIterator<Item = &'a ObjectGroup>
+    {
+        assert!(cell_size > 0.0, "cell size must be positive, got {}", cell_size);
+        let mut index = ObjectIndex {
+            cell_size,
+            objects: Vec::new(),
+            cells: HashMap::new(),
+            oversized: Vec::new(),
+        };
+        for object in groups.into_iter().flat_map(ObjectGroup::objects) {
+            index.insert(object);
+        }
+        index
+    }
+
+    fn insert(&mut self, object: &'a Object) {
+        let aabb = object.bounding_box();
+        let position = self.objects.len();
+        self.objects.push((object, aabb));
+        match self.cell_range(&aabb) {
+            Some((min, max)) if cell_count(min, max) <= MAX_CELLS_PER_OBJECT => {
+                for x in min.0..=max.0 {
+                    for y in min.1..=max.1 {
+                        self.cells.entry((x, y)).or_default().push(position);
+                    }
+                }
+            }
+            _ => self.oversized.push(position),
+        }
+    }
+
+    // Returns the first and last cells covered by `aabb`, or `None` if it is
+    // not finite.
+    fn cell_range(&self, aabb: &Aabb) -> Option<((i64, i64), (i64, i64))> {
+        let coordinates = [aabb.min_x, aabb.min_y, aabb.max_x, aabb.max_y];
+        if !coordinates.iter().all(|c| c.is_finite()) {
+            return None;
+        }
+        let cell = |c: f64| (c / self.cell_size).floor() as i64;
+        Some(((cell(aabb.min_x), cell(aabb.min_y)), (cell(aabb.max_x), cell(aabb.max_y))))
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Iterates over the objects whose bounding box overlaps `rect`, touching
+    /// edges included, in the order they were indexed.
+    pub fn query_rect(&self, rect: Aabb) -> impl Iterator<Item = &'a Object> + '_ {
+        let mut candidates = self.oversized.clone();
+        if let Some((min, max)) = self.cell_range(&rect) {
+            if cell_count(min, max) as u64 <= self.cells.len() as u64 {
+                for x in min.0..=max.0 {
+                    for y in min.1..=max.1 {
+                        candidates.extend(self.cells.get(&(x, y)).into_iter().flatten());
+                    }
+                }
+            } else {
+                // Faster to go through the cells in use than through the rectangle.
+                for (&(x, y), positions) in &self.cells {
+                    if min.0 <= x && x <= max.0 && min.1 <= y && y <= max.1 {
+                        candidates.extend(positions);
+                    }
+                }
+            }
+        }
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates.into_iter()
+            .map(move |position| self.objects[position])
+            .filter(move |(_, aabb)| aabb.intersects(&rect))
+            .map(|(object, _)| object)
+    }
+
+    /// Iterates over the objects whose bounding box contains the point, edges
+    /// included, in the order they were indexed.
+    pub fn query_point(&self, x: f64, y: f64) -> impl Iterator<Item = &'a Object> + '_ {
+        self.query_rect(Aabb::new(x, y, x, y))
+    }
+}
+
+// Counts the cells from `min` to `max`, saturating for huge boxes.
+fn cell_count(min: (i64, i64), max: (i64, i64)) -> i64 {
+    let columns = max.0.saturating_sub(min.0).saturating_add(1);
+    let rows = max.1.saturating_sub(min.1).saturating_add(1);
+    columns.saturating_mul(rows)
+}
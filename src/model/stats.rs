@@ -0,0 +1,136 @@
+// This file is part of tmx
+// Copyright 2017 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use model::gid::Gid;
+use model::map::Map;
+use model::tileset::Tile;
+
+/// A summary of the content of a map, as returned by `Map::statistics`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MapStats {
+    pub layers: usize,
+    pub image_layers: usize,
+    pub object_groups: usize,
+    pub objects: usize,
+    /// The name and number of objects of each object group, in map order.
+    pub objects_per_group: Vec<(String, usize)>,
+    /// The number of objects of each type, by type name, the empty name
+    /// counting the objects without a type.
+    pub objects_per_type: BTreeMap<String, usize>,
+    /// The names of the tile layers without any tile.
+    pub empty_layers: Vec<String>,
+    /// The use of each tileset by the tile layers, in map order.
+    pub tilesets: Vec<TilesetStats>,
+    /// The number of tiles with an animation, in all the tilesets.
+    pub animated_tiles: usize,
+    /// The number of cells of all the tile layers, empty ones included.
+    pub decoded_cells: usize,
+}
+
+impl MapStats {
+    /// Returns the memory taken by the decoded data of the tile layers, in bytes.
+    pub fn decoded_bytes(&self) -> usize {
+        self.decoded_cells * 4
+    }
+}
+
+/// The use of a tileset by the tile layers of a map.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TilesetStats {
+    pub name: String,
+    pub first_gid: u32,
+    /// The number of cells showing a tile of the tileset.
+    pub cells: usize,
+    /// The number of different tiles of the tileset shown.
+    pub distinct_tiles: usize,
+}
+
+impl Map {
+    /// Sums up the content of the map.
+    ///
+    /// The data of the tile layers is decoded if it is not yet, and cached as
+    /// by `Data::decode`. Fails if the data of a layer cannot be decoded.
+    pub fn statistics(&self) -> ::Result<MapStats> {
+        let mut stats = MapStats {
+            layers: self.layer_count(),
+            image_layers: self.image_layer_count(),
+            object_groups: self.object_group_count(),
+            ..MapStats::default()
+        };
+        for group in self.object_groups() {
+            stats.objects += group.objects().len();
+            stats.objects_per_group.push((group.name().to_string(), group.objects().len()));
+            for object in group.objects() {
+                *stats.objects_per_type.entry(object.object_type().to_string()).or_insert(0) += 1;
+            }
+        }
+
+        let mut used: Vec<HashSet<u32>> = vec![HashSet::new(); self.tilesets().len()];
+        let mut cells = vec![0; used.len()];
+        for layer in self.layers() {
+            let gids = match layer.data() {
+                Some(data) => data.decode()?,
+                None => &[],
+            };
+            stats.decoded_cells += gids.len();
+            if gids.iter().all(|&gid| Gid(gid).is_empty()) {
+                stats.empty_layers.push(layer.name().to_string());
+            }
+            for &gid in gids {
+                if let Some(position) = self.tileset_index_for_gid(gid) {
+                    used[position].insert(Gid(gid).id());
+                    cells[position] += 1;
+                }
+            }
+        }
+        for ((tileset, used), cells) in self.tilesets().zip(used).zip(cells) {
+            stats.animated_tiles += tileset.tiles().filter_map(Tile::animation).count();
+            stats.tilesets.push(TilesetStats {
+                name: tileset.name().to_string(),
+                first_gid: tileset.first_gid(),
+                cells,
+                distinct_tiles: used.len(),
+            });
+        }
+        Ok(stats)
+    }
+}
+
+impl fmt::Display for MapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} tile layer(s), {} image layer(s), {} object group(s)",
+                 self.layers, self.image_layers, self.object_groups)?;
+        writeln!(f, "{} decoded cell(s), {} bytes", self.decoded_cells, self.decoded_bytes())?;
+        if !self.empty_layers.is_empty() {
+            writeln!(f, "Empty layers: {}", self.empty_layers.join(", "))?;
+        }
+        writeln!(f, "{} object(s)", self.objects)?;
+        for &(ref name, count) in &self.objects_per_group {
+            writeln!(f, "  group `{}`: {}", name, count)?;
+        }
+        for (object_type, count) in &self.objects_per_type {
+            writeln!(f, "  type `{}`: {}", object_type, count)?;
+        }
+        writeln!(f, "{} animated tile(s)", self.animated_tiles)?;
+        for tileset in &self.tilesets {
+            writeln!(f, "Tileset `{}` (firstgid {}): {} cell(s), {} distinct tile(s)",
+                     tileset.name, tileset.first_gid, tileset.cells, tileset.distinct_tiles)?;
+        }
+        Ok(())
+    }
+}
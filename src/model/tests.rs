@@ -13,14 +13,37 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use error::Error;
 use model::color::*;
+use model::data::*;
+use model::geometry::*;
+use model::gid::*;
+use model::image::*;
 use model::map::*;
 use model::property::*;
+use model::reader::*;
+use model::resource::*;
 use model::shape::*;
+use model::spatial::*;
+use model::stats::*;
 use model::tileset::*;
+use model::visitor::*;
+use model::writer::*;
+
+// Most snippets below leave out the attributes a map requires, so they are
+// read with that check turned off.
+fn lenient_options() -> ReaderOptions {
+    ReaderOptions::new().with_require_attributes(false)
+}
+
+fn read_map(xml: &str) -> ::Result<Map> {
+    Map::from_str_with(xml, &lenient_options())
+}
 
 #[test]
 fn after_reading_valid_xml_expect_map_to_have_version() {
@@ -42,7 +65,7 @@ fn after_reading_valid_xml_without_render_order_expect_map_to_have_default_rende
 
 #[test]
 fn after_reading_valid_xml_with_render_order_expect_map_to_have_that_render_order() {
-    let map = Map::from_str("<map renderorder=\"left-up\"></map>").unwrap();
+    let map = read_map("<map renderorder=\"left-up\"></map>").unwrap();
     assert_eq!(RenderOrder::LeftUp, map.render_order());
 }
 
@@ -72,7 +95,7 @@ fn after_reading_valid_xml_expect_map_to_have_tile_height() {
 
 #[test]
 fn after_reading_hexagonal_map_xml_expect_map_to_have_special_attributes() {
-    let map = Map::from_str("<map/>").unwrap();
+    let map = read_map("<map/>").unwrap();
     assert_eq!(None, map.hex_side_length());
     assert_eq!(None, map.stagger_axis());
     assert_eq!(None, map.stagger_index());
@@ -90,15 +113,15 @@ fn after_reading_valid_xml_expect_map_to_have_next_object_id() {
 
 #[test]
 fn after_reading_valid_xml_with_background_color_expect_map_to_have_that_background_color() {
-    let map = Map::from_str("<map/>").unwrap();
+    let map = read_map("<map/>").unwrap();
     assert_eq!(None, map.background_color());
-    let map = Map::from_str("<map backgroundcolor=\"#80a0b0c0\"></map>").unwrap();
-    assert_eq!(Some(&Color(128, 160, 176, 192)), map.background_color());
+    let map = read_map("<map backgroundcolor=\"#80a0b0c0\"></map>").unwrap();
+    assert_eq!(Some(&Color::argb(128, 160, 176, 192)), map.background_color());
 }
 
 #[test]
 fn after_reading_valid_xml_with_properties_expect_map_to_have_properties() {
-    let map = Map::from_str(r#"<map>
+    let map = read_map(r#"<map>
         <properties>
             <property name="prop1_name" value="prop1_value"/>
             <property name="prop2_name" value="0" type="int"/>
@@ -109,46 +132,531 @@ fn after_reading_valid_xml_with_properties_expect_map_to_have_properties() {
     assert_eq!(4, map.properties().count());
 }
 
+#[test]
+fn after_reading_valid_xml_with_properties_expect_properties_to_be_found_by_name() {
+    let map = read_map(r#"<map>
+        <properties>
+            <property name="spawn_rate" value="3" type="int"/>
+            <property name="title" value="first"/>
+            <property name="title" value="second"/>
+        </properties>
+        <layer>
+            <properties>
+                <property name="parallax" value="0.5" type="float"/>
+            </properties>
+        </layer>
+    </map>"#).unwrap();
+
+    assert_eq!("3", map.property("spawn_rate").unwrap().value());
+    assert_eq!("first", map.property("title").unwrap().value());
+    assert!(map.property("Title").is_none());
+    assert!(map.property("parallax").is_none());
+
+    let layer = map.layers().next().unwrap();
+    assert_eq!(PropertyType::Float, layer.property("parallax").unwrap().property_type());
+}
+
+#[test]
+fn expect_property_collection_to_be_queryable_by_name() {
+    let mut properties = PropertyCollection::new();
+    assert!(!properties.contains("a"));
+    properties.push(Property::new("a", "1", PropertyType::Int));
+    properties.push(Property::new("a", "2", PropertyType::Int));
+    assert!(properties.contains("a"));
+    assert_eq!("1", properties.get("a").unwrap().value());
+    assert!(properties.get("b").is_none());
+}
+
+#[test]
+fn expect_typed_property_accessors_to_check_the_declared_type() {
+    let int = Property::new("a", "-12", PropertyType::Int);
+    assert_eq!(Some(-12), int.as_int());
+    assert_eq!(None, int.as_float());
+    assert_eq!(None, int.as_string());
+
+    let float = Property::new("a", "0.25", PropertyType::Float);
+    assert_eq!(Some(0.25), float.as_float());
+    assert_eq!(None, float.as_int());
+
+    assert_eq!(Some(true), Property::new("a", "true", PropertyType::Bool).as_bool());
+    assert_eq!(Some(false), Property::new("a", "0", PropertyType::Bool).as_bool());
+    assert_eq!(None, Property::new("a", "yes", PropertyType::Bool).as_bool());
+    assert_eq!(None, Property::new("a", "1", PropertyType::Int).as_bool());
+
+    assert_eq!(Some(Color::rgb(1, 2, 3)), Property::new("a", "#ff010203", PropertyType::Color).as_color());
+    assert_eq!(None, Property::new("a", "", PropertyType::Color).as_color());
+    assert_eq!(None, Property::new("a", "#ff010203", PropertyType::String).as_color());
+
+    assert_eq!(Some("text"), Property::new("a", "text", PropertyType::String).as_string());
+    assert_eq!(Some(""), Property::new("a", "", PropertyType::String).as_string());
+    assert_eq!(None, Property::new("a", "", PropertyType::Int).as_int());
+    assert_eq!(None, Property::new("a", "", PropertyType::Float).as_float());
+    assert_eq!(None, Property::new("a", "1.5", PropertyType::Int).as_int());
+}
+
+#[test]
+fn after_reading_valid_xml_with_properties_expect_properties_to_convert_to_value_map() {
+    let map = read_map(r##"<map>
+        <properties>
+            <property name="bool" type="bool" value="true"/>
+            <property name="int" type="int" value="-3"/>
+            <property name="float" type="float" value="1.5"/>
+            <property name="string" value="text"/>
+            <property name="color" type="color" value="#ff102030"/>
+            <property name="unset_color" type="color" value=""/>
+            <property name="file" type="file" value="a.png"/>
+            <property name="object" type="object" value="12"/>
+            <property name="class" type="class" propertytype="Spawn">
+                <properties>
+                    <property name="count" type="int" value="2"/>
+                </properties>
+            </property>
+            <property name="int" type="int" value="4"/>
+        </properties>
+    </map>"##).unwrap();
+
+    let values = map.properties().to_map().unwrap();
+    assert_eq!(9, values.len());
+    assert_eq!(Some(&PropertyValue::Bool(true)), values.get("bool"));
+    // The second `int` property is dropped while reading.
+    assert_eq!(Some(&PropertyValue::Int(-3)), values.get("int"));
+    assert_eq!(Some(&PropertyValue::Float(1.5)), values.get("float"));
+    assert_eq!(Some(&PropertyValue::String("text".to_string())), values.get("string"));
+    assert_eq!(Some(&PropertyValue::Color(Color::rgb(16, 32, 48))), values.get("color"));
+    assert_eq!(Some(&PropertyValue::Color(Color::argb(0, 0, 0, 0))), values.get("unset_color"));
+    assert_eq!(Some(&PropertyValue::File("a.png".to_string())), values.get("file"));
+    assert_eq!(Some(&PropertyValue::Object(12)), values.get("object"));
+
+    let class = map.property("class").unwrap();
+    assert_eq!("Spawn", class.custom_type());
+    let mut members = HashMap::new();
+    members.insert("count".to_string(), PropertyValue::Int(2));
+    assert_eq!(Some(&PropertyValue::Class(members)), values.get("class"));
+}
+
+#[test]
+fn when_converting_properties_with_unparsable_values_expect_property_value_error() {
+    let properties: PropertyCollection = vec![
+        Property::new("ok", "1", PropertyType::Int),
+        Property::new("bad", "1.5", PropertyType::Int),
+    ].into_iter().collect();
+    assert_matches!(properties.to_map(), Err(Error::BadPropertyValue { ref name, .. }) if name == "bad");
+
+    let properties: PropertyCollection = vec![Property::new("empty", "", PropertyType::Float)].into_iter().collect();
+    assert_matches!(properties.to_map(), Err(Error::BadPropertyValue { .. }));
+}
+
 #[test]
 fn when_reading_map_xml_with_invalid_attribute_expect_attribute_error() {
-    let result = Map::from_str(r#"<map bad=""></map>"#);
-    assert_matches!(result, Err(Error::UnknownAttribute(..)));
+    let result = read_map(r#"<map bad=""></map>"#);
+    assert_matches!(result, Err(Error::UnknownAttribute { ref element, ref attribute })
+                    if element == "map" && attribute == "bad");
+}
+
+#[test]
+fn when_reading_unknown_attributes_expect_reader_options_policy_on_every_element() {
+    let xml = r#"<map version="1.0" nextlayerid="4">
+                   <tileset firstgid="1" name="t" tilewidth="8" tileheight="8" tilerendersize="grid"/>
+                   <layer name="Ground" id="3"/>
+                   <objectgroup><object id="1" x="2" template="door.tx"/></objectgroup>
+                 </map>"#;
+    assert_matches!(read_map(xml), Err(Error::UnknownAttribute { ref element, .. }) if element == "map");
+
+    let options = lenient_options().with_unknown_attributes(UnknownPolicy::Ignore);
+    let map = Map::from_str_with(xml, &options).unwrap();
+    assert_eq!(Some(2.0), map.object_groups().next().and_then(|group| group.objects().next()).map(Object::x));
+    assert!(map.unknown_attributes().is_empty());
+
+    let options = lenient_options().with_unknown_attributes(UnknownPolicy::Collect);
+    let map = Map::from_str_with(xml, &options).unwrap();
+    let unknown: Vec<_> = map.unknown_attributes()
+        .iter()
+        .map(|unknown| (unknown.element.as_str(), unknown.attribute.as_str()))
+        .collect();
+    assert_eq!(vec![("map", "nextlayerid"), ("tileset", "tilerendersize"), ("layer", "id"), ("object", "template")],
+               unknown);
+
+    let tileset = Tileset::from_str_with(r#"<tileset name="t"><tile id="0" type="Wall"/></tileset>"#, &options).unwrap();
+    assert_eq!(1, tileset.unknown_attributes().len());
+    assert_eq!(Some(0), tileset.tiles().next().map(Tile::id));
+}
+
+#[test]
+fn when_reading_map_over_dimension_limit_expect_limit_error() {
+    let xml = r#"<map width="1000000" height="1000000"><layer width="10" height="10"/></map>"#;
+    assert!(read_map(xml).is_ok());
+
+    let limits = Limits { max_dimension: Some(1000), ..Limits::default() };
+    let options = lenient_options().with_limits(limits);
+    assert_matches!(Map::from_str_with(xml, &options), Err(Error::LimitExceeded { ref limit, max: 1000 }) if limit == "max_dimension");
+    let xml = r#"<map width="10" height="10"><layer width="10" height="5000"/></map>"#;
+    assert_matches!(Map::from_str_with(xml, &options), Err(Error::LimitExceeded { ref limit, .. }) if limit == "max_dimension");
+}
+
+#[test]
+fn when_reading_map_over_object_and_property_limits_expect_limit_error() {
+    let xml = r#"<map>
+                   <properties><property name="a" value="1"/></properties>
+                   <objectgroup><object id="1"/><object id="2"/></objectgroup>
+                   <objectgroup><object id="3"><properties><property name="b" value="2"/></properties></object></objectgroup>
+                 </map>"#;
+    let limits = Limits { max_objects: Some(3), max_properties: Some(2), ..Limits::default() };
+    assert!(Map::from_str_with(xml, &lenient_options().with_limits(limits)).is_ok());
+
+    let options = lenient_options().with_limits(Limits { max_objects: Some(2), ..limits });
+    assert_matches!(Map::from_str_with(xml, &options), Err(Error::LimitExceeded { ref limit, .. }) if limit == "max_objects");
+    let options = lenient_options().with_limits(Limits { max_properties: Some(1), ..limits });
+    assert_matches!(Map::from_str_with(xml, &options), Err(Error::LimitExceeded { ref limit, .. }) if limit == "max_properties");
+}
+
+#[test]
+fn when_reading_layer_data_over_limits_expect_limit_error() {
+    let csv = r#"<map><layer width="2" height="2"><data encoding="csv">1,2,3,4</data></layer></map>"#;
+    let options = lenient_options().with_limits(Limits { max_data_length: Some(6), ..Limits::default() });
+    assert_matches!(Map::from_str_with(csv, &options), Err(Error::LimitExceeded { ref limit, .. }) if limit == "max_data_length");
+
+    let options = lenient_options().with_limits(Limits { max_layer_bytes: Some(12), ..Limits::default() });
+    let map = Map::from_str_with(csv, &options).unwrap();
+    let data = map.layers().next().unwrap().data().unwrap();
+    assert_matches!(data.decode(), Err(Error::LimitExceeded { ref limit, max: 12 }) if limit == "max_layer_bytes");
+
+    // 16 empty tiles, that is 64 bytes once inflated.
+    let zlib = r#"<map><layer width="4" height="4">
+                    <data encoding="base64" compression="zlib">eJxjYKAMAAAAQAAB</data>
+                  </layer></map>"#;
+    let map = Map::from_str_with(zlib, &options).unwrap();
+    let data = map.layers().next().unwrap().data().unwrap();
+    assert_matches!(data.decode(), Err(Error::LimitExceeded { ref limit, .. }) if limit == "max_layer_bytes");
+
+    let options = lenient_options().with_limits(Limits { max_layer_bytes: Some(64), ..Limits::default() });
+    let map = Map::from_str_with(zlib, &options).unwrap();
+    assert_eq!(16, map.layers().next().unwrap().data().unwrap().decode().unwrap().len());
+
+    // Tiles listed without encoding are counted while reading.
+    let xml = r#"<map><layer width="2" height="2"><data>
+                   <tile gid="1"/><tile gid="2"/><tile gid="3"/><tile gid="4"/>
+                 </data></layer></map>"#;
+    let options = lenient_options().with_limits(Limits { max_layer_bytes: Some(12), ..Limits::default() });
+    assert_matches!(Map::from_str_with(xml, &options), Err(Error::LimitExceeded { ref limit, max: 12 }) if limit == "max_layer_bytes");
+    let options = lenient_options().with_limits(Limits { max_dimension: Some(2), ..Limits::default() });
+    assert!(Map::from_str_with(xml, &options).is_ok());
+    let xml = xml.replace(r#"<tile gid="4"/>"#, r#"<tile gid="4"/><tile gid="5"/>"#);
+    assert_matches!(Map::from_str_with(&xml, &options), Err(Error::LimitExceeded { ref limit, max: 4 }) if limit == "max_dimension");
+}
+
+#[test]
+fn when_reading_lenient_map_expect_diagnostics_for_skipped_and_deprecated_content() {
+    let xml = r#"<map nextlayerid="2">
+        <tileset name="t"><terraintypes><terrain name="Grass" tile="0"/></terraintypes></tileset>
+        <layer name="Empty"/>
+    </map>"#;
+    let options = lenient_options().with_unknown_attributes(UnknownPolicy::Collect);
+    let mut reader = TmxReader::with_options(xml.as_bytes(), options);
+    assert!(reader.read_map().is_ok());
+    let diagnostics = reader.take_diagnostics();
+    let found: Vec<_> = diagnostics.iter().map(|d| (d.severity, d.line)).collect();
+    assert_eq!(vec![(Severity::Warning, 1), (Severity::Info, 2), (Severity::Info, 3)], found);
+    assert!(diagnostics[0].message.contains("nextlayerid"));
+    assert!(diagnostics[2].message.contains("Empty"));
+    assert!(reader.take_diagnostics().is_empty());
+
+    let options = lenient_options().with_unknown_attributes(UnknownPolicy::Ignore);
+    let mut reader = TmxReader::with_options(xml.as_bytes(), options);
+    assert!(reader.read_map().is_ok());
+    assert_eq!(2, reader.take_diagnostics().len());
+}
+
+#[test]
+fn when_reading_deeply_nested_properties_expect_depth_error() {
+    let depth = 100_000;
+    let mut xml = String::from("<map>");
+    for _ in 0..depth {
+        xml.push_str(r#"<properties><property name="p" type="class">"#);
+    }
+    for _ in 0..depth {
+        xml.push_str("</property></properties>");
+    }
+    xml.push_str("</map>");
+    assert_matches!(read_map(&xml), Err(Error::TooDeep(256)));
+
+    let nested = r#"<map><properties><property name="p" type="class"><properties>
+        <property name="q" value="1"/></properties></property></properties></map>"#;
+    assert!(Map::from_str_with(nested, &lenient_options().with_max_depth(5)).is_ok());
+    assert_matches!(Map::from_str_with(nested, &lenient_options().with_max_depth(4)), Err(Error::TooDeep(4)));
+}
+
+#[test]
+fn when_reading_duplicate_property_names_expect_reader_options_policy() {
+    let xml = r#"<map>
+        <properties>
+            <property name="speed" value="1"/>
+            <property name="title" value="Level 1"/>
+            <property name="speed" value="2"/>
+        </properties>
+    </map>"#;
+    let values = |map: &Map| map.properties().map(|p| (p.name().to_string(), p.value().to_string())).collect::<Vec<_>>();
+
+    let map = read_map(xml).unwrap();
+    assert_eq!(vec![("speed".to_string(), "1".to_string()), ("title".to_string(), "Level 1".to_string())], values(&map));
+
+    let options = lenient_options().with_duplicate_properties(DuplicatePolicy::KeepLast);
+    let map = Map::from_str_with(xml, &options).unwrap();
+    assert_eq!(vec![("speed".to_string(), "2".to_string()), ("title".to_string(), "Level 1".to_string())], values(&map));
+
+    let options = lenient_options().with_duplicate_properties(DuplicatePolicy::Strict);
+    assert_matches!(Map::from_str_with(xml, &options),
+                    Err(Error::DuplicateProperty { ref name, line: 5, .. }) if name == "speed");
+
+    // Names are only compared within the same block.
+    let nested = r#"<map><properties>
+        <property name="speed" value="1"/>
+        <property name="boost" type="class"><properties><property name="speed" value="2"/></properties></property>
+    </properties></map>"#;
+    assert!(Map::from_str_with(nested, &options).is_ok());
+}
+
+#[test]
+fn after_dedup_of_property_collection_expect_first_of_each_name() {
+    let mut properties: PropertyCollection = vec![
+        Property::new("a", "1", PropertyType::Int),
+        Property::new("b", "2", PropertyType::Int),
+        Property::new("a", "3", PropertyType::Int),
+    ].into_iter().collect();
+    assert_eq!(Some(&PropertyValue::Int(1)), properties.to_map().unwrap().get("a"));
+    assert_eq!(Some("1"), properties.get("a").map(Property::value));
+    properties.dedup();
+    let values: Vec<_> = properties.iter().map(|p| (p.name(), p.value())).collect();
+    assert_eq!(vec![("a", "1"), ("b", "2")], values);
+}
+
+#[test]
+fn when_nothing_is_set_expect_compact_properties_and_tiles() {
+    assert_eq!(std::mem::size_of::<PropertyCollection>(), std::mem::size_of::<usize>());
+    assert!(std::mem::size_of::<Tile>() <= 96);
+
+    let mut properties = PropertyCollection::new();
+    assert!(properties.is_empty());
+    properties.push(Property::new("a", "1", PropertyType::Int));
+    assert_eq!(properties.len(), 1);
+    properties.remove("a");
+    assert!(properties.is_empty());
+    assert_eq!(properties.iter().count(), 0);
+}
+
+#[test]
+fn when_skipping_unknown_element_expect_whole_subtree_to_be_consumed() {
+    let xml = r#"<map>
+                   <objectgroup name="Zones">
+                     <wangsets><objectgroup name="Inner"/><object id="7"/></wangsets>
+                     <object id="1"/>
+                   </objectgroup>
+                   <group name="Background"><layer name="Sky"/></group>
+                 </map>"#;
+    let options = lenient_options().with_unknown_elements(UnknownPolicy::Collect);
+    let map = Map::from_str_with(xml, &options).unwrap();
+    let group = map.object_groups().next().unwrap();
+    assert_eq!(("Zones", 1), (group.name(), group.objects().count()));
+    assert_eq!(Some(1), group.objects().next().map(Object::id));
+    assert_eq!(1, map.unknown_elements().len());
+    assert_eq!(Some("Sky"), map.layers().next().map(Layer::name));
+}
+
+#[test]
+fn when_reading_bad_attribute_value_in_lenient_mode_expect_error_anyway() {
+    let options = lenient_options().with_unknown_attributes(UnknownPolicy::Ignore);
+    let result = Map::from_str_with(r#"<map width="wide"/>"#, &options);
+    assert_matches!(result, Err(Error::InvalidAttribute { .. }));
+}
+
+#[test]
+fn when_reading_nested_element_with_bad_attribute_expect_element_and_attribute_in_error() {
+    let result = read_map(r#"<map><layer name="Ground" width="wide"/></map>"#);
+    assert_matches!(result, Err(Error::InvalidAttribute { ref element, ref attribute, ref cause })
+                    if element == "layer" && attribute == "width" && matches!(**cause, Error::InvalidNumber(..)));
+    let message = result.unwrap_err().to_string();
+    assert_eq!("Invalid `width` attribute on element `layer`: Invalid number: `wide`", message);
+
+    let result = read_map(r#"<map><objectgroup><object x="1" radius="2"/></objectgroup></map>"#);
+    assert_eq!("Unknown attribute `radius` on element `object`", result.unwrap_err().to_string());
 }
 
 #[test]
 fn when_reading_map_xml_with_invalid_staggeraxis_expect_axis_error() {
-    let result = Map::from_str(r#"<map staggeraxis="bad"></map>"#);
-    assert_matches!(result, Err(Error::BadAxis(..)));
+    let result = read_map(r#"<map staggeraxis="bad"></map>"#);
+    assert_matches!(result, Err(Error::InvalidAttribute { ref cause, .. }) if matches!(**cause, Error::BadAxis(..)));
+}
+
+#[test]
+fn when_reading_visible_attributes_as_words_expect_booleans() {
+    let map = read_map(r#"<map>
+        <layer visible="false"/>
+        <layer visible="true"/>
+        <imagelayer visible="false"/>
+        <objectgroup visible="false">
+            <object id="1" visible="false"/>
+            <object id="2" visible="1"/>
+        </objectgroup>
+    </map>"#).unwrap();
+    let visible: Vec<_> = map.layers().map(|layer| layer.is_visible()).collect();
+    assert_eq!(vec![false, true], visible);
+    assert!(!map.image_layers().next().unwrap().is_visible());
+    let group = map.object_groups().next().unwrap();
+    assert!(!group.is_visible());
+    let visible: Vec<_> = group.objects().map(|object| object.is_visible()).collect();
+    assert_eq!(vec![false, true], visible);
+}
+
+#[test]
+fn when_reading_map_xml_with_invalid_visible_attribute_expect_boolean_error() {
+    let result = read_map(r#"<map><layer visible="2"/></map>"#);
+    assert_matches!(result, Err(Error::InvalidAttribute { ref cause, .. }) if matches!(**cause, Error::BadBoolean(..)));
+    let result = read_map(r#"<map><objectgroup><object visible="yes"/></objectgroup></map>"#);
+    assert_matches!(result, Err(Error::InvalidAttribute { ref cause, .. }) if matches!(**cause, Error::BadBoolean(..)));
+}
+
+#[test]
+fn when_reading_opacity_attributes_expect_values_clamped_to_unit_range() {
+    let map = read_map(r#"<map>
+        <layer opacity="0"/>
+        <layer opacity="1"/>
+        <layer opacity="0.5"/>
+        <layer opacity="7"/>
+        <layer opacity="-1"/>
+        <imagelayer opacity="1.5"/>
+        <objectgroup opacity="-0.25"/>
+    </map>"#).unwrap();
+    let opacities: Vec<_> = map.layers().map(|layer| layer.opacity()).collect();
+    assert_eq!(vec![0.0, 1.0, 0.5, 1.0, 0.0], opacities);
+    assert_eq!(1.0, map.image_layers().next().unwrap().opacity());
+    assert_eq!(0.0, map.object_groups().next().unwrap().opacity());
+}
+
+#[test]
+fn when_making_opacities_expect_out_of_range_values_rejected_or_clamped() {
+    assert_eq!(0.25, Opacity::new(0.25).unwrap().value());
+    assert_matches!(Opacity::new(255.0), Err(Error::BadOpacity(_)));
+    assert_matches!(Opacity::new(f64::NAN), Err(Error::BadOpacity(_)));
+    assert_eq!(Opacity::OPAQUE, Opacity::new_clamped(255.0));
+    assert_eq!(Opacity::TRANSPARENT, Opacity::new_clamped(-3.0));
+    assert_eq!(Opacity::OPAQUE, Opacity::new_clamped(f64::NAN));
+    assert!(Opacity::default().is_opaque());
+    assert!(Opacity::new_clamped(0.0).is_transparent());
+    assert_eq!(0.5, f64::from(Opacity::new_clamped(0.5)));
+}
+
+#[test]
+fn when_reading_map_xml_with_non_finite_opacity_expect_opacity_error() {
+    for xml in &[r#"<map><layer opacity="NaN"/></map>"#,
+                 r#"<map><imagelayer opacity="inf"/></map>"#,
+                 r#"<map><objectgroup opacity="-inf"/></map>"#] {
+        let result = read_map(xml);
+        assert_matches!(result, Err(Error::InvalidAttribute { ref cause, .. }) if matches!(**cause, Error::BadOpacity(..)));
+    }
+    let result = read_map(r#"<map><layer opacity="half"/></map>"#);
+    assert_matches!(result, Err(Error::InvalidAttribute { ref cause, .. }) if matches!(**cause, Error::InvalidNumber(..)));
+}
+
+#[test]
+fn when_reading_map_xml_without_required_attributes_expect_missing_attribute_error() {
+    let result = Map::from_str(r#"<map width="10" height="10" tilewidth="16"/>"#);
+    assert_matches!(result, Err(Error::MissingAttribute { ref element, ref attribute })
+                    if element == "map" && attribute == "tileheight");
+
+    let result = Map::from_str(r#"<map width="10" height="10" tilewidth="16" tileheight="16">
+        <tileset firstgid="1" source="tiles.tsx"/>
+        <tileset firstgid="5" name="embedded" tileheight="16"/>
+    </map>"#);
+    assert_matches!(result, Err(Error::MissingAttribute { ref element, ref attribute })
+                    if element == "tileset" && attribute == "tilewidth");
+
+    let map = Map::from_str_with("<map/>", &lenient_options()).unwrap();
+    assert_eq!((0, 0), (map.width(), map.tile_width()));
 }
 
 #[test]
 fn when_reading_map_xml_with_invalid_staggerindex_expect_index_error() {
-    let result = Map::from_str(r#"<map staggerindex="bad"></map>"#);
-    assert_matches!(result, Err(Error::BadIndex(..)));
+    let result = read_map(r#"<map staggerindex="bad"></map>"#);
+    assert_matches!(result, Err(Error::InvalidAttribute { ref cause, .. }) if matches!(**cause, Error::BadIndex(..)));
 }
 
 #[test]
 fn when_reading_map_xml_with_invalid_orientation_expect_orientation_error() {
-    let result = Map::from_str(r#"<map orientation="bad"></map>"#);
-    assert_matches!(result, Err(Error::BadOrientation(..)));
+    let result = read_map(r#"<map orientation="bad"></map>"#);
+    assert_matches!(result, Err(Error::InvalidAttribute { ref cause, .. }) if matches!(**cause, Error::BadOrientation(..)));
 }
 
 #[test]
 fn when_reading_map_xml_with_invalid_render_order_expect_render_order_error() {
-    let result = Map::from_str(r#"<map renderorder="bad"></map>"#);
-    assert_matches!(result, Err(Error::BadRenderOrder(..)));
+    let result = read_map(r#"<map renderorder="bad"></map>"#);
+    assert_matches!(result, Err(Error::InvalidAttribute { ref cause, .. }) if matches!(**cause, Error::BadRenderOrder(..)));
+}
+
+#[test]
+fn when_formatting_attribute_enums_expect_strings_to_parse_back() {
+    for &axis in &[Axis::X, Axis::Y] {
+        assert_eq!(axis, Axis::from_str(axis.as_str()).unwrap());
+    }
+    for &index in &[Index::Even, Index::Odd] {
+        assert_eq!(index, Index::from_str(index.as_str()).unwrap());
+    }
+    for &orientation in &[Orientation::Orthogonal, Orientation::Isometric,
+                          Orientation::Staggered, Orientation::Hexagonal] {
+        assert_eq!(orientation, Orientation::from_str(orientation.as_str()).unwrap());
+    }
+    for &render_order in &[RenderOrder::RightDown, RenderOrder::RightUp,
+                           RenderOrder::LeftDown, RenderOrder::LeftUp] {
+        assert_eq!(render_order, RenderOrder::from_str(render_order.as_str()).unwrap());
+    }
+    for &draw_order in &[DrawOrder::TopDown, DrawOrder::Index] {
+        assert_eq!(draw_order, DrawOrder::from_str(draw_order.as_str()).unwrap());
+    }
+    for &property_type in &[PropertyType::Bool, PropertyType::Color, PropertyType::File,
+                            PropertyType::Float, PropertyType::Int, PropertyType::String,
+                            PropertyType::Object, PropertyType::Class] {
+        assert_eq!(property_type, PropertyType::from_str(property_type.as_str()).unwrap());
+    }
+
+    assert_eq!("right-down", RenderOrder::RightDown.to_string());
+    assert_eq!("topdown", format!("{}", DrawOrder::TopDown));
+    assert_eq!("staggered", Orientation::Staggered.to_string());
 }
 
 #[test]
 fn when_reading_invalid_xml_element_expect_error() {
-    let result = Map::from_str("<nomap/>");
+    let result = read_map("<nomap/>");
+    assert_matches!(result, Err(Error::BadXml));
+}
+
+#[test]
+fn when_reading_xml_with_root_inside_other_element_expect_error() {
+    let result = read_map("<wrapper><map/></wrapper>");
+    assert_matches!(result, Err(Error::BadXml));
+    let result = Tileset::from_str("<map><tileset/></map>");
     assert_matches!(result, Err(Error::BadXml));
 }
 
+#[test]
+fn when_reading_map_xml_with_two_root_elements_expect_trailing_content_error() {
+    let result = read_map(r#"<map/><map width="999"/>"#);
+    assert_matches!(result, Err(Error::TrailingContent(..)));
+    let result = Tileset::from_str(r#"<tileset/><tileset name="other"/>"#);
+    assert_matches!(result, Err(Error::TrailingContent(..)));
+}
+
+#[test]
+fn when_reading_map_xml_with_trailing_text_expect_error() {
+    let result = read_map("<map/>oops");
+    assert_matches!(result, Err(Error::Xml(..)));
+    let result = read_map("<map/><layer/>");
+    assert_matches!(result, Err(Error::TrailingContent(..)));
+    let result = read_map("<map/>\n  <!-- comment -->\n");
+    assert!(result.is_ok());
+}
+
 #[test]
 fn when_reading_map_xml_with_invalid_background_color_expect_invalid_color_error() {
-    let result = Map::from_str(r#"<map backgroundcolor="bad"/>"#);
-    assert_matches!(result, Err(Error::InvalidColor(..)));
+    let result = read_map(r#"<map backgroundcolor="bad"/>"#);
+    assert_matches!(result, Err(Error::InvalidAttribute { ref cause, .. }) if matches!(**cause, Error::InvalidColor(..)));
 }
 
 #[test]
@@ -204,322 +712,2654 @@ fn after_reading_xml_with_layers_expect_map_to_be_iterable_over_layers() {
 }
 
 #[test]
-fn after_reading_xml_with_image_layers_expect_map_to_be_iterable_over_image_layers() {
-    let map = get_map_with_image_layers();
-    assert_eq!(7, map.image_layers().count());
-
-    let mut layers = map.image_layers();
-    let layer1 = layers.next().unwrap();
-    assert_eq!("layer1_name", layer1.name());
-    assert_eq!(1.0, layer1.opacity());
-    assert!(layer1.is_visible());
-    assert_eq!(0, layer1.offset_x());
-    assert_eq!(0, layer1.offset_y());
-
-    let layer2 = layers.next().unwrap();
-    assert_eq!(0.0, layer2.opacity());
+fn when_reading_data_not_matching_its_encoding_expect_it_ignored_with_diagnostics() {
+    let xml = r#"<map>
+        <layer name="csv"><data encoding="csv">1,2<tile gid="3"/><tile gid="4"/></data></layer>
+        <layer name="xml"><data><tile gid="3"/>1,2<tile gid="4"/>5</data></layer>
+    </map>"#;
+    let mut reader = TmxReader::with_options(xml.as_bytes(), lenient_options());
+    let map = reader.read_map().unwrap();
+    let mut layers = map.layers();
+    let csv = layers.next().unwrap().data().unwrap();
+    assert_eq!((Some("1,2"), 0), (csv.raw_content(), csv.tiles().count()));
+    assert_eq!(&[1, 2], csv.decode().unwrap());
+    let xml = layers.next().unwrap().data().unwrap();
+    assert_eq!((None, 2), (xml.raw_content(), xml.tiles().count()));
+    assert_eq!(&[3, 4], xml.decode().unwrap());
+
+    let found: Vec<_> = reader.take_diagnostics().iter().map(|d| (d.severity, d.line)).collect();
+    assert_eq!(vec![(Severity::Warning, 2), (Severity::Warning, 3)], found);
+}
 
-    let layer3 = layers.next().unwrap();
-    assert!(!layer3.is_visible());
+#[test]
+fn after_reading_layer_data_expect_every_encoding_to_decode_to_the_same_gids() {
+    let map = read_map(r#"<map>
+        <layer><data><tile gid="1"/><tile gid="2"/><tile gid="0"/><tile gid="2147483651"/></data></layer>
+        <layer><data encoding="csv">
+1,2,
+0,2147483651
+</data></layer>
+        <layer><data encoding="base64">
+            AQAAAAIAAAAAAAAAAwAAgA==
+        </data></layer>
+        <layer><data encoding="base64" compression="zlib">eJxjZGBgYGKAAGYGhgYAAMQAhw==</data></layer>
+        <layer><data encoding="base64" compression="gzip">H4sIAAAAAAACA2NkYGBgYoAAZgaGBgCVaOVREAAAAA==</data></layer>
+    </map>"#).unwrap();
 
-    let layer4 = layers.next().unwrap();
-    assert_eq!(1, layer4.offset_x());
-    assert_eq!(2, layer4.offset_y());
+    let mut buffer = vec![7; 10];
+    for layer in map.layers() {
+        let data = layer.data().unwrap();
+        data.decode_into(&mut buffer).unwrap();
+        assert_eq!(&[1, 2, 0, 0x8000_0003][..], &buffer[..]);
+        assert_eq!(&[1, 2, 0, 0x8000_0003][..], data.decode().unwrap());
+        assert_eq!(data.decode().unwrap().as_ptr(), data.decode().unwrap().as_ptr());
+        buffer.push(7);
+        data.decode_into(&mut buffer).unwrap();
+        assert_eq!(data.decode().unwrap(), &buffer[..]);
+    }
+}
 
-    let layer5 = layers.next().unwrap();
-    assert_eq!(1, layer5.properties().count());
+#[test]
+fn when_decoding_unsupported_layer_data_expect_errors() {
+    let map = get_map_with_layers();
+    let data = map.layers().last().unwrap().data().unwrap();
+    assert_matches!(data.decode(), Err(Error::InvalidData(_)));
+
+    let map = read_map(r#"<map>
+        <layer><data encoding="hex">00</data></layer>
+        <layer><data encoding="base64" compression="lzma">AAAAAA==</data></layer>
+        <layer><data encoding="base64">AAAA</data></layer>
+        <layer><data encoding="csv">1,x</data></layer>
+    </map>"#).unwrap();
+    let mut layers = map.layers();
+    assert_matches!(layers.next().unwrap().data().unwrap().decode(), Err(Error::BadEncoding(ref e)) if e == "hex");
+    assert_matches!(layers.next().unwrap().data().unwrap().decode(), Err(Error::BadCompression(ref e)) if e == "lzma");
+    assert_matches!(layers.next().unwrap().data().unwrap().decode(), Err(Error::InvalidData(_)));
+    let data = layers.next().unwrap().data().unwrap();
+    assert_matches!(data.decode(), Err(Error::InvalidNumber(_)));
+    let mut buffer = vec![7];
+    assert_matches!(data.decode_into(&mut buffer), Err(Error::InvalidNumber(_)));
+    assert!(buffer.is_empty());
+}
 
-    let layer6 = layers.next().unwrap();
-    assert!(layer6.image().is_some());
+const CHUNKED_MAP: &str = r#"<map width="4" height="4" tilewidth="16" tileheight="16" infinite="1">
+    <tileset firstgid="1" name="t" tilewidth="16" tileheight="16" tilecount="8"/>
+    <layer name="Ground" width="4" height="4">
+        <data encoding="csv">
+            <chunk x="-2" y="0" width="2" height="2">
+1,2,
+3,4
+</chunk>
+            <chunk x="2" y="2" width="2" height="2">
+5,0,
+0,6
+</chunk>
+        </data>
+    </layer>
+</map>"#;
 
-    let layer7 = layers.next().unwrap();
-    assert_eq!(1, layer7.x());
-    assert_eq!(2, layer7.y());
-    assert_eq!(3, layer7.width());
-    assert_eq!(4, layer7.height());
+#[test]
+fn after_reading_chunked_data_expect_chunks_and_their_bounds_decoded() {
+    let map = read_map(CHUNKED_MAP).unwrap();
+    assert!(map.is_infinite());
+    let data = map.layers().next().unwrap().data().unwrap();
+    assert!(data.is_chunked());
+    assert_eq!((None, 0), (data.raw_content(), data.tiles().count()));
+    let chunks: Vec<_> = data.chunks().map(|c| (c.x(), c.y(), c.width(), c.height(), c.gids().to_vec())).collect();
+    assert_eq!(vec![(-2, 0, 2, 2, vec![1, 2, 3, 4]), (2, 2, 2, 2, vec![5, 0, 0, 6])], chunks);
+    assert_eq!(Some((-2, 0, 6, 4)), data.chunk_bounds());
+    assert_eq!(&[1, 2, 0, 0, 0, 0,
+                 3, 4, 0, 0, 0, 0,
+                 0, 0, 0, 0, 5, 0,
+                 0, 0, 0, 0, 0, 6][..], data.decode().unwrap());
+    let mut buffer = vec![7];
+    data.decode_into(&mut buffer).unwrap();
+    assert_eq!(data.decode().unwrap(), &buffer[..]);
+
+    let resolved: Vec<_> = map.resolved_tiles(map.layers().next().unwrap()).unwrap().map(|t| (t.x, t.y)).collect();
+    assert_eq!(vec![(0, 0), (1, 0), (0, 1), (1, 1), (4, 2), (5, 3)], resolved);
 }
 
 #[test]
-fn after_reading_xml_with_object_groups_expect_map_to_be_iterable_over_object_groups() {
-    let map = get_map_with_objectgroups();
-    assert_eq!(3, map.object_groups().count());
+fn after_reading_chunks_expect_every_encoding_to_decode_to_the_same_chunks() {
+    let map = read_map(r#"<map>
+        <layer><data><chunk x="0" y="-2" width="2" height="2">
+            <tile gid="1"/><tile gid="2"/><tile gid="3"/><tile gid="4"/>
+        </chunk></data></layer>
+        <layer><data encoding="base64"><chunk x="0" y="-2" width="2" height="2">
+            AQAAAAIAAAADAAAABAAAAA==
+        </chunk></data></layer>
+        <layer><data encoding="base64" compression="zlib">
+            <chunk x="0" y="-2" width="2" height="2">eJxjZGBgYAJiZiBmAWIAAGAACw==</chunk>
+        </data></layer>
+    </map>"#).unwrap();
+    for layer in map.layers() {
+        let chunk = layer.data().unwrap().chunks().next().unwrap();
+        assert_eq!((0, -2, &[1, 2, 3, 4][..]), (chunk.x(), chunk.y(), chunk.gids()));
+    }
+}
 
-    let mut object_groups = map.object_groups();
-    let group1 = object_groups.next().unwrap();
-    assert_eq!("some_name", group1.name());
-    assert_eq!(1.0, group1.opacity());
-    assert!(group1.is_visible());
-    assert_eq!(0, group1.offset_x());
-    assert_eq!(0, group1.offset_y());
-    assert_eq!(0, group1.x());
-    assert_eq!(0, group1.y());
-    assert_eq!(0, group1.width());
-    assert_eq!(0, group1.height());
-    assert_eq!(DrawOrder::TopDown, group1.draw_order());
-    assert_eq!(1, group1.properties().count());
+#[test]
+fn when_writing_chunked_data_expect_chunks_written_with_the_encoding() {
+    let map = read_map(CHUNKED_MAP).unwrap();
+    let written = map.to_xml_string().unwrap();
+    assert!(written.contains("infinite=\"1\""), "{}", written);
+    assert!(written.contains("<chunk x=\"-2\" y=\"0\" width=\"2\" height=\"2\">\n1,2,\n3,4\n</chunk>"), "{}", written);
+    assert_eq!(map, read_map(&written).unwrap());
+
+    for encoding in vec![DataEncoding::Base64 { compression: Compression::Zlib }, DataEncoding::Csv] {
+        let options = WriteOptions::new().with_encoding(encoding);
+        let written = map.to_xml_string_with_options(&options).unwrap();
+        let read = read_map(&written).unwrap();
+        let data = read.layers().next().unwrap().data().unwrap();
+        assert_eq!(Some(encoding), DataEncoding::from_attributes(data.encoding(), data.compression()));
+        assert!(map.layers().next().unwrap().data().unwrap().chunks().eq(data.chunks()));
+    }
 
-    let group2 = object_groups.next().unwrap();
-    assert_eq!(0.0, group2.opacity());
-    assert!(!group2.is_visible());
-    assert_eq!(1, group2.offset_x());
-    assert_eq!(2, group2.offset_y());
-    assert_eq!(3, group2.x());
-    assert_eq!(4, group2.y());
-    assert_eq!(5, group2.width());
-    assert_eq!(6, group2.height());
-    assert_eq!(DrawOrder::Index, group2.draw_order());
-    assert_eq!(None, group2.color());
+    let mut map = map;
+    map.layers_mut().next().unwrap().set_data(read_map(r#"<map><layer><data>
+        <chunk x="0" y="0" width="1" height="1"><tile gid="9"/></chunk>
+    </data></layer></map>"#).unwrap().into_layers().next().unwrap().into_data().unwrap());
+    let written = map.to_xml_string().unwrap();
+    assert!(written.contains("<chunk x=\"0\" y=\"0\" width=\"1\" height=\"1\">"), "{}", written);
+    assert_eq!(map, read_map(&written).unwrap());
+}
 
-    let group3 = object_groups.next().unwrap();
-    assert_eq!(Some(&Color(255, 0, 0, 0)), group3.color());
+#[test]
+fn when_reading_chunks_of_wrong_size_or_beside_other_content_expect_error_or_diagnostics() {
+    let xml = r#"<map><layer><data encoding="csv"><chunk x="0" y="0" width="2" height="2">1,2,3</chunk></data></layer></map>"#;
+    assert_matches!(read_map(xml), Err(Error::InvalidData(_)));
+
+    let xml = r#"<map>
+        <layer><data encoding="csv">1,2<chunk x="0" y="0" width="1" height="1">3</chunk>4</data></layer>
+    </map>"#;
+    let mut reader = TmxReader::with_options(xml.as_bytes(), lenient_options());
+    let map = reader.read_map().unwrap();
+    assert_eq!(&[3], map.layers().next().unwrap().data().unwrap().decode().unwrap());
+    let found: Vec<_> = reader.take_diagnostics().iter().map(|d| (d.severity, d.line)).collect();
+    assert_eq!(vec![(Severity::Warning, 2)], found);
 }
 
 #[test]
-fn after_reading_xml_with_objects_expect_object_groups_to_be_iterable_over_objects() {
-    let map = get_map_with_objects();
-    let group = map.object_groups().next().unwrap();
-    assert_eq!(6, group.objects().count());
+fn when_resolving_layer_tiles_expect_tileset_and_local_ids() {
+    let map = read_map(r#"<map width="3" height="2">
+        <tileset firstgid="1" tilecount="4"><tile id="1" probability="0.5"/></tileset>
+        <tileset firstgid="5" tilecount="4"/>
+        <layer width="3" height="2"><data encoding="csv">0,2,5,0,0,2147483656</data></layer>
+    </map>"#).unwrap();
+    let layer = map.layers().next().unwrap();
 
-    let mut objects = group.objects();
+    let tiles: Vec<_> = map.resolved_tiles(layer).unwrap().collect();
+    assert_eq!(3, tiles.len());
 
-    let object = objects.next().unwrap();
-    assert!(object.is_visible());
-    assert_eq!(None, object.gid());
-    assert_eq!(0, object.properties().count());
-    assert_eq!(None, object.shape());
+    assert_eq!((1, 0), (tiles[0].x, tiles[0].y));
+    assert_eq!(1, tiles[0].tileset.first_gid());
+    assert_eq!(1, tiles[0].local_id);
+    assert_eq!(Some(0.5), tiles[0].tile.and_then(Tile::probability));
 
-    let object = objects.next().unwrap();
-    assert_eq!(1, object.id());
-    assert_eq!("obj", object.name());
-    assert_eq!("ty", object.object_type());
-    assert_eq!(1.0, object.x());
-    assert_eq!(2.0, object.y());
-    assert_eq!(3.0, object.width());
-    assert_eq!(4.0, object.height());
-    assert_eq!(0.707, object.rotation());
-    assert!(!object.is_visible());
-    assert_eq!(Some(123), object.gid());
+    assert_eq!((2, 0), (tiles[1].x, tiles[1].y));
+    assert_eq!(5, tiles[1].tileset.first_gid());
+    assert_eq!(0, tiles[1].local_id);
+    assert!(tiles[1].tile.is_none());
 
-    let object = objects.next().unwrap();
-    assert_eq!(1, object.properties().count());
+    assert_eq!((2, 1), (tiles[2].x, tiles[2].y));
+    assert!(tiles[2].gid.flipped_horizontally());
+    assert_eq!(8, tiles[2].gid.id());
+    assert_eq!(3, tiles[2].local_id);
 
-    let object = objects.next().unwrap();
-    assert_matches!(object.shape(), Some(&Shape::Ellipse));
+    assert!(map.tileset_for_gid(0).is_none());
+    assert_eq!(5, map.tileset_for_gid(0x4000_0005).unwrap().first_gid());
+}
 
-    let object = objects.next().unwrap();
-    assert_matches!(object.shape(), Some(&Shape::Polygon(..)));
-    if let Some(&Shape::Polygon(ref polygon)) = object.shape() {
-        assert_eq!(3, polygon.points().count());
-        let mut points = polygon.points();
-        assert_eq!(&Point {x: 0, y: 1}, points.next().unwrap());
-        assert_eq!(&Point {x: 2, y: 3}, points.next().unwrap());
-        assert_eq!(&Point {x: 4, y: 5}, points.next().unwrap());
-    }
+#[test]
+fn when_exporting_gpu_buffers_expect_local_indices_flags_and_tilesets() {
+    let map = read_map(r#"<map width="3" height="2">
+        <tileset firstgid="1" tilecount="4"/>
+        <tileset firstgid="5" tilecount="4"/>
+        <layer width="3" height="2"><data encoding="csv">0,2,5,1610612737,3221225480,9</data></layer>
+    </map>"#).unwrap();
+    let layer = map.layers().next().unwrap();
+    let tilesets: Vec<_> = map.tilesets().collect();
+    const EMPTY: u32 = GpuLayerBuffer::EMPTY;
 
-    let object = objects.next().unwrap();
-    assert_matches!(object.shape(), Some(&Shape::Polyline(..)));
+    // The gid 9 is past the last tile of the last tileset.
+    let buffer = layer.to_gpu_buffer(&map, tilesets[0]).unwrap();
+    assert_eq!(vec![EMPTY, 1, EMPTY, 0, EMPTY, EMPTY], buffer.indices);
+    assert_eq!(vec![0, 0, 0, 6, 12, 0], buffer.flags);
+    assert_eq!(vec![EMPTY, 0, 1, 0, 1, EMPTY], buffer.tilesets);
+
+    let buffer = layer.to_gpu_buffer(&map, tilesets[1]).unwrap();
+    assert_eq!(vec![EMPTY, EMPTY, 0, EMPTY, 3, EMPTY], buffer.indices);
+
+    let buffer = layer.to_gpu_buffer_all_tilesets(&map).unwrap();
+    assert_eq!(vec![EMPTY, 1, 0, 0, 3, EMPTY], buffer.indices);
+    assert_eq!(vec![EMPTY, 0, 1, 0, 1, EMPTY], buffer.tilesets);
+
+    let layer = Layer::default();
+    assert_eq!(GpuLayerBuffer::default(), layer.to_gpu_buffer_all_tilesets(&map).unwrap());
 }
 
-#[test]
-fn after_reading_valid_xml_expect_tileset_to_have_first_gid() {
-    let tileset = get_simple_valid_tileset();
-    assert_eq!(1, tileset.first_gid());
+const CROP_MAP: &str = r#"<map width="4" height="3" tilewidth="16" tileheight="8" nextobjectid="9">
+    <tileset firstgid="1" tilecount="16"/>
+    <layer name="ground" width="4" height="3"><data encoding="csv">
+        1,2,3,4,
+        5,6,7,8,
+        9,10,11,12
+    </data></layer>
+    <layer name="shifted" x="2" y="1" width="2" height="2"><data encoding="csv">13,14,15,16</data></layer>
+    <layer name="nodata"/>
+    <imagelayer name="sky" offsetx="40" offsety="4"/>
+    <objectgroup name="things">
+        <object id="1" x="20" y="10" width="8" height="4"/>
+        <object id="2" x="0" y="0" width="4" height="4"/>
+        <object id="8" x="60" y="20"><point/></object>
+    </objectgroup>
+</map>"#;
+
+// Returns the tiles of each layer of the map holding data, row by row.
+fn layer_gids(map: &Map) -> Vec<Vec<u32>> {
+    map.layers().filter_map(Layer::data).map(|data| data.decode().unwrap().to_vec()).collect()
 }
 
 #[test]
-fn after_reading_valid_xml_expect_tileset_to_have_name() {
-    let tileset = get_simple_valid_tileset();
-    assert_eq!("simple", tileset.name());
+fn when_cropping_map_expect_region_rebased_and_objects_filtered() {
+    let map = read_map(CROP_MAP).unwrap();
+    let cropped = map.crop(TileRect::new(1, 1, 2, 2)).unwrap();
+    assert_eq!((2, 2), (cropped.width(), cropped.height()));
+    assert_eq!(vec![vec![6, 7, 10, 11], vec![0, 13, 0, 15]], layer_gids(&cropped));
+    for layer in cropped.layers() {
+        assert_eq!((0, 0, 2, 2), (layer.x(), layer.y(), layer.width(), layer.height()));
+    }
+    assert!(cropped.layers().nth(2).unwrap().data().is_none());
+    let data = cropped.layers().next().unwrap().data().unwrap();
+    assert_eq!((Some("csv"), Some("\n6,7,\n10,11\n")), (data.encoding(), data.raw_content()));
+
+    // The region spans pixels 0 to 32 by 0 to 16 once moved by (-16, -8).
+    let objects: Vec<_> = cropped.object_groups().flat_map(ObjectGroup::objects)
+        .map(|object| (object.id(), object.x(), object.y()))
+        .collect();
+    assert_eq!(vec![(1, 4.0, 2.0)], objects);
+    assert_eq!(9, cropped.next_object_id());
+    assert_eq!(Some(1), cropped.find_object(1).map(|(_, object)| object.id()));
+    assert!(cropped.find_object(2).is_none());
+    let sky = cropped.image_layers().next().unwrap();
+    assert_eq!((24, -4), (sky.offset_x(), sky.offset_y()));
+
+    // The source map is left as it was.
+    assert_eq!(12, layer_gids(&map)[0].len());
+    assert_eq!(3, map.object_groups().next().unwrap().objects().len());
+
+    // Cells outside of the map are empty, and the result is written anew.
+    let cropped = map.crop(TileRect::new(3, 2, 2, 2)).unwrap();
+    assert_eq!(vec![vec![12, 0, 0, 0], vec![16, 0, 0, 0]], layer_gids(&cropped));
+    let reread = read_map(&cropped.to_xml_string().unwrap()).unwrap();
+    assert_eq!(layer_gids(&cropped), layer_gids(&reread));
+    assert_eq!(vec![8], reread.object_groups().flat_map(ObjectGroup::objects).map(Object::id).collect::<Vec<_>>());
 }
 
 #[test]
-fn after_reading_valid_xml_expect_tileset_to_have_tile_width() {
-    let tileset = get_simple_valid_tileset();
-    assert_eq!(32, tileset.tile_width());
+fn when_resizing_map_expect_content_kept_at_anchor() {
+    let mut map = read_map(CROP_MAP).unwrap();
+    map.resize(6, 5, Anchor::Center).unwrap();
+    assert_eq!((6, 5), (map.width(), map.height()));
+    assert_eq!(vec![0, 0, 0, 0, 0, 0,
+                    0, 1, 2, 3, 4, 0,
+                    0, 5, 6, 7, 8, 0,
+                    0, 9, 10, 11, 12, 0,
+                    0, 0, 0, 0, 0, 0], layer_gids(&map)[0]);
+    assert_eq!(vec![0, 0, 0, 0, 0, 0,
+                    0, 0, 0, 0, 0, 0,
+                    0, 0, 0, 13, 14, 0,
+                    0, 0, 0, 15, 16, 0,
+                    0, 0, 0, 0, 0, 0], layer_gids(&map)[1]);
+    // No object is dropped, even out of the map.
+    let objects: Vec<_> = map.object_groups().flat_map(ObjectGroup::objects).map(|o| (o.x(), o.y())).collect();
+    assert_eq!(vec![(36.0, 18.0), (16.0, 8.0), (76.0, 28.0)], objects);
+
+    map.resize(2, 2, Anchor::BottomRight).unwrap();
+    assert_eq!(vec![vec![12, 0, 0, 0], vec![16, 0, 0, 0]], layer_gids(&map));
+    let objects: Vec<_> = map.object_groups().flat_map(ObjectGroup::objects).map(|o| (o.x(), o.y())).collect();
+    assert_eq!(vec![(-28.0, -6.0), (-48.0, -16.0), (12.0, 4.0)], objects);
+
+    let mut map = read_map(r#"<map orientation="staggered" width="2" height="2" tilewidth="32" tileheight="16"
+                                  staggeraxis="y" staggerindex="odd">
+        <objectgroup><object id="1" x="40" y="20"/></objectgroup>
+    </map>"#).unwrap();
+    map.resize(2, 3, Anchor::Bottom).unwrap();
+    assert_eq!(Some(Index::Even), map.stagger_index());
+    let object = map.object_groups().next().unwrap().objects().next().unwrap();
+    assert_eq!((40.0, 28.0), (object.x(), object.y()));
+    map.resize(2, 5, Anchor::Bottom).unwrap();
+    assert_eq!(Some(Index::Even), map.stagger_index());
+
+    let mut map = read_map(r#"<map orientation="hexagonal" width="2" height="2" tilewidth="32" tileheight="32"
+                                  staggeraxis="y" staggerindex="odd"/>"#).unwrap();
+    assert_matches!(map.resize(2, 3, Anchor::Bottom), Err(Error::MissingAttribute { ref attribute, .. }) if attribute == "hexsidelength");
+    assert_eq!((2, 2, Some(Index::Odd)), (map.width(), map.height(), map.stagger_index()));
+
+    // Offsets out of range stop at the largest value.
+    let mut map = read_map(r#"<map width="2" height="2" tilewidth="16" tileheight="16">
+        <imagelayer name="far" offsetx="2147483600"/>
+    </map>"#).unwrap();
+    map.resize(6, 2, Anchor::Right).unwrap();
+    assert_eq!(i32::MAX, map.image_layers().next().unwrap().offset_x());
 }
 
 #[test]
-fn after_reading_valid_xml_expect_tileset_to_have_tile_height() {
-    let tileset = get_simple_valid_tileset();
-    assert_eq!(16, tileset.tile_height());
+fn when_cropping_or_resizing_infinite_map_expect_chunks_cut_into_finite_layers() {
+    let map = read_map(CHUNKED_MAP).unwrap();
+    let cropped = map.crop(TileRect::new(1, 1, 3, 3)).unwrap();
+    assert!(!cropped.is_infinite());
+    let layer = cropped.layers().next().unwrap();
+    assert_eq!((0, 0, 3, 3), (layer.x(), layer.y(), layer.width(), layer.height()));
+    let data = layer.data().unwrap();
+    assert!(!data.is_chunked());
+    assert_eq!(Some("csv"), data.encoding());
+    assert_eq!(&[0, 0, 0,
+                 0, 5, 0,
+                 0, 0, 6][..], data.decode().unwrap());
+    let reread = read_map(&cropped.to_xml_string().unwrap()).unwrap();
+    assert_eq!(cropped, reread);
+
+    // The chunks left of the map come back in when it grows to the left.
+    let mut map = map;
+    map.resize(6, 4, Anchor::Right).unwrap();
+    assert!(!map.is_infinite());
+    assert_eq!(vec![vec![1, 2, 0, 0, 0, 0,
+                         3, 4, 0, 0, 0, 0,
+                         0, 0, 0, 0, 5, 0,
+                         0, 0, 0, 0, 0, 6]], layer_gids(&map));
 }
 
-#[test]
-fn after_reading_valid_xml_expect_tileset_to_have_spacing() {
-    let tileset = get_simple_valid_tileset();
-    assert_eq!(4, tileset.spacing());
+const REMAP_MAP: &str = r#"<map width="2" height="2" tilewidth="16" tileheight="16">
+    <tileset firstgid="1" name="a" tilecount="4"/>
+    <tileset firstgid="5" name="b" tilecount="4"/>
+    <layer name="csv" width="2" height="2"><data encoding="csv">1,0,6,2147483656</data></layer>
+    <layer name="zlib" width="2" height="2">
+        <data encoding="base64" compression="zlib">eJxjZIAANiDmYGBoAAAA8ACQ</data>
+    </layer>
+    <layer name="xml" width="2" height="2"><data><tile gid="1"/><tile/><tile gid="6"/><tile gid="2147483656"/></data></layer>
+    <objectgroup><object id="1" gid="1073741830" width="16" height="16"/><object id="2"/></objectgroup>
+</map>"#;
+
+// Returns what each cell of each layer and each tile object shows, as
+// tileset name, local id and flip flags.
+fn shown_tiles(map: &Map) -> Vec<(String, u32, u32)> {
+    let mut gids: Vec<u32> = map.layers().flat_map(|layer| layer.data().unwrap().decode().unwrap().to_vec()).collect();
+    gids.extend(map.object_groups().flat_map(ObjectGroup::objects).filter_map(Object::gid));
+    gids.into_iter()
+        .filter_map(|gid| {
+            let tileset = map.tileset_for_gid(gid)?;
+            Some((tileset.name().to_string(), Gid(gid).id() - tileset.first_gid(), Gid(gid).flags()))
+        })
+        .collect()
 }
 
 #[test]
-fn after_reading_valid_xml_expect_tileset_to_have_margin() {
-    let tileset = get_simple_valid_tileset();
-    assert_eq!(2, tileset.margin());
+fn when_swapping_first_gids_expect_same_tiles_shown() {
+    let mut map = read_map(REMAP_MAP).unwrap();
+    let before = shown_tiles(&map);
+    assert_eq!(10, before.len());
+
+    map.change_first_gids(&[(1, 5), (5, 1)]).unwrap();
+    assert_eq!(vec![5, 1], map.tilesets().map(Tileset::first_gid).collect::<Vec<_>>());
+    assert_eq!(before, shown_tiles(&map));
+    let csv = map.layers().next().unwrap().data().unwrap();
+    assert_eq!(&[5, 0, 2, 0x8000_0004], csv.decode().unwrap());
+    assert_eq!(Some(0x4000_0002), map.object_groups().next().unwrap().objects().next().unwrap().gid());
+
+    // The layers keep their encoding, with the new tile ids.
+    let reread = read_map(&map.to_xml_string().unwrap()).unwrap();
+    assert_eq!(before, shown_tiles(&reread));
+    let encodings: Vec<_> = reread.layers().map(|layer| (layer.data().unwrap().encoding(), layer.data().unwrap().compression())).collect();
+    assert_eq!(vec![(Some("csv"), None), (Some("base64"), Some("zlib")), (None, None)], encodings);
+}
+
+#[test]
+fn when_moving_tiles_past_the_largest_gid_expect_error_and_map_untouched() {
+    let mut map = read_map(REMAP_MAP).unwrap();
+    let before = map.clone();
+    assert_matches!(map.change_first_gids(&[(5, 0x1FFF_FFFD)]), Err(Error::InvalidTileset(..)));
+    assert_eq!(before, map);
+    assert_eq!(vec![1, 5], map.tilesets().map(Tileset::first_gid).collect::<Vec<_>>());
+}
+
+#[test]
+fn when_remapping_gids_expect_flags_and_empty_cells_kept() {
+    let mut map = read_map(REMAP_MAP).unwrap();
+    map.remap_gids(|id| id + 10).unwrap();
+    for layer in map.layers() {
+        assert_eq!(&[11, 0, 16, 0x8000_0012], layer.data().unwrap().decode().unwrap());
+    }
+    let gids: Vec<_> = map.object_groups().flat_map(ObjectGroup::objects).map(Object::gid).collect();
+    assert_eq!(vec![Some(0x4000_0010), None], gids);
+
+    let mut map = read_map(CHUNKED_MAP).unwrap();
+    map.remap_gids(|id| id + 10).unwrap();
+    let data = map.layers().next().unwrap().data().unwrap();
+    let chunks: Vec<_> = data.chunks().map(|chunk| (chunk.x(), chunk.gids().to_vec())).collect();
+    assert_eq!(vec![(-2, vec![11, 12, 13, 14]), (2, vec![15, 0, 0, 16])], chunks);
+
+    let mut map = read_map(&BAD_LAYERS_MAP.replace("<layer name=\"Empty\"/>", "")).unwrap();
+    assert_matches!(map.remap_gids(|id| id + 1), Err(Error::BadEncoding(_)));
+    assert_eq!(&[1, 2], map.layers().next().unwrap().data().unwrap().decode().unwrap());
+}
+
+const MERGE_MAP: &str = r#"<map width="3" height="1" tilewidth="16" tileheight="16">
+    <tileset firstgid="1" name="a" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+        <image source="a.png" width="32" height="32"/>
+        <terraintypes><terrain name="grass" tile="0"/></terraintypes>
+        <tile id="3" terrain="0,0,0,0"/>
+    </tileset>
+    <tileset firstgid="20" name="b" tilewidth="16" tileheight="16" tilecount="2" columns="2">
+        <properties><property name="biome" value="desert"/></properties>
+        <image source="b.png" width="32" height="16"/>
+        <terraintypes><terrain name="sand" tile="1"/></terraintypes>
+        <tile id="1" terrain=",0,,0">
+            <properties><property name="solid" type="bool" value="true"/></properties>
+            <animation><frame tileid="1" duration="100"/><frame tileid="0" duration="100"/></animation>
+        </tile>
+    </tileset>
+    <layer name="ground" width="3" height="1"><data encoding="csv">1,20,2147483669</data></layer>
+</map>"#;
+
+#[test]
+fn when_merging_tilesets_expect_tiles_appended_and_gids_remapped() {
+    let mut map = read_map(MERGE_MAP).unwrap();
+    let from = map.tilesets().nth(1).unwrap().clone();
+    let table = Tileset::merge(map.tilesets_mut().next().unwrap(), &from).unwrap();
+
+    let old_gids: Vec<_> = table.entries().iter().map(|entry| (entry.old_gid, entry.new_gid)).collect();
+    assert_eq!(vec![(20, 5), (21, 6)], old_gids);
+    assert_eq!(Some(Rect::new(16, 0, 16, 16)), table.get(21).unwrap().source_rect);
+    assert_eq!(Some("b.png"), table.source_image().map(Image::source));
+    assert_eq!(1, table.remap(1));
+
+    map.remap_gids(|id| table.remap(id)).unwrap();
+    assert_eq!(&[1, 5, 0x8000_0006], map.layers().next().unwrap().data().unwrap().decode().unwrap());
+
+    let merged = map.tilesets().next().unwrap();
+    assert_eq!(6, merged.tile_count());
+    assert_eq!(Some("desert"), merged.property("biome").map(Property::value));
+    let names: Vec<_> = merged.terrain_types().map(|terrain| (terrain.name(), terrain.tile())).collect();
+    assert_eq!(vec![("grass", "0"), ("sand", "5")], names);
+    let tile = merged.tile_by_id(5).unwrap();
+    assert_eq!(Some(&Corners(None, Some(1), None, Some(1))), tile.terrain());
+    assert!(tile.property("solid").is_some());
+    let frames: Vec<_> = tile.animation().unwrap().frames().map(Frame::tile_id).collect();
+    assert_eq!(vec![5, 4], frames);
+}
+
+#[test]
+fn when_merging_tilesets_of_different_tile_size_expect_error() {
+    let mut into = TilesetBuilder::new("a", 16, 16).build().unwrap();
+    let from = TilesetBuilder::new("b", 16, 32).build().unwrap();
+    assert_matches!(Tileset::merge(&mut into, &from),
+                    Err(Error::TilesetMismatch { ref setting, .. }) if setting == "tile size");
+
+    let from = TilesetBuilder::new("b", 16, 16).with_tile_offset(0, 4).build().unwrap();
+    assert_matches!(Tileset::merge(&mut into, &from), Err(Error::TilesetMismatch { .. }));
+    assert_eq!(0, into.tiles().count());
+
+    let mut atlas = TilesetBuilder::new("c", 16, 16).with_image(Image::new("c.png", 32, 32)).build().unwrap();
+    assert_matches!(Tileset::merge(&mut into, &atlas),
+                    Err(Error::TilesetMismatch { ref setting, .. }) if setting == "kind");
+    assert_matches!(Tileset::merge(&mut atlas, &into),
+                    Err(Error::TilesetMismatch { ref setting, .. }) if setting == "kind");
+
+    // A frame showing a tile whose id overflows once shifted.
+    let mut into = TilesetBuilder::new("a", 16, 16).with_tile_image(0, Image::new("a.png", 16, 16)).build().unwrap();
+    let from = TilesetBuilder::new("b", 16, 16)
+        .with_tile_image(0, Image::new("b.png", 16, 16))
+        .with_tile_animation(0, Animation::new(vec![Frame::new(u32::MAX, 100)]))
+        .build()
+        .unwrap();
+    assert_matches!(Tileset::merge(&mut into, &from), Err(Error::InvalidTileset(..)));
+    assert_eq!(1, into.tiles().count());
+}
+
+#[test]
+fn after_setting_tiles_expect_edits_written_with_the_encoding_read() {
+    let mut map = read_map(REMAP_MAP).unwrap();
+    for layer in map.layers_mut() {
+        layer.set_tile(1, 0, 0x4000_0003).unwrap();
+        layer.fill_rect(TileRect { x: 0, y: 1, w: 2, h: 1 }, 7).unwrap();
+        assert_eq!(&[1, 0x4000_0003, 7, 7], layer.data().unwrap().decode().unwrap());
+        assert!(layer.data().unwrap().raw_content().is_none());
+    }
+
+    let reread = read_map(&map.to_xml_string().unwrap()).unwrap();
+    for layer in reread.layers() {
+        assert_eq!(&[1, 0x4000_0003, 7, 7], layer.data().unwrap().decode().unwrap(), "layer {}", layer.name());
+    }
+    let encodings: Vec<_> = reread.layers().map(|layer| (layer.data().unwrap().encoding(), layer.data().unwrap().compression())).collect();
+    assert_eq!(vec![(Some("csv"), None), (Some("base64"), Some("zlib")), (None, None)], encodings);
+}
+
+#[test]
+fn after_setting_tiles_of_chunked_layer_expect_new_chunks_written_and_read_back() {
+    let mut map = read_map(CHUNKED_MAP).unwrap();
+    {
+        let layer = map.layers_mut().next().unwrap();
+        layer.set_tile(1, 1, 0x8000_0009).unwrap();
+        layer.set_tile(6, 0, 7).unwrap();
+        layer.set_tile(3, 5, 0).unwrap();
+        layer.fill_rect(TileRect { x: 0, y: 4, w: 2, h: 1 }, 8).unwrap();
+        assert_matches!(layer.set_tile(u32::MAX, 0, 1), Err(Error::OutOfBounds { x: 0xFFFF_FFFF, y: 0, .. }));
+    }
+
+    let reread = read_map(&map.to_xml_string().unwrap()).unwrap();
+    let data = reread.layers().next().unwrap().data().unwrap();
+    let chunks: Vec<_> = data.chunks().map(|c| (c.x(), c.y(), c.width(), c.height(), c.gids().to_vec())).collect();
+    assert_eq!(vec![(-2, 0, 2, 2, vec![1, 2, 3, 0x8000_0009]),
+                    (2, 2, 2, 2, vec![5, 0, 0, 6]),
+                    (4, 0, 2, 2, vec![7, 0, 0, 0]),
+                    (-2, 4, 2, 2, vec![8, 8, 0, 0])], chunks);
+    assert_eq!(Some((-2, 0, 8, 6)), data.chunk_bounds());
+    assert_eq!(7, data.decode().unwrap()[6]);
+    assert_eq!(map, reread);
+
+    let layer = map.layers_mut().next().unwrap();
+    layer.clear().unwrap();
+    let data = layer.data().unwrap();
+    assert_eq!(4, data.chunks().count());
+    assert!(data.decode().unwrap().iter().all(|&gid| gid == 0));
+}
+
+#[test]
+fn when_editing_cells_outside_of_layer_expect_error_and_layer_untouched() {
+    let mut map = read_map(REMAP_MAP).unwrap();
+    let layer = map.layers_mut().next().unwrap();
+    assert_matches!(layer.set_tile(2, 0, 1), Err(Error::OutOfBounds { x: 2, y: 0, width: 2, height: 2 }));
+    assert_matches!(layer.fill_rect(TileRect { x: 1, y: 0, w: 1, h: 3 }, 1), Err(Error::OutOfBounds { x: 1, y: 2, .. }));
+    assert_eq!(Some("1,0,6,2147483656"), layer.data().unwrap().raw_content());
+
+    layer.clear().unwrap();
+    assert_eq!(&[0, 0, 0, 0], layer.data().unwrap().decode().unwrap());
+
+    let mut layer = Layer::default();
+    layer.set_width(2);
+    layer.set_height(1);
+    layer.set_tile(1, 0, 5).unwrap();
+    assert_eq!(&[0, 5], layer.data().unwrap().decode().unwrap());
+}
+
+#[test]
+fn when_editing_layer_without_size_expect_size_of_map() {
+    let mut map = read_map(r#"<map width="3" height="2"><layer name="a"/></map>"#).unwrap();
+    let layer = map.layers_mut().next().unwrap();
+    assert_eq!((3, 2), (layer.width(), layer.height()));
+    layer.set_tile(2, 1, 4).unwrap();
+    assert_eq!(&[0, 0, 0, 0, 0, 4], layer.data().unwrap().decode().unwrap());
+}
+
+#[test]
+fn when_filling_empty_rect_at_edge_of_layer_expect_layer_untouched() {
+    let mut map = read_map(REMAP_MAP).unwrap();
+    let layer = map.layers_mut().next().unwrap();
+    layer.fill_rect(TileRect { x: 2, y: 0, w: 0, h: 2 }, 1).unwrap();
+    layer.fill_rect(TileRect { x: 0, y: 2, w: 2, h: 0 }, 1).unwrap();
+    assert_matches!(layer.fill_rect(TileRect { x: 3, y: 0, w: 0, h: 1 }, 1), Err(Error::OutOfBounds { x: 3, y: 0, .. }));
+    assert_eq!(Some("1,0,6,2147483656"), layer.data().unwrap().raw_content());
+}
+
+#[test]
+fn when_looking_up_gid_expect_tileset_index_to_follow_edits() {
+    let mut map = read_map(r#"<map>
+        <tileset firstgid="40" name="c"/>
+        <tileset firstgid="1" name="a"/>
+        <tileset firstgid="20" name="b"/>
+    </map>"#).unwrap();
+    let name = |map: &Map, gid| map.tileset_for_gid(gid).map(|tileset| tileset.name().to_string());
+    assert_eq!(Some("a".to_string()), name(&map, 19));
+    assert_eq!(Some("b".to_string()), name(&map, 20));
+    assert_eq!(Some("c".to_string()), name(&map, 1000));
+
+    for tileset in map.tilesets_mut() {
+        if tileset.name() == "c" {
+            tileset.set_first_gid(10);
+        }
+    }
+    assert_eq!(Some("c".to_string()), name(&map, 19));
+    assert_eq!(Some("a".to_string()), name(&map, 9));
+}
+
+#[test]
+fn after_reading_xml_with_mixed_layers_expect_layer_stack_in_document_order() {
+    let map = read_map(r#"<map>
+        <layer name="ground"/>
+        <objectgroup name="spawns"/>
+        <imagelayer name="clouds"/>
+        <layer name="roofs"/>
+        <objectgroup name="triggers"/>
+    </map>"#).unwrap();
+
+    let names: Vec<_> = map.layer_stack().map(|layer| layer.name()).collect();
+    assert_eq!(vec!["ground", "spawns", "clouds", "roofs", "triggers"], names);
+
+    let mut stack = map.layer_stack();
+    assert_matches!(stack.next(), Some(LayerKind::Tile(..)));
+    assert_matches!(stack.next(), Some(LayerKind::Objects(..)));
+    assert_matches!(stack.next(), Some(LayerKind::Image(..)));
+    assert_matches!(stack.next(), Some(LayerKind::Tile(..)));
+    assert_matches!(stack.next(), Some(LayerKind::Objects(..)));
+    assert!(stack.next().is_none());
+
+    let names: Vec<_> = map.layers().map(Layer::name).collect();
+    assert_eq!(vec!["ground", "roofs"], names);
+}
+
+const GROUP_MAP: &str = r#"<map width="2" height="2">
+    <properties><property name="mapprop" value="1"/></properties>
+    <layer name="Ground"/>
+    <group name="Background" offsetx="4" opacity="0.5">
+        <properties><property name="groupprop" value="2"/></properties>
+        <imagelayer name="Sky"/>
+        <group name="Inner"><layer name="Hills"/></group>
+    </group>
+    <objectgroup name="Top"/>
+</map>"#;
+
+#[test]
+fn after_reading_xml_with_groups_expect_their_layers_in_map_and_their_own_content_dropped() {
+    let mut reader = TmxReader::with_options(GROUP_MAP.as_bytes(), lenient_options());
+    let map = reader.read_map().unwrap();
+
+    let names: Vec<_> = map.properties().map(Property::name).collect();
+    assert_eq!(vec!["mapprop"], names);
+    let names: Vec<_> = map.layer_stack().map(|layer| layer.name()).collect();
+    assert_eq!(vec!["Ground", "Sky", "Hills", "Top"], names);
+    let hills = map.layers().nth(1).unwrap();
+    assert_eq!((2, 2), (hills.width(), hills.height()));
+
+    let warnings: Vec<_> = reader.take_diagnostics().into_iter()
+        .filter(|d| d.severity == Severity::Warning)
+        .map(|d| (d.line, d.message))
+        .collect();
+    assert_eq!(vec![(4, "Group `Background` read as layers of the map, dropping its offsetx, opacity, properties".to_string())],
+               warnings);
+}
+
+#[test]
+fn after_reading_xml_with_several_layers_expect_iterators_to_know_their_length_and_reverse() {
+    let map = read_map(r#"<map>
+        <layer name="ground"/>
+        <objectgroup name="below"/>
+        <layer name="walls"/>
+        <objectgroup name="above"/>
+        <layer name="roofs"/>
+    </map>"#).unwrap();
+
+    let mut layers = map.layers();
+    assert_eq!(3, layers.len());
+    layers.next();
+    assert_eq!(2, layers.len());
+    assert_eq!(3, map.layers().count());
+
+    let names: Vec<_> = map.layers().rev().map(Layer::name).collect();
+    assert_eq!(vec!["roofs", "walls", "ground"], names);
+    let names: Vec<_> = map.object_groups().rev().map(ObjectGroup::name).collect();
+    assert_eq!(vec!["above", "below"], names);
+    assert_eq!(Some(1), map.layers().rposition(|layer| layer.name() == "walls"));
+    assert_eq!("roofs", map.layers().nth(2).unwrap().name());
+
+    assert_eq!(5, map.layer_stack().len());
+    assert_eq!("roofs", map.layer_stack().next_back().unwrap().name());
+}
+
+#[test]
+fn after_reading_xml_expect_collections_to_be_counted_and_indexed() {
+    let map = read_map(r#"<map>
+        <tileset firstgid="1"><tile id="0"/><tile id="4"/></tileset>
+        <layer name="ground"/>
+        <objectgroup name="spawns">
+            <object id="1" name="first"><polyline points="0,0 2,3 4,1"/></object>
+            <object id="2" name="second"/>
+        </objectgroup>
+    </map>"#).unwrap();
+
+    assert_eq!(1, map.tileset_count());
+    assert_eq!(1, map.layer_count());
+    assert_eq!(0, map.image_layer_count());
+    assert_eq!(1, map.object_group_count());
+
+    let group = map.object_groups().next().unwrap();
+    assert_eq!(2, group.objects_slice().len());
+    assert_eq!("second", group[1].name());
+    assert_eq!(Some(1), group.objects_slice().iter().position(|object| object.id() == 2));
+
+    let tileset = map.tilesets().next().unwrap();
+    assert_eq!(4, tileset.tiles_slice()[1].id());
+
+    match group[0].shape() {
+        Some(&Shape::Polyline(ref polyline)) => {
+            assert_eq!(3, polyline.points_slice().len());
+            assert_eq!(Point { x: 2.0, y: 3.0 }, polyline[1]);
+        }
+        _ => panic!("expected a polyline"),
+    }
+
+    let properties: PropertyCollection = vec![
+        Property::new("a", "1", PropertyType::String),
+        Property::new("b", "2", PropertyType::String),
+    ].into_iter().collect();
+    assert_eq!(2, properties.len());
+    assert!(!properties.is_empty());
+    assert_eq!("b", properties[1].name());
+    assert!(PropertyCollection::new().is_empty());
+}
+
+// Moves the items out of an iterator; fails to compile if they were borrowed.
+fn collect_owned<T: 'static, I: Iterator<Item = T>>(iter: I) -> Vec<T> {
+    iter.collect()
+}
+
+#[test]
+fn after_consuming_a_map_expect_owned_layers_and_objects() {
+    let xml = r#"<map>
+        <tileset firstgid="1"><tile id="3"/></tileset>
+        <layer name="ground"><data encoding="csv">1,2</data></layer>
+        <objectgroup name="spawns">
+            <object id="1"><polygon points="0,0 1,1"/></object>
+        </objectgroup>
+    </map>"#;
+
+    let layers = collect_owned(read_map(xml).unwrap().into_layers());
+    assert_eq!(1, layers.len());
+    let data = layers.into_iter().next().and_then(Layer::into_data).unwrap();
+    assert_eq!(Some("1,2"), data.raw_content());
+
+    let mut groups = collect_owned(read_map(xml).unwrap().into_object_groups());
+    let objects = collect_owned(groups.remove(0).into_objects());
+    let shape = objects.into_iter().next().and_then(Object::into_shape);
+    let points = match shape {
+        Some(Shape::Polygon(polygon)) => collect_owned(polygon.into_points()),
+        _ => panic!("expected a polygon"),
+    };
+    assert_eq!(vec![Point { x: 0.0, y: 0.0 }, Point { x: 1.0, y: 1.0 }], points);
+
+    let tilesets = collect_owned(read_map(xml).unwrap().into_tilesets());
+    let tiles = collect_owned(tilesets.into_iter().next().unwrap().into_tiles());
+    assert_eq!(3, tiles[0].id());
+}
+
+#[test]
+fn after_reading_xml_with_image_layers_expect_map_to_be_iterable_over_image_layers() {
+    let map = get_map_with_image_layers();
+    assert_eq!(7, map.image_layers().count());
+
+    let mut layers = map.image_layers();
+    let layer1 = layers.next().unwrap();
+    assert_eq!("layer1_name", layer1.name());
+    assert_eq!(1.0, layer1.opacity());
+    assert!(layer1.is_visible());
+    assert_eq!(0, layer1.offset_x());
+    assert_eq!(0, layer1.offset_y());
+
+    let layer2 = layers.next().unwrap();
+    assert_eq!(0.0, layer2.opacity());
+
+    let layer3 = layers.next().unwrap();
+    assert!(!layer3.is_visible());
+
+    let layer4 = layers.next().unwrap();
+    assert_eq!(1, layer4.offset_x());
+    assert_eq!(2, layer4.offset_y());
+
+    let layer5 = layers.next().unwrap();
+    assert_eq!(1, layer5.properties().count());
+
+    let layer6 = layers.next().unwrap();
+    assert!(layer6.image().is_some());
+
+    let layer7 = layers.next().unwrap();
+    assert_eq!(1, layer7.x());
+    assert_eq!(2, layer7.y());
+    assert_eq!(3, layer7.width());
+    assert_eq!(4, layer7.height());
+}
+
+#[test]
+fn after_reading_xml_with_object_groups_expect_map_to_be_iterable_over_object_groups() {
+    let map = get_map_with_objectgroups();
+    assert_eq!(3, map.object_groups().count());
+
+    let mut object_groups = map.object_groups();
+    let group1 = object_groups.next().unwrap();
+    assert_eq!("some_name", group1.name());
+    assert_eq!(1.0, group1.opacity());
+    assert!(group1.is_visible());
+    assert_eq!(0, group1.offset_x());
+    assert_eq!(0, group1.offset_y());
+    assert_eq!(0, group1.x());
+    assert_eq!(0, group1.y());
+    assert_eq!(0, group1.width());
+    assert_eq!(0, group1.height());
+    assert_eq!(DrawOrder::TopDown, group1.draw_order());
+    assert_eq!(1, group1.properties().count());
+
+    let group2 = object_groups.next().unwrap();
+    assert_eq!(0.0, group2.opacity());
+    assert!(!group2.is_visible());
+    assert_eq!(1, group2.offset_x());
+    assert_eq!(2, group2.offset_y());
+    assert_eq!(3, group2.x());
+    assert_eq!(4, group2.y());
+    assert_eq!(5, group2.width());
+    assert_eq!(6, group2.height());
+    assert_eq!(DrawOrder::Index, group2.draw_order());
+    assert_eq!(None, group2.color());
+
+    let group3 = object_groups.next().unwrap();
+    assert_eq!(Some(&Color::rgb(0, 0, 0)), group3.color());
+}
+
+#[test]
+fn after_reading_xml_with_objects_expect_object_groups_to_be_iterable_over_objects() {
+    let map = get_map_with_objects();
+    let group = map.object_groups().next().unwrap();
+    assert_eq!(6, group.objects().count());
+
+    let mut objects = group.objects();
+
+    let object = objects.next().unwrap();
+    assert!(object.is_visible());
+    assert_eq!(None, object.gid());
+    assert_eq!(0, object.properties().count());
+    assert_eq!(None, object.shape());
+
+    let object = objects.next().unwrap();
+    assert_eq!(1, object.id());
+    assert_eq!("obj", object.name());
+    assert_eq!("ty", object.object_type());
+    assert_eq!(1.0, object.x());
+    assert_eq!(2.0, object.y());
+    assert_eq!(3.0, object.width());
+    assert_eq!(4.0, object.height());
+    assert_eq!(0.707, object.rotation());
+    assert!(!object.is_visible());
+    assert_eq!(Some(123), object.gid());
+
+    let object = objects.next().unwrap();
+    assert_eq!(1, object.properties().count());
+
+    let object = objects.next().unwrap();
+    assert_matches!(object.shape(), Some(&Shape::Ellipse));
+
+    let object = objects.next().unwrap();
+    assert_matches!(object.shape(), Some(&Shape::Polygon(..)));
+    if let Some(&Shape::Polygon(ref polygon)) = object.shape() {
+        assert_eq!(3, polygon.points().count());
+        let mut points = polygon.points();
+        assert_eq!(&Point { x: 0.0, y: 1.0 }, points.next().unwrap());
+        assert_eq!(&Point { x: 2.0, y: 3.0 }, points.next().unwrap());
+        assert_eq!(&Point { x: 4.0, y: 5.0 }, points.next().unwrap());
+    }
+
+    let object = objects.next().unwrap();
+    assert_matches!(object.shape(), Some(&Shape::Polyline(..)));
+}
+
+#[test]
+fn after_reading_xml_with_object_groups_expect_groups_and_objects_to_be_found_by_name() {
+    let map = read_map(r#"<map>
+        <objectgroup>
+            <object id="1"/>
+        </objectgroup>
+        <objectgroup name="triggers">
+            <object id="2" name="exit_door"/>
+            <object id="3"/>
+            <object id="4" name="exit_door"/>
+        </objectgroup>
+        <objectgroup name="triggers"/>
+    </map>"#).unwrap();
+
+    let triggers = map.object_group_by_name("triggers").unwrap();
+    assert_eq!(3, triggers.objects().count());
+    assert_eq!(2, triggers.object_by_name("exit_door").unwrap().id());
+    assert_eq!(3, triggers.object_by_name("").unwrap().id());
+    assert!(triggers.object_by_name("Exit_Door").is_none());
+
+    let unnamed = map.object_group_by_name("").unwrap();
+    assert_eq!(1, unnamed.objects().next().unwrap().id());
+    assert!(map.object_group_by_name("Triggers").is_none());
+}
+
+#[test]
+fn after_reading_xml_with_objects_expect_objects_to_be_found_by_id() {
+    let map = read_map(r#"<map>
+        <tileset firstgid="1">
+            <tile id="0">
+                <objectgroup>
+                    <object id="9"/>
+                </objectgroup>
+            </tile>
+        </tileset>
+        <objectgroup name="first">
+            <object id="1"/>
+            <object id="2"/>
+        </objectgroup>
+        <objectgroup name="second">
+            <object id="3" name="three"/>
+        </objectgroup>
+    </map>"#).unwrap();
+
+    let (group, object) = map.find_object(3).unwrap();
+    assert_eq!("second", group.name());
+    assert_eq!("three", object.name());
+    let (group, object) = map.find_object(2).unwrap();
+    assert_eq!("first", group.name());
+    assert_eq!(2, object.id());
+    assert!(map.find_object(9).is_none());
+    assert!(map.find_object(4).is_none());
+}
+
+#[test]
+fn after_reading_xml_with_typed_objects_expect_objects_to_be_filtered_by_type() {
+    let map = read_map(r#"<map>
+        <objectgroup>
+            <object id="1" type="enemy"/>
+            <object id="2" type="pickup"/>
+            <object id="3"/>
+        </objectgroup>
+        <objectgroup>
+            <object id="4" class="enemy"/>
+            <object id="5" type="Enemy"/>
+        </objectgroup>
+    </map>"#).unwrap();
+
+    let group = map.object_groups().next().unwrap();
+    let ids: Vec<_> = group.objects_of_type("enemy").map(Object::id).collect();
+    assert_eq!(vec![1], ids);
+
+    let ids: Vec<_> = map.objects_of_type("enemy").map(Object::id).collect();
+    assert_eq!(vec![1, 4], ids);
+
+    let ids: Vec<_> = map.objects_of_type("").map(Object::id).collect();
+    assert_eq!(vec![3], ids);
+}
+
+#[test]
+fn when_computing_tile_rects_expect_margin_and_spacing_to_be_accounted_for() {
+    // 4 columns and 3 rows of 16x16 tiles, 2px margin, 1px spacing.
+    let tileset = Tileset::from_str(r#"<tileset tilewidth="16" tileheight="16"
+        spacing="1" margin="2" tilecount="12" columns="4">
+        <image source="atlas.png" width="71" height="54"/>
+    </tileset>"#).unwrap();
+    assert_eq!(Some(Rect::new(2, 2, 16, 16)), tileset.tile_rect(0));
+    assert_eq!(Some(Rect::new(53, 2, 16, 16)), tileset.tile_rect(3));
+    assert_eq!(Some(Rect::new(19, 19, 16, 16)), tileset.tile_rect(5));
+    assert_eq!(Some(Rect::new(53, 36, 16, 16)), tileset.tile_rect(11));
+    assert_eq!(None, tileset.tile_rect(12));
+
+    let tileset = Tileset::from_str(r#"<tileset tilewidth="16" tileheight="16" spacing="1" margin="2">
+        <image source="atlas.png" width="71" height="54"/>
+    </tileset>"#).unwrap();
+    assert_eq!(Some(Rect::new(19, 19, 16, 16)), tileset.tile_rect(5));
+    assert_eq!(None, tileset.tile_rect(12));
+
+    let tileset = Tileset::from_str(r#"<tileset tilewidth="16" tileheight="16" tilecount="2">
+        <tile id="0"><image source="a.png" width="16" height="16"/></tile>
+        <tile id="1"><image source="b.png" width="16" height="16"/></tile>
+    </tileset>"#).unwrap();
+    assert_eq!(None, tileset.tile_rect(0));
+
+    let tileset = Tileset::from_str(r#"<tileset tilewidth="16" tileheight="16"
+        spacing="4294967295" margin="4294967295">
+        <image source="atlas.png" width="71" height="54"/>
+    </tileset>"#).unwrap();
+    assert_eq!(None, tileset.effective_columns());
+    assert_eq!(None, tileset.tile_rect(0));
+
+    let tileset = Tileset::from_str(r#"<tileset tilewidth="3000000000" tileheight="16" tilecount="4" columns="4">
+        <image source="atlas.png" width="71" height="54"/>
+    </tileset>"#).unwrap();
+    assert_eq!(Some(Rect::new(0, 0, 3000000000, 16)), tileset.tile_rect(0));
+    assert_eq!(Some(Rect::new(3000000000, 0, 3000000000, 16)), tileset.tile_rect(1));
+    assert_eq!(None, tileset.tile_rect(2));
+}
+
+#[test]
+fn when_computing_gid_uvs_expect_normalized_rects_and_flips() {
+    // 4 columns and 3 rows of 16x16 tiles, 2px margin, 1px spacing, then an image collection.
+    let map = Map::from_str(r#"<map version="1.0" width="1" height="1" tilewidth="16" tileheight="16">
+        <tileset firstgid="1" tilewidth="16" tileheight="16" spacing="1" margin="2" tilecount="12" columns="4">
+            <image source="atlas.png" width="71" height="54"/>
+        </tileset>
+        <tileset firstgid="13" tilewidth="16" tileheight="16" tilecount="3">
+            <tile id="0"><image source="a.png" width="32" height="48"/></tile>
+            <tile id="1"><image source="b.png"/></tile>
+        </tileset>
+    </map>"#).unwrap();
+
+    let (u0, v0, u1, v1) = (19.0 / 71.0, 19.0 / 54.0, 35.0 / 71.0, 35.0 / 54.0);
+    let uv = map.gid_uv(6).unwrap();
+    assert_eq!("atlas.png", uv.source);
+    assert_eq!(Rect::new(19, 19, 16, 16), uv.rect);
+    assert_eq!((u0, v0, u1, v1), (uv.u0, uv.v0, uv.u1, uv.v1));
+    assert_eq!((false, false, false), (uv.swap, uv.mirror_u, uv.mirror_v));
+    assert_eq!([(u0, v0), (u1, v0), (u1, v1), (u0, v1)], uv.corners());
+
+    let uv = map.gid_uv(1).unwrap();
+    assert_eq!((2.0 / 71.0, 2.0 / 54.0, 18.0 / 71.0, 18.0 / 54.0), (uv.u0, uv.v0, uv.u1, uv.v1));
+    let uv = map.gid_uv(12).unwrap();
+    assert_eq!((53.0 / 71.0, 36.0 / 54.0, 69.0 / 71.0, 52.0 / 54.0), (uv.u0, uv.v0, uv.u1, uv.v1));
+
+    let uv = map.gid_uv(6 | FLIPPED_HORIZONTALLY_FLAG).unwrap();
+    assert_eq!((false, true, false), (uv.swap, uv.mirror_u, uv.mirror_v));
+    assert_eq!([(u1, v0), (u0, v0), (u0, v1), (u1, v1)], uv.corners());
+    let uv = map.gid_uv(6 | FLIPPED_VERTICALLY_FLAG).unwrap();
+    assert_eq!([(u0, v1), (u1, v1), (u1, v0), (u0, v0)], uv.corners());
+    let uv = map.gid_uv(6 | FLIPPED_DIAGONALLY_FLAG).unwrap();
+    assert_eq!([(u0, v0), (u0, v1), (u1, v1), (u1, v0)], uv.corners());
+    // Rotated 90 degrees clockwise: the top-left corner shows the bottom-left one.
+    let uv = map.gid_uv(6 | FLIPPED_DIAGONALLY_FLAG | FLIPPED_HORIZONTALLY_FLAG).unwrap();
+    assert_eq!((true, false, true), (uv.swap, uv.mirror_u, uv.mirror_v));
+    assert_eq!([(u0, v1), (u0, v0), (u1, v0), (u1, v1)], uv.corners());
+
+    let uv = map.gid_uv(13).unwrap();
+    assert_eq!("a.png", uv.source);
+    assert_eq!(Rect::new(0, 0, 32, 48), uv.rect);
+    assert_eq!((0.0, 0.0, 1.0, 1.0), (uv.u0, uv.v0, uv.u1, uv.v1));
+
+    // No declared size, no image, no tileset.
+    assert_eq!(None, map.gid_uv(14));
+    assert_eq!(None, map.gid_uv(15));
+    assert_eq!(None, map.gid_uv(0));
+}
+
+#[test]
+fn when_tileset_lacks_columns_and_tile_count_expect_them_derived_from_the_image() {
+    // 5 columns and 2 rows of 24x16 tiles, 3px margin, 2px spacing.
+    let tileset = Tileset::from_str(r#"<tileset tilewidth="24" tileheight="16" spacing="2" margin="3">
+        <image source="atlas.png" width="134" height="40"/>
+    </tileset>"#).unwrap();
+    assert_eq!(0, tileset.columns());
+    assert_eq!(0, tileset.tile_count());
+    assert_eq!(Some(5), tileset.effective_columns());
+    assert_eq!(Some(10), tileset.effective_tile_count());
+    assert_eq!(Some(Rect::new(107, 21, 24, 16)), tileset.tile_rect(9));
+
+    let tileset = Tileset::from_str(r#"<tileset tilewidth="24" tileheight="16" tilecount="7" columns="3">
+        <image source="atlas.png" width="134" height="40"/>
+    </tileset>"#).unwrap();
+    assert_eq!(Some(3), tileset.effective_columns());
+    assert_eq!(Some(7), tileset.effective_tile_count());
+
+    let tileset = Tileset::from_str(r#"<tileset tilewidth="24" tileheight="16"/>"#).unwrap();
+    assert_eq!(None, tileset.effective_columns());
+    assert_eq!(None, tileset.effective_tile_count());
+
+    // 100000 columns and 100000 rows of 1x1 tiles, more than a u32 can count.
+    let tileset = Tileset::from_str(r#"<tileset tilewidth="1" tileheight="1">
+        <image source="atlas.png" width="100000" height="100000"/>
+    </tileset>"#).unwrap();
+    assert_eq!(Some(100000), tileset.effective_columns());
+    assert_eq!(None, tileset.effective_tile_count());
+}
+
+#[test]
+fn after_reading_valid_xml_expect_tileset_to_have_first_gid() {
+    let tileset = get_simple_valid_tileset();
+    assert_eq!(1, tileset.first_gid());
+}
+
+#[test]
+fn after_reading_valid_xml_expect_tileset_to_have_name() {
+    let tileset = get_simple_valid_tileset();
+    assert_eq!("simple", tileset.name());
+}
+
+#[test]
+fn after_reading_valid_xml_expect_tileset_to_have_tile_width() {
+    let tileset = get_simple_valid_tileset();
+    assert_eq!(32, tileset.tile_width());
+}
+
+#[test]
+fn after_reading_valid_xml_expect_tileset_to_have_tile_height() {
+    let tileset = get_simple_valid_tileset();
+    assert_eq!(16, tileset.tile_height());
+}
+
+#[test]
+fn after_reading_valid_xml_expect_tileset_to_have_spacing() {
+    let tileset = get_simple_valid_tileset();
+    assert_eq!(4, tileset.spacing());
+}
+
+#[test]
+fn after_reading_valid_xml_expect_tileset_to_have_margin() {
+    let tileset = get_simple_valid_tileset();
+    assert_eq!(2, tileset.margin());
 }
 
 #[test]
 fn after_reading_valid_xml_expect_tileset_to_have_tile_count() {
     let tileset = get_simple_valid_tileset();
-    assert_eq!(100, tileset.tile_count());
+    assert_eq!(100, tileset.tile_count());
+}
+
+#[test]
+fn after_reading_valid_xml_expect_tileset_to_have_source() {
+    let tileset = get_simple_valid_tileset();
+    assert_eq!("some_file.tsx", tileset.source());
+}
+
+#[test]
+fn after_reading_valid_xml_expect_tileset_to_have_columns() {
+    let tileset = get_simple_valid_tileset();
+    assert_eq!(24, tileset.columns());
+}
+
+#[test]
+fn after_reading_valid_xml_with_image_element_expect_tileset_to_have_image() {
+    let tileset = Tileset::from_str(
+        r#"<tileset>
+        <image format="png"
+               trans="FF00FF"
+               width="1024"
+               height="768">
+            <data encoding="base64" compression="gzip"></data>
+        </image>
+    </tileset>"#).unwrap();
+    let image = tileset.image().unwrap();
+    assert_eq!("png", image.format());
+    assert_eq!("", image.source());
+    assert_eq!(Some(&Color::rgb(255, 0, 255)), image.trans());
+    assert_eq!(Some(1024), image.width());
+    assert_eq!(Some(768), image.height());
+    assert!(image.data().is_some());
+    assert_matches!(image.source_kind(), Some(&ImageSource::Embedded { ref format, .. }) if format == "png");
+}
+
+#[test]
+fn when_reading_image_with_source_and_data_expect_file_and_diagnostic() {
+    let xml = r#"<tileset name="t">
+        <image source="some_file.png" format="png">
+            <data encoding="base64">AAAA</data>
+        </image>
+        <tile id="0"><image source="tile.png"/></tile>
+        <tile id="1"><image/></tile>
+    </tileset>"#;
+    let mut reader = TmxReader::new(xml.as_bytes());
+    let tileset = reader.read_tileset().unwrap();
+    let image = tileset.image().unwrap();
+    assert_matches!(image.source_kind(), Some(&ImageSource::File(ref path)) if path == "some_file.png");
+    assert_eq!("", image.format());
+    assert!(image.data().is_none());
+    let diagnostics = reader.take_diagnostics();
+    assert_eq!(vec![(Severity::Warning, 3)], diagnostics.iter().map(|d| (d.severity, d.line)).collect::<Vec<_>>());
+
+    assert_eq!("tile.png", tileset.tile_image(0).unwrap().source());
+    assert!(tileset.tile_image(1).unwrap().source_kind().is_none());
+}
+
+#[test]
+fn after_reading_image_without_size_expect_no_size_and_none_written() {
+    let tileset = Tileset::from_str(r#"<tileset name="t" tilewidth="16" tileheight="16" columns="2">
+        <image source="atlas.png"/>
+    </tileset>"#).unwrap();
+    let image = tileset.image().unwrap();
+    assert_eq!((None, None, None), (image.width(), image.height(), image.size()));
+    assert_eq!(None, tileset.effective_tile_count());
+    assert_eq!(None, tileset.tile_rect(0));
+
+    let xml = tileset.to_xml_string().unwrap();
+    assert!(xml.contains(r#"<image source="atlas.png"/>"#), "{}", xml);
+
+    let builder = TilesetBuilder::new("t", 16, 16).with_image(Image::default());
+    assert_matches!(builder.build(), Err(Error::InvalidTileset(_)));
+}
+
+#[test]
+fn after_reading_valid_xml_with_properties_expect_tileset_to_have_properties() {
+    let tileset = Tileset::from_str(
+        r#"<tileset>
+        <properties>
+            <property name="prop1_name" value="prop1_value"/>
+            <property name="prop2_name" value="0" type="int"/>
+            <property name="prop3_name" value="0.0" type="float"/>
+            <property name="prop4_name" value="true" type="bool"/>
+        </properties>
+    </tileset>"#).unwrap();
+    assert_eq!(4, tileset.properties().count());
+    let mut props = tileset.properties();
+
+    let prop1 = props.next().unwrap();
+    assert_eq!("prop1_name", prop1.name());
+    assert_eq!("prop1_value", prop1.value());
+    assert_eq!(PropertyType::String, prop1.property_type());
+
+    let prop2 = props.next().unwrap();
+    assert_eq!(PropertyType::Int, prop2.property_type());
+
+    let prop3 = props.next().unwrap();
+    assert_eq!(PropertyType::Float, prop3.property_type());
+
+    let prop4 = props.next().unwrap();
+    assert_eq!(PropertyType::Bool, prop4.property_type());
+}
+
+#[test]
+fn after_reading_valid_xml_with_tile_offset_expect_tileset_to_have_tile_offset() {
+    let tileset = Tileset::from_str(
+        r#"<tileset>
+        <tileoffset x="0" y="1"/>
+    </tileset>"#).unwrap();
+    let offset = tileset.tile_offset().unwrap();
+    assert_eq!(0, offset.x());
+    assert_eq!(1, offset.y());
+}
+
+#[test]
+fn after_reading_valid_xml_with_terrains_expect_tileset_to_have_terrains() {
+    let tileset = Tileset::from_str(r#"
+    <tileset>
+        <terraintypes>
+            <terrain name="terrain1"/>
+            <terrain tile="tile-id">
+                <properties>
+                    <property/>
+                </properties>
+            </terrain>
+        </terraintypes>
+    </tileset>"#).unwrap();
+    assert_eq!(2, tileset.terrain_types().count());
+    let mut terrain_types = tileset.terrain_types();
+
+    let terrain1 = terrain_types.next().unwrap();
+    assert_eq!("terrain1", terrain1.name());
+
+    let terrain2 = terrain_types.next().unwrap();
+    assert_eq!("tile-id", terrain2.tile());
+    assert_eq!(1, terrain2.properties().count());
+}
+
+#[test]
+fn when_parsing_terrain_with_empty_corners_expect_none_for_those_corners() {
+    let corners = Corners::from_str("0,,1,").unwrap();
+    assert_eq!(Corners::new(Some(0), None, Some(1), None), corners);
+    assert_eq!(Corners::from([Some(0), None, Some(1), None]), corners);
+    assert_eq!(None, corners.top_right());
+    assert_eq!(None, corners.bottom_right());
+
+    assert_matches!(Corners::from_str("0,1,2"), Err(Error::InvalidTerrain(_)));
+    assert_matches!(Corners::from_str("0,1,2,x"), Err(Error::InvalidNumber(_)));
+}
+
+#[test]
+fn after_reading_valid_xml_with_tiles_expect_tileset_to_be_iterable_over_tiles() {
+    let tileset = Tileset::from_str(r#"
+    <tileset>
+        <tile id="123">
+            <properties>
+                <property name="some_name" value="some_value"/>
+            </properties>
+        </tile>
+        <tile>
+            <image source="some_file.png" width="8" height="16"/>
+        </tile>
+        <tile>
+            <objectgroup/>
+        </tile>
+        <tile>
+            <animation>
+                <frame tileid="123" duration="500"/>
+            </animation>
+        </tile>
+        <tile probability="0.5"/>
+        <tile terrain="0,1,2,3"/>
+    </tileset>"#).unwrap();
+    assert_eq!(6, tileset.tiles().count());
+
+    let tile1 = tileset.tiles().next().unwrap();
+    assert_eq!(123, tile1.id());
+    assert_eq!(1, tile1.properties().count());
+
+    let tile2 = tileset.tiles().nth(1).unwrap();
+    assert!(tile2.image().is_some());
+
+    let tile3 = tileset.tiles().nth(2).unwrap();
+    assert!(tile3.object_group().is_some());
+
+    let tile4 = tileset.tiles().nth(3).unwrap();
+    assert!(tile4.animation().is_some());
+    let animation = tile4.animation().unwrap();
+    assert!(animation.frame().is_some());
+    let frame = animation.frame().unwrap();
+    assert_eq!(123, frame.tile_id());
+    assert_eq!(500, frame.duration());
+
+    let tile5 = tileset.tiles().nth(4).unwrap();
+    assert_eq!(Some(0.5), tile5.probability());
+
+    let tile6 = tileset.tiles().nth(5).unwrap();
+    let corners = tile6.terrain().unwrap();
+    assert_eq!(Some(0), corners.top_left());
+    assert_eq!(Some(1), corners.top_right());
+    assert_eq!(Some(2), corners.bottom_left());
+    assert_eq!(Some(3), corners.bottom_right());
+    assert_eq!([Some(0), Some(1), Some(2), Some(3)], corners.corners());
+}
+
+#[test]
+fn expect_typed_property_shortcuts_on_every_element_with_properties() {
+    fn check<T: HasProperties>(element: &T, what: &str) {
+        assert_eq!(Some(true), element.property_bool("solid"), "{}", what);
+        assert_eq!(Some(-3), element.property_int("depth"), "{}", what);
+        assert_eq!(Some(0.5), element.property_float("speed"), "{}", what);
+        assert_eq!(Some("crate"), element.property_str("name"), "{}", what);
+        assert_eq!(Some(Color::rgb(255, 0, 0)), element.property_color("tint"), "{}", what);
+        // Missing properties and properties of another type give nothing.
+        assert_eq!(None, element.property_bool("missing"), "{}", what);
+        assert_eq!(None, element.property_int("speed"), "{}", what);
+        assert_eq!(None, element.property_str("solid"), "{}", what);
+    }
+
+    let properties = r##"<properties>
+        <property name="solid" type="bool" value="true"/>
+        <property name="depth" type="int" value="-3"/>
+        <property name="speed" type="float" value="0.5"/>
+        <property name="name" value="crate"/>
+        <property name="tint" type="color" value="#ffff0000"/>
+    </properties>"##;
+    let xml = format!(r#"<map width="1" height="1" tilewidth="8" tileheight="8">{0}
+        <tileset firstgid="1" name="t" tilewidth="8" tileheight="8">{0}<tile id="0">{0}</tile></tileset>
+        <layer name="l" width="1" height="1">{0}</layer>
+        <imagelayer name="i">{0}</imagelayer>
+        <objectgroup name="o">{0}<object id="1">{0}</object></objectgroup>
+    </map>"#, properties);
+    let map = read_map(&xml).unwrap();
+
+    check(&map, "map");
+    let tileset = map.tilesets().next().unwrap();
+    check(tileset, "tileset");
+    check(tileset.tiles().next().unwrap(), "tile");
+    check(map.layers().next().unwrap(), "layer");
+    check(map.image_layers().next().unwrap(), "image layer");
+    let group = map.object_groups().next().unwrap();
+    check(group, "object group");
+    check(&group[0], "object");
+}
+
+#[test]
+fn expect_file_properties_to_expose_their_path() {
+    let map = read_map(r#"<map>
+        <properties>
+            <property name="a" type="file" value="images/tiles.png"/>
+            <property name="b" type="file" value=""/>
+            <property name="c" value="images/tiles.png"/>
+        </properties>
+    </map>"#).unwrap();
+    let mut props = map.properties();
+
+    let a = props.next().unwrap();
+    assert_eq!(Some("images/tiles.png"), a.as_file());
+    assert_eq!(Some(PathBuf::from("images/tiles.png")), map.resolve_file_property(a));
+
+    let b = props.next().unwrap();
+    assert_eq!(None, b.as_file());
+    assert_eq!(None, map.resolve_file_property(b));
+
+    let c = props.next().unwrap();
+    assert_eq!(None, c.as_file());
+}
+
+struct MemoryLoader {
+    files: HashMap<String, &'static str>,
+    requests: Vec<String>,
+}
+
+impl ResourceLoader for MemoryLoader {
+    fn load(&mut self, path: &str) -> ::Result<Box<dyn Read>> {
+        self.requests.push(path.to_string());
+        match self.files.get(path) {
+            Some(content) => Ok(Box::new(content.as_bytes())),
+            None => Err(Error::Io(io::Error::new(io::ErrorKind::NotFound, path.to_string()))),
+        }
+    }
+}
+
+#[test]
+fn when_opening_map_with_loader_expect_external_tilesets_to_be_read_through_loader() {
+    let mut files = HashMap::new();
+    files.insert(Path::new("levels").join("level1.tmx").to_string_lossy().into_owned(), r#"<map width="1" height="1" tilewidth="16" tileheight="16">
+        <tileset firstgid="1" source="../tiles/ground.tsx"/>
+        <tileset firstgid="2" name="embedded" tilewidth="16" tileheight="16"/>
+    </map>"#);
+    files.insert(Path::new("tiles").join("ground.tsx").to_string_lossy().into_owned(),
+                 r#"<tileset name="ground" tilewidth="16" tileheight="16"/>"#);
+    let mut loader = MemoryLoader { files, requests: Vec::new() };
+
+    let map = Map::open_with_loader(&Path::new("levels").join("level1.tmx").to_string_lossy(), &mut loader).unwrap();
+    assert_eq!(2, loader.requests.len());
+    let tileset = map.tilesets().next().unwrap();
+    assert_eq!(1, tileset.first_gid());
+    assert_eq!("ground", tileset.name());
+    assert_eq!(16, tileset.tile_width());
+
+    // Loaders other than the filesystem keep the paths as they are.
+    let map_path = Path::new("levels").join("level1.tmx");
+    assert_eq!(Some(map_path.as_path()), map.source_path());
+    assert_eq!(Some(Path::new("levels")), map.base_dir());
+    assert_eq!(Some(Path::new("tiles").join("ground.tsx").as_path()), tileset.source_path());
+    assert_eq!(Some(map_path.as_path()), map.tilesets().nth(1).unwrap().source_path());
+}
+
+#[test]
+fn when_opening_map_with_loader_expect_external_tilesets_to_keep_their_first_gid() {
+    let mut files = HashMap::new();
+    files.insert("level1.tmx".to_string(), r#"<map width="1" height="1" tilewidth="16" tileheight="16">
+        <tileset firstgid="1" source="ground.tsx"/>
+        <tileset firstgid="257" source="walls.tsx"/>
+    </map>"#);
+    files.insert("ground.tsx".to_string(), r#"<tileset name="ground" tilewidth="16" tileheight="16" tilecount="256"/>"#);
+    files.insert("walls.tsx".to_string(), r#"<tileset name="walls" tilewidth="16" tileheight="16" tilecount="64"/>"#);
+    let mut loader = MemoryLoader { files, requests: Vec::new() };
+
+    let map = Map::open_with_loader("level1.tmx", &mut loader).unwrap();
+    let summary: Vec<_> = map.tilesets()
+        .map(|tileset| (tileset.first_gid(), tileset.source(), tileset.name(), tileset.is_resolved()))
+        .collect();
+    assert_eq!(vec![(1, "ground.tsx", "ground", true), (257, "walls.tsx", "walls", true)], summary);
+    assert_eq!(Some("ground"), map.tileset_for_gid(256).map(Tileset::name));
+    assert_eq!(Some("walls"), map.tileset_for_gid(257).map(Tileset::name));
+    assert_eq!(Some("walls"), map.tileset_for_gid(257 | 0x80000000).map(Tileset::name));
+
+    let written = map.to_xml_string().unwrap();
+    assert!(written.contains(r#"<tileset firstgid="257" source="walls.tsx"/>"#), "{}", written);
+}
+
+#[test]
+fn when_opening_map_with_loader_and_missing_tileset_expect_error_only_if_required() {
+    let mut files = HashMap::new();
+    files.insert("level1.tmx".to_string(),
+                 r#"<map width="1" height="1" tilewidth="16" tileheight="16"><tileset firstgid="1" source="missing.tsx"/></map>"#);
+    let mut loader = MemoryLoader { files, requests: Vec::new() };
+    let options = ReaderOptions::new().with_require_tilesets(true);
+    let result = Map::load("level1.tmx", &mut loader, &options);
+    assert_matches!(result, Err(Error::File { ref path, ref source })
+                    if path == Path::new("missing.tsx") && matches!(**source, Error::Io(..)));
+    assert_eq!(vec!["level1.tmx".to_string(), "missing.tsx".to_string()], loader.requests);
+
+    let map = Map::open_with_loader("level1.tmx", &mut loader).unwrap();
+    assert!(map.tilesets().next().unwrap().is_external());
+}
+
+#[test]
+fn after_reading_valid_xml_with_tiles_expect_tiles_to_be_found_by_id() {
+    let tileset = Tileset::from_str(r#"
+    <tileset>
+        <tile id="7" probability="0.1"/>
+        <tile id="3"/>
+        <tile id="7" probability="0.9"/>
+    </tileset>"#).unwrap();
+    assert_eq!(3, tileset.tile_by_id(3).unwrap().id());
+    assert_eq!(Some(0.1), tileset.tile_by_id(7).unwrap().probability());
+    assert!(tileset.tile_by_id(0).is_none());
+}
+
+#[test]
+fn expect_point_to_be_constructible_from_comma_separated_pair_of_numbers() {
+    assert_matches!(Point::from_str(""), Err(Error::InvalidPoint(..)));
+    assert_eq!(Point { x: 1.0, y: 2.0 }, Point::from_str("1,2").unwrap());
+    assert_eq!(Point { x: 10.5, y: -3.25 }, Point::from_str("10.5,-3.25").unwrap());
+    assert_eq!(Point { x: 0.000001, y: -2e10 }, Point::from_str("1e-06,-2E10").unwrap());
+    assert_matches!(Point::from_str("a,2"), Err(Error::InvalidNumber(..)));
+    assert_matches!(Point::from_str("1,b"), Err(Error::InvalidNumber(..)));
+    assert_matches!(Point::from_str("1,2,3"), Err(Error::InvalidPoint(..)));
+}
+
+#[test]
+fn expect_points_to_support_vector_arithmetic() {
+    let a = Point::new(1.0, 2.0);
+    let b = Point::new(4.0, -2.0);
+    assert_eq!(Point::new(5.0, 0.0), a + b);
+    assert_eq!(Point::new(3.0, -4.0), b - a);
+    assert_eq!(Point::new(-1.0, -2.0), -a);
+    assert_eq!(Point::new(2.5, 5.0), a * 2.5);
+    assert_eq!(Point::new(2.0, -1.0), b / 2.0);
+
+    assert_eq!(Point::new(1.5, -0.5), Point::from((1.5, -0.5)));
+    assert_eq!((1.0, 2.0), a.into());
+
+    assert_eq!(5.0, a.distance_to(b));
+    assert_eq!(5.0, b.distance_to(a));
+    assert_eq!(0.0, a.distance_to(a));
+
+    assert_eq!(a, a.lerp(b, 0.0));
+    assert_eq!(b, a.lerp(b, 1.0));
+    assert_eq!(Point::new(2.5, 0.0), a.lerp(b, 0.5));
+    assert_eq!(Point::new(7.0, -6.0), a.lerp(b, 2.0));
+}
+
+#[test]
+fn after_writing_map_with_fractional_polygon_points_expect_same_points() {
+    let map = read_map(r#"<map version="1.0"><objectgroup>
+        <object id="1"><polygon points="0,0 10.5,3.25 -7.2,9"/></object>
+        <object id="2"><polyline points="1e-06,0 2,-0.5"/></object>
+    </objectgroup></map>"#).unwrap();
+    let written = map.to_xml_string().unwrap();
+    assert!(written.contains(r#"points="0,0 10.5,3.25 -7.2,9""#));
+    assert!(written.contains(r#"points="0.000001,0 2,-0.5""#));
+
+    let reread = read_map(&written).unwrap();
+    let objects: Vec<_> = reread.object_groups().next().unwrap().objects().collect();
+    if let Some(&Shape::Polygon(ref polygon)) = objects[0].shape() {
+        let points: Vec<_> = polygon.points().map(|p| (p.x, p.y)).collect();
+        assert_eq!(vec![(0.0, 0.0), (10.5, 3.25), (-7.2, 9.0)], points);
+    } else {
+        panic!("expected a polygon");
+    }
+}
+
+fn assert_aabb_eq(expected: Aabb, actual: Aabb) {
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+    assert!(close(expected.min_x, actual.min_x) && close(expected.min_y, actual.min_y) &&
+            close(expected.max_x, actual.max_x) && close(expected.max_y, actual.max_y),
+            "expected {:?}, got {:?}", expected, actual);
+}
+
+#[test]
+fn when_computing_object_bounding_boxes_expect_rotation_to_be_applied() {
+    let map = read_map(r#"<map>
+        <objectgroup>
+            <object id="1" x="10" y="20" width="30" height="10"/>
+            <object id="2" x="10" y="20" width="30" height="10" rotation="90"/>
+            <object id="3" x="10" y="20" width="30" height="10" rotation="90" gid="1"/>
+            <object id="4" x="10" y="20" rotation="180"><polygon points="0,0 4,0 4,2"/></object>
+            <object id="5" x="10" y="20" width="20" height="10" rotation="90"><ellipse/></object>
+            <object id="6" x="10" y="20"><point/></object>
+        </objectgroup>
+    </map>"#).unwrap();
+    let group = map.object_groups().next().unwrap();
+
+    assert_eq!(Aabb::new(10.0, 20.0, 40.0, 30.0), group[0].bounding_box());
+    // Rotating clockwise around the top-left corner swings the rectangle to the left.
+    assert_aabb_eq(Aabb::new(0.0, 20.0, 10.0, 50.0), group[1].bounding_box());
+    // Tile objects rotate around their bottom-left corner.
+    assert_aabb_eq(Aabb::new(10.0, 20.0, 20.0, 50.0), group[2].bounding_box());
+    assert_aabb_eq(Aabb::new(6.0, 18.0, 10.0, 20.0), group[3].bounding_box());
+    assert_aabb_eq(Aabb::new(0.0, 20.0, 10.0, 40.0), group[4].bounding_box());
+    assert_eq!(Aabb::new(10.0, 20.0, 10.0, 20.0), group[5].bounding_box());
+}
+
+#[test]
+fn when_resolving_tile_objects_expect_tileset_and_tile_data() {
+    let map = read_map(r#"<map width="4" height="4" tilewidth="16" tileheight="16">
+        <tileset firstgid="1" name="ground" tilewidth="16" tileheight="16" tilecount="4"/>
+        <tileset firstgid="5" name="props" tilewidth="16" tileheight="16" tilecount="4">
+            <tile id="2"><properties><property name="solid" type="bool" value="true"/></properties></tile>
+        </tileset>
+        <objectgroup>
+            <object id="1" gid="7" x="0" y="16"/>
+            <object id="2" gid="3221225479" x="0" y="16"/>
+            <object id="3" gid="2" x="0" y="16"/>
+            <object id="4" x="0" y="16"/>
+            <object id="5" gid="2147483648" x="0" y="16"/>
+        </objectgroup>
+    </map>"#).unwrap();
+    let group = map.object_groups().next().unwrap();
+    let resolve = |object: &Object| {
+        map.tile_for_object(object).map(|(tileset, tile)| (tileset.name().to_string(), tile.map(Tile::id)))
+    };
+
+    assert_eq!(Some(("props".to_string(), Some(2))), resolve(&group[0]));
+    // Flip flags are ignored.
+    assert_eq!(Some(("props".to_string(), Some(2))), resolve(&group[1]));
+    let (_, tile) = map.tile_for_object(&group[1]).unwrap();
+    assert_eq!(Some(true), tile.and_then(|tile| tile.property("solid")).and_then(Property::as_bool));
+    // Tiles without extra data still have a tileset.
+    assert_eq!(Some(("ground".to_string(), None)), resolve(&group[2]));
+    assert_eq!(None, resolve(&group[3]));
+    assert_eq!(None, resolve(&group[4]));
+}
+
+#[test]
+fn when_resolving_tile_object_properties_expect_object_to_override_tile() {
+    let map = read_map(r#"<map width="4" height="4" tilewidth="16" tileheight="16">
+        <tileset firstgid="1" name="props" tilewidth="16" tileheight="16" tilecount="4">
+            <tile id="0">
+                <properties>
+                    <property name="solid" type="bool" value="true"/>
+                    <property name="weight" type="int" value="5"/>
+                </properties>
+            </tile>
+        </tileset>
+        <objectgroup>
+            <object id="1" gid="1" x="0" y="16">
+                <properties>
+                    <property name="solid" type="bool" value="false"/>
+                    <property name="label" value="crate"/>
+                </properties>
+            </object>
+            <object id="2" gid="2" x="0" y="16"/>
+            <object id="3" x="0" y="16"><properties><property name="label" value="zone"/></properties></object>
+        </objectgroup>
+    </map>"#).unwrap();
+    let group = map.object_groups().next().unwrap();
+
+    let properties = group[0].resolved_properties(&map);
+    let names: Vec<_> = properties.iter().map(Property::name).collect();
+    assert_eq!(vec!["solid", "label", "weight"], names);
+    assert_eq!(Some(false), properties.get("solid").and_then(Property::as_bool));
+    assert_eq!(Some(5), properties.get("weight").and_then(Property::as_int));
+    // The object itself is left as it is.
+    assert_eq!(2, group[0].properties().count());
+
+    assert!(group[1].resolved_properties(&map).is_empty());
+    assert_eq!(1, group[2].resolved_properties(&map).len());
+}
+
+#[test]
+fn when_computing_tile_draw_rects_on_orthogonal_maps_expect_bottom_left_alignment() {
+    let map = read_map(r#"<map orientation="orthogonal" width="10" height="10" tilewidth="32" tileheight="32">
+        <tileset firstgid="1" name="tiles" tilewidth="32" tileheight="32" tilecount="4" columns="2">
+            <image source="tiles.png" width="64" height="64"/>
+        </tileset>
+        <tileset firstgid="5" name="shifted" tilewidth="32" tileheight="32" tilecount="4" columns="2" objectalignment="center">
+            <tileoffset x="4" y="-8"/>
+            <image source="tiles.png" width="64" height="64"/>
+        </tileset>
+        <tileset firstgid="9" name="props">
+            <tile id="0"><image source="tree.png" width="20" height="40"/></tile>
+        </tileset>
+        <objectgroup>
+            <object id="1" gid="1" x="64" y="96"/>
+            <object id="2" gid="2147483649" x="64" y="96" width="64" height="64"/>
+            <object id="3" gid="5" x="64" y="96" width="64" height="64"/>
+            <object id="4" gid="9" x="0" y="40"/>
+            <object id="5" x="64" y="96" width="32" height="32"/>
+            <object id="6" gid="20" x="64" y="96"/>
+        </objectgroup>
+    </map>"#).unwrap();
+    let group = map.object_groups().next().unwrap();
+
+    assert_eq!(Some(Aabb::new(64.0, 64.0, 96.0, 96.0)), group[0].tile_draw_rect(&map));
+    // Flip flags are ignored and the tile is scaled to the size of the object.
+    assert_eq!(Some(Aabb::new(64.0, 32.0, 128.0, 96.0)), group[1].tile_draw_rect(&map));
+    // The tile offset is scaled along with the tile.
+    assert_eq!(Some(Aabb::new(40.0, 48.0, 104.0, 112.0)), group[2].tile_draw_rect(&map));
+    assert_eq!(Some(Aabb::new(0.0, 0.0, 20.0, 40.0)), group[3].tile_draw_rect(&map));
+    assert_eq!(None, group[4].tile_draw_rect(&map));
+    assert_eq!(None, group[5].tile_draw_rect(&map));
+}
+
+#[test]
+fn when_computing_tile_draw_rects_on_isometric_maps_expect_bottom_center_alignment() {
+    let xml = |alignment: &str| format!(r#"<map orientation="isometric" width="4" height="4" tilewidth="64" tileheight="32">
+        <tileset firstgid="1" name="tiles" tilewidth="64" tileheight="32" tilecount="1" columns="1" objectalignment="{}">
+            <image source="tiles.png" width="64" height="32"/>
+        </tileset>
+        <objectgroup><object id="1" gid="1" x="32" y="32"/></objectgroup>
+    </map>"#, alignment);
+
+    let map = read_map(&xml("unspecified")).unwrap();
+    assert_eq!(None, map.tilesets().next().unwrap().object_alignment());
+    // Object coordinates count `tileheight` pixels per cell: (32, 32) is the
+    // top corner of the diamond of cell (1, 1).
+    assert_eq!((128.0, 32.0), map.object_to_pixel(32.0, 32.0));
+    let object = &map.object_groups().next().unwrap()[0];
+    assert_eq!(Some(Aabb::new(96.0, 0.0, 160.0, 32.0)), object.tile_draw_rect(&map));
+
+    let map = read_map(&xml("topleft")).unwrap();
+    assert_eq!(Some(Anchor::TopLeft), map.tilesets().next().unwrap().object_alignment());
+    let object = &map.object_groups().next().unwrap()[0];
+    assert_eq!(Some(Aabb::new(128.0, 32.0, 192.0, 64.0)), object.tile_draw_rect(&map));
+    assert!(map.to_xml_string().unwrap().contains(r#"columns="1" objectalignment="topleft">"#));
+
+    assert_matches!(read_map(&xml("middle")), Err(Error::InvalidAttribute { ref cause, .. })
+                    if matches!(**cause, Error::BadObjectAlignment(ref value) if value == "middle"));
+}
+
+#[test]
+fn when_listing_animated_tiles_expect_static_tiles_to_be_skipped() {
+    let map = read_map(r#"<map width="1" height="1" tilewidth="8" tileheight="8">
+        <tileset firstgid="1" name="ground" tilewidth="8" tileheight="8" tilecount="4"/>
+        <tileset firstgid="5" name="water" tilewidth="8" tileheight="8" tilecount="8">
+            <tile id="0"><properties><property name="wet" type="bool" value="true"/></properties></tile>
+            <tile id="1"><animation><frame tileid="1" duration="100"/><frame tileid="2" duration="100"/></animation></tile>
+            <tile id="3" probability="0.5"/>
+            <tile id="6"><animation><frame tileid="7" duration="50"/></animation></tile>
+            <tile id="7"/>
+        </tileset>
+    </map>"#).unwrap();
+    let water = map.tilesets().nth(1).unwrap();
+
+    let animated: Vec<_> = water.animated_tiles().map(|(id, animation)| (id, animation.total_duration())).collect();
+    assert_eq!(vec![(1, 200), (6, 50)], animated);
+    assert_eq!(0, map.tilesets().next().unwrap().animated_tiles().count());
+
+    let animated: Vec<_> = map.animated_gids().map(|(gid, animation)| (gid, animation.frames().len())).collect();
+    assert_eq!(vec![(6, 2), (11, 1)], animated);
+
+    let (gid, animation) = map.animated_gids().next().unwrap();
+    let tileset = map.tileset_for_gid(gid).unwrap();
+    let frames: Vec<_> = animation.frames().map(|frame| tileset.gid_for(frame.tile_id())).collect();
+    assert_eq!(vec![Some(6), Some(7)], frames);
+    assert_eq!(None, tileset.gid_for(0x1fff_ffff));
+}
+
+#[test]
+fn after_reading_animated_tile_expect_frames_to_be_timed() {
+    let tileset = Tileset::from_str(r#"<tileset>
+        <tile id="0">
+            <animation>
+                <frame tileid="1" duration="100"/>
+                <frame tileid="2" duration="0"/>
+                <frame tileid="3" duration="300"/>
+            </animation>
+        </tile>
+        <tile id="1"><animation/></tile>
+    </tileset>"#).unwrap();
+
+    let animation = tileset.tile_by_id(0).and_then(Tile::animation).unwrap();
+    assert_eq!(3, animation.frames().len());
+    assert_eq!(1, animation.frame().unwrap().tile_id());
+    assert_eq!(400, animation.total_duration());
+    assert_eq!(Some(1), animation.frame_at(0).map(Frame::tile_id));
+    assert_eq!(Some(1), animation.frame_at(99).map(Frame::tile_id));
+    assert_eq!(Some(3), animation.frame_at(100).map(Frame::tile_id));
+    assert_eq!(Some(3), animation.frame_at(399).map(Frame::tile_id));
+    assert_eq!(Some(1), animation.frame_at(400).map(Frame::tile_id));
+    assert_eq!(Some(3), animation.frame_at(4250).map(Frame::tile_id));
+
+    let long = Animation::new(vec![Frame::new(1, u32::MAX), Frame::new(2, u32::MAX)]);
+    assert_eq!(2 * u64::from(u32::MAX), long.total_duration());
+    assert_eq!(Some(2), long.frame_at(u64::from(u32::MAX)).map(Frame::tile_id));
+
+    let empty = tileset.tile_by_id(1).and_then(Tile::animation).unwrap();
+    assert_eq!(0, empty.total_duration());
+    assert!(empty.frame().is_none());
+    assert!(empty.frame_at(10).is_none());
+}
+
+#[test]
+fn when_advancing_animation_state_expect_frame_at_each_timestamp() {
+    let tileset = Tileset::from_str(r#"<tileset>
+        <tile id="0">
+            <animation>
+                <frame tileid="4" duration="100"/>
+                <frame tileid="5" duration="250"/>
+                <frame tileid="6" duration="50"/>
+            </animation>
+        </tile>
+        <tile id="1">
+            <animation>
+                <frame tileid="7" duration="0"/>
+                <frame tileid="8" duration="10"/>
+                <frame tileid="9" duration="0"/>
+            </animation>
+        </tile>
+        <tile id="2"><animation/></tile>
+    </tileset>"#).unwrap();
+
+    let mut state = AnimationState::for_tile(&tileset, 0).unwrap();
+    assert_eq!(Some(4), state.tile_id());
+    // Timestamps 99, 100, 349, 350, 399, 400 and 1000.
+    assert_eq!(Some(4), state.advance(99));
+    assert_eq!(Some(5), state.advance(1));
+    assert_eq!(Some(5), state.advance(249));
+    assert_eq!(Some(6), state.advance(1));
+    assert_eq!(Some(6), state.advance(49));
+    assert_eq!(Some(4), state.advance(1));
+    assert_eq!(Some(5), state.advance(600));
+    // Several cycles at once, landing at 3170, which is 370 into a cycle.
+    assert_eq!(Some(6), state.advance(2170));
+    assert!(!state.is_finished());
+    state.reset();
+    assert_eq!(Some(4), state.tile_id());
+    // 4294967295 is 95 into a cycle.
+    assert_eq!(Some(4), state.advance(u32::MAX));
+
+    let animation = tileset.tile_by_id(0).and_then(Tile::animation).unwrap();
+    let mut state = AnimationState::once(animation);
+    assert_eq!(Some(5), state.advance(300));
+    assert!(!state.is_finished());
+    assert_eq!(Some(6), state.advance(99));
+    assert_eq!(Some(6), state.advance(1));
+    assert!(state.is_finished());
+    assert_eq!(Some(6), state.advance(10_000));
+    state.reset();
+    assert!(!state.is_finished());
+    assert_eq!(Some(4), state.tile_id());
+
+    let mut state = AnimationState::for_tile(&tileset, 1).unwrap();
+    assert_eq!(Some(8), state.advance(5));
+    assert_eq!(Some(8), state.advance(10));
+    let animation = tileset.tile_by_id(1).and_then(Tile::animation).unwrap();
+    assert_eq!(Some(8), AnimationState::once(animation).advance(20));
+
+    let mut state = AnimationState::for_tile(&tileset, 2).unwrap();
+    assert_eq!(None, state.advance(16));
+    let animation = tileset.tile_by_id(2).and_then(Tile::animation).unwrap();
+    let mut state = AnimationState::once(animation);
+    assert!(state.is_finished());
+    assert_eq!(None, state.advance(16));
+    assert!(AnimationState::for_tile(&tileset, 3).is_none());
 }
 
 #[test]
-fn after_reading_valid_xml_expect_tileset_to_have_source() {
-    let tileset = get_simple_valid_tileset();
-    assert_eq!("some_file.tsx", tileset.source());
+fn after_reading_tile_with_collision_group_expect_collision_shapes_relative_to_tile() {
+    let tileset = Tileset::from_str(r#"<tileset tilewidth="32" tileheight="32">
+        <tile id="0">
+            <objectgroup draworder="index">
+                <object id="1" x="2" y="4" width="10" height="6"/>
+                <object id="2" x="16" y="16" width="8" height="4"><ellipse/></object>
+                <object id="3" x="8" y="8" rotation="90"><polygon points="0,0 4,0 0,2"/></object>
+                <object id="4" x="0" y="30"><polyline points="0,0 32,0"/></object>
+                <object id="5" x="1" y="1"><point/></object>
+                <object id="6" x="0" y="0" width="32" height="32" visible="0"/>
+            </objectgroup>
+        </tile>
+    </tileset>"#).unwrap();
+    let tile = tileset.tile_by_id(0).unwrap();
+
+    let shapes: Vec<_> = tile.collision_shapes(false).collect();
+    assert_eq!(4, shapes.len());
+    assert_eq!(CollisionShape::Rect { x: 2.0, y: 4.0, width: 10.0, height: 6.0, rotation: 0.0 }, shapes[0]);
+    assert_eq!(CollisionShape::Ellipse { center_x: 20.0, center_y: 18.0, radius_x: 4.0, radius_y: 2.0, rotation: 0.0 },
+               shapes[1]);
+    match shapes[2] {
+        CollisionShape::Polygon(ref points) => {
+            let expected = [(8.0, 8.0), (8.0, 12.0), (6.0, 8.0)];
+            assert_eq!(expected.len(), points.len());
+            for (&(ex, ey), &(x, y)) in expected.iter().zip(points) {
+                assert!((ex - x).abs() < 1e-9 && (ey - y).abs() < 1e-9, "{:?}", points);
+            }
+        }
+        ref shape => panic!("expected a polygon, got {:?}", shape),
+    }
+    assert_eq!(CollisionShape::Polyline(vec![(0.0, 30.0), (32.0, 30.0)]), shapes[3]);
+
+    assert_eq!(5, tile.collision_shapes(true).count());
+    assert_eq!(0, Tile::default().collision_shapes(true).count());
 }
 
 #[test]
-fn after_reading_valid_xml_expect_tileset_to_have_columns() {
-    let tileset = get_simple_valid_tileset();
-    assert_eq!(24, tileset.columns());
+fn when_converting_tile_to_pixel_coordinates_expect_tiled_projections() {
+    let map = read_map(r#"<map orientation="orthogonal" width="4" height="4"
+        tilewidth="16" tileheight="8"/>"#).unwrap();
+    assert_eq!((0.0, 0.0), map.tile_to_pixel(0, 0).unwrap());
+    assert_eq!((48.0, 16.0), map.tile_to_pixel(3, 2).unwrap());
+    assert_eq!((-16.0, -24.0), map.tile_to_pixel(-1, -3).unwrap());
+
+    let map = read_map(r#"<map orientation="isometric" width="4" height="3"
+        tilewidth="64" tileheight="32"/>"#).unwrap();
+    assert_eq!((96.0, 0.0), map.tile_to_pixel(0, 0).unwrap());
+    assert_eq!((128.0, 16.0), map.tile_to_pixel(1, 0).unwrap());
+    assert_eq!((64.0, 16.0), map.tile_to_pixel(0, 1).unwrap());
+    assert_eq!((128.0, 80.0), map.tile_to_pixel(3, 2).unwrap());
+    assert_eq!((96.0, -32.0), map.tile_to_pixel(-1, -1).unwrap());
+    assert_eq!((-32.0, -32.0), map.tile_to_pixel(-3, 1).unwrap());
 }
 
 #[test]
-fn after_reading_valid_xml_with_image_element_expect_tileset_to_have_image() {
-    let tileset = Tileset::from_str(
-        r#"<tileset>
-        <image format="png"
-               source="some_file.png"
-               trans="FF00FF"
-               width="1024"
-               height="768">
-            <data encoding="base64" compression="gzip"></data>
-        </image>
-    <tileset>"#).unwrap();
-    let image = tileset.image().unwrap();
-    assert_eq!("png", image.format());
-    assert_eq!("some_file.png", image.source());
-    assert_eq!(Some(&Color(255, 255, 0, 255)), image.trans());
-    assert_eq!(1024, image.width());
-    assert_eq!(768, image.height());
-    assert!(image.data().is_some());
+fn when_converting_tile_to_pixel_on_hexagonal_maps_expect_stagger_to_be_applied() {
+    let hex_map = |axis: &str, index: &str| {
+        read_map(&format!(r#"<map orientation="hexagonal" width="4" height="4"
+            tilewidth="32" tileheight="32" hexsidelength="16"
+            staggeraxis="{}" staggerindex="{}"/>"#, axis, index)).unwrap()
+    };
+
+    let map = hex_map("x", "odd");
+    assert_eq!((0.0, 0.0), map.tile_to_pixel(0, 0).unwrap());
+    assert_eq!((24.0, 16.0), map.tile_to_pixel(1, 0).unwrap());
+    assert_eq!((48.0, 32.0), map.tile_to_pixel(2, 1).unwrap());
+    assert_eq!((72.0, 112.0), map.tile_to_pixel(3, 3).unwrap());
+
+    let map = hex_map("x", "even");
+    assert_eq!((0.0, 16.0), map.tile_to_pixel(0, 0).unwrap());
+    assert_eq!((24.0, 0.0), map.tile_to_pixel(1, 0).unwrap());
+    assert_eq!((48.0, 48.0), map.tile_to_pixel(2, 1).unwrap());
+    assert_eq!((72.0, 96.0), map.tile_to_pixel(3, 3).unwrap());
+
+    let map = hex_map("y", "odd");
+    assert_eq!((0.0, 0.0), map.tile_to_pixel(0, 0).unwrap());
+    assert_eq!((16.0, 24.0), map.tile_to_pixel(0, 1).unwrap());
+    assert_eq!((32.0, 48.0), map.tile_to_pixel(1, 2).unwrap());
+    assert_eq!((112.0, 72.0), map.tile_to_pixel(3, 3).unwrap());
+
+    let map = hex_map("y", "even");
+    assert_eq!((16.0, 0.0), map.tile_to_pixel(0, 0).unwrap());
+    assert_eq!((0.0, 24.0), map.tile_to_pixel(0, 1).unwrap());
+    assert_eq!((48.0, 48.0), map.tile_to_pixel(1, 2).unwrap());
+    assert_eq!((96.0, 72.0), map.tile_to_pixel(3, 3).unwrap());
+
+    let map = read_map(r#"<map orientation="hexagonal" tilewidth="32" tileheight="32"
+        staggeraxis="x" staggerindex="odd"/>"#).unwrap();
+    assert_matches!(map.tile_to_pixel(0, 0), Err(Error::MissingAttribute { ref attribute, .. }) if attribute == "hexsidelength");
 }
 
 #[test]
-fn after_reading_valid_xml_with_properties_expect_tileset_to_have_properties() {
-    let tileset = Tileset::from_str(
-        r#"<tileset>
+fn when_getting_stagger_params_expect_them_to_depend_on_orientation() {
+    let map = read_map(r#"<map orientation="hexagonal" tilewidth="32" tileheight="32"
+        hexsidelength="16" staggeraxis="x" staggerindex="even"/>"#).unwrap();
+    let stagger = StaggerParams { axis: Axis::X, index: Index::Even };
+    assert_eq!(Some(HexParams { side_length: 16, stagger }), map.hex_params());
+    assert_eq!(Some(stagger), map.stagger_params());
+    assert_matches!(map.validate(), Ok(()));
+
+    let map = read_map(r#"<map orientation="staggered" tilewidth="64" tileheight="32"/>"#).unwrap();
+    assert_eq!(Some(StaggerParams { axis: Axis::Y, index: Index::Odd }), map.stagger_params());
+    assert_eq!(None, map.hex_params());
+
+    let map = read_map(r#"<map orientation="orthogonal" tilewidth="32" tileheight="32"
+        staggeraxis="x" staggerindex="even"/>"#).unwrap();
+    assert_eq!(None, map.stagger_params());
+    assert_eq!(None, map.hex_params());
+    assert_matches!(map.validate(), Ok(()));
+
+    let map = read_map(r#"<map orientation="hexagonal" tilewidth="32" tileheight="32"
+        hexsidelength="16" staggeraxis="x"/>"#).unwrap();
+    assert_eq!(None, map.hex_params());
+    assert_eq!(None, map.stagger_params());
+    assert_matches!(map.validate(), Err(Error::MissingAttribute { ref attribute, .. }) if attribute == "staggerindex");
+}
+
+#[test]
+fn when_converting_tile_to_pixel_on_staggered_maps_expect_alternate_rows_to_be_shifted() {
+    let map = read_map(r#"<map orientation="staggered" width="4" height="4"
+        tilewidth="64" tileheight="32" staggeraxis="y" staggerindex="odd"/>"#).unwrap();
+    assert_eq!((0.0, 0.0), map.tile_to_pixel(0, 0).unwrap());
+    assert_eq!((32.0, 16.0), map.tile_to_pixel(0, 1).unwrap());
+    assert_eq!((128.0, 32.0), map.tile_to_pixel(2, 2).unwrap());
+    assert_eq!((224.0, 48.0), map.tile_to_pixel(3, 3).unwrap());
+    assert_eq!((-32.0, -16.0), map.tile_to_pixel(-1, -1).unwrap());
+}
+
+#[test]
+fn when_sorting_objects_expect_draw_order_to_be_honored() {
+    let map = read_map(r#"<map>
+        <objectgroup draworder="topdown">
+            <object id="1" x="0" y="64" width="32" height="32" gid="1"/>
+            <object id="2" x="16" y="48" width="32" height="32" gid="1"/>
+            <object id="3" x="8" y="64" width="32" height="32" gid="1"/>
+            <object id="4" x="4" y="40" width="32" height="32" gid="1"/>
+        </objectgroup>
+        <objectgroup draworder="index">
+            <object id="1" x="0" y="64" width="32" height="32" gid="1"/>
+            <object id="2" x="16" y="48" width="32" height="32" gid="1"/>
+        </objectgroup>
+    </map>"#).unwrap();
+    let mut groups = map.object_groups();
+
+    let ids: Vec<_> = groups.next().unwrap().objects_sorted().iter().map(|object| object.id()).collect();
+    assert_eq!(vec![4, 2, 1, 3], ids);
+
+    let ids: Vec<_> = groups.next().unwrap().objects_sorted().iter().map(|object| object.id()).collect();
+    assert_eq!(vec![1, 2], ids);
+}
+
+#[test]
+fn when_getting_object_shapes_in_map_space_expect_position_and_rotation_applied() {
+    let map = read_map(r#"<map>
+        <objectgroup>
+            <object id="1" x="100" y="50" rotation="30"><polygon points="0,0 20,0 20,10"/></object>
+            <object id="2" x="10" y="20" width="8" height="4" rotation="45"><ellipse/></object>
+            <object id="3" x="10" y="20" width="32" height="16" rotation="90" gid="1"/>
+            <object id="4" x="7" y="9"><point/></object>
+            <object id="5" x="1" y="2"><polyline points="0,0 3,4"/></object>
+        </objectgroup>
+    </map>"#).unwrap();
+    let group = map.object_groups().next().unwrap();
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-6;
+
+    match group[0].shape_in_map_space() {
+        Some(WorldShape::Polygon(points)) => {
+            let expected = [(100.0, 50.0), (117.320508, 60.0), (112.320508, 68.660254)];
+            for (&(ex, ey), &(x, y)) in expected.iter().zip(&points) {
+                assert!(close(ex, x) && close(ey, y), "{:?}", points);
+            }
+        }
+        shape => panic!("expected a polygon, got {:?}", shape),
+    }
+    match group[1].shape_in_map_space() {
+        Some(WorldShape::Ellipse { center_x, center_y, radius_x, radius_y, rotation }) => {
+            assert!(close(10.0 + 1.0 * 2f64.sqrt(), center_x) && close(20.0 + 3.0 * 2f64.sqrt(), center_y));
+            assert_eq!((4.0, 2.0, 45.0), (radius_x, radius_y, rotation));
+        }
+        shape => panic!("expected an ellipse, got {:?}", shape),
+    }
+    match group[2].shape_in_map_space() {
+        Some(WorldShape::Rect { x, y, width, height, rotation }) => {
+            assert!(close(26.0, x) && close(20.0, y));
+            assert_eq!((32.0, 16.0, 90.0), (width, height, rotation));
+        }
+        shape => panic!("expected a rectangle, got {:?}", shape),
+    }
+    assert_eq!(Some(WorldShape::Point(7.0, 9.0)), group[3].shape_in_map_space());
+    assert_eq!(Some(WorldShape::Polyline(vec![(1.0, 2.0), (4.0, 6.0)])), group[4].shape_in_map_space());
+}
+
+#[test]
+fn after_reading_map_version_expect_typed_format_version() {
+    let map = read_map(r#"<map version="1.10"/>"#).unwrap();
+    assert_eq!("1.10", map.version());
+    assert_eq!(Some(FormatVersion::new(1, 10)), map.format_version());
+    assert!(map.format_version().unwrap() > FormatVersion::new(1, 9));
+    assert!(map.format_version().unwrap() >= FormatVersion::new(1, 5));
+    assert!(FormatVersion::new(1, 0) < FormatVersion::new(1, 5));
+    assert!(FormatVersion::new(2, 0) > FormatVersion::new(1, 10));
+    assert_eq!("1.10", FormatVersion::new(1, 10).to_string());
+
+    let map = read_map(r#"<map version="1.0"/>"#).unwrap();
+    assert_eq!(Some(FormatVersion::new(1, 0)), map.format_version());
+
+    for version in &["", "1", "1.x", "1.2.3", "one.two"] {
+        let map = read_map(&format!(r#"<map version="{}"/>"#, version)).unwrap();
+        assert_eq!(None, map.format_version());
+    }
+    assert!(read_map("<map/>").unwrap().format_version().is_none());
+}
+
+#[test]
+fn when_writing_map_expect_same_document_after_reading_it_back() {
+    let xml = r##"<map version="1.0" orientation="hexagonal" renderorder="left-up" width="2" height="2"
+                      tilewidth="32" tileheight="32" hexsidelength="16" staggeraxis="x" staggerindex="even"
+                      backgroundcolor="#80ff0000" nextobjectid="4">
         <properties>
-            <property name="prop1_name" value="prop1_value"/>
-            <property name="prop2_name" value="0" type="int"/>
-            <property name="prop3_name" value="0.0" type="float"/>
-            <property name="prop4_name" value="true" type="bool"/>
+            <property name="title" value="A &quot;quoted&quot; &amp; escaped &lt;title&gt;"/>
+            <property name="speed" type="float" value="1.5"/>
+            <property name="hero" type="class" propertytype="Actor">
+                <properties><property name="hp" type="int" value="3"/></properties>
+            </property>
         </properties>
-    <tileset>"#).unwrap();
-    assert_eq!(4, tileset.properties().count());
-    let mut props = tileset.properties();
+        <tileset firstgid="1" name="tiles" tilewidth="32" tileheight="32" spacing="1" margin="2" tilecount="4" columns="2">
+            <tileoffset x="0" y="4"/>
+            <image source="tiles.png" trans="ff00ff" width="67" height="67"/>
+            <terraintypes><terrain name="Grass" tile="0"/></terraintypes>
+            <tile id="0" terrain="0,,0," probability="0.5">
+                <objectgroup draworder="index"><object id="1" x="0" y="0" width="32" height="16"/></objectgroup>
+                <animation><frame tileid="0" duration="100"/><frame tileid="1" duration="200"/></animation>
+            </tile>
+        </tileset>
+        <tileset firstgid="5" source="other.tsx"/>
+        <imagelayer name="Sky" offsetx="3" opacity="0.5"><image source="sky.png"/></imagelayer>
+        <layer name="Ground" width="2" height="2" visible="0">
+            <data encoding="base64" compression="zlib">eJxjZGBgYAJiZiBmAWIAAGAACw==</data>
+        </layer>
+        <objectgroup name="Things" color="#a0a0a4">
+            <object id="2" name="spawn" type="Spawn" x="10.5" y="20" rotation="45"><point/></object>
+            <object id="3" gid="2147483650" x="0" y="64" width="32" height="32" visible="0">
+                <polyline points="0,0 16,-8 32,0"/>
+            </object>
+        </objectgroup>
+        <layer name="Top" width="2" height="2">
+            <data><tile gid="1"/><tile gid="0"/><tile gid="0"/><tile gid="4"/></data>
+        </layer>
+    </map>"##;
+    let map = read_map(xml).unwrap();
+    let written = map.to_xml_string().unwrap();
+    assert!(written.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<map version=\"1.0\""));
+    let reread = read_map(&written).unwrap();
+    assert_eq!(written, reread.to_xml_string().unwrap());
+    assert_eq!(map, reread);
+
+    assert_eq!(map.background_color(), reread.background_color());
+    assert_eq!(Some(Axis::X), reread.stagger_axis());
+    let title = reread.property("title").unwrap();
+    assert_eq!(r#"A "quoted" & escaped <title>"#, title.value());
+    assert_eq!(1, reread.property("hero").unwrap().members().count());
+    let names: Vec<_> = reread.layer_stack().map(|layer| layer.name()).collect();
+    assert_eq!(vec!["Sky", "Ground", "Things", "Top"], names);
+    let tileset = reread.tilesets().next().unwrap();
+    assert_eq!(Some(Corners(Some(0), None, Some(0), None)), tileset.tiles().next().unwrap().terrain().cloned());
+    let ground = reread.layers().next().unwrap();
+    assert!(!ground.is_visible());
+    assert_eq!(&[1, 2, 3, 4][..], ground.data().unwrap().decode().unwrap());
+    let top = reread.layers().nth(1).unwrap();
+    assert_eq!(&[1, 0, 0, 4][..], top.data().unwrap().decode().unwrap());
+    let things = reread.object_groups().next().unwrap();
+    assert_matches!(things[0].shape(), Some(&Shape::Point));
+    assert_eq!(Some(2147483650), things[1].gid());
+    assert!(!things[1].is_visible());
+}
 
-    let prop1 = props.next().unwrap();
-    assert_eq!("prop1_name", prop1.name());
-    assert_eq!("prop1_value", prop1.value());
-    assert_eq!(PropertyType::String, prop1.property_type());
+#[test]
+fn when_comparing_maps_expect_content_to_be_compared() {
+    let xml = r#"<map width="2" height="1" tilewidth="8" tileheight="8">
+        <layer name="Ground" width="2" height="1"><data encoding="csv">1,2</data></layer>
+        <objectgroup name="Things"><object id="1" x="0.1" y="0"/></objectgroup>
+    </map>"#;
+    let map = read_map(xml).unwrap();
+    assert_eq!(map, read_map(xml).unwrap());
+
+    // Edited tile ids equal the ones read.
+    let mut edited = map.clone();
+    edited.layers_mut().next().unwrap().set_tile(1, 0, 2).unwrap();
+    assert_eq!(map, edited);
+    edited.layers_mut().next().unwrap().set_tile(1, 0, 3).unwrap();
+    assert_ne!(map, edited);
+
+    // Floating point values are compared exactly.
+    let mut moved = map.clone();
+    let object = moved.object_groups_mut().next().unwrap().objects_mut().next().unwrap();
+    object.set_x(0.3 - 0.2);
+    assert_ne!(map, moved);
+}
 
-    let prop2 = props.next().unwrap();
-    assert_eq!(PropertyType::Int, prop2.property_type());
+#[test]
+fn when_writing_map_expect_defaults_to_be_omitted() {
+    let map = read_map(r#"<map orientation="orthogonal" width="1" height="1" tilewidth="8" tileheight="8">
+        <layer name="Ground" width="1" height="1" opacity="1" visible="1"/>
+    </map>"#).unwrap();
+    let expected = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                    <map orientation=\"orthogonal\" renderorder=\"right-down\" width=\"1\" height=\"1\" tilewidth=\"8\" tileheight=\"8\">\n \
+                    <layer name=\"Ground\" width=\"1\" height=\"1\"/>\n\
+                    </map>\n";
+    assert_eq!(expected, map.to_xml_string().unwrap());
+}
 
-    let prop3 = props.next().unwrap();
-    assert_eq!(PropertyType::Float, prop3.property_type());
+#[test]
+fn when_writing_map_with_csv_encoding_expect_tiled_csv_layout() {
+    let map = read_map(r#"<map width="3" height="2">
+        <layer name="Ground" width="3" height="2">
+            <data><tile gid="1"/><tile gid="2"/><tile gid="3"/><tile gid="4"/><tile gid="5"/><tile gid="6"/></data>
+        </layer>
+    </map>"#).unwrap();
+    let options = WriteOptions::new().with_encoding(DataEncoding::Csv);
+    let written = map.to_xml_string_with_options(&options).unwrap();
+    let expected = " <layer name=\"Ground\" width=\"3\" height=\"2\">\n  \
+                    <data encoding=\"csv\">\n1,2,3,\n4,5,6\n</data>\n \
+                    </layer>\n";
+    assert!(written.contains(expected), "{}", written);
+
+    let reread = read_map(&written).unwrap();
+    let data = reread.layers().next().unwrap().data().unwrap();
+    assert_eq!(&[1, 2, 3, 4, 5, 6][..], data.decode().unwrap());
+}
 
-    let prop4 = props.next().unwrap();
-    assert_eq!(PropertyType::Bool, prop4.property_type());
+#[test]
+fn when_writing_map_with_base64_encoding_expect_compressed_data_to_decode_back() {
+    let map = read_map(r#"<map width="2" height="2">
+        <layer name="Ground" width="2" height="2"><data encoding="csv">1,2,3,2147483652</data></layer>
+        <layer name="Top" width="2" height="2"><data encoding="csv">0,0,7,0</data></layer>
+    </map>"#).unwrap();
+    let base64 = |compression| DataEncoding::Base64 { compression };
+    let compressions = [Compression::None, Compression::Gzip, Compression::Zlib,
+                        #[cfg(feature = "zstd")] Compression::Zstd];
+    for &compression in &compressions {
+        let options = WriteOptions::new().with_encoding(base64(compression));
+        let written = map.to_xml_string_with_options(&options).unwrap();
+        let reread = read_map(&written).unwrap();
+        let mut layers = reread.layers();
+        let data = layers.next().unwrap().data().unwrap();
+        assert_eq!(Some("base64"), data.encoding());
+        assert_eq!(base64(compression).attributes().1, data.compression());
+        assert_eq!(&[1, 2, 3, 2147483652][..], data.decode().unwrap());
+        assert_eq!(&[0, 0, 7, 0][..], layers.next().unwrap().data().unwrap().decode().unwrap());
+    }
+
+    let options = WriteOptions::new().with_encoding(base64(Compression::Zlib));
+    let written = map.to_xml_string_with_options(&options).unwrap();
+    assert!(written.contains("<data encoding=\"base64\" compression=\"zlib\">\n   eJ"), "{}", written);
+    assert!(written.contains("\n  </data>\n"), "{}", written);
 }
 
 #[test]
-fn after_reading_valid_xml_with_tile_offset_expect_tileset_to_have_tile_offset() {
-    let tileset = Tileset::from_str(
-        r#"<tileset>
-        <tileoffset x="0" y="1"/>
-    <tileset>"#).unwrap();
-    let offset = tileset.tile_offset().unwrap();
-    assert_eq!(0, offset.x());
-    assert_eq!(1, offset.y());
+#[cfg(not(feature = "zstd"))]
+fn when_zstd_feature_is_disabled_expect_unsupported_compression() {
+    let map = read_map(r#"<map width="1" height="1">
+        <layer name="Ground" width="1" height="1"><data encoding="base64" compression="zstd">KLUv/SAEIQAAAQAAAA==</data></layer>
+    </map>"#).unwrap();
+    let data = map.layers().next().unwrap().data().unwrap();
+    assert_matches!(data.decode(), Err(Error::UnsupportedCompression(ref compression)) if compression == "zstd");
+
+    let map = read_map(r#"<map width="1" height="1">
+        <layer name="Ground" width="1" height="1"><data encoding="csv">1</data></layer>
+    </map>"#).unwrap();
+    let options = WriteOptions::new().with_encoding(DataEncoding::Base64 { compression: Compression::Zstd });
+    assert_matches!(map.to_xml_string_with_options(&options), Err(Error::UnsupportedCompression(..)));
 }
 
 #[test]
-fn after_reading_valid_xml_with_terrains_expect_tileset_to_have_terrains() {
-    let tileset = Tileset::from_str(r#"
-    <tileset>
-        <terraintypes>
-            <terrain name="terrain1"/>
-            <terrain tile="tile-id">
-                <properties>
-                    <property/>
-                </properties>
-            </terrain>
-        </terraintypes>
-    <tileset>"#).unwrap();
-    assert_eq!(2, tileset.terrain_types().count());
-    let mut terrain_types = tileset.terrain_types();
+fn when_writing_map_with_layer_encoding_expect_override_and_verbatim_passthrough() {
+    let map = read_map(r#"<map width="2" height="2">
+        <layer name="Ground" width="2" height="2">
+            <data encoding="base64" compression="zlib">eJxjZGBgYAJiZiBmAWIAAGAACw==</data>
+        </layer>
+        <layer name="Top" width="2" height="2"><data encoding="csv">0,0,7,0</data></layer>
+    </map>"#).unwrap();
+    let options = WriteOptions::new()
+        .with_encoding(DataEncoding::Base64 { compression: Compression::Zlib })
+        .with_layer_encoding("Top", DataEncoding::Csv);
+    let written = map.to_xml_string_with_options(&options).unwrap();
+    assert!(written.contains("compression=\"zlib\">eJxjZGBgYAJiZiBmAWIAAGAACw==</data>"), "{}", written);
+    assert!(written.contains("<data encoding=\"csv\">0,0,7,0</data>"), "{}", written);
+}
 
-    let terrain1 = terrain_types.next().unwrap();
-    assert_eq!("terrain1", terrain1.name());
+#[test]
+fn when_building_layer_from_gids_expect_it_to_write_and_decode_back() {
+    let gids = vec![1, 2, 0, 3, 0x8000_0004, 5];
+    let layer = LayerBuilder::new("Generated", 3, 2, gids.clone())
+        .with_opacity(Opacity::new(0.75).unwrap())
+        .with_visible(false)
+        .with_offset(4, -8)
+        .with_tint_color(Color::rgb(255, 0, 0))
+        .with_property(Property::new("seed", "42", PropertyType::Int))
+        .build()
+        .unwrap();
+    assert_eq!(&gids[..], layer.data().unwrap().decode().unwrap());
+    assert_eq!(Some(&Color::rgb(255, 0, 0)), layer.tint_color());
+
+    let encodings = [None,
+                     Some(DataEncoding::Csv),
+                     Some(DataEncoding::Base64 { compression: Compression::None }),
+                     #[cfg(feature = "zstd")]
+                     Some(DataEncoding::Base64 { compression: Compression::Zstd })];
+    for &encoding in &encodings {
+        let options = WriteOptions { encoding, ..WriteOptions::default() };
+        let mut output = Vec::new();
+        TmxWriter::with_options(&mut output, options).write_element(&layer).unwrap();
+        let xml = format!("<map>{}</map>", String::from_utf8(output).unwrap());
+        let map = read_map(&xml).unwrap();
+        let reread = map.layers().next().unwrap();
+        assert_eq!(&gids[..], reread.data().unwrap().decode().unwrap(), "{}", xml);
+        assert_eq!(("Generated", 3, 2), (reread.name(), reread.width(), reread.height()));
+        assert_eq!((0.75, false, 4, -8), (reread.opacity().value(), reread.is_visible(), reread.offset_x(), reread.offset_y()));
+        assert_eq!(Some(&Color::rgb(255, 0, 0)), reread.tint_color());
+        assert_eq!(Some(42), reread.property("seed").and_then(Property::as_int));
+    }
+}
 
-    let terrain2 = terrain_types.next().unwrap();
-    assert_eq!("tile-id", terrain2.tile());
-    assert_eq!(1, terrain2.properties().count());
+#[test]
+fn when_building_layer_with_wrong_number_of_gids_expect_data_error() {
+    assert_matches!(Layer::from_gids("Ground", 2, 2, vec![1, 2, 3]), Err(Error::InvalidData(..)));
+    assert_matches!(Layer::from_gids("Ground", 2, 2, vec![1, 2, 3, 4, 5]), Err(Error::InvalidData(..)));
+    let layer = Layer::from_gids("Ground", 2, 2, vec![1, 2, 3, 4]).unwrap();
+    assert_eq!(&[1, 2, 3, 4][..], layer.data().unwrap().decode().unwrap());
 }
 
 #[test]
-fn after_reading_valid_xml_with_tiles_expect_tileset_to_be_iterable_over_tiles() {
-    let tileset = Tileset::from_str(r#"
-    <tileset>
-        <tile id="123">
-            <properties>
-                <property name="some_name" value="some_value"/>
-            </properties>
-        </tile>
-        <tile>
-            <image source="some_file.png" width="8" height="16"/>
-        </tile>
-        <tile>
-            <objectgroup/>
-        </tile>
-        <tile>
-            <animation>
-                <frame tileid="123" duration="500"/>
-            </animation>
+fn when_building_tileset_expect_tile_rects_tiles_and_tsx_to_match() {
+    let tileset = TilesetBuilder::new("Atlas", 16, 16)
+        .with_margin(1)
+        .with_spacing(2)
+        .with_image(Image::new("atlas.png", 54, 36))
+        .with_tile_offset(0, 4)
+        .with_property(Property::new("packed", "true", PropertyType::Bool))
+        .with_tile_property(3, Property::new("solid", "true", PropertyType::Bool))
+        .with_tile_animation(1, Animation::new(vec![Frame::new(1, 100), Frame::new(2, 150)]))
+        .with_tile_object_group(0, ObjectGroup::default())
+        .build()
+        .unwrap();
+    assert_eq!((3, 6), (tileset.columns(), tileset.tile_count()));
+    assert_eq!(Some(Rect::new(19, 19, 16, 16)), tileset.tile_rect(4));
+    assert_eq!(None, tileset.tile_rect(6));
+    assert_eq!(vec![0, 1, 3], tileset.tiles().map(Tile::id).collect::<Vec<_>>());
+    assert_eq!(Some(true), tileset.tile_by_id(3).and_then(|tile| tile.property("solid")).and_then(Property::as_bool));
+
+    let reread = Tileset::from_str(&tileset.to_xml_string().unwrap()).unwrap();
+    assert_ne!(tileset, reread);
+    let mut tileset = tileset;
+    // Standalone tilesets are written with the version of the format.
+    tileset.set_version("1.0");
+    assert_eq!(tileset, reread);
+}
+
+#[test]
+fn when_building_tileset_inconsistent_with_image_expect_tileset_error() {
+    let atlas = || TilesetBuilder::new("Atlas", 16, 16).with_image(Image::new("atlas.png", 64, 32));
+    assert_matches!(atlas().with_columns(3).build(), Err(Error::InvalidTileset(..)));
+    assert_matches!(atlas().with_tile_count(9).build(), Err(Error::InvalidTileset(..)));
+    assert_matches!(atlas().with_tile_property(8, Property::default()).build(), Err(Error::InvalidTileset(..)));
+    assert_matches!(atlas().with_tile_animation(2, Animation::new(vec![Frame::new(8, 100)])).build(),
+                    Err(Error::InvalidTileset(..)));
+    let tileset = atlas().with_columns(4).with_tile_count(6).with_tile_probability(5, 0.5).build().unwrap();
+    assert_eq!((4, 6), (tileset.columns(), tileset.tile_count()));
+    let huge = TilesetBuilder::new("Huge", 1, 1).with_image(Image::new("huge.png", 100000, 100000));
+    assert_matches!(huge.build(), Err(Error::InvalidTileset(..)));
+
+    let collection = TilesetBuilder::new("Props", 32, 32)
+        .with_tile_image(7, Image::new("crate.png", 32, 32))
+        .with_tile_image(2, Image::new("barrel.png", 24, 32))
+        .build()
+        .unwrap();
+    assert!(collection.is_image_collection());
+    assert_eq!(2, collection.tile_count());
+    assert_eq!(Some("crate.png"), collection.tile_image(7).map(Image::source));
+}
+
+#[derive(Default)]
+struct RecordingVisitor {
+    skipped_layer: &'static str,
+    stop_at_object_group: bool,
+    skip_after_object: bool,
+    events: Vec<String>,
+}
+
+impl MapVisitor for RecordingVisitor {
+    fn on_map_attributes(&mut self, map: &Map) -> Flow {
+        self.events.push(format!("map {}x{}", map.width(), map.height()));
+        Flow::Continue
+    }
+
+    fn on_map_properties(&mut self, properties: PropertyCollection) -> Flow {
+        self.events.push(format!("properties {}", properties.len()));
+        Flow::Continue
+    }
+
+    fn on_tileset(&mut self, tileset: Tileset) -> Flow {
+        self.events.push(format!("tileset {}", tileset.name()));
+        Flow::Continue
+    }
+
+    fn on_layer_begin(&mut self, layer: &Layer) -> Flow {
+        if layer.name() == self.skipped_layer {
+            return Flow::Skip;
+        }
+        self.events.push(format!("layer {}", layer.name()));
+        Flow::Continue
+    }
+
+    fn on_layer_data_chunk(&mut self, gids: &[u32]) -> Flow {
+        self.events.push(format!("row {:?}", gids));
+        Flow::Continue
+    }
+
+    fn on_layer_end(&mut self, layer: Layer) {
+        self.events.push(format!("end layer {} {}", layer.name(), layer.properties().count()));
+    }
+
+    fn on_object_group_begin(&mut self, object_group: &ObjectGroup) -> Flow {
+        self.events.push(format!("objectgroup {}", object_group.name()));
+        if self.stop_at_object_group { Flow::Stop } else { Flow::Continue }
+    }
+
+    fn on_object(&mut self, object: Object) -> Flow {
+        self.events.push(format!("object {}", object.name()));
+        if self.skip_after_object { Flow::Skip } else { Flow::Continue }
+    }
+
+    fn on_object_group_end(&mut self, object_group: ObjectGroup) {
+        self.events.push(format!("end objectgroup {}", object_group.name()));
+    }
+}
+
+const VISITED_MAP: &str = r#"<map width="2" height="2" tilewidth="8" tileheight="8">
+    <properties><property name="a" value="1"/></properties>
+    <tileset firstgid="1" name="Tiles" tilewidth="8" tileheight="8"/>
+    <layer name="Big" width="2" height="2">
+        <data encoding="bogus">not decoded</data>
+    </layer>
+    <layer name="Ground" width="2" height="2">
+        <properties><property name="b" value="2"/></properties>
+        <data encoding="csv">1,2,3,4</data>
+    </layer>
+    <objectgroup name="Spawns">
+        <object id="1" name="first"/>
+        <object id="2" name="second"/>
+    </objectgroup>
+</map>"#;
+
+fn visit(xml: &str, visitor: &mut RecordingVisitor) -> ::Result<()> {
+    TmxReader::new(xml.as_bytes()).visit_map(visitor)
+}
+
+#[test]
+fn when_visiting_map_expect_callbacks_in_document_order() {
+    let mut visitor = RecordingVisitor { skipped_layer: "Big", ..RecordingVisitor::default() };
+    visit(VISITED_MAP, &mut visitor).unwrap();
+    assert_eq!(vec!["map 2x2", "properties 1", "tileset Tiles", "layer Ground", "row [1, 2]", "row [3, 4]",
+                    "end layer Ground 1", "objectgroup Spawns", "object first", "object second",
+                    "end objectgroup Spawns"],
+               visitor.events);
+}
+
+#[test]
+fn when_visiting_map_with_groups_expect_their_layers_as_layers_of_the_map() {
+    let mut visitor = RecordingVisitor::default();
+    let mut reader = TmxReader::with_options(GROUP_MAP.as_bytes(), lenient_options());
+    reader.visit_map(&mut visitor).unwrap();
+    assert_eq!(vec!["map 2x2", "properties 1", "layer Ground", "end layer Ground 0", "layer Hills",
+                    "end layer Hills 0", "objectgroup Top", "end objectgroup Top"],
+               visitor.events);
+    assert_eq!(1, reader.take_diagnostics().iter().filter(|d| d.severity == Severity::Warning).count());
+}
+
+#[test]
+fn when_visiting_map_without_skipping_bad_layer_expect_its_data_to_be_decoded() {
+    let mut visitor = RecordingVisitor::default();
+    assert_matches!(visit(VISITED_MAP, &mut visitor), Err(Error::BadEncoding(_)));
+}
+
+#[test]
+fn when_visitor_skips_after_object_expect_rest_of_group_to_be_skipped() {
+    let mut visitor = RecordingVisitor { skipped_layer: "Big", skip_after_object: true, ..RecordingVisitor::default() };
+    visit(VISITED_MAP, &mut visitor).unwrap();
+    assert_eq!(&["object first", "end objectgroup Spawns"], &visitor.events[visitor.events.len() - 2..]);
+}
+
+#[test]
+fn when_visitor_stops_expect_rest_of_document_to_be_left_unread() {
+    let xml = r#"<map width="2" height="2" tilewidth="8" tileheight="8">
+        <objectgroup name="Spawns"/>
+        <objectgroup name="Triggers"><object id="x"/></objectgroup>
+    </map>
+    <map/>"#;
+    let mut visitor = RecordingVisitor { stop_at_object_group: true, ..RecordingVisitor::default() };
+    visit(xml, &mut visitor).unwrap();
+    assert_eq!(vec!["map 2x2", "objectgroup Spawns"], visitor.events);
+}
+
+#[test]
+fn when_visiting_map_without_required_attributes_expect_error() {
+    let mut visitor = RecordingVisitor::default();
+    assert_matches!(visit("<map/>", &mut visitor), Err(Error::MissingAttribute { .. }));
+}
+
+const SELECTIVE_MAP: &str = r#"<map width="2" height="1" tilewidth="8" tileheight="8">
+    <tileset firstgid="1" name="Tiles" tilewidth="8" tileheight="8">
+        <tile id="0">
+            <objectgroup><object id="1" width="8" height="8"/></objectgroup>
         </tile>
-        <tile probability="0.5"/>
-        <tile terrain="0,1,2,3"/>
-    </tileset>"#).unwrap();
-    assert_eq!(6, tileset.tiles().count());
+    </tileset>
+    <layer name="Ground" width="2" height="1">
+        <data encoding="csv">1,1</data>
+    </layer>
+    <objectgroup name="Spawns"><object id="2" name="spawn"/></objectgroup>
+    <imagelayer name="Sky"><image source="sky.png"/></imagelayer>
+</map>"#;
 
-    let tile1 = tileset.tiles().next().unwrap();
-    assert_eq!(123, tile1.id());
-    assert_eq!(1, tile1.properties().count());
+#[test]
+fn when_skipping_layer_data_expect_layers_without_data() {
+    let options = ReaderOptions::new().with_skip_layer_data(true);
+    let mut reader = TmxReader::with_options(SELECTIVE_MAP.as_bytes(), options);
+    let map = reader.read_map().unwrap();
+    let layer = map.layers().next().unwrap();
+    assert_eq!("Ground", layer.name());
+    assert!(layer.data().is_none());
+    assert_eq!(1, map.object_group_count());
+    assert_eq!(1, map.image_layer_count());
+    assert!(reader.take_diagnostics().is_empty());
+}
 
-    let tile2 = tileset.tiles().nth(1).unwrap();
-    assert!(tile2.image().is_some());
+#[test]
+fn when_skipping_objects_expect_no_object_groups_in_map_or_tiles() {
+    let options = ReaderOptions::new().with_skip_objects(true);
+    let map = Map::from_str_with(SELECTIVE_MAP, &options).unwrap();
+    assert_eq!(0, map.object_group_count());
+    let tile = map.tilesets().next().unwrap().tiles().next().unwrap();
+    assert!(tile.object_group().is_none());
+    assert!(map.layers().next().unwrap().data().is_some());
+}
 
-    let tile3 = tileset.tiles().nth(2).unwrap();
-    assert!(tile3.object_group().is_some());
+#[test]
+fn when_skipping_image_layers_expect_no_image_layers() {
+    let options = ReaderOptions::new().with_skip_image_layers(true);
+    let map = Map::from_str_with(SELECTIVE_MAP, &options).unwrap();
+    assert_eq!(0, map.image_layer_count());
+    assert_eq!(1, map.object_group_count());
+}
 
-    let tile4 = tileset.tiles().nth(3).unwrap();
-    assert!(tile4.animation().is_some());
-    let animation = tile4.animation().unwrap();
-    assert!(animation.frame().is_some());
-    let frame = animation.frame().unwrap();
-    assert_eq!(123, frame.tile_id());
-    assert_eq!(500, frame.duration());
+#[test]
+fn when_visiting_map_with_skip_options_expect_skipped_parts_not_visited() {
+    let options = ReaderOptions::new().with_skip_layer_data(true).with_skip_objects(true);
+    let mut visitor = RecordingVisitor::default();
+    TmxReader::with_options(VISITED_MAP.as_bytes(), options).visit_map(&mut visitor).unwrap();
+    assert!(visitor.events.iter().all(|event| !event.starts_with("row") && !event.contains("object")));
+}
 
-    let tile5 = tileset.tiles().nth(4).unwrap();
-    assert_eq!(Some(0.5), tile5.probability());
+#[test]
+fn when_layer_declares_huge_size_expect_xml_tiles_to_be_read() {
+    let xml = r#"<map><layer width="4000000000" height="4000000000"><data><tile gid="7"/></data></layer></map>"#;
+    let map = read_map(xml).unwrap();
+    let data = map.layers().next().unwrap().data().unwrap();
+    assert_eq!(&[7], data.decode().unwrap());
+}
 
-    let tile6 = tileset.tiles().nth(5).unwrap();
-    assert_eq!(Some(&Corners(0, 1, 2, 3)), tile6.terrain());
+const BAD_LAYERS_MAP: &str = r#"<map>
+    <layer name="Good" width="2" height="1"><data encoding="csv">1,2</data></layer>
+    <layer name="Bogus" width="2" height="1"><data encoding="bogus">1,2</data></layer>
+    <layer name="Empty"/>
+    <layer name="Truncated" width="2" height="1"><data encoding="base64">AQAA</data></layer>
+</map>"#;
+
+fn assert_bad_layers(result: ::Result<()>) {
+    match result {
+        Err(Error::LayerData(errors)) => {
+            let names: Vec<&str> = errors.iter().map(|(name, _)| name.as_str()).collect();
+            assert_eq!(vec!["Bogus", "Truncated"], names);
+            assert_matches!(errors[0].1, Error::BadEncoding(_));
+            assert_matches!(errors[1].1, Error::InvalidData(_));
+        }
+        result => panic!("unexpected result {:?}", result),
+    }
 }
 
 #[test]
-fn expect_point_to_be_constructible_from_comma_separated_pair_of_ints() {
-    assert_matches!(Point::from_str(""), Err(Error::InvalidPoint(..)));
-    assert_matches!(Point::from_str("1,2"), Ok(Point {x: 1, y: 2}));
-    assert_matches!(Point::from_str("a,2"), Err(Error::InvalidNumber(..)));
-    assert_matches!(Point::from_str("1,b"), Err(Error::InvalidNumber(..)));
-    assert_matches!(Point::from_str("1,2,3"), Err(Error::InvalidPoint(..)));
+fn when_decoding_all_layers_expect_every_failing_layer_reported() {
+    let map = read_map(BAD_LAYERS_MAP).unwrap();
+    assert_bad_layers(map.decode_all_layers());
+    let data = map.layers().next().unwrap().data().unwrap();
+    assert_eq!(&[1, 2], data.decode().unwrap());
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn when_decoding_all_layers_in_parallel_expect_same_errors_in_layer_order() {
+    let map = read_map(BAD_LAYERS_MAP).unwrap();
+    assert_bad_layers(map.decode_all_layers_parallel());
+    assert!(read_map("<map><layer><data encoding=\"csv\">1</data></layer></map>").unwrap()
+        .decode_all_layers_parallel().is_ok());
+}
+
+#[test]
+fn when_computing_map_statistics_expect_counts_and_tileset_usage() {
+    let map = read_map(r#"<map width="3" height="2">
+        <tileset firstgid="1" name="ground" tilecount="4">
+            <tile id="0"><animation><frame tileid="1" duration="100"/></animation></tile>
+            <tile id="2"><animation><frame tileid="3" duration="100"/></animation></tile>
+        </tileset>
+        <tileset firstgid="5" name="props" tilecount="4"/>
+        <layer name="floor" width="3" height="2"><data encoding="csv">1,1,2,5,2147483649,0</data></layer>
+        <layer name="nothing" width="3" height="2"><data encoding="csv">0,0,0,0,0,0</data></layer>
+        <imagelayer name="sky"/>
+        <objectgroup name="spawns">
+            <object id="1" type="Enemy"/>
+            <object id="2" type="Enemy"/>
+            <object id="3"/>
+        </objectgroup>
+        <objectgroup name="doors"><object id="4" type="Door"/></objectgroup>
+    </map>"#).unwrap();
+
+    let stats = map.statistics().unwrap();
+    assert_eq!((2, 1, 2), (stats.layers, stats.image_layers, stats.object_groups));
+    assert_eq!(4, stats.objects);
+    assert_eq!(vec![("spawns".to_string(), 3), ("doors".to_string(), 1)], stats.objects_per_group);
+    let types: Vec<_> = stats.objects_per_type.iter().map(|(name, &count)| (name.as_str(), count)).collect();
+    assert_eq!(vec![("", 1), ("Door", 1), ("Enemy", 2)], types);
+    assert_eq!(vec!["nothing".to_string()], stats.empty_layers);
+    assert_eq!(2, stats.animated_tiles);
+    assert_eq!((12, 48), (stats.decoded_cells, stats.decoded_bytes()));
+    assert_eq!(TilesetStats { name: "ground".to_string(), first_gid: 1, cells: 4, distinct_tiles: 2 }, stats.tilesets[0]);
+    assert_eq!(TilesetStats { name: "props".to_string(), first_gid: 5, cells: 1, distinct_tiles: 1 }, stats.tilesets[1]);
+
+    let report = stats.to_string();
+    assert!(report.starts_with("2 tile layer(s), 1 image layer(s), 2 object group(s)\n"), "{}", report);
+    assert!(report.contains("Empty layers: nothing\n"), "{}", report);
+    assert!(report.contains("Tileset `ground` (firstgid 1): 4 cell(s), 2 distinct tile(s)\n"), "{}", report);
+
+    assert_matches!(read_map(BAD_LAYERS_MAP).unwrap().statistics(), Err(Error::BadEncoding(_)));
+}
+
+// A small linear congruential generator, for reproducible random maps.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, bound: u32) -> u32 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((self.0 >> 33) % u64::from(bound)) as u32
+    }
+}
+
+#[test]
+fn when_querying_object_index_expect_same_objects_as_brute_force() {
+    let mut rng = Lcg(42);
+    let mut xml = String::from(r#"<map width="64" height="64" tilewidth="16" tileheight="16">"#);
+    for group in 0..2 {
+        xml.push_str(&format!(r#"<objectgroup name="group{}">"#, group));
+        for id in 0..500 {
+            let id = group * 1000 + id;
+            let (x, y) = (rng.next(2000) as i32 - 500, rng.next(2000) as i32 - 500);
+            let rotation = [0, 30, 45, 90, 200][rng.next(5) as usize];
+            let (width, height) = (rng.next(200), rng.next(200));
+            let attributes = format!(r#"id="{}" x="{}" y="{}" width="{}" height="{}" rotation="{}""#,
+                                     id, x, y, width, height, rotation);
+            match rng.next(5) {
+                0 => xml.push_str(&format!("<object {}><point/></object>", attributes)),
+                1 => xml.push_str(&format!("<object {}><ellipse/></object>", attributes)),
+                2 => xml.push_str(&format!(r#"<object {} gid="1"/>"#, attributes)),
+                _ => xml.push_str(&format!("<object {}/>", attributes)),
+            }
+        }
+        xml.push_str("</objectgroup>");
+    }
+    xml.push_str(r#"<objectgroup><object id="5000" x="-100000" y="0" width="200000" height="1"/></objectgroup></map>"#);
+    let map = Map::from_str(&xml).unwrap();
+    let index = ObjectIndex::new(&map);
+    assert_eq!(1001, index.len());
+
+    let brute_force = |rect: Aabb| -> Vec<u32> {
+        map.object_groups()
+            .flat_map(ObjectGroup::objects)
+            .filter(|object| object.bounding_box().intersects(&rect))
+            .map(Object::id)
+            .collect()
+    };
+    for _ in 0..200 {
+        let (x, y) = (f64::from(rng.next(2400)) - 700.0, f64::from(rng.next(2400)) - 700.0);
+        let (width, height) = (f64::from(rng.next(300)), f64::from(rng.next(300)));
+        let rect = Aabb::new(x, y, x + width, y + height);
+        let ids: Vec<u32> = index.query_rect(rect).map(Object::id).collect();
+        assert_eq!(brute_force(rect), ids);
+        let ids: Vec<u32> = index.query_point(x, y).map(Object::id).collect();
+        assert_eq!(brute_force(Aabb::new(x, y, x, y)), ids);
+    }
+    let everything = Aabb::new(-1e9, -1e9, 1e9, 1e9);
+    assert_eq!(1001, index.query_rect(everything).count());
+}
+
+#[test]
+fn when_querying_object_index_expect_point_objects_and_edges_to_match() {
+    let map = read_map(r#"<map>
+        <objectgroup>
+            <object id="1" x="10" y="10"><point/></object>
+            <object id="2" x="32" y="0" width="32" height="32"/>
+        </objectgroup>
+    </map>"#).unwrap();
+    let index = ObjectIndex::with_cell_size(map.object_groups(), 16.0);
+    assert_eq!(vec![1], index.query_point(10.0, 10.0).map(Object::id).collect::<Vec<_>>());
+    assert_eq!(0, index.query_point(10.0, 10.5).count());
+    assert_eq!(vec![2], index.query_point(32.0, 32.0).map(Object::id).collect::<Vec<_>>());
+    let ids: Vec<u32> = index.query_rect(Aabb::new(0.0, 0.0, 32.0, 10.0)).map(Object::id).collect();
+    assert_eq!(vec![1, 2], ids);
 }
 
 fn get_simple_valid_map() -> Map {
-    Map::from_str(r#"<map version="1.0"
+    read_map(r#"<map version="1.0"
         orientation="orthogonal"
         width="200"
         height="100"
@@ -530,14 +3370,14 @@ fn get_simple_valid_map() -> Map {
 }
 
 fn get_map_with_tilesets() -> Map {
-    Map::from_str("<map>
+    read_map("<map>
         <tileset></tileset>
         <tileset></tileset>
     </map>").unwrap()
 }
 
 fn get_map_with_layers() -> Map {
-    Map::from_str(r#"<map>
+    read_map(r#"<map>
         <layer name="layer1_name"/>
         <layer name="layer2_name" opacity="0"/>
         <layer name="layer3_name" visible="0"/>
@@ -561,7 +3401,7 @@ fn get_map_with_layers() -> Map {
 }
 
 fn get_map_with_image_layers() -> Map {
-    Map::from_str(r#"<map>
+    read_map(r#"<map>
         <imagelayer name="layer1_name"/>
         <imagelayer name="layer2_name" opacity="0"/>
         <imagelayer name="layer3_name" visible="0"/>
@@ -595,7 +3435,7 @@ fn get_simple_valid_tileset() -> Tileset {
 }
 
 fn get_map_with_objectgroups() -> Map {
-    Map::from_str("<map>
+    read_map("<map>
         <objectgroup name=\"some_name\">
             <properties>
                 <property/>
@@ -609,7 +3449,7 @@ fn get_map_with_objectgroups() -> Map {
 }
 
 fn get_map_with_objects() -> Map {
-    Map::from_str(r#"<map>
+    read_map(r#"<map>
         <objectgroup>
             <object/>
             <object id="1" name="obj" type="ty"
@@ -635,7 +3475,6 @@ fn get_map_with_objects() -> Map {
 }
 
 fn get_hexagonal_map() -> Map {
-    Map::from_str(r#"<map orientation="hexagonal" hexsidelength="32"
+    read_map(r#"<map orientation="hexagonal" hexsidelength="32"
         staggeraxis="y" staggerindex="even"/>"#).unwrap()
 }
-
@@ -13,26 +13,48 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::Read;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Read, Write};
 use std::str::FromStr;
-use std::path::Path;
-use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::sync::OnceLock;
+use std::vec;
 
+#[cfg(feature = "image")]
+use image_crate::RgbaImage;
 use xml::attribute::OwnedAttribute;
 
 use error::Error;
-use model::image::Image;
-use model::map::ObjectGroup;
-use model::property::{Properties, PropertyCollection};
-use model::reader::{self, TmxReader, ElementReader};
+use model::geometry::{Anchor, Rect};
+use model::gid::Gid;
+use model::image::{Image, ImageSource};
+use model::map::{Object, ObjectGroup};
+use model::property::{Properties, PropertyCollection, Property};
+use model::reader::{self, Diagnostic, ElementReader, ReaderOptions, Severity, TmxReader, UnknownAttribute, UnknownElement};
+#[cfg(feature = "image")]
+use model::reader::Limits;
+use model::resource::{self, FileLoader, ResourceLoader};
+use model::shape::CollisionShape;
+use model::writer::{Attributes, ElementWriter, TmxWriter, WriteOptions};
+
+// Most tiles reserved up front for the `<tile>` elements of a tileset, which
+// may describe only a few of its `tilecount` tiles.
+const MAX_RESERVED_TILES: usize = 1 << 12;
 
 define_iterator_wrapper!(Tiles, Tile);
 define_iterator_wrapper!(TerrainTypes, Terrain);
+define_iterator_wrapper!(Frames, Frame);
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Tileset {
     first_gid: u32,
     source: String,
+    resolved: bool,
+    version: String,
+    tiled_version: String,
     name: String,
     tile_width: u32,
     tile_height: u32,
@@ -40,25 +62,166 @@ pub struct Tileset {
     margin: u32,
     tile_count: u32,
     columns: u32,
+    object_alignment: Option<Anchor>,
     image: Option<Image>,
     tile_offset: Option<TileOffset>,
     properties: PropertyCollection,
     terrain_types: TerrainCollection,
     tiles: Vec<Tile>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    tile_index: OnceLock<HashMap<u32, usize>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    unknown_attributes: Vec<UnknownAttribute>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    unknown_elements: Vec<UnknownElement>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    source_path: Option<PathBuf>,
 }
 
 impl Tileset {
     pub fn open<P: AsRef<Path>>(path: P) -> ::Result<Tileset> {
-        let file = File::open(path)?;
-        let mut reader = TmxReader::new(file);
-        reader.read_tileset()
+        Tileset::open_with(path, &ReaderOptions::default())
+    }
+
+    /// Reads a tileset as requested by `options`.
+    pub fn open_with<P: AsRef<Path>>(path: P, options: &ReaderOptions) -> ::Result<Tileset> {
+        Tileset::load(resource::path_str(path.as_ref())?, &mut FileLoader, options).map(|(tileset, _)| tileset)
+    }
+
+    /// Reads a tileset through `loader`.
+    pub fn open_with_loader<L: ResourceLoader + ?Sized>(path: &str, loader: &mut L) -> ::Result<Tileset> {
+        Tileset::load(path, loader, &ReaderOptions::default()).map(|(tileset, _)| tileset)
+    }
+
+    /// Reads a tileset through `loader`, as requested by `options`, along with
+    /// the problems found that did not prevent reading it.
+    ///
+    /// The `open` functions are shorthands for this one.
+    pub fn load<L>(path: &str, loader: &mut L, options: &ReaderOptions) -> ::Result<(Tileset, Vec<Diagnostic>)>
+        where L: ResourceLoader + ?Sized
+    {
+        let (mut tileset, mut diagnostics) = loader.load_bytes(path)
+            .and_then(|bytes| Tileset::read_str(&reader::decode_text(&bytes)?, options))
+            .map_err(|err| resource::file_error(path, err))?;
+        reader::set_diagnostics_file(&mut diagnostics, path);
+        tileset.set_source_path(loader.canonicalize(path));
+        Ok((tileset, diagnostics))
+    }
+
+    /// Reads a tileset from a string as requested by `options`.
+    pub fn from_str_with(s: &str, options: &ReaderOptions) -> ::Result<Tileset> {
+        Tileset::read_str(s, options).map(|(tileset, _)| tileset)
+    }
+
+    fn read_str(s: &str, options: &ReaderOptions) -> ::Result<(Tileset, Vec<Diagnostic>)> {
+        let s = s.strip_prefix('\u{feff}').unwrap_or(s);
+        let mut tsx = TmxReader::with_options(s.as_bytes(), options.clone());
+        let tileset = tsx.read_tileset()?;
+        Ok((tileset, tsx.take_diagnostics()))
+    }
+
+    /// Reads a tileset from raw bytes, in UTF-8 or, if it starts with a byte
+    /// order mark, in UTF-16.
+    pub fn from_bytes(bytes: &[u8]) -> ::Result<Tileset> {
+        Tileset::from_bytes_with(bytes, &ReaderOptions::default())
+    }
+
+    /// Reads a tileset from raw bytes as requested by `options`.
+    pub fn from_bytes_with(bytes: &[u8], options: &ReaderOptions) -> ::Result<Tileset> {
+        Tileset::from_str_with(&reader::decode_text(bytes)?, options)
+    }
+
+    /// Reads a tileset from any reader, such as a file inside an archive, in
+    /// UTF-8 or, if it starts with a byte order mark, in UTF-16.
+    ///
+    /// The whole content is read first. As with `from_str`, the directory of
+    /// the tileset is unknown, so the paths found in it are left relative.
+    pub fn from_reader<R: Read>(reader: R) -> ::Result<Tileset> {
+        Tileset::from_reader_with(reader, &ReaderOptions::default())
+    }
+
+    /// Reads a tileset from any reader as requested by `options`.
+    pub fn from_reader_with<R: Read>(mut reader: R, options: &ReaderOptions) -> ::Result<Tileset> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Tileset::from_bytes_with(&bytes, options)
+    }
+
+    /// Returns the attributes skipped while reading the tileset with the
+    /// `UnknownPolicy::Collect` policy.
+    pub fn unknown_attributes(&self) -> &[UnknownAttribute] {
+        &self.unknown_attributes
+    }
+
+    pub(crate) fn set_unknown_attributes(&mut self, unknown_attributes: Vec<UnknownAttribute>) {
+        self.unknown_attributes = unknown_attributes;
+    }
+
+    /// Returns the child elements skipped while reading the tileset with the
+    /// `UnknownPolicy::Collect` policy.
+    pub fn unknown_elements(&self) -> &[UnknownElement] {
+        &self.unknown_elements
+    }
+
+    pub(crate) fn set_unknown_elements(&mut self, unknown_elements: Vec<UnknownElement>) {
+        self.unknown_elements = unknown_elements;
+    }
+
+    /// Returns the path of the file the tileset was read from, canonicalized
+    /// when read from the filesystem, or `None` for tilesets read from a
+    /// string, bytes or a reader.
+    ///
+    /// Tilesets embedded in a map read from a file have the path of the map.
+    pub fn source_path(&self) -> Option<&Path> {
+        self.source_path.as_deref()
+    }
+
+    pub(crate) fn set_source_path(&mut self, source_path: PathBuf) {
+        self.source_path = Some(source_path);
+    }
+
+    /// Returns the directory of the file the tileset was read from, against
+    /// which the paths found in the tileset are resolved.
+    pub fn base_dir(&self) -> Option<&Path> {
+        self.source_path().and_then(Path::parent)
+    }
+
+    // Replaces the content of a map's reference to an external tileset with the
+    // tileset read from the referenced file. The `firstgid` and `source` attributes
+    // only exist on the reference and are kept.
+    pub(crate) fn resolve_reference(&mut self, external: Tileset) {
+        let first_gid = self.first_gid;
+        let source = ::std::mem::take(&mut self.source);
+        *self = external;
+        self.first_gid = first_gid;
+        self.source = source;
+        self.resolved = true;
+    }
+
+    /// Resolves the value of a `file` property against the directory of the tileset file.
+    ///
+    /// Returns `None` if the property is not a `file` property or has an empty value.
+    /// Tilesets read from a string have no directory, so the path is only normalized.
+    pub fn resolve_file_property(&self, property: &Property) -> Option<PathBuf> {
+        property.as_file().map(|file| resource::resolve_path(self.base_dir(), file))
+    }
+
+    /// Resolves the file of an image of the tileset, its own or that of a
+    /// tile, against the directory of the tileset file.
+    ///
+    /// Returns `None` for embedded images.
+    pub fn resolve_image_path(&self, image: &Image) -> Option<PathBuf> {
+        match image.source_kind() {
+            Some(ImageSource::File(source)) => Some(resource::resolve_path(self.base_dir(), source)),
+            _ => None,
+        }
     }
 
     pub fn first_gid(&self) -> u32 {
         self.first_gid
     }
 
-    fn set_first_gid(&mut self, first_gid: u32) {
+    pub fn set_first_gid(&mut self, first_gid: u32) {
         self.first_gid = first_gid;
     }
 
@@ -70,11 +233,69 @@ impl Tileset {
         self.source = source.into();
     }
 
+    /// Returns `true` if the tileset is a map's reference to a TSX file.
+    ///
+    /// External tilesets are written back as a reference, even once resolved.
+    pub fn is_external(&self) -> bool {
+        !self.source.is_empty()
+    }
+
+    /// Returns `true` if the content of an external tileset was read from its TSX file.
+    ///
+    /// Maps read from a string leave their external tilesets unresolved, with
+    /// only `firstgid` and `source` set.
+    pub fn is_resolved(&self) -> bool {
+        self.resolved
+    }
+
+    /// Returns the format version of an external tileset file.
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn set_version<S: Into<String>>(&mut self, version: S) {
+        self.version = version.into();
+    }
+
+    /// Returns the version of Tiled that saved an external tileset file.
+    pub fn tiled_version(&self) -> &str {
+        &self.tiled_version
+    }
+
+    pub fn set_tiled_version<S: Into<String>>(&mut self, tiled_version: S) {
+        self.tiled_version = tiled_version.into();
+    }
+
+    /// Writes the tileset as a standalone TSX document.
+    ///
+    /// The `firstgid` and `source` attributes belong to the map's reference to
+    /// the tileset and are not written.
+    pub fn to_writer<W: Write>(&self, writer: W) -> ::Result<()> {
+        self.to_writer_with_options(writer, &WriteOptions::default())
+    }
+
+    /// Writes the tileset as a standalone TSX document, formatted according to `options`.
+    pub fn to_writer_with_options<W: Write>(&self, writer: W, options: &WriteOptions) -> ::Result<()> {
+        TmxWriter::with_options(writer, options.clone()).write_tileset(self)
+    }
+
+    /// Returns the tileset as a standalone TSX document.
+    pub fn to_xml_string(&self) -> ::Result<String> {
+        self.to_xml_string_with_options(&WriteOptions::default())
+    }
+
+    /// Returns the tileset as a standalone TSX document, formatted according to `options`.
+    pub fn to_xml_string_with_options(&self, options: &WriteOptions) -> ::Result<String> {
+        let mut buffer = Vec::new();
+        self.to_writer_with_options(&mut buffer, options)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
 
-    fn set_name<S: Into<String>>(&mut self, name: S) {
+    pub fn set_name<S: Into<String>>(&mut self, name: S) {
         self.name = name.into();
     }
 
@@ -82,7 +303,7 @@ impl Tileset {
         self.tile_width
     }
 
-    fn set_tile_width(&mut self, tile_width: u32) {
+    pub fn set_tile_width(&mut self, tile_width: u32) {
         self.tile_width = tile_width;
     }
 
@@ -90,7 +311,7 @@ impl Tileset {
         self.tile_height
     }
 
-    fn set_tile_height(&mut self, tile_height: u32) {
+    pub fn set_tile_height(&mut self, tile_height: u32) {
         self.tile_height = tile_height;
     }
 
@@ -98,7 +319,7 @@ impl Tileset {
         self.spacing
     }
 
-    fn set_spacing(&mut self, spacing: u32) {
+    pub fn set_spacing(&mut self, spacing: u32) {
         self.spacing = spacing;
     }
 
@@ -106,7 +327,7 @@ impl Tileset {
         self.margin
     }
 
-    fn set_margin(&mut self, margin: u32) {
+    pub fn set_margin(&mut self, margin: u32) {
         self.margin = margin;
     }
 
@@ -114,7 +335,7 @@ impl Tileset {
         self.tile_count
     }
 
-    fn set_tile_count(&mut self, tile_count: u32) {
+    pub fn set_tile_count(&mut self, tile_count: u32) {
         self.tile_count = tile_count;
     }
 
@@ -122,15 +343,104 @@ impl Tileset {
         self.columns
     }
 
-    fn set_columns(&mut self, columns: u32) {
+    pub fn set_columns(&mut self, columns: u32) {
         self.columns = columns;
     }
 
+    /// Returns the number of columns of the tileset image.
+    ///
+    /// Falls back to the number of tiles fitting in the width of the image when
+    /// the `columns` attribute is absent, as in older files. Returns `None`
+    /// then if the image does not declare its width.
+    pub fn effective_columns(&self) -> Option<u32> {
+        if self.columns > 0 {
+            return Some(self.columns);
+        }
+        let width = self.image.as_ref()?.width()?;
+        Some(fitting_tiles(width, self.tile_width, self.margin, self.spacing)).filter(|&n| n > 0)
+    }
+
+    /// Returns the number of tiles in the tileset.
+    ///
+    /// Falls back to the number of tiles fitting in the image when the
+    /// `tilecount` attribute is absent, as in older files, provided that the
+    /// image declares its size.
+    pub fn effective_tile_count(&self) -> Option<u32> {
+        if self.tile_count > 0 {
+            return Some(self.tile_count);
+        }
+        let height = self.image.as_ref()?.height()?;
+        let rows = fitting_tiles(height, self.tile_height, self.margin, self.spacing);
+        self.effective_columns().and_then(|columns| columns.checked_mul(rows)).filter(|&n| n > 0)
+    }
+
+    /// Decodes the image of the tileset, the `trans` color made transparent;
+    /// see `Image::load`.
+    ///
+    /// Fails with `Error::InvalidTileset` for an image collection.
+    #[cfg(feature = "image")]
+    pub fn load_image(&self, base_dir: &Path) -> ::Result<RgbaImage> {
+        self.load_image_with(base_dir, &mut FileLoader, &Limits::default())
+    }
+
+    /// Same as `load_image`, against the directory of the tileset file; see
+    /// `base_dir`. Tilesets read from a string have no directory, so the file
+    /// is looked for relative to the current directory.
+    #[cfg(feature = "image")]
+    pub fn open_image(&self) -> ::Result<RgbaImage> {
+        self.load_image(self.base_dir().unwrap_or_else(|| Path::new("")))
+    }
+
+    /// Same as `load_image`, through `loader` and within `limits`; see
+    /// `Image::load_with`.
+    #[cfg(feature = "image")]
+    pub fn load_image_with<L>(&self, base_dir: &Path, loader: &mut L, limits: &Limits) -> ::Result<RgbaImage>
+        where L: ResourceLoader + ?Sized
+    {
+        match self.image {
+            Some(ref image) => image.load_with(base_dir, loader, limits),
+            None => Err(Error::InvalidTileset("the tileset has no image".to_string())),
+        }
+    }
+
+    /// Returns the area of the tileset image showing the tile with the given local id.
+    ///
+    /// Returns `None` if the id is out of range, if the position of the tile
+    /// overflows or if the tileset is an image collection; use `tile_image` for
+    /// those.
+    pub fn tile_rect(&self, local_id: u32) -> Option<Rect> {
+        self.image.as_ref()?;
+        let columns = self.effective_columns()?;
+        if local_id >= self.effective_tile_count()? {
+            return None;
+        }
+        let offset = |index: u32, tile_size: u32| {
+            tile_size.checked_add(self.spacing)
+                .and_then(|pitch| index.checked_mul(pitch))
+                .and_then(|offset| offset.checked_add(self.margin))
+        };
+        Some(Rect::new(offset(local_id % columns, self.tile_width)?,
+                       offset(local_id / columns, self.tile_height)?,
+                       self.tile_width,
+                       self.tile_height))
+    }
+
+    /// Returns the point of tile objects placed at their position, `None` if
+    /// unspecified: tile objects are then aligned at their bottom-left corner
+    /// on orthogonal maps and at their bottom center on isometric maps.
+    pub fn object_alignment(&self) -> Option<Anchor> {
+        self.object_alignment
+    }
+
+    pub fn set_object_alignment(&mut self, object_alignment: Option<Anchor>) {
+        self.object_alignment = object_alignment;
+    }
+
     pub fn tile_offset(&self) -> Option<TileOffset> {
         self.tile_offset
     }
 
-    fn set_tile_offset(&mut self, tile_offset: TileOffset) {
+    pub fn set_tile_offset(&mut self, tile_offset: TileOffset) {
         self.tile_offset = Some(tile_offset);
     }
 
@@ -138,15 +448,27 @@ impl Tileset {
         self.properties.iter()
     }
 
-    fn set_properties(&mut self, properties: PropertyCollection) {
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.get(name)
+    }
+
+    pub fn set_properties(&mut self, properties: PropertyCollection) {
         self.properties = properties;
     }
 
+    pub fn properties_mut(&mut self) -> &mut PropertyCollection {
+        &mut self.properties
+    }
+
     pub fn image(&self) -> Option<&Image> {
         self.image.as_ref()
     }
 
-    fn set_image(&mut self, image: Image) {
+    pub fn into_image(self) -> Option<Image> {
+        self.image
+    }
+
+    pub fn set_image(&mut self, image: Image) {
         self.image = Some(image);
     }
 
@@ -162,21 +484,396 @@ impl Tileset {
         Tiles(self.tiles.iter())
     }
 
+    pub fn tiles_slice(&self) -> &[Tile] {
+        &self.tiles
+    }
+
+    pub fn into_tiles(self) -> vec::IntoIter<Tile> {
+        self.tiles.into_iter()
+    }
+
+    /// Iterates mutably over the tiles having extra data.
+    ///
+    /// Tile ids may be changed: the index used by `tile_by_id` is rebuilt on the next lookup.
+    pub fn tiles_mut(&mut self) -> slice::IterMut<Tile> {
+        self.tile_index = OnceLock::new();
+        self.tiles.iter_mut()
+    }
+
+    /// Tells whether the tileset is a collection of images, each tile having its
+    /// own image instead of being a part of a shared one.
+    pub fn is_image_collection(&self) -> bool {
+        self.image.is_none()
+    }
+
+    /// Iterates over the tiles having their own image, with their local ids.
+    ///
+    /// In image collections, the ids may not be contiguous.
+    pub fn tile_images(&self) -> impl Iterator<Item = (u32, &Image)> {
+        self.tiles.iter().filter_map(|tile| tile.image().map(|image| (tile.id(), image)))
+    }
+
+    /// Iterates over the tiles having an animation, with their local ids.
+    ///
+    /// The frames of the animations refer to tiles by local id too; see `gid_for`.
+    pub fn animated_tiles(&self) -> impl Iterator<Item = (u32, &Animation)> {
+        self.tiles.iter().filter_map(|tile| tile.animation().map(|animation| (tile.id(), animation)))
+    }
+
+    /// Returns the global id of the tile with the given local id, such as
+    /// that of an animation frame, in the map giving the tileset its `firstgid`.
+    ///
+    /// Returns `None` if the id does not fit in a gid, the flip flags excluded.
+    pub fn gid_for(&self, local_id: u32) -> Option<u32> {
+        self.first_gid.checked_add(local_id).filter(|&gid| Gid(gid).flags() == 0)
+    }
+
+    /// Returns the image of the tile with the given local id, when the tile has its own.
+    pub fn tile_image(&self, local_id: u32) -> Option<&Image> {
+        self.tile_by_id(local_id).and_then(Tile::image)
+    }
+
+    /// Returns the tile with the given local id, if the tileset defines extra data for it.
+    ///
+    /// If a malformed file defines the same id several times, the first tile wins.
+    /// The id index is built on the first call.
+    pub fn tile_by_id(&self, id: u32) -> Option<&Tile> {
+        let index = self.tile_index.get_or_init(|| {
+            let mut index = HashMap::new();
+            for (tile_index, tile) in self.tiles.iter().enumerate() {
+                index.entry(tile.id()).or_insert(tile_index);
+            }
+            index
+        });
+        index.get(&id).map(|&index| &self.tiles[index])
+    }
+
+    /// Keeps only the extra data of the tiles for which `f` returns `true`.
+    ///
+    /// The tiles themselves remain part of the tileset; only their properties,
+    /// images, animations and the like are dropped.
+    pub fn retain_tiles<F: FnMut(&Tile) -> bool>(&mut self, f: F) {
+        self.tile_index = OnceLock::new();
+        self.tiles.retain(f);
+    }
+
     fn add_tile(&mut self, tile: Tile) {
+        self.tile_index = OnceLock::new();
         self.tiles.push(tile);
     }
+
+    // Number of local ids in use: the tile count, or more if some tile has a
+    // larger id, as in image collections with removed tiles.
+    pub(crate) fn id_count(&self) -> u32 {
+        let tile_count = self.effective_tile_count().unwrap_or(0);
+        self.tiles.iter().map(|tile| tile.id().saturating_add(1)).fold(tile_count, u32::max)
+    }
+
+    /// Appends the tiles of `from` to those of `into`, making a single tileset
+    /// of both, and returns the new tile ids of the tiles of `from`.
+    ///
+    /// The extra data of the tiles of `from` comes along: properties,
+    /// collision groups, animations, whose frames are renumbered, and terrain
+    /// corners, the terrain types of `from` being appended to those of `into`.
+    /// The properties of `from` missing from `into` are added to it.
+    ///
+    /// The image of `into` is left as it is: tilesets having their own image
+    /// need a new one packing both, for which the returned table gives the
+    /// area of each tile in the image of `from`. Maps are updated by passing
+    /// `GidRemapTable::remap` to `Map::remap_gids`.
+    ///
+    /// Fails with `Error::TilesetMismatch` if one tileset is an image collection
+    /// and not the other, or if they differ in tile size, tile offset or object
+    /// alignment, and with `Error::InvalidTileset` if the tile ids of the merged
+    /// tileset do not fit in a gid. `into` is left untouched on failure.
+    pub fn merge(into: &mut Tileset, from: &Tileset) -> ::Result<GidRemapTable> {
+        let mismatch = |setting: &str, into: String, from: String| {
+            Err(Error::TilesetMismatch { setting: setting.to_string(), into, from })
+        };
+        if into.is_image_collection() != from.is_image_collection() {
+            let kind = |tileset: &Tileset| {
+                if tileset.is_image_collection() { "image collection" } else { "single image" }.to_string()
+            };
+            return mismatch("kind", kind(into), kind(from));
+        }
+        if (into.tile_width, into.tile_height) != (from.tile_width, from.tile_height) {
+            return mismatch("tile size",
+                            format!("{}x{}", into.tile_width, into.tile_height),
+                            format!("{}x{}", from.tile_width, from.tile_height));
+        }
+        let into_offset = into.tile_offset.map(|offset| (offset.x, offset.y)).unwrap_or((0, 0));
+        let from_offset = from.tile_offset.map(|offset| (offset.x, offset.y)).unwrap_or((0, 0));
+        if into_offset != from_offset {
+            return mismatch("tile offset",
+                            format!("{},{}", into_offset.0, into_offset.1),
+                            format!("{},{}", from_offset.0, from_offset.1));
+        }
+        if into.object_alignment != from.object_alignment {
+            let name = |alignment: Option<Anchor>| alignment.map_or("unspecified", |anchor| anchor.as_str()).to_string();
+            return mismatch("object alignment", name(into.object_alignment), name(from.object_alignment));
+        }
+
+        let id_offset = into.id_count();
+        let count = from.id_count();
+        let end_gid = into.first_gid.checked_add(id_offset).and_then(|gid| gid.checked_add(count));
+        if end_gid.is_none_or(|gid| Gid(gid).flags() != 0) {
+            return Err(Error::InvalidTileset("too many tiles to merge".to_string()));
+        }
+        let first_new_gid = into.first_gid + id_offset;
+
+        // Renumbered before `into` is changed, so as to leave it untouched on
+        // failure.
+        let out_of_range = |what: &str, id: u32| Error::InvalidTileset(format!("{} {} out of range once merged", what, id));
+        let shift_id = |id: u32| id.checked_add(id_offset).ok_or_else(|| out_of_range("tile id", id));
+        let terrain_offset = into.terrain_types.0.len() as u32;
+        let shift_terrain = |corner: Option<u32>| match corner {
+            Some(terrain) => terrain.checked_add(terrain_offset).map(Some).ok_or_else(|| out_of_range("terrain", terrain)),
+            None => Ok(None),
+        };
+        let mut terrain_types = Vec::with_capacity(from.terrain_types.0.len());
+        for terrain in &from.terrain_types.0 {
+            let mut terrain = terrain.clone();
+            if let Ok(id) = terrain.tile.parse::<u32>() {
+                terrain.tile = shift_id(id)?.to_string();
+            }
+            terrain_types.push(terrain);
+        }
+        let mut tiles = Vec::with_capacity(from.tiles.len());
+        for tile in &from.tiles {
+            let mut tile = tile.clone();
+            tile.id = shift_id(tile.id)?;
+            if let Some(ref mut corners) = tile.corners {
+                *corners = Corners(shift_terrain(corners.0)?, shift_terrain(corners.1)?,
+                                   shift_terrain(corners.2)?, shift_terrain(corners.3)?);
+            }
+            if let Some(ref mut animation) = tile.animation {
+                for frame in &mut animation.frames {
+                    frame.tile_id = shift_id(frame.tile_id)?;
+                }
+            }
+            tiles.push(tile);
+        }
+
+        for terrain in terrain_types {
+            into.terrain_types.push(terrain);
+        }
+        for tile in tiles {
+            into.add_tile(tile);
+        }
+        for property in from.properties() {
+            if !into.properties.contains(property.name()) {
+                into.properties.push(property.clone());
+            }
+        }
+        into.tile_count = id_offset + count;
+
+        Ok(GidRemapTable {
+            first_old_gid: from.first_gid,
+            source_image: from.image.clone(),
+            entries: (0..count).map(|id| GidRemap {
+                old_gid: from.first_gid + id,
+                new_gid: first_new_gid + id,
+                source_rect: from.tile_rect(id),
+            }).collect(),
+        })
+    }
+}
+
+// Number of tiles of the given size fitting along one dimension of an image,
+// 0 if the sizes overflow.
+fn fitting_tiles(image_size: u32, tile_size: u32, margin: u32, spacing: u32) -> u32 {
+    if tile_size == 0 {
+        return 0;
+    }
+    let span = image_size.checked_add(spacing);
+    let margins = margin.checked_mul(2);
+    let pitch = tile_size.checked_add(spacing);
+    match (span, margins, pitch) {
+        (Some(span), Some(margins), Some(pitch)) => span.saturating_sub(margins) / pitch,
+        _ => 0,
+    }
+}
+
+/// Builder of a tileset made in code, for instance from a generated atlas.
+///
+/// When the tileset has an image, `build` checks the number of columns and tiles
+/// against the image and fills them in when they were not given.
+#[derive(Debug)]
+pub struct TilesetBuilder {
+    tileset: Tileset,
+}
+
+impl TilesetBuilder {
+    /// Starts a tileset of tiles `tile_width` by `tile_height` pixels.
+    pub fn new<S: Into<String>>(name: S, tile_width: u32, tile_height: u32) -> TilesetBuilder {
+        let mut tileset = Tileset::default();
+        tileset.set_name(name);
+        tileset.set_tile_width(tile_width);
+        tileset.set_tile_height(tile_height);
+        TilesetBuilder { tileset }
+    }
+
+    pub fn with_first_gid(mut self, first_gid: u32) -> TilesetBuilder {
+        self.tileset.set_first_gid(first_gid);
+        self
+    }
+
+    pub fn with_spacing(mut self, spacing: u32) -> TilesetBuilder {
+        self.tileset.set_spacing(spacing);
+        self
+    }
+
+    pub fn with_margin(mut self, margin: u32) -> TilesetBuilder {
+        self.tileset.set_margin(margin);
+        self
+    }
+
+    pub fn with_columns(mut self, columns: u32) -> TilesetBuilder {
+        self.tileset.set_columns(columns);
+        self
+    }
+
+    pub fn with_tile_count(mut self, tile_count: u32) -> TilesetBuilder {
+        self.tileset.set_tile_count(tile_count);
+        self
+    }
+
+    pub fn with_image(mut self, image: Image) -> TilesetBuilder {
+        self.tileset.set_image(image);
+        self
+    }
+
+    pub fn with_tile_offset(mut self, x: i32, y: i32) -> TilesetBuilder {
+        self.tileset.set_tile_offset(TileOffset::new(x, y));
+        self
+    }
+
+    pub fn with_property(mut self, property: Property) -> TilesetBuilder {
+        self.tileset.properties.push(property);
+        self
+    }
+
+    pub fn with_tile_property(mut self, id: u32, property: Property) -> TilesetBuilder {
+        self.tile_mut(id).properties.push(property);
+        self
+    }
+
+    pub fn with_tile_probability(mut self, id: u32, probability: f32) -> TilesetBuilder {
+        self.tile_mut(id).set_probability(probability);
+        self
+    }
+
+    /// Gives its own image to the tile `id`, as in image collections.
+    pub fn with_tile_image(mut self, id: u32, image: Image) -> TilesetBuilder {
+        self.tile_mut(id).set_image(image);
+        self
+    }
+
+    pub fn with_tile_animation(mut self, id: u32, animation: Animation) -> TilesetBuilder {
+        self.tile_mut(id).set_animation(animation);
+        self
+    }
+
+    /// Sets the collision group of the tile `id`.
+    pub fn with_tile_object_group(mut self, id: u32, object_group: ObjectGroup) -> TilesetBuilder {
+        self.tile_mut(id).set_object_group(object_group);
+        self
+    }
+
+    fn tile_mut(&mut self, id: u32) -> &mut Tile {
+        let tiles = &mut self.tileset.tiles;
+        match tiles.iter().position(|tile| tile.id == id) {
+            Some(index) => &mut tiles[index],
+            None => {
+                let mut tile = Tile::default();
+                tile.set_id(id);
+                tiles.push(tile);
+                tiles.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Builds the tileset.
+    ///
+    /// With an image, whose size must be given, the columns must be those
+    /// fitting in the width of the image, the tiles must fit in the image, and
+    /// the ids of the tiles and animation frames must be below the tile count.
+    /// In image collections, the tile count defaults to the number of tiles.
+    pub fn build(self) -> ::Result<Tileset> {
+        let mut tileset = self.tileset;
+        tileset.tiles.sort_by_key(Tile::id);
+        if let Some(image) = tileset.image.as_ref() {
+            let (width, height) = image.size()
+                .ok_or_else(|| Error::InvalidTileset("the size of the image is not given".to_string()))?;
+            let columns = fitting_tiles(width, tileset.tile_width, tileset.margin, tileset.spacing);
+            let rows = fitting_tiles(height, tileset.tile_height, tileset.margin, tileset.spacing);
+            if tileset.columns > 0 && tileset.columns != columns {
+                return Err(Error::InvalidTileset(format!("{} columns given, but {} fit in a {}px wide image",
+                                                         tileset.columns, columns, width)));
+            }
+            let fitting = columns.checked_mul(rows).ok_or_else(|| {
+                Error::InvalidTileset(format!("too many tiles fit in a {}x{}px image", width, height))
+            })?;
+            if tileset.tile_count > fitting {
+                return Err(Error::InvalidTileset(format!("{} tiles given, but {} fit in a {}x{}px image",
+                                                         tileset.tile_count, fitting, width, height)));
+            }
+            tileset.set_columns(columns);
+            if tileset.tile_count == 0 {
+                tileset.set_tile_count(fitting);
+            }
+            let tile_count = tileset.tile_count;
+            for tile in &tileset.tiles {
+                let frame_ids = tile.animation().into_iter().flat_map(Animation::frames).map(Frame::tile_id);
+                if let Some(id) = Some(tile.id).into_iter().chain(frame_ids).find(|&id| id >= tile_count) {
+                    return Err(Error::InvalidTileset(format!("tile id {} out of range for {} tiles", id, tile_count)));
+                }
+            }
+        } else if tileset.tile_count == 0 {
+            tileset.tile_count = tileset.tiles.len() as u32;
+        }
+        Ok(tileset)
+    }
+}
+
+/// Tilesets are equal when their content is, compared field by field,
+/// floating point values exactly.
+///
+/// Where the tileset was read from and the unknown attributes and elements
+/// skipped while reading it are not compared.
+impl PartialEq for Tileset {
+    fn eq(&self, other: &Tileset) -> bool {
+        self.first_gid == other.first_gid
+            && self.source == other.source
+            && self.resolved == other.resolved
+            && self.version == other.version
+            && self.tiled_version == other.tiled_version
+            && self.name == other.name
+            && self.tile_width == other.tile_width
+            && self.tile_height == other.tile_height
+            && self.spacing == other.spacing
+            && self.margin == other.margin
+            && self.tile_count == other.tile_count
+            && self.columns == other.columns
+            && self.object_alignment == other.object_alignment
+            && self.image == other.image
+            && self.tile_offset == other.tile_offset
+            && self.properties == other.properties
+            && self.terrain_types == other.terrain_types
+            && self.tiles == other.tiles
+    }
 }
 
 impl FromStr for Tileset {
     type Err = Error;
 
     fn from_str(s: &str) -> ::Result<Tileset> {
-        let mut tsx = TmxReader::new(s.as_bytes());
-        tsx.read_tileset()
+        Tileset::from_str_with(s, &ReaderOptions::default())
     }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TileOffset {
     x: i32,
     y: i32,
@@ -184,17 +881,14 @@ pub struct TileOffset {
 
 impl TileOffset {
     pub fn new(x: i32, y: i32) -> TileOffset {
-        TileOffset {
-            x: x,
-            y: y,
-        }
+        TileOffset { x, y }
     }
 
     pub fn x(&self) -> i32 {
         self.x
     }
 
-    fn set_x(&mut self, x: i32) {
+    pub fn set_x(&mut self, x: i32) {
         self.x = x;
     }
 
@@ -202,12 +896,13 @@ impl TileOffset {
         self.y
     }
 
-    fn set_y(&mut self, y: i32) {
+    pub fn set_y(&mut self, y: i32) {
         self.y = y;
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TerrainCollection(Vec<Terrain>);
 
 impl TerrainCollection {
@@ -220,14 +915,17 @@ impl TerrainCollection {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Tile {
     id: u32,
     corners: Option<Corners>,
     probability: Option<f32>,
-    animation: Option<Animation>,
-    image: Option<Image>,
-    object_group: Option<ObjectGroup>,
+    // Few tiles have an animation, an image or a collision group: boxing
+    // them keeps the others small.
+    animation: Option<Box<Animation>>,
+    image: Option<Box<Image>>,
+    object_group: Option<Box<ObjectGroup>>,
     properties: PropertyCollection,
 }
 
@@ -236,7 +934,7 @@ impl Tile {
         self.id
     }
 
-    fn set_id(&mut self, id: u32) {
+    pub fn set_id(&mut self, id: u32) {
         self.id = id;
     }
 
@@ -244,7 +942,7 @@ impl Tile {
         self.corners.as_ref()
     }
 
-    fn set_corners(&mut self, corners: Corners) {
+    pub fn set_corners(&mut self, corners: Corners) {
         self.corners = Some(corners);
     }
 
@@ -252,7 +950,7 @@ impl Tile {
         self.probability
     }
 
-    fn set_probability(&mut self, probability: f32) {
+    pub fn set_probability(&mut self, probability: f32) {
         self.probability = Some(probability);
     }
 
@@ -260,44 +958,134 @@ impl Tile {
         self.properties.iter()
     }
 
-    fn set_properties(&mut self, properties: PropertyCollection) {
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.get(name)
+    }
+
+    pub fn set_properties(&mut self, properties: PropertyCollection) {
         self.properties = properties;
     }
 
+    pub fn properties_mut(&mut self) -> &mut PropertyCollection {
+        &mut self.properties
+    }
+
     pub fn image(&self) -> Option<&Image> {
-        self.image.as_ref()
+        self.image.as_deref()
     }
 
-    fn set_image(&mut self, image: Image) {
-        self.image = Some(image);
+    pub fn into_image(self) -> Option<Image> {
+        self.image.map(|image| *image)
+    }
+
+    pub fn set_image(&mut self, image: Image) {
+        self.image = Some(Box::new(image));
     }
 
     pub fn object_group(&self) -> Option<&ObjectGroup> {
-        self.object_group.as_ref()
+        self.object_group.as_deref()
     }
 
-    fn set_object_group(&mut self, object_group: ObjectGroup) {
-        self.object_group = Some(object_group);
+    /// Iterates over the shapes of the tile's collision group, relative to the
+    /// top-left corner of the tile.
+    ///
+    /// Hidden objects are skipped unless `include_hidden` is set. Point objects
+    /// are always skipped.
+    pub fn collision_shapes(&self, include_hidden: bool) -> impl Iterator<Item = CollisionShape> + '_ {
+        self.object_group
+            .iter()
+            .flat_map(|group| group.objects())
+            .filter(move |object| include_hidden || object.is_visible())
+            .filter_map(Object::shape_in_map_space)
+            .filter(|shape| !matches!(shape, CollisionShape::Point(..)))
+    }
+
+    pub fn into_object_group(self) -> Option<ObjectGroup> {
+        self.object_group.map(|object_group| *object_group)
+    }
+
+    pub fn set_object_group(&mut self, object_group: ObjectGroup) {
+        self.object_group = Some(Box::new(object_group));
     }
 
     pub fn animation(&self) -> Option<&Animation> {
-        self.animation.as_ref()
+        self.animation.as_deref()
+    }
+
+    pub fn set_animation(&mut self, animation: Animation) {
+        self.animation = Some(Box::new(animation));
+    }
+}
+
+/// The terrain types at the corners of a tile.
+///
+/// The fields are in the order used by Tiled: top-left, top-right, bottom-left
+/// and bottom-right. A corner without terrain is `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Corners(pub Option<u32>, pub Option<u32>, pub Option<u32>, pub Option<u32>);
+
+impl Corners {
+    pub fn new(top_left: Option<u32>,
+               top_right: Option<u32>,
+               bottom_left: Option<u32>,
+               bottom_right: Option<u32>)
+               -> Corners {
+        Corners(top_left, top_right, bottom_left, bottom_right)
+    }
+
+    pub fn top_left(&self) -> Option<u32> {
+        self.0
+    }
+
+    pub fn top_right(&self) -> Option<u32> {
+        self.1
     }
 
-    fn set_animation(&mut self, animation: Animation) {
-        self.animation = Some(animation);
+    pub fn bottom_left(&self) -> Option<u32> {
+        self.2
+    }
+
+    pub fn bottom_right(&self) -> Option<u32> {
+        self.3
+    }
+
+    /// Returns the corners as `[top_left, top_right, bottom_left, bottom_right]`.
+    pub fn corners(&self) -> [Option<u32>; 4] {
+        [self.0, self.1, self.2, self.3]
+    }
+}
+
+impl From<[Option<u32>; 4]> for Corners {
+    fn from(corners: [Option<u32>; 4]) -> Corners {
+        Corners(corners[0], corners[1], corners[2], corners[3])
+    }
+}
+
+impl fmt::Display for Corners {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, corner) in self.corners().iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            if let Some(id) = *corner {
+                write!(f, "{}", id)?;
+            }
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct Corners(pub u32, pub u32, pub u32, pub u32);
+#[cfg(feature = "serde")]
+serde_as_string!(Corners);
 
 impl FromStr for Corners {
     type Err = Error;
 
     fn from_str(s: &str) -> ::Result<Corners> {
         let ids = {
-            let result: Result<Vec<u32>, _> = s.split(',').map(reader::read_num).collect();
+            let result: Result<Vec<Option<u32>>, _> = s.split(',')
+                .map(|id| if id.is_empty() { Ok(None) } else { reader::read_num(id).map(Some) })
+                .collect();
             result?
         };
         if ids.len() == 4 {
@@ -308,33 +1096,195 @@ impl FromStr for Corners {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Animation {
-    frame: Option<Frame>,
+    frames: Vec<Frame>,
 }
 
 impl Animation {
+    pub fn new(frames: Vec<Frame>) -> Animation {
+        Animation { frames }
+    }
+
+    /// Returns the first frame of the animation.
     pub fn frame(&self) -> Option<&Frame> {
-        self.frame.as_ref()
+        self.frames.first()
+    }
+
+    pub fn frames(&self) -> Frames {
+        Frames(self.frames.iter())
+    }
+
+    fn add_frame(&mut self, frame: Frame) {
+        self.frames.push(frame);
+    }
+
+    /// Returns the duration of a whole cycle, in milliseconds.
+    ///
+    /// Counted in a `u64`, since the frame durations may add up to more than a
+    /// `u32` holds.
+    pub fn total_duration(&self) -> u64 {
+        self.frames.iter().map(|frame| u64::from(frame.duration())).sum()
+    }
+
+    /// Returns the frame shown after `elapsed_ms` milliseconds, the animation looping forever.
+    ///
+    /// Frames with a zero duration are never shown. Returns `None` if the
+    /// animation has no frame with a non-zero duration.
+    pub fn frame_at(&self, elapsed_ms: u64) -> Option<&Frame> {
+        let total_duration = self.total_duration();
+        if total_duration == 0 {
+            return None;
+        }
+        let mut remaining = elapsed_ms % total_duration;
+        for frame in &self.frames {
+            let duration = u64::from(frame.duration());
+            if remaining < duration {
+                return Some(frame);
+            }
+            remaining -= duration;
+        }
+        None
+    }
+}
+
+/// The playback of an animation, advanced by the time elapsed between two
+/// updates of a game loop.
+#[derive(Debug, Clone, Copy)]
+pub struct AnimationState<'a> {
+    animation: &'a Animation,
+    looping: bool,
+    // Time elapsed since the start of the current cycle, in milliseconds.
+    elapsed: u64,
+}
+
+impl<'a> AnimationState<'a> {
+    /// Starts playing an animation looping forever.
+    pub fn new(animation: &'a Animation) -> AnimationState<'a> {
+        AnimationState {
+            animation,
+            looping: true,
+            elapsed: 0,
+        }
+    }
+
+    /// Starts playing an animation once, stopping on its last frame.
+    pub fn once(animation: &'a Animation) -> AnimationState<'a> {
+        AnimationState {
+            looping: false,
+            ..AnimationState::new(animation)
+        }
+    }
+
+    /// Starts playing the animation of the tile with the given local id, if it has one.
+    pub fn for_tile(tileset: &'a Tileset, tile_id: u32) -> Option<AnimationState<'a>> {
+        tileset.tile_by_id(tile_id).and_then(Tile::animation).map(AnimationState::new)
+    }
+
+    /// Moves the animation `dt_ms` milliseconds forward and returns the local
+    /// id of the tile shown then.
+    ///
+    /// Frames with a zero duration are never shown. Returns `None` if the
+    /// animation has no frame with a non-zero duration.
+    pub fn advance(&mut self, dt_ms: u32) -> Option<u32> {
+        let total_duration = self.animation.total_duration();
+        let elapsed = self.elapsed.saturating_add(u64::from(dt_ms));
+        self.elapsed = if self.looping {
+            elapsed % total_duration.max(1)
+        } else {
+            elapsed.min(total_duration)
+        };
+        self.tile_id()
+    }
+
+    /// Returns the local id of the tile shown, or `None` if the animation has
+    /// no frame with a non-zero duration.
+    pub fn tile_id(&self) -> Option<u32> {
+        if self.is_finished() {
+            return self.animation.frames.iter().rev().find(|frame| frame.duration() > 0).map(Frame::tile_id);
+        }
+        self.animation.frame_at(self.elapsed).map(Frame::tile_id)
+    }
+
+    /// Goes back to the first frame.
+    pub fn reset(&mut self) {
+        self.elapsed = 0;
+    }
+
+    /// Tells whether an animation played once has reached its end. An
+    /// animation looping forever never does.
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.elapsed >= self.animation.total_duration()
+    }
+}
+
+/// The tile ids given by `Tileset::merge` to the tiles of the tileset merged
+/// into another.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GidRemapTable {
+    first_old_gid: u32,
+    source_image: Option<Image>,
+    entries: Vec<GidRemap>,
+}
+
+impl GidRemapTable {
+    /// Returns the image of the merged tileset, holding the areas given by
+    /// `GidRemap::source_rect`.
+    pub fn source_image(&self) -> Option<&Image> {
+        self.source_image.as_ref()
     }
 
-    fn set_frame(&mut self, frame: Frame) {
-        self.frame = Some(frame);
+    /// Returns the new tile id of every tile of the merged tileset, in order.
+    pub fn entries(&self) -> &[GidRemap] {
+        &self.entries
+    }
+
+    /// Returns the entry of the tile with the given tile id in the merged tileset.
+    /// The flip flags must already be stripped from the id.
+    pub fn get(&self, old_id: u32) -> Option<&GidRemap> {
+        old_id.checked_sub(self.first_old_gid).and_then(|index| self.entries.get(index as usize))
+    }
+
+    /// Returns the new tile id of a tile of the merged tileset, other ids
+    /// being returned as they are. The flip flags must already be stripped
+    /// from the id.
+    pub fn remap(&self, id: u32) -> u32 {
+        self.get(id).map_or(id, |entry| entry.new_gid)
     }
 }
 
-#[derive(Debug, Default)]
+/// The new tile id of a tile moved by `Tileset::merge`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GidRemap {
+    pub old_gid: u32,
+    pub new_gid: u32,
+    /// The area of the tile in the image of the merged tileset, or `None` if
+    /// it is an image collection.
+    pub source_rect: Option<Rect>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Frame {
     duration: u32,
     tile_id: u32,
 }
 
 impl Frame {
+    /// Creates a frame showing the tile with the local id `tile_id` for `duration` milliseconds.
+    pub fn new(tile_id: u32, duration: u32) -> Frame {
+        Frame {
+            duration,
+            tile_id,
+        }
+    }
+
     pub fn tile_id(&self) -> u32 {
         self.tile_id
     }
 
-    fn set_tile_id(&mut self, tile_id: u32) {
+    pub fn set_tile_id(&mut self, tile_id: u32) {
         self.tile_id = tile_id;
     }
 
@@ -342,12 +1292,13 @@ impl Frame {
         self.duration
     }
 
-    fn set_duration(&mut self, duration: u32) {
+    pub fn set_duration(&mut self, duration: u32) {
         self.duration = duration;
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Terrain {
     name: String,
     tile: String,
@@ -375,13 +1326,17 @@ impl Terrain {
         self.properties.iter()
     }
 
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.get(name)
+    }
+
     fn set_properties(&mut self, properties: PropertyCollection) {
         self.properties = properties;
     }
 }
 
 impl<R: Read> ElementReader<Tileset> for TmxReader<R> {
-    fn read_attributes(&mut self, tileset: &mut Tileset, name: &str, value: &str) -> ::Result<()> {
+    fn read_attributes(&mut self, tileset: &mut Tileset, tag: &str, name: &str, value: &str) -> ::Result<()> {
         match name {
             "firstgid" => {
                 let first_gid = reader::read_num(value)?;
@@ -390,6 +1345,12 @@ impl<R: Read> ElementReader<Tileset> for TmxReader<R> {
             "source" => {
                 tileset.set_source(value);
             }
+            "version" => {
+                tileset.set_version(value);
+            }
+            "tiledversion" => {
+                tileset.set_tiled_version(value);
+            }
             "name" => {
                 tileset.set_name(value);
             }
@@ -417,14 +1378,21 @@ impl<R: Read> ElementReader<Tileset> for TmxReader<R> {
                 let columns = reader::read_num(value)?;
                 tileset.set_columns(columns);
             }
+            "objectalignment" => {
+                let object_alignment = match value {
+                    "unspecified" => None,
+                    _ => Some(Anchor::from_str(value)?),
+                };
+                tileset.set_object_alignment(object_alignment);
+            }
             _ => {
-                return Err(Error::UnknownAttribute(name.to_string()));
+                return Err(reader::unknown_attribute(tag, name));
             }
         };
         Ok(())
     }
 
-    fn read_children(&mut self, tileset: &mut Tileset, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
+    fn read_children(&mut self, tileset: &mut Tileset, tag: &str, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
         match name {
             "tileoffset" => {
                 let tile_offset = self.on_tile_offset(attributes)?;
@@ -439,21 +1407,29 @@ impl<R: Read> ElementReader<Tileset> for TmxReader<R> {
                 tileset.set_image(image);
             }
             "terraintypes" => {
+                self.diagnose(Severity::Info,
+                              "The `terraintypes` element is deprecated, Tiled 1.5 replaced terrains with Wang sets".to_string());
                 let terrain_types = self.on_terrain_types(attributes)?;
                 tileset.set_terrain_types(terrain_types);
             }
             "tile" => {
+                if tileset.tiles.is_empty() {
+                    let count = tileset.tile_count as usize;
+                    tileset.tiles.reserve(count.min(MAX_RESERVED_TILES));
+                }
                 let tile = self.on_tile(attributes)?;
                 tileset.add_tile(tile);
             }
-            _ => {}
+            _ => {
+                return Err(reader::unknown_element(tag, name));
+            }
         };
         Ok(())
     }
 }
 
 impl<R: Read> ElementReader<TileOffset> for TmxReader<R> {
-    fn read_attributes(&mut self, tile_offset: &mut TileOffset, name: &str, value: &str) -> ::Result<()> {
+    fn read_attributes(&mut self, tile_offset: &mut TileOffset, tag: &str, name: &str, value: &str) -> ::Result<()> {
         match name {
             "x" => {
                 let x = reader::read_num(value)?;
@@ -464,7 +1440,7 @@ impl<R: Read> ElementReader<TileOffset> for TmxReader<R> {
                 tile_offset.set_y(y);
             }
             _ => {
-                return Err(Error::UnknownAttribute(name.to_string()));
+                return Err(reader::unknown_attribute(tag, name));
             }
         };
         Ok(())
@@ -472,7 +1448,7 @@ impl<R: Read> ElementReader<TileOffset> for TmxReader<R> {
 }
 
 impl<R: Read> ElementReader<Terrain> for TmxReader<R> {
-    fn read_attributes(&mut self, terrain: &mut Terrain, name: &str, value: &str) -> ::Result<()> {
+    fn read_attributes(&mut self, terrain: &mut Terrain, tag: &str, name: &str, value: &str) -> ::Result<()> {
         match name {
             "name" => {
                 terrain.set_name(value);
@@ -481,23 +1457,25 @@ impl<R: Read> ElementReader<Terrain> for TmxReader<R> {
                 terrain.set_tile(value);
             }
             _ => {
-                return Err(Error::UnknownAttribute(name.to_string()));
+                return Err(reader::unknown_attribute(tag, name));
             }
         };
         Ok(())
     }
 
-    fn read_children(&mut self, terrain: &mut Terrain, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
+    fn read_children(&mut self, terrain: &mut Terrain, tag: &str, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
         if let "properties" = name {
             let properties = self.on_properties(attributes)?;
             terrain.set_properties(properties);
+        } else {
+            return Err(reader::unknown_element(tag, name));
         }
         Ok(())
     }
 }
 
 impl<R: Read> ElementReader<Tile> for TmxReader<R> {
-    fn read_attributes(&mut self, tile: &mut Tile, name: &str, value: &str) -> ::Result<()> {
+    fn read_attributes(&mut self, tile: &mut Tile, tag: &str, name: &str, value: &str) -> ::Result<()> {
         match name {
             "id" => {
                 let id = reader::read_num(value)?;
@@ -515,13 +1493,13 @@ impl<R: Read> ElementReader<Tile> for TmxReader<R> {
                 tile.set_probability(probability);
             }
             _ => {
-                return Err(Error::UnknownAttribute(name.to_string()));
+                return Err(reader::unknown_attribute(tag, name));
             }
         };
         Ok(())
     }
 
-    fn read_children(&mut self, tile: &mut Tile, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
+    fn read_children(&mut self, tile: &mut Tile, tag: &str, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
         match name {
             "properties" => {
                 let properties = self.on_properties(attributes)?;
@@ -531,6 +1509,9 @@ impl<R: Read> ElementReader<Tile> for TmxReader<R> {
                 let image = self.on_image(attributes)?;
                 tile.set_image(image);
             }
+            "objectgroup" if self.options().skip_objects => {
+                self.skip_element()?;
+            }
             "objectgroup" => {
                 let object_group = self.on_object_group(attributes)?;
                 tile.set_object_group(object_group);
@@ -539,44 +1520,40 @@ impl<R: Read> ElementReader<Tile> for TmxReader<R> {
                 let animation = self.on_animation(attributes)?;
                 tile.set_animation(animation);
             }
-            _ => {}
+            _ => {
+                return Err(reader::unknown_element(tag, name));
+            }
         };
         Ok(())
     }
 }
 
-impl<R: Read> ElementReader<PropertyCollection> for TmxReader<R> {
-    fn read_children(&mut self, properties: &mut PropertyCollection, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
-        if name == "property" {
-            let property = self.on_property(attributes)?;
-            properties.push(property);
-        }
-        Ok(())
-    }
-}
-
 impl<R: Read> ElementReader<TerrainCollection> for TmxReader<R> {
-    fn read_children(&mut self, terrain_types: &mut TerrainCollection, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
+    fn read_children(&mut self, terrain_types: &mut TerrainCollection, tag: &str, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
         if name == "terrain" {
             let terrain = self.on_terrain(attributes)?;
             terrain_types.push(terrain);
+        } else {
+            return Err(reader::unknown_element(tag, name));
         }
         Ok(())
     }
 }
 
 impl<R: Read> ElementReader<Animation> for TmxReader<R> {
-    fn read_children(&mut self, animation: &mut Animation, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
+    fn read_children(&mut self, animation: &mut Animation, tag: &str, name: &str, attributes: &[OwnedAttribute]) -> ::Result<()>{
         if let "frame" = name {
             let frame = self.on_frame(attributes)?;
-            animation.set_frame(frame);
+            animation.add_frame(frame);
+        } else {
+            return Err(reader::unknown_element(tag, name));
         }
         Ok(())
     }
 }
 
 impl<R: Read> ElementReader<Frame> for TmxReader<R> {
-    fn read_attributes(&mut self, frame: &mut Frame, name: &str, value: &str) -> ::Result<()> {
+    fn read_attributes(&mut self, frame: &mut Frame, tag: &str, name: &str, value: &str) -> ::Result<()> {
         match name {
             "tileid" => {
                 let tile_id = reader::read_num(value)?;
@@ -587,10 +1564,135 @@ impl<R: Read> ElementReader<Frame> for TmxReader<R> {
                 frame.set_duration(duration);
             }
             _ => {
-                return Err(Error::UnknownAttribute(name.to_string()));
+                return Err(reader::unknown_attribute(tag, name));
             }
         };
         Ok(())
     }
 }
 
+impl<W: Write> ElementWriter<Tileset> for TmxWriter<W> {
+    fn write_element(&mut self, tileset: &Tileset) -> ::Result<()> {
+        self.write_tileset_element(tileset, false)
+    }
+}
+
+impl<W: Write> TmxWriter<W> {
+    // Writes either a map's reference to a tileset, which only has `firstgid` and
+    // `source` when the tileset is external, or the root element of a TSX document.
+    pub(crate) fn write_tileset_element(&mut self, tileset: &Tileset, standalone: bool) -> ::Result<()> {
+        let mut attributes = Attributes::new();
+        if standalone {
+            attributes.add("version", if tileset.version.is_empty() { "1.0" } else { &tileset.version });
+            attributes.add_if(!tileset.tiled_version.is_empty(), "tiledversion", &tileset.tiled_version);
+        } else {
+            attributes.add_if(tileset.first_gid > 0, "firstgid", tileset.first_gid);
+            if tileset.is_external() {
+                attributes.add("source", &tileset.source);
+                return self.empty_element("tileset", &attributes);
+            }
+        }
+        attributes.add("name", &tileset.name);
+        attributes.add("tilewidth", tileset.tile_width);
+        attributes.add("tileheight", tileset.tile_height);
+        attributes.add_if(tileset.spacing > 0, "spacing", tileset.spacing);
+        attributes.add_if(tileset.margin > 0, "margin", tileset.margin);
+        attributes.add_if(tileset.tile_count > 0, "tilecount", tileset.tile_count);
+        attributes.add_if(tileset.columns > 0, "columns", tileset.columns);
+        attributes.add_option("objectalignment", tileset.object_alignment.map(|anchor| anchor.as_str()));
+        let has_children = tileset.tile_offset.is_some() || !tileset.properties.is_empty() || tileset.image.is_some() ||
+                           !tileset.terrain_types.0.is_empty() || !tileset.tiles.is_empty();
+        self.element("tileset", &attributes, has_children, |w| {
+            if let Some(ref tile_offset) = tileset.tile_offset {
+                w.write_element(tile_offset)?;
+            }
+            w.write_element(&tileset.properties)?;
+            if let Some(ref image) = tileset.image {
+                w.write_element(image)?;
+            }
+            w.write_element(&tileset.terrain_types)?;
+            for tile in &tileset.tiles {
+                w.write_element(tile)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<W: Write> ElementWriter<TileOffset> for TmxWriter<W> {
+    fn write_element(&mut self, tile_offset: &TileOffset) -> ::Result<()> {
+        let mut attributes = Attributes::new();
+        attributes.add("x", tile_offset.x);
+        attributes.add("y", tile_offset.y);
+        self.empty_element("tileoffset", &attributes)
+    }
+}
+
+impl<W: Write> ElementWriter<TerrainCollection> for TmxWriter<W> {
+    fn write_element(&mut self, terrain_types: &TerrainCollection) -> ::Result<()> {
+        if terrain_types.0.is_empty() {
+            return Ok(());
+        }
+        self.element("terraintypes", &Attributes::new(), true, |w| {
+            for terrain in terrain_types.iter() {
+                w.write_element(terrain)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<W: Write> ElementWriter<Terrain> for TmxWriter<W> {
+    fn write_element(&mut self, terrain: &Terrain) -> ::Result<()> {
+        let mut attributes = Attributes::new();
+        attributes.add("name", &terrain.name);
+        attributes.add("tile", &terrain.tile);
+        let has_children = !terrain.properties.is_empty();
+        self.element("terrain", &attributes, has_children, |w| w.write_element(&terrain.properties))
+    }
+}
+
+impl<W: Write> ElementWriter<Tile> for TmxWriter<W> {
+    fn write_element(&mut self, tile: &Tile) -> ::Result<()> {
+        let mut attributes = Attributes::new();
+        attributes.add("id", tile.id);
+        attributes.add_option("terrain", tile.corners);
+        attributes.add_option("probability", tile.probability);
+        let has_children = !tile.properties.is_empty() || tile.image.is_some() || tile.object_group.is_some() ||
+                           tile.animation.is_some();
+        self.element("tile", &attributes, has_children, |w| {
+            w.write_element(&tile.properties)?;
+            if let Some(image) = tile.image() {
+                w.write_element(image)?;
+            }
+            if let Some(object_group) = tile.object_group() {
+                w.write_element(object_group)?;
+            }
+            if let Some(animation) = tile.animation() {
+                w.write_element(animation)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<W: Write> ElementWriter<Animation> for TmxWriter<W> {
+    fn write_element(&mut self, animation: &Animation) -> ::Result<()> {
+        let has_children = !animation.frames.is_empty();
+        self.element("animation", &Attributes::new(), has_children, |w| {
+            for frame in &animation.frames {
+                w.write_element(frame)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<W: Write> ElementWriter<Frame> for TmxWriter<W> {
+    fn write_element(&mut self, frame: &Frame) -> ::Result<()> {
+        let mut attributes = Attributes::new();
+        attributes.add("tileid", frame.tile_id);
+        attributes.add("duration", frame.duration);
+        self.empty_element("frame", &attributes)
+    }
+}
@@ -0,0 +1,315 @@
+// This file is part of tmx
+// Copyright 2017 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::Read;
+
+use xml::attribute::OwnedAttribute;
+use xml::reader::XmlEvent;
+
+use model::map::{Group, ImageLayer, Layer, Map, Object, ObjectGroup};
+use model::property::PropertyCollection;
+use model::reader::{self, TmxReader};
+use model::tileset::Tileset;
+
+/// Tells the reader how to go on after a `MapVisitor` callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// Keeps reading.
+    Continue,
+    /// Skips the rest of the element the callback is about: the content of a
+    /// layer or object group after its `*_begin` callback, the remaining
+    /// chunks of a layer, the remaining objects of a group, or the rest of
+    /// the map for the callbacks of the map itself.
+    Skip,
+    /// Stops reading the document.
+    Stop,
+}
+
+/// Receives the parts of a map as they are read by `TmxReader::visit_map`.
+///
+/// Only what the visitor asks for is built: a layer or object group whose
+/// `*_begin` callback returns `Flow::Skip` is fast-forwarded without reading
+/// its properties, data or objects. Tile data is not kept in the map: the
+/// data of a layer is decoded as a whole, then handed out one row at a time
+/// and dropped at the end of the layer. As with `Map`, the layers of a group
+/// are visited as layers of the map. All callbacks do nothing by default.
+#[allow(unused_variables)]
+pub trait MapVisitor {
+    /// Called with a map holding the attributes of the `<map>` element only.
+    fn on_map_attributes(&mut self, map: &Map) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called with the properties of the map.
+    fn on_map_properties(&mut self, properties: PropertyCollection) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called with each tileset, embedded or referenced. External tilesets
+    /// are not loaded.
+    fn on_tileset(&mut self, tileset: Tileset) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called with a layer holding the attributes of the `<layer>` element only.
+    fn on_layer_begin(&mut self, layer: &Layer) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called with the decoded global tile ids of a row of the current layer,
+    /// flip flags included. The rows of chunked data are those of
+    /// `Data::chunk_bounds`.
+    fn on_layer_data_chunk(&mut self, gids: &[u32]) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called at the end of a layer that was not skipped, with its properties
+    /// but without its data.
+    fn on_layer_end(&mut self, layer: Layer) {}
+
+    /// Called with each image layer.
+    fn on_image_layer(&mut self, image_layer: ImageLayer) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called with an object group holding the attributes of the
+    /// `<objectgroup>` element only.
+    fn on_object_group_begin(&mut self, object_group: &ObjectGroup) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called with each object of the current object group.
+    fn on_object(&mut self, object: Object) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called at the end of an object group that was not skipped, with its
+    /// properties but without its objects.
+    fn on_object_group_end(&mut self, object_group: ObjectGroup) {}
+}
+
+impl<R: Read> TmxReader<R> {
+    /// Reads a map, handing its parts to `visitor` instead of building a `Map`.
+    pub fn visit_map<V: MapVisitor + ?Sized>(&mut self, visitor: &mut V) -> ::Result<()> {
+        let attributes = self.read_root("map")?;
+        self.check_required_attributes("map", &attributes, reader::REQUIRED_MAP_ATTRIBUTES)?;
+        match self.visit_map_element(&attributes, visitor)? {
+            Flow::Stop => Ok(()),
+            _ => self.read_end_of_document(),
+        }
+    }
+
+    fn visit_map_element<V: MapVisitor + ?Sized>(&mut self, attributes: &[OwnedAttribute], visitor: &mut V)
+        -> ::Result<Flow>
+    {
+        self.enter_element()?;
+        let mut map = Map::default();
+        self.read_element_attributes(&mut map, "map", attributes)?;
+        let mut flow = visitor.on_map_attributes(&map);
+        while flow == Flow::Continue {
+            match self.next_event()? {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    flow = match &name.local_name[..] {
+                        "properties" => {
+                            let properties = self.on_properties(&attributes)?;
+                            visitor.on_map_properties(properties)
+                        }
+                        "tileset" => {
+                            let tileset = self.on_map_tileset(&attributes)?;
+                            visitor.on_tileset(tileset)
+                        }
+                        _ => self.visit_layer_element("map", name.local_name, &attributes, &map, visitor)?,
+                    };
+                }
+                XmlEvent::EndElement { ref name, .. } if name.local_name == "map" => break,
+                XmlEvent::EndDocument => break,
+                _ => {}
+            }
+        }
+        if flow == Flow::Skip {
+            self.skip_element()?;
+            flow = Flow::Continue;
+        }
+        self.leave_element();
+        Ok(flow)
+    }
+
+    // Visits a child element of a map or group that holds a layer.
+    fn visit_layer_element<V: MapVisitor + ?Sized>(&mut self, parent: &str, name: String,
+                                                   attributes: &[OwnedAttribute], map: &Map, visitor: &mut V)
+        -> ::Result<Flow>
+    {
+        let flow = match &name[..] {
+            "layer" => self.visit_layer(attributes, map, visitor)?,
+            "objectgroup" if self.options().skip_objects => {
+                self.skip_element()?;
+                Flow::Continue
+            }
+            "objectgroup" => self.visit_object_group(attributes, visitor)?,
+            "imagelayer" if self.options().skip_image_layers => {
+                self.skip_element()?;
+                Flow::Continue
+            }
+            "imagelayer" => {
+                let image_layer = self.on_image_layer(attributes)?;
+                visitor.on_image_layer(image_layer)
+            }
+            "group" => self.visit_group(attributes, map, visitor)?,
+            _ => {
+                self.on_unknown_element(parent.to_string(), name)?;
+                Flow::Continue
+            }
+        };
+        Ok(flow)
+    }
+
+    // Visits the layers of a group as layers of the map, as `Map` reads them.
+    // A `Flow::Skip` from the visitor skips the rest of the group, then the
+    // rest of the map.
+    fn visit_group<V: MapVisitor + ?Sized>(&mut self, attributes: &[OwnedAttribute], map: &Map, visitor: &mut V)
+        -> ::Result<Flow>
+    {
+        self.enter_element()?;
+        let position = self.position();
+        let mut group = Group::default();
+        self.read_element_attributes(&mut group, "group", attributes)?;
+        let mut flow = Flow::Continue;
+        while flow == Flow::Continue {
+            match self.next_event()? {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    flow = match &name.local_name[..] {
+                        "properties" => {
+                            self.on_properties(&attributes)?;
+                            group.drop_properties();
+                            Flow::Continue
+                        }
+                        _ => self.visit_layer_element("group", name.local_name, &attributes, map, visitor)?,
+                    };
+                }
+                XmlEvent::EndElement { ref name, .. } if name.local_name == "group" => break,
+                XmlEvent::EndDocument => break,
+                _ => {}
+            }
+        }
+        match flow {
+            Flow::Stop => return Ok(Flow::Stop),
+            Flow::Skip => self.skip_element()?,
+            Flow::Continue => {}
+        }
+        self.leave_element();
+        self.diagnose_group(position, &group);
+        Ok(flow)
+    }
+
+    // Visits a layer, returning `Flow::Stop` if the visitor asked to stop.
+    fn visit_layer<V: MapVisitor + ?Sized>(&mut self, attributes: &[OwnedAttribute], map: &Map, visitor: &mut V)
+        -> ::Result<Flow>
+    {
+        self.enter_element()?;
+        let mut layer = Layer::default();
+        self.read_element_attributes(&mut layer, "layer", attributes)?;
+        layer.default_size(map.width(), map.height());
+        match visitor.on_layer_begin(&layer) {
+            Flow::Continue => {}
+            flow => return self.skip_visited_element(flow),
+        }
+        loop {
+            match self.next_event()? {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    match &name.local_name[..] {
+                        "properties" => {
+                            let properties = self.on_properties(&attributes)?;
+                            layer.set_properties(properties);
+                        }
+                        "data" if self.options().skip_layer_data => self.skip_element()?,
+                        "data" => {
+                            self.expect_data_tiles(layer.width(), layer.height());
+                            let data = self.on_data(&attributes)?;
+                            let width = data.row_width(layer.width()).max(1) as usize;
+                            for row in data.decode()?.chunks(width) {
+                                match visitor.on_layer_data_chunk(row) {
+                                    Flow::Continue => {}
+                                    Flow::Skip => break,
+                                    Flow::Stop => return Ok(Flow::Stop),
+                                }
+                            }
+                        }
+                        _ => self.on_unknown_element("layer".to_string(), name.local_name)?,
+                    }
+                }
+                XmlEvent::EndElement { ref name, .. } if name.local_name == "layer" => break,
+                XmlEvent::EndDocument => break,
+                _ => {}
+            }
+        }
+        self.leave_element();
+        visitor.on_layer_end(layer);
+        Ok(Flow::Continue)
+    }
+
+    // Visits an object group, returning `Flow::Stop` if the visitor asked to stop.
+    fn visit_object_group<V: MapVisitor + ?Sized>(&mut self, attributes: &[OwnedAttribute], visitor: &mut V)
+        -> ::Result<Flow>
+    {
+        self.enter_element()?;
+        let mut object_group = ObjectGroup::default();
+        self.read_element_attributes(&mut object_group, "objectgroup", attributes)?;
+        match visitor.on_object_group_begin(&object_group) {
+            Flow::Continue => {}
+            flow => return self.skip_visited_element(flow),
+        }
+        loop {
+            match self.next_event()? {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    match &name.local_name[..] {
+                        "properties" => {
+                            let properties = self.on_properties(&attributes)?;
+                            object_group.set_properties(properties);
+                        }
+                        "object" => {
+                            self.count_object()?;
+                            let object = self.on_object(&attributes)?;
+                            match visitor.on_object(object) {
+                                Flow::Continue => {}
+                                Flow::Skip => {
+                                    self.skip_element()?;
+                                    break;
+                                }
+                                Flow::Stop => return Ok(Flow::Stop),
+                            }
+                        }
+                        _ => self.on_unknown_element("objectgroup".to_string(), name.local_name)?,
+                    }
+                }
+                XmlEvent::EndElement { ref name, .. } if name.local_name == "objectgroup" => break,
+                XmlEvent::EndDocument => break,
+                _ => {}
+            }
+        }
+        self.leave_element();
+        visitor.on_object_group_end(object_group);
+        Ok(Flow::Continue)
+    }
+
+    // Leaves the element just started as a `*_begin` callback asked.
+    fn skip_visited_element(&mut self, flow: Flow) -> ::Result<Flow> {
+        if flow == Flow::Stop {
+            return Ok(Flow::Stop);
+        }
+        self.skip_element()?;
+        self.leave_element();
+        Ok(Flow::Continue)
+    }
+}
@@ -0,0 +1,258 @@
+// This file is part of tmx
+// Copyright 2017 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io::Write;
+
+use model::data::DataEncoding;
+use model::map::Map;
+use model::tileset::Tileset;
+
+/// Attributes of an element, written in insertion order.
+#[derive(Debug, Default)]
+pub struct Attributes(Vec<(&'static str, String)>);
+
+impl Attributes {
+    pub fn new() -> Attributes {
+        Attributes(Vec::new())
+    }
+
+    pub fn add<T: Display>(&mut self, name: &'static str, value: T) {
+        self.0.push((name, value.to_string()));
+    }
+
+    pub fn add_if<T: Display>(&mut self, condition: bool, name: &'static str, value: T) {
+        if condition {
+            self.add(name, value);
+        }
+    }
+
+    pub fn add_option<T: Display>(&mut self, name: &'static str, value: Option<T>) {
+        if let Some(value) = value {
+            self.add(name, value);
+        }
+    }
+}
+
+/// Options controlling how a map is written.
+///
+/// The defaults give one space of indentation per level and an XML
+/// declaration, after the files saved by Tiled 1.0. The output is not checked
+/// against files saved by Tiled, so it may still differ from them.
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    /// Encoding of the data of every tile layer. Layers keep the encoding they
+    /// were read with when it is `None`.
+    pub encoding: Option<DataEncoding>,
+    /// Encodings of individual tile layers, by layer name, taking precedence over `encoding`.
+    pub layer_encodings: HashMap<String, DataEncoding>,
+    /// Character used to indent nested elements.
+    pub indent_char: char,
+    /// Number of `indent_char` per nesting level.
+    pub indent_width: usize,
+    /// Whether the document starts with an XML declaration.
+    pub xml_declaration: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> WriteOptions {
+        WriteOptions {
+            encoding: None,
+            layer_encodings: HashMap::new(),
+            indent_char: ' ',
+            indent_width: 1,
+            xml_declaration: true,
+        }
+    }
+}
+
+impl WriteOptions {
+    pub fn new() -> WriteOptions {
+        WriteOptions::default()
+    }
+
+    pub fn with_indent(mut self, indent_char: char, indent_width: usize) -> WriteOptions {
+        self.indent_char = indent_char;
+        self.indent_width = indent_width;
+        self
+    }
+
+    pub fn with_xml_declaration(mut self, xml_declaration: bool) -> WriteOptions {
+        self.xml_declaration = xml_declaration;
+        self
+    }
+
+    pub fn with_encoding(mut self, encoding: DataEncoding) -> WriteOptions {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    pub fn with_layer_encoding<S: Into<String>>(mut self, layer_name: S, encoding: DataEncoding) -> WriteOptions {
+        self.layer_encodings.insert(layer_name.into(), encoding);
+        self
+    }
+
+    /// Returns the encoding requested for the data of the layer named `layer_name`.
+    pub fn layer_encoding(&self, layer_name: &str) -> Option<DataEncoding> {
+        self.layer_encodings.get(layer_name).cloned().or(self.encoding)
+    }
+}
+
+pub trait ElementWriter<T> {
+    fn write_element(&mut self, element: &T) -> ::Result<()>;
+}
+
+pub struct TmxWriter<W: Write> {
+    writer: W,
+    depth: usize,
+    options: WriteOptions,
+}
+
+impl<W: Write> TmxWriter<W> {
+    pub fn new(writer: W) -> TmxWriter<W> {
+        TmxWriter::with_options(writer, WriteOptions::default())
+    }
+
+    pub fn with_options(writer: W, options: WriteOptions) -> TmxWriter<W> {
+        TmxWriter {
+            writer,
+            depth: 0,
+            options,
+        }
+    }
+
+    pub fn options(&self) -> &WriteOptions {
+        &self.options
+    }
+
+    pub fn write_map(&mut self, map: &Map) -> ::Result<()> {
+        self.write_declaration()?;
+        self.write_element(map)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn write_tileset(&mut self, tileset: &Tileset) -> ::Result<()> {
+        self.write_declaration()?;
+        self.write_tileset_element(tileset, true)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn write_declaration(&mut self) -> ::Result<()> {
+        if !self.options.xml_declaration {
+            return Ok(());
+        }
+        writeln!(self.writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        Ok(())
+    }
+
+    /// Writes an element, as an empty element if it has no children.
+    pub fn element<F>(&mut self, name: &str, attributes: &Attributes, has_children: bool, children: F) -> ::Result<()>
+        where F: FnOnce(&mut Self) -> ::Result<()>
+    {
+        if !has_children {
+            self.write_tag(name, attributes, "/>\n")?;
+            return Ok(());
+        }
+        self.write_tag(name, attributes, ">\n")?;
+        self.depth += 1;
+        children(self)?;
+        self.depth -= 1;
+        self.write_indent()?;
+        writeln!(self.writer, "</{}>", name)?;
+        Ok(())
+    }
+
+    pub fn empty_element(&mut self, name: &str, attributes: &Attributes) -> ::Result<()> {
+        self.element(name, attributes, false, |_| Ok(()))
+    }
+
+    /// Writes an element whose content is text, written as is apart from escaping.
+    pub fn text_element(&mut self, name: &str, attributes: &Attributes, text: &str) -> ::Result<()> {
+        self.write_tag(name, attributes, ">")?;
+        writeln!(self.writer, "{}</{}>", escape(text, false), name)?;
+        Ok(())
+    }
+
+    fn write_tag(&mut self, name: &str, attributes: &Attributes, end: &str) -> ::Result<()> {
+        self.write_indent()?;
+        write!(self.writer, "<{}", name)?;
+        for &(name, ref value) in &attributes.0 {
+            write!(self.writer, " {}=\"{}\"", name, escape(value, true))?;
+        }
+        write!(self.writer, "{}", end)?;
+        Ok(())
+    }
+
+    /// Returns the indentation of an element `extra` levels below the current one.
+    pub fn indentation(&self, extra: usize) -> String {
+        let count = (self.depth + extra) * self.options.indent_width;
+        self.options.indent_char.to_string().repeat(count)
+    }
+
+    fn write_indent(&mut self) -> ::Result<()> {
+        let indentation = self.indentation(0);
+        self.writer.write_all(indentation.as_bytes())?;
+        Ok(())
+    }
+}
+
+fn escape(s: &str, in_attribute: bool) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' if in_attribute => escaped.push_str("&quot;"),
+            '\n' if in_attribute => escaped.push_str("&#10;"),
+            '\r' if in_attribute => escaped.push_str("&#13;"),
+            '\t' if in_attribute => escaped.push_str("&#9;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!("a &amp; &lt;b&gt; \"c\"\n", escape("a & <b> \"c\"\n", false));
+        assert_eq!("&quot;a&quot;&#10;&#9;b", escape("\"a\"\n\tb", true));
+    }
+
+    #[test]
+    fn test_nested_elements() {
+        let mut output = Vec::new();
+        {
+            let mut writer = TmxWriter::new(&mut output);
+            let mut attributes = Attributes::new();
+            attributes.add("name", "a<b");
+            attributes.add_if(false, "hidden", 1);
+            attributes.add_option("width", Some(3));
+            writer.element("outer", &attributes, true, |w| {
+                w.empty_element("inner", &Attributes::new())?;
+                w.text_element("data", &Attributes::new(), "1,2")
+            }).unwrap();
+        }
+        assert_eq!("<outer name=\"a&lt;b\" width=\"3\">\n <inner/>\n <data>1,2</data>\n</outer>\n",
+                   String::from_utf8(output).unwrap());
+    }
+}
@@ -0,0 +1,92 @@
+extern crate tmx;
+extern crate xml;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt::Write;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+// Counts the allocations made and those alive, so that the cost of reading
+// and of holding a map can be told.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static LIVE_ALLOCATIONS: AtomicIsize = AtomicIsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        LIVE_ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_ALLOCATIONS.fetch_sub(1, Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// The counters are shared, so the tests must not run concurrently.
+static COUNTING: Mutex<()> = Mutex::new(());
+
+fn counting() -> MutexGuard<'static, ()> {
+    COUNTING.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+const OBJECTS: usize = 5000;
+
+// Returns the number of allocations held by the map read from `xml`.
+fn live_allocations(xml: &str, options: &tmx::ReaderOptions) -> isize {
+    let before = LIVE_ALLOCATIONS.load(Ordering::SeqCst);
+    let map = tmx::Map::from_str_with(xml, options).unwrap();
+    let held = LIVE_ALLOCATIONS.load(Ordering::SeqCst) - before;
+    assert_eq!(OBJECTS, map.object_groups().next().unwrap().objects().count());
+    held
+}
+
+#[test]
+fn when_interning_strings_expect_repeated_names_to_be_allocated_once() {
+    let _counting = counting();
+    let mut xml = String::from(r#"<map width="1" height="1" tilewidth="16" tileheight="16"><objectgroup name="Spawns">"#);
+    for id in 1..=OBJECTS {
+        write!(xml, r#"<object id="{}" name="spawn" type="enemy"><properties>"#, id).unwrap();
+        xml.push_str(r#"<property name="health" type="int" value="10"/></properties></object>"#);
+    }
+    xml.push_str("</objectgroup></map>");
+
+    let plain = live_allocations(&xml, &tmx::ReaderOptions::new());
+    let interned = live_allocations(&xml, &tmx::ReaderOptions::new().with_intern_strings(true));
+    // Each object held its own name, type and property name.
+    assert!(plain - interned >= 3 * OBJECTS as isize - 3, "{} vs {} allocations", plain, interned);
+}
+
+#[test]
+fn when_reading_objects_expect_no_allocation_beyond_xml_events_and_stored_strings() {
+    let _counting = counting();
+    let mut xml = String::from(r#"<map width="1" height="1" tilewidth="16" tileheight="16"><objectgroup name="Triggers">"#);
+    for id in 1..=OBJECTS {
+        write!(xml, r#"<object id="{}" name="door" type="trigger" x="1.5" y="2" width="3" height="4""#, id).unwrap();
+        xml.push_str(r#" rotation="5" visible="1" gid="3"/>"#);
+    }
+    xml.push_str("</objectgroup></map>");
+
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    for event in xml::reader::EventReader::new(xml.as_bytes()) {
+        event.unwrap();
+    }
+    let parsing = ALLOCATIONS.load(Ordering::SeqCst) - before;
+
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    let map = tmx::Map::from_str(&xml).unwrap();
+    let reading = ALLOCATIONS.load(Ordering::SeqCst) - before;
+    assert_eq!(OBJECTS, map.object_groups().next().unwrap().objects().count());
+
+    // Attributes are read from the events as they are: each object only
+    // costs its name and type, plus the growth of the object list.
+    let overhead = reading - parsing;
+    assert!(overhead <= 2 * OBJECTS + 100, "{} allocations on top of {} for parsing", overhead, parsing);
+}
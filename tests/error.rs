@@ -0,0 +1,39 @@
+extern crate tmx;
+
+use std::error::Error;
+use std::str::FromStr;
+
+// Matching on the error from another crate needs a wildcard arm.
+fn kind(err: &tmx::Error) -> &'static str {
+    match *err {
+        tmx::Error::File { .. } => "file",
+        tmx::Error::Io(..) => "io",
+        tmx::Error::Xml(..) => "xml",
+        tmx::Error::InvalidAttribute { .. } => "attribute",
+        _ => "other",
+    }
+}
+
+#[test]
+fn when_reading_nonexistent_file_expect_io_error_as_source() {
+    let err = tmx::Map::open("non_existent_file.tmx").unwrap_err();
+    assert_eq!("file", kind(&err));
+    let source = err.source().unwrap().downcast_ref::<tmx::Error>().unwrap();
+    assert_eq!("io", kind(source));
+    assert!(source.source().unwrap().downcast_ref::<std::io::Error>().is_some());
+}
+
+#[test]
+fn when_reading_malformed_xml_expect_xml_error_as_source() {
+    let err = tmx::Map::from_str(r#"<map width="2" height="2" tilewidth="16" tileheight="16"><layer></map>"#).unwrap_err();
+    assert_eq!("xml", kind(&err));
+    assert!(err.source().is_some());
+}
+
+#[test]
+fn when_reading_bad_attribute_value_expect_value_error_as_source() {
+    let err = tmx::Map::from_str(r#"<map width="wide" height="2" tilewidth="16" tileheight="16"/>"#).unwrap_err();
+    assert_eq!("attribute", kind(&err));
+    assert_eq!("Invalid number: `wide`", err.source().unwrap().to_string());
+    assert_eq!("other", kind(&tmx::Map::from_str("<nomap/>").unwrap_err()));
+}
@@ -0,0 +1,123 @@
+#![cfg(feature = "image")]
+
+extern crate tmx;
+
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use tmx::ResourceLoader;
+
+const ATLAS_BASE64: &str = "iVBORw0KGgoAAAANSUhEUgAAAAQAAAACCAIAAADwyuo0AAAAFElEQVR4nGP4z8DwHwUy/P8PQv8AnXsM8/XEXvcAAAAASUVORK5CYII=";
+
+// Loads the image of a tileset holding `image` from the data directory.
+fn load(image: &str) -> tmx::Result<Vec<[u8; 4]>> {
+    let xml = format!(r#"<tileset tilewidth="2" tileheight="2">{}</tileset>"#, image);
+    let tileset = tmx::Tileset::from_str(&xml).unwrap();
+    let pixels = tileset.load_image(Path::new("data"))?;
+    Ok(pixels.pixels().map(|pixel| pixel.0).collect())
+}
+
+// Returns the error wrapped in `Error::File`.
+fn file_error_source(err: tmx::Error) -> tmx::Error {
+    match err {
+        tmx::Error::File { source, .. } => *source,
+        err => panic!("not a file error: {}", err),
+    }
+}
+
+#[test]
+fn when_loading_tileset_image_expect_trans_color_keyed_out() {
+    let tileset = tmx::Tileset::open("data/trans_tileset.tsx").unwrap();
+    let pixels = tileset.load_image(Path::new("data")).unwrap();
+    assert_eq!((4, 2), pixels.dimensions());
+    assert_eq!([255, 0, 0, 255], pixels.get_pixel(0, 0).0);
+    assert_eq!([255, 0, 255, 0], pixels.get_pixel(1, 0).0);
+    assert_eq!([0, 0, 255, 255], pixels.get_pixel(1, 1).0);
+    // Close to the key but not equal.
+    assert_eq!([255, 0, 254, 255], pixels.get_pixel(3, 1).0);
+    // The tileset knows its directory.
+    assert_eq!(pixels, tileset.open_image().unwrap());
+
+    // A map read from a string looks for images in the current directory.
+    let map = tmx::Map::from_str(r#"<map width="1" height="1" tilewidth="4" tileheight="2">
+        <imagelayer name="sky"><image source="data/trans_atlas.png"/></imagelayer></map>"#).unwrap();
+    let image = map.image_layers().next().unwrap().image().unwrap();
+    assert_eq!((4, 2), map.open_image(image).unwrap().dimensions());
+
+    let pixels = load(r#"<image source="trans_atlas.png"/>"#).unwrap();
+    assert!(pixels.iter().all(|pixel| pixel[3] == 255));
+}
+
+#[test]
+fn when_loading_embedded_image_expect_same_pixels_as_file() {
+    let embedded = load(&format!(r#"<image format="png" trans="ff00ff" width="4" height="2">
+        <data encoding="base64">{}</data></image>"#, ATLAS_BASE64)).unwrap();
+    let file = load(r#"<image source="trans_atlas.png" trans="ff00ff"/>"#).unwrap();
+    assert_eq!(8, embedded.len());
+    assert_eq!(file, embedded);
+}
+
+#[test]
+fn when_loading_bad_image_expect_distinct_errors() {
+    let err = file_error_source(load(r#"<image source="missing.png"/>"#).unwrap_err());
+    match err {
+        tmx::Error::Io(ref err) => assert_eq!(std::io::ErrorKind::NotFound, err.kind()),
+        err => panic!("unexpected error: {}", err),
+    }
+
+    let err = file_error_source(load(r#"<image source="trans_tileset.tsx"/>"#).unwrap_err());
+    assert_eq!("Unsupported image format: `trans_tileset.tsx`", err.to_string());
+    let err = load(&format!(r#"<image format="webp"><data encoding="base64">{}</data></image>"#, ATLAS_BASE64))
+        .unwrap_err();
+    assert_eq!("Unsupported image format: `webp`", err.to_string());
+
+    let err = load(r#"<image format="png"><data encoding="base64">AAAA</data></image>"#).unwrap_err();
+    match err {
+        tmx::Error::InvalidImage(_) => {}
+        err => panic!("unexpected error: {}", err),
+    }
+
+    let err = file_error_source(load(r#"<image source="trans_atlas.png" width="4" height="4"/>"#).unwrap_err());
+    match err {
+        tmx::Error::ImageSize { expected, actual } => assert_eq!(((4, 4), (4, 2)), (expected, actual)),
+        err => panic!("unexpected error: {}", err),
+    }
+
+    let tileset = tmx::Tileset::open("data/image_collection.tsx").unwrap();
+    assert!(tileset.load_image(Path::new("data")).is_err());
+}
+
+#[test]
+fn when_loading_image_with_loader_expect_file_read_through_it_within_limits() {
+    // Reads from the filesystem, recording the paths asked for.
+    struct RecordingLoader(Vec<String>);
+
+    impl ResourceLoader for RecordingLoader {
+        fn load(&mut self, path: &str) -> tmx::Result<Box<dyn Read>> {
+            self.0.push(path.to_string());
+            tmx::FileLoader.load(path)
+        }
+    }
+
+    let tileset = tmx::Tileset::from_str(r#"<tileset tilewidth="2" tileheight="2">
+        <image source="trans_atlas.png"/></tileset>"#).unwrap();
+    let mut loader = RecordingLoader(Vec::new());
+    let pixels = tileset.load_image_with(Path::new("data"), &mut loader, &tmx::Limits::default()).unwrap();
+    assert_eq!((4, 2), pixels.dimensions());
+    assert_eq!(vec![Path::new("data").join("trans_atlas.png").to_string_lossy()], loader.0);
+
+    let size = std::fs::metadata("data/trans_atlas.png").unwrap().len() as usize;
+    let limits = tmx::Limits { max_image_bytes: Some(size), ..tmx::Limits::default() };
+    assert!(tileset.load_image_with(Path::new("data"), &mut loader, &limits).is_ok());
+    let limits = tmx::Limits { max_image_bytes: Some(size - 1), ..limits };
+    let err = file_error_source(tileset.load_image_with(Path::new("data"), &mut loader, &limits).unwrap_err());
+    assert_eq!(format!("Limit `max_image_bytes` of {} exceeded", size - 1), err.to_string());
+
+    let xml = format!(r#"<tileset tilewidth="2" tileheight="2">
+        <image format="png"><data encoding="base64">{}</data></image></tileset>"#, ATLAS_BASE64);
+    let tileset = tmx::Tileset::from_str(&xml).unwrap();
+    let limits = tmx::Limits { max_image_bytes: Some(10), max_layer_bytes: Some(1 << 20), ..limits };
+    let err = tileset.load_image_with(Path::new("data"), &mut loader, &limits).unwrap_err();
+    assert_eq!("Limit `max_image_bytes` of 10 exceeded", err.to_string());
+}
@@ -3,10 +3,50 @@ extern crate tmx;
 #[macro_use]
 extern crate assert_matches;
 
+#[cfg(any(feature = "serde", feature = "json"))]
+extern crate serde_json;
+
 #[test]
 fn when_reading_nonexistent_map_file_expect_io_error() {
     let result = tmx::Map::open("non_existent_file.tmx");
-    assert_matches!(result, Err(tmx::Error::Io(..)));
+    assert_matches!(result, Err(tmx::Error::File { ref source, .. }) if matches!(**source, tmx::Error::Io(..)));
+}
+
+#[test]
+fn when_reading_missing_files_expect_path_in_error_message() {
+    let err = tmx::Map::open("data/non_existent_file.tmx").unwrap_err();
+    assert!(err.to_string().starts_with("data/non_existent_file.tmx: I/O error: "), "{}", err);
+
+    // External tilesets are looked for in the canonical directory of the map.
+    let options = tmx::ReaderOptions::new().with_require_tilesets(true);
+    let err = tmx::Map::open_with("data/missing_tileset_map.tmx", &options).unwrap_err();
+    let expected = format!("{}: I/O error: ", std::fs::canonicalize("data").unwrap().join("missing_tiles.tsx").display());
+    assert!(err.to_string().starts_with(&expected), "{}", err);
+}
+
+#[test]
+fn when_opening_map_with_missing_tileset_expect_reference_kept_with_warning() {
+    let (map, diagnostics) = tmx::Map::open_with_diagnostics("data/missing_tileset_map.tmx").unwrap();
+    let tileset = map.tilesets().next().unwrap();
+    assert!(tileset.is_external());
+    assert_eq!("missing_tiles.tsx", tileset.source());
+
+    let diagnostic = diagnostics.iter().find(|d| d.severity == tmx::Severity::Warning).unwrap();
+    assert!(diagnostic.message.starts_with("External tileset `missing_tiles.tsx` left unresolved: "), "{}", diagnostic.message);
+    assert_eq!(Some(std::path::Path::new("data/missing_tileset_map.tmx")), diagnostic.file.as_deref());
+}
+
+#[cfg(unix)]
+#[test]
+fn when_opening_file_with_non_utf8_path_expect_error_instead_of_other_file() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::path::Path::new(OsStr::from_bytes(b"data/empty_map\xff.tmx"));
+    let err = tmx::Map::open(path).unwrap_err();
+    assert_matches!(err, tmx::Error::File { ref source, .. } if matches!(**source, tmx::Error::InvalidText(..)));
+    assert_matches!(tmx::Tileset::open(path), Err(tmx::Error::File { .. }));
+    assert_matches!(tmx::ObjectTypes::open(path), Err(tmx::Error::File { .. }));
 }
 
 #[test]
@@ -18,7 +58,7 @@ fn after_reading_valid_tmx_file_expect_valid_map() {
 #[test]
 fn when_reading_nonexistent_tileset_file_expect_io_error() {
     let result = tmx::Tileset::open("non_existent_file.tsx");
-    assert_matches!(result, Err(tmx::Error::Io(..)));
+    assert_matches!(result, Err(tmx::Error::File { ref source, .. }) if matches!(**source, tmx::Error::Io(..)));
 }
 
 #[test]
@@ -27,7 +67,682 @@ fn after_reading_valid_tsx_file_expect_valid_tileset() {
     assert_matches!(result, Ok(tmx::Tileset {..}));
     let tileset = result.unwrap();
     let image = tileset.image().unwrap();
-    assert_eq!(image.width(), 480);
-    assert_eq!(image.height(), 480);
+    assert_eq!(image.width(), Some(480));
+    assert_eq!(image.height(), Some(480));
+}
+
+#[test]
+fn when_reading_truncated_tmx_file_expect_xml_error() {
+    let result = tmx::Map::open("data/truncated_map.tmx");
+    assert_matches!(result, Err(tmx::Error::File { ref source, .. }) if matches!(**source, tmx::Error::Xml(..)));
+}
+
+#[test]
+fn when_reading_tmx_file_with_mismatched_closing_tag_expect_xml_error() {
+    let result = tmx::Map::open("data/mismatched_tag.tmx");
+    assert_matches!(result, Err(tmx::Error::File { ref source, .. }) if matches!(**source, tmx::Error::Xml(..)));
+}
+
+#[test]
+fn when_reading_tmx_file_with_misspelled_child_expect_error_only_in_strict_mode() {
+    use tmx::{ReaderOptions, UnknownPolicy};
+
+    let map = tmx::Map::open("data/misspelled_child.tmx").unwrap();
+    assert_eq!(0, map.object_group_count());
+    assert_eq!(vec!["Ground"], map.layers().map(tmx::map::Layer::name).collect::<Vec<_>>());
+
+    let strict = ReaderOptions::new().with_unknown_elements(UnknownPolicy::Strict);
+    let result = tmx::Map::open_with("data/misspelled_child.tmx", &strict);
+    assert_matches!(result, Err(tmx::Error::File { ref source, .. })
+                    if matches!(**source, tmx::Error::UnknownElement { ref parent, ref element }
+                                if parent == "map" && element == "objectgrop"));
+
+    let collect = ReaderOptions::new().with_unknown_elements(UnknownPolicy::Collect);
+    let map = tmx::Map::open_with("data/misspelled_child.tmx", &collect).unwrap();
+    assert_eq!(1, map.unknown_elements().len());
+    assert_eq!(("map", "objectgrop"), (map.unknown_elements()[0].parent.as_str(), map.unknown_elements()[0].element.as_str()));
+    assert_eq!(1, map.layer_count());
+}
+
+#[test]
+fn after_reading_tmx_files_with_byte_order_mark_expect_same_map() {
+    for path in &["data/utf8_bom.tmx", "data/utf16le_bom.tmx"] {
+        let map = tmx::Map::open(path).unwrap();
+        let layer = map.layers().next().unwrap();
+        assert_eq!("Sol élevé", layer.name(), "{}", path);
+        assert_eq!(&[1, 2, 3, 4][..], layer.data().unwrap().decode().unwrap());
+
+        let bytes = std::fs::read(path).unwrap();
+        let map = tmx::Map::from_bytes(&bytes).unwrap();
+        assert_eq!("Sol élevé", map.layers().next().unwrap().name());
+    }
+
+    let tileset = tmx::Tileset::open("data/utf16be_bom.tsx").unwrap();
+    assert_eq!("Briques", tileset.name());
+}
+
+#[test]
+fn when_reading_badly_encoded_bytes_expect_invalid_text_error() {
+    assert_matches!(tmx::Map::from_bytes(b"<map name=\"\xff\"/>"), Err(tmx::Error::InvalidText(..)));
+    assert_matches!(tmx::Map::from_bytes(b"\xff\xfe<\0m"), Err(tmx::Error::InvalidText(..)));
+    // An unpaired surrogate.
+    assert_matches!(tmx::Tileset::from_bytes(b"\xfe\xff\xd8\x00"), Err(tmx::Error::InvalidText(..)));
+}
+
+#[test]
+fn after_reading_tmx_file_with_quirks_expect_map_and_one_diagnostic_per_quirk() {
+    use tmx::{FileLoader, ReaderOptions, Severity, UnknownPolicy};
+
+    let options = ReaderOptions::new().with_unknown_elements(UnknownPolicy::Collect);
+    let (map, diagnostics) = tmx::Map::load("data/quirky_map.tmx", &mut FileLoader, &options).unwrap();
+    assert_eq!(1, map.layer_count());
+    assert_eq!(2, map.properties().count());
+
+    assert_eq!(2, diagnostics.len(), "{:?}", diagnostics);
+    assert_eq!(Severity::Warning, diagnostics[0].severity);
+    assert!(diagnostics[0].message.contains("speed"), "{}", diagnostics[0].message);
+    assert_eq!(4, diagnostics[0].line);
+    assert_eq!(Severity::Warning, diagnostics[1].severity);
+    assert!(diagnostics[1].message.contains("editorsettings"), "{}", diagnostics[1].message);
+    assert_eq!(7, diagnostics[1].line);
+    assert!(diagnostics.iter().all(|d| d.file == Some(std::path::PathBuf::from("data/quirky_map.tmx"))));
+
+    // Unknown elements are skipped silently by default.
+    let (_, diagnostics) = tmx::Map::open_with_diagnostics("data/quirky_map.tmx").unwrap();
+    assert_eq!(1, diagnostics.len(), "{:?}", diagnostics);
+}
+
+#[test]
+fn after_reading_layer_data_fixtures_with_any_parser_settings_expect_same_tiles() {
+    use tmx::ReaderOptions;
+
+    let expected = tmx::Map::open("data/tiled_layout.tmx").unwrap();
+    let expected: Vec<_> = expected.layers().map(|layer| layer.data().unwrap().decode().unwrap().to_vec()).collect();
+    for &coalesce in &[true, false] {
+        for &trim in &[true, false] {
+            let options = ReaderOptions::new().with_coalesce_characters(coalesce).with_trim_whitespace(trim);
+            let map = tmx::Map::open_with("data/tiled_layout.tmx", &options).unwrap();
+            let tiles: Vec<_> = map.layers().map(|layer| layer.data().unwrap().decode().unwrap().to_vec()).collect();
+            assert_eq!(expected, tiles, "coalesce: {}, trim: {}", coalesce, trim);
+        }
+    }
+}
+
+#[test]
+fn when_reading_layer_data_split_by_comments_and_cdata_expect_all_pieces() {
+    use std::str::FromStr;
+    use tmx::ReaderOptions;
+
+    let xml = r#"<map width="2" height="2" tilewidth="16" tileheight="16"><layer width="2" height="2">
+                   <data encoding="csv">1,2,<!-- second row -->3,<![CDATA[4]]></data>
+                 </layer></map>"#;
+    let data = |map: &tmx::Map| map.layers().next().unwrap().data().unwrap().decode().unwrap().to_vec();
+    assert_eq!(vec![1, 2, 3], data(&tmx::Map::from_str(xml).unwrap()));
+
+    for &coalesce in &[true, false] {
+        let options = ReaderOptions::new().with_coalesce_characters(coalesce).with_cdata_to_characters(true);
+        assert_eq!(vec![1, 2, 3, 4], data(&tmx::Map::from_str_with(xml, &options).unwrap()));
+    }
+}
+
+#[test]
+fn after_opening_tmx_file_expect_source_path_to_be_remembered() {
+    use std::str::FromStr;
+
+    let data = std::fs::canonicalize("data").unwrap();
+    let map = tmx::Map::open("data/../data/external_tileset_map.tmx").unwrap();
+    assert_eq!(Some(data.join("external_tileset_map.tmx").as_path()), map.source_path());
+    assert_eq!(Some(data.as_path()), map.base_dir());
+    let tileset = map.tilesets().next().unwrap();
+    assert_eq!(Some(data.join("simple_tileset.tsx").as_path()), tileset.source_path());
+    let image = tileset.image().unwrap();
+    assert_eq!(Some(data.join("brick.png")), tileset.resolve_image_path(image));
+
+    let map = tmx::Map::from_str(&std::fs::read_to_string("data/external_tileset_map.tmx").unwrap()).unwrap();
+    assert_eq!(None, map.source_path());
+    assert_eq!(None, map.base_dir());
+    assert_eq!(None, map.tilesets().next().unwrap().source_path());
+
+    let tileset = tmx::Tileset::open("data/simple_tileset.tsx").unwrap();
+    assert_eq!(Some(data.join("simple_tileset.tsx").as_path()), tileset.source_path());
+    let tileset = tmx::Tileset::from_str(&std::fs::read_to_string("data/simple_tileset.tsx").unwrap()).unwrap();
+    assert_eq!(None, tileset.base_dir());
+    let image = tileset.image().unwrap();
+    assert_eq!(Some(std::path::PathBuf::from("brick.png")), tileset.resolve_image_path(image));
+}
+
+#[cfg(unix)]
+#[test]
+fn when_opening_map_through_symlink_expect_tilesets_next_to_its_target() {
+    let data = std::fs::canonicalize("data").unwrap();
+    let dir = std::env::temp_dir().join(format!("tmx-symlink-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let link = dir.join("map.tmx");
+    std::os::unix::fs::symlink(data.join("external_tileset_map.tmx"), &link).unwrap();
+
+    let map = tmx::Map::open(&link);
+    std::fs::remove_dir_all(&dir).unwrap();
+    let map = map.unwrap();
+    assert_eq!(Some(data.as_path()), map.base_dir());
+    let tileset = map.tilesets().next().unwrap();
+    assert_eq!(Some(data.join("simple_tileset.tsx").as_path()), tileset.source_path());
+}
+
+#[test]
+fn after_reading_tmx_file_with_file_properties_expect_paths_relative_to_the_map() {
+    let map = tmx::Map::open("data/file_properties.tmx").unwrap();
+    let mut props = map.properties();
+    let music = props.next().unwrap();
+    let root = std::fs::canonicalize(".").unwrap();
+    assert_eq!(Some(root.join("sounds").join("theme.ogg")), map.resolve_file_property(music));
+    let script = props.next().unwrap();
+    assert_eq!(None, map.resolve_file_property(script));
+    let title = props.next().unwrap();
+    assert_eq!(None, map.resolve_file_property(title));
+}
+
+#[test]
+fn after_reading_tmx_file_with_external_tileset_expect_tileset_to_be_loaded() {
+    let map = tmx::Map::open("data/external_tileset_map.tmx").unwrap();
+    let tileset = map.tilesets().next().unwrap();
+    assert_eq!(5, tileset.first_gid());
+    assert_eq!("simple_tileset.tsx", tileset.source());
+    assert_eq!("Bricks", tileset.name());
+    assert_eq!(1, tileset.tiles().count());
+}
+
+#[test]
+fn after_reading_image_collection_tsx_file_expect_per_tile_images() {
+    let tileset = tmx::Tileset::open("data/image_collection.tsx").unwrap();
+    assert!(tileset.is_image_collection());
+    assert_eq!(None, tileset.tile_rect(0));
+
+    let images: Vec<_> = tileset.tile_images().map(|(id, image)| (id, image.source())).collect();
+    assert_eq!(vec![(0, "props/barrel.png"), (3, "props/tree.png"), (17, "props/bench.png")], images);
+
+    let tree = tileset.tile_image(3).unwrap();
+    assert_eq!(Some((64, 96)), tree.size());
+    let bench = tileset.tile_image(17).unwrap();
+    assert_eq!(Some((48, 16)), bench.size());
+    assert!(tileset.tile_image(1).is_none());
+
+    let tileset = tmx::Tileset::open("data/simple_tileset.tsx").unwrap();
+    assert!(!tileset.is_image_collection());
+    assert_eq!(0, tileset.tile_images().count());
+}
+
+#[test]
+fn after_writing_tmx_fixtures_expect_them_to_read_back_identically() {
+    use std::str::FromStr;
+
+    for path in &["data/empty_map.tmx", "data/external_tileset_map.tmx", "data/file_properties.tmx"] {
+        let map = tmx::Map::open(path).unwrap();
+        let written = map.to_xml_string().unwrap();
+        let reread = tmx::Map::from_str(&written).unwrap();
+        assert_eq!(written, reread.to_xml_string().unwrap(), "{}", path);
+        assert_eq!(map.width(), reread.width());
+        assert_eq!(map.tilesets().count(), reread.tilesets().count());
+        assert_eq!(map.properties().count(), reread.properties().count());
+    }
+}
+
+#[test]
+fn after_writing_tsx_fixtures_expect_them_to_read_back_identically() {
+    use std::str::FromStr;
+
+    for path in &["data/simple_tileset.tsx", "data/rich_tileset.tsx"] {
+        let tileset = tmx::Tileset::open(path).unwrap();
+        let written = tileset.to_xml_string().unwrap();
+        assert!(!written.contains("firstgid"), "{}", path);
+        let reread = tmx::Tileset::from_str(&written).unwrap();
+        assert_eq!(written, reread.to_xml_string().unwrap(), "{}", path);
+        assert_eq!(tileset.name(), reread.name());
+        assert_eq!(tileset.tile_count(), reread.tile_count());
+        assert_eq!(tileset.tiles().count(), reread.tiles().count());
+        assert_eq!(tileset.image().map(|image| image.source()), reread.image().map(|image| image.source()));
+    }
+}
+
+#[test]
+fn after_writing_rich_tsx_file_expect_all_content_to_be_kept() {
+    use std::str::FromStr;
+
+    let tileset = tmx::Tileset::open("data/rich_tileset.tsx").unwrap();
+    let written = tileset.to_xml_string().unwrap();
+    assert!(written.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                                 <tileset version=\"1.2\" tiledversion=\"1.2.4\" name=\"Terrain\""));
+    let tileset = tmx::Tileset::from_str(&written).unwrap();
+    assert_eq!("1.2.4", tileset.tiled_version());
+    assert_eq!(8, tileset.tile_offset().unwrap().y());
+    assert_eq!(Some("forest"), tileset.property("biome").map(|property| property.value()));
+    assert_eq!(2, tileset.terrain_types().count());
+    assert_eq!(1, tileset.terrain_types().next().unwrap().properties().count());
+
+    let tile = tileset.tile_by_id(0).unwrap();
+    assert_eq!(Some(0.75), tile.probability());
+    assert_eq!(Some(1), tile.property("cost").and_then(|property| property.as_int()));
+
+    let tile = tileset.tile_by_id(5).unwrap();
+    assert_eq!(Some(&tmx::tileset::Corners(Some(1), Some(1), None, Some(1))), tile.terrain());
+    assert_eq!(2, tile.object_group().unwrap().objects().count());
+    let animation = tile.animation().unwrap();
+    assert_eq!(3, animation.frames().count());
+    assert_eq!(1000, animation.total_duration());
+}
+
+// The fixture was written by hand in the layout of the default write options.
+// It is not a file saved by Tiled, so this only checks that the writer keeps
+// that layout.
+#[test]
+fn after_writing_tmx_file_in_default_layout_expect_identical_bytes() {
+    use std::fs;
+
+    let expected = fs::read_to_string("data/tiled_layout.tmx").unwrap();
+    let map = tmx::Map::open("data/tiled_layout.tmx").unwrap();
+    assert_eq!("1.0.3", map.tiled_version());
+    assert_eq!(expected, map.to_xml_string().unwrap());
+}
+
+#[test]
+fn when_writing_with_formatting_options_expect_them_to_be_applied() {
+    use std::str::FromStr;
+
+    let map = tmx::Map::open("data/tiled_layout.tmx").unwrap();
+    let options = tmx::WriteOptions::new().with_indent('\t', 1).with_xml_declaration(false);
+    let written = map.to_xml_string_with_options(&options).unwrap();
+    assert!(written.starts_with("<map version=\"1.0\" tiledversion=\"1.0.3\" orientation=\"orthogonal\""));
+    assert!(written.contains("\n\t<tileset firstgid=\"9\" source=\"simple_tileset.tsx\"/>\n"));
+    assert!(written.contains("\n\t\t\t\t<frame tileid=\"5\" duration=\"200\"/>\n"));
+
+    let options = tmx::WriteOptions::new()
+        .with_indent(' ', 2)
+        .with_encoding(tmx::DataEncoding::Base64 { compression: tmx::Compression::Gzip });
+    let written = map.to_xml_string_with_options(&options).unwrap();
+    assert!(written.contains("\n  <layer name=\"Ground\" width=\"4\" height=\"3\">\n    <data encoding=\"base64\" compression=\"gzip\">\n      H4sI"));
+    let reread = tmx::Map::from_str(&written).unwrap();
+    let ground = reread.layers().next().unwrap();
+    assert_eq!(&[1, 2, 3, 4, 5, 6, 7, 8, 1, 1, 1, 1][..], ground.data().unwrap().decode().unwrap());
+}
+
+#[test]
+fn after_writing_map_with_resolved_external_tileset_expect_reference_to_be_kept() {
+    use std::str::FromStr;
+
+    let map = tmx::Map::open("data/external_tileset_map.tmx").unwrap();
+    let tileset = map.tilesets().next().unwrap();
+    assert!(tileset.is_external());
+    assert!(tileset.is_resolved());
+
+    let written = map.to_xml_string().unwrap();
+    assert!(written.contains("\n <tileset firstgid=\"5\" source=\"simple_tileset.tsx\"/>\n"), "{}", written);
+    assert!(!written.contains("Bricks"));
+    assert!(!written.contains("<image"));
+
+    let reread = tmx::Map::from_str(&written).unwrap();
+    let tileset = reread.tilesets().next().unwrap();
+    assert!(tileset.is_external());
+    assert!(!tileset.is_resolved());
+    assert_eq!("simple_tileset.tsx", tileset.source());
+    assert_eq!(written, reread.to_xml_string().unwrap());
+}
+
+#[test]
+fn after_editing_parsed_map_expect_changes_through_accessors_and_writer() {
+    use std::str::FromStr;
+    use tmx::color::Color;
+    use tmx::property::{Property, PropertyType};
+
+    let mut map = tmx::Map::open("data/tiled_layout.tmx").unwrap();
+    map.set_background_color(Color::rgb(0, 0, 0));
+    map.properties_mut().get_mut("title").unwrap().set_value("Edited");
+    assert!(map.properties_mut().remove("indoor").is_some());
+    map.properties_mut().push(Property::new("level", "2", PropertyType::Int));
+    for layer in map.layers_mut() {
+        if layer.name() == "Ground" {
+            layer.set_name("Floor");
+        } else {
+            layer.set_visible(!layer.is_visible());
+        }
+    }
+    for group in map.object_groups_mut() {
+        for object in group.objects_mut() {
+            if object.id() == 1 {
+                object.set_id(10);
+                object.set_x(40.0);
+            }
+            if let Some(speed) = object.properties_mut().get_mut("speed") {
+                let bumped = speed.as_int().unwrap() + 1;
+                speed.set_value(bumped.to_string());
+            }
+        }
+    }
+    for tileset in map.tilesets_mut().filter(|tileset| !tileset.is_external()) {
+        for tile in tileset.tiles_mut() {
+            tile.set_probability(0.5);
+        }
+    }
+
+    for map in [tmx::Map::from_str(&map.to_xml_string().unwrap()).unwrap(), map] {
+        assert_eq!(Some(&Color::rgb(0, 0, 0)), map.background_color());
+        assert_eq!(Some("Edited"), map.property("title").and_then(Property::as_string));
+        assert!(map.property("indoor").is_none());
+        assert_eq!(Some(2), map.property("level").and_then(Property::as_int));
+        let layers: Vec<_> = map.layers().map(|layer| (layer.name(), layer.is_visible())).collect();
+        assert_eq!(vec![("Floor", true), ("Decoration", true)], layers);
+        assert!(map.find_object(1).is_none());
+        let (_, door) = map.find_object(10).unwrap();
+        assert_eq!(("door", 40.0), (door.name(), door.x()));
+        let (_, walker) = map.find_object(4).unwrap();
+        assert_eq!(Some(4), walker.property("speed").and_then(Property::as_int));
+        let tiles = map.tilesets().next().unwrap().tiles();
+        assert!(tiles.map(tmx::tileset::Tile::probability).all(|probability| probability == Some(0.5)));
+    }
 }
 
+#[cfg(feature = "serde")]
+#[test]
+fn after_serializing_map_with_serde_expect_tmx_spellings_and_same_map_back() {
+    let map = tmx::Map::open("data/tiled_layout.tmx").unwrap();
+    let json = serde_json::to_value(&map).unwrap();
+    assert_eq!("orthogonal", json["orientation"]);
+    assert_eq!("right-down", json["render_order"]);
+    assert_eq!("#4d4d4d", json["background_color"]);
+    assert_eq!("index", json["object_groups"][0]["draw_order"]);
+    assert_eq!("float", json["properties"][1]["type"]);
+    assert_eq!("Trigger", json["object_groups"][0]["objects"][0]["type"]);
+    assert_eq!("ellipse", json["object_groups"][0]["objects"][1]["shape"]);
+    assert_eq!(8.0, json["object_groups"][0]["objects"][2]["shape"]["polygon"]["points"][2]["x"]);
+    assert!(json.get("object_index").is_none());
+
+    let map_back: tmx::Map = serde_json::from_value(json).unwrap();
+    assert_eq!(map.to_xml_string().unwrap(), map_back.to_xml_string().unwrap());
+    let tileset = map_back.tilesets().next().unwrap();
+    assert!(tileset.tile_by_id(2).is_some());
+    assert_eq!(&[1, 2, 3, 4, 5, 6, 7, 8, 1, 1, 1, 1][..],
+               map_back.layers().next().unwrap().data().unwrap().decode().unwrap());
+
+    let mut json = serde_json::to_value(&map).unwrap();
+    json["layers"] = serde_json::json!([]);
+    let err = serde_json::from_value::<tmx::Map>(json).unwrap_err();
+    assert!(err.to_string().contains("layer order"), "{}", err);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn after_writing_map_as_json_expect_tiled_json_layout() {
+    let map = tmx::Map::open("data/tiled_layout.tmx").unwrap();
+    let json: serde_json::Value = serde_json::from_str(&map.to_json_string().unwrap()).unwrap();
+    assert_eq!("map", json["type"]);
+    assert_eq!("1.0.3", json["tiledversion"]);
+    assert_eq!("right-down", json["renderorder"]);
+    assert_eq!("#4d4d4d", json["backgroundcolor"]);
+    assert_eq!(serde_json::json!({"name": "gravity", "type": "float", "value": 9.81}), json["properties"][1]);
+    assert_eq!(false, json["properties"][2]["value"]);
+
+    let tilesets = json["tilesets"].as_array().unwrap();
+    assert_eq!("tiles.png", tilesets[0]["image"]);
+    assert_eq!("#ff00ff", tilesets[0]["transparentcolor"]);
+    assert_eq!(200, tilesets[0]["tiles"][1]["animation"][1]["duration"]);
+    assert_eq!(serde_json::json!({"firstgid": 9, "source": "simple_tileset.tsx"}), tilesets[1]);
+
+    let layers = json["layers"].as_array().unwrap();
+    let types: Vec<_> = layers.iter().map(|layer| layer["type"].as_str().unwrap()).collect();
+    assert_eq!(vec!["imagelayer", "tilelayer", "tilelayer", "objectgroup"], types);
+    assert_eq!("sky.png", layers[0]["image"]);
+    assert_eq!(serde_json::json!([1, 2, 3, 4, 5, 6, 7, 8, 1, 1, 1, 1]), layers[1]["data"]);
+    assert!(layers[1].get("encoding").is_none());
+    assert_eq!("eJxjYGBgYGQgDJigNAAAbAAE", layers[2]["data"]);
+    assert_eq!("zlib", layers[2]["compression"]);
+    assert_eq!(false, layers[2]["visible"]);
+
+    let objects = layers[3]["objects"].as_array().unwrap();
+    assert_eq!("Trigger", objects[0]["type"]);
+    assert_eq!(true, objects[1]["ellipse"]);
+    assert_eq!(serde_json::json!([{"x": 0.0, "y": 0.0}, {"x": 16.0, "y": 0.0}, {"x": 8.0, "y": 12.0}]), objects[2]["polygon"]);
+    assert_eq!(3, objects[3]["properties"][0]["value"]);
+    assert_eq!(6, objects[4]["gid"]);
+}
+
+// Compares the structure of two JSON documents: the keys of their objects,
+// the `type` values and whether `data` is an array or a base64 string. The
+// keys `expected` has that are not modeled are listed in `unmodeled`, by the
+// path of their object.
+#[cfg(feature = "json")]
+fn assert_same_json_structure(path: &str, expected: &serde_json::Value, actual: &serde_json::Value,
+                              unmodeled: &[(&str, &str)]) {
+    use serde_json::Value;
+
+    match (expected, actual) {
+        (&Value::Object(ref expected), &Value::Object(ref actual)) => {
+            for key in actual.keys() {
+                assert!(expected.contains_key(key), "unexpected key {} in {}", key, path);
+            }
+            for (key, expected) in expected {
+                let actual = match actual.get(key) {
+                    Some(actual) => actual,
+                    None => {
+                        assert!(unmodeled.contains(&(path, key)), "missing key {} in {}", key, path);
+                        continue;
+                    }
+                };
+                match &key[..] {
+                    "type" => assert_eq!(expected, actual, "type of {}", path),
+                    "data" => assert_eq!(expected.is_string(), actual.is_string(), "data of {}", path),
+                    _ => {}
+                }
+                assert_same_json_structure(&format!("{}.{}", path, key), expected, actual, unmodeled);
+            }
+        }
+        (&Value::Array(ref expected), &Value::Array(ref actual)) => {
+            assert_eq!(expected.len(), actual.len(), "length of {}", path);
+            for (index, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                assert_same_json_structure(&format!("{}[{}]", path, index), expected, actual, unmodeled);
+            }
+        }
+        _ => {}
+    }
+}
+
+// The fixture was written after the JSON map format documented by Tiled, in
+// the layout of Tiled 1.2, for the map of data/tiled_layout.tmx. It was not
+// exported by Tiled.
+#[cfg(feature = "json")]
+#[test]
+fn after_writing_map_as_json_expect_structure_of_reference_tmj_file() {
+    let map = tmx::Map::open("data/tiled_layout.tmx").unwrap();
+    let expected: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("data/tiled_layout.tmj").unwrap()).unwrap();
+    let actual: serde_json::Value = serde_json::from_str(&map.to_json_string().unwrap()).unwrap();
+    // Layer ids are not modeled.
+    let unmodeled = [("map", "nextlayerid"), ("map.layers[0]", "id"), ("map.layers[1]", "id"),
+                     ("map.layers[2]", "id"), ("map.layers[3]", "id")];
+    assert_same_json_structure("map", &expected, &actual, &unmodeled);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn after_writing_map_as_json_with_encoding_options_expect_data_to_match_the_model() {
+    use std::str::FromStr;
+
+    let map = tmx::Map::open("data/tiled_layout.tmx").unwrap();
+    let options = tmx::WriteOptions::new()
+        .with_encoding(tmx::DataEncoding::Base64 { compression: tmx::Compression::Gzip })
+        .with_layer_encoding("Decoration", tmx::DataEncoding::Csv);
+    let json: serde_json::Value = serde_json::from_str(&map.to_json_string_with_options(&options).unwrap()).unwrap();
+    let layers = json["layers"].as_array().unwrap();
+    assert_eq!("base64", layers[1]["encoding"]);
+    assert_eq!("gzip", layers[1]["compression"]);
+    assert_eq!(serde_json::json!([0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0]), layers[2]["data"]);
+
+    let xml = format!(r#"<map width="4" height="3" tilewidth="16" tileheight="16"><layer>
+                           <data encoding="base64" compression="gzip">{}</data>
+                         </layer></map>"#,
+                      layers[1]["data"].as_str().unwrap());
+    let reread = tmx::Map::from_str(&xml).unwrap();
+    let original = map.layers().next().unwrap().data().unwrap().decode().unwrap();
+    assert_eq!(original, reread.layers().next().unwrap().data().unwrap().decode().unwrap());
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn after_writing_chunked_map_as_json_expect_chunks_with_their_own_data() {
+    use std::str::FromStr;
+
+    let map = tmx::Map::from_str(r#"<map width="4" height="4" tilewidth="16" tileheight="16" infinite="1"><layer>
+        <data encoding="csv"><chunk x="-2" y="4" width="2" height="1">1,2</chunk></data>
+    </layer></map>"#).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&map.to_json_string().unwrap()).unwrap();
+    assert_eq!(true, json["infinite"]);
+    let layer = &json["layers"][0];
+    assert!(layer.get("data").is_none());
+    assert_eq!((-2, 4), (layer["startx"].as_i64().unwrap(), layer["starty"].as_i64().unwrap()));
+    assert_eq!(serde_json::json!([{"data": [1, 2], "x": -2, "y": 4, "width": 2, "height": 1}]), layer["chunks"]);
+
+    let options = tmx::WriteOptions::new().with_encoding(tmx::DataEncoding::Base64 { compression: tmx::Compression::None });
+    let json: serde_json::Value = serde_json::from_str(&map.to_json_string_with_options(&options).unwrap()).unwrap();
+    assert_eq!("AQAAAAIAAAA=", json["layers"][0]["chunks"][0]["data"]);
+    assert_eq!("base64", json["layers"][0]["encoding"]);
+}
+
+#[test]
+fn when_loading_file_bytes_expect_whole_content() {
+    use tmx::ResourceLoader;
+    let bytes = tmx::FileLoader.load_bytes("data/empty_map.tmx").unwrap();
+    assert_eq!(std::fs::read("data/empty_map.tmx").unwrap(), &bytes[..]);
+    assert_matches!(tmx::FileLoader.load_bytes("data/no_such_file.tmx").err(), Some(tmx::Error::Io(..)));
+}
+
+#[test]
+fn when_exporting_layers_expect_golden_csv_and_pgm_whatever_the_encoding() {
+    // Chunked layers are exported with the cells the chunks cover.
+    for &(map, golden) in &[("data/export_map.tmx", "data/export_layer"),
+                            ("data/export_chunked_map.tmx", "data/export_chunked_layer")] {
+        let map = tmx::Map::open(map).unwrap();
+        let csv = std::fs::read_to_string(format!("{}.csv", golden)).unwrap();
+        let pgm = std::fs::read_to_string(format!("{}.pgm", golden)).unwrap();
+        for layer in map.layers() {
+            let mut exported = Vec::new();
+            layer.export_csv(&map, &mut exported).unwrap();
+            assert_eq!(csv, String::from_utf8(exported).unwrap(), "layer {}", layer.name());
+
+            let mut exported = Vec::new();
+            layer.export_pgm(&map, &mut exported).unwrap();
+            assert_eq!(pgm, String::from_utf8(exported).unwrap(), "layer {}", layer.name());
+        }
+    }
+
+    let map = tmx::Map::open("data/export_map.tmx").unwrap();
+
+    let mut empty = tmx::map::Layer::default();
+    empty.set_width(2);
+    empty.set_height(2);
+    let mut exported = Vec::new();
+    empty.export_pgm(&map, &mut exported).unwrap();
+    assert_eq!("P2\n2 2\n1\n0 0\n0 0\n", String::from_utf8(exported).unwrap());
+
+    // A layer built without a size takes that of the map.
+    let mut exported = Vec::new();
+    tmx::map::Layer::default().export_csv(&map, &mut exported).unwrap();
+    let empty_row = vec!["0"; map.width() as usize].join(",") + "\n";
+    assert_eq!(empty_row.repeat(map.height() as usize), String::from_utf8(exported).unwrap());
+}
+
+#[test]
+fn when_applying_object_types_expect_missing_properties_filled_and_set_ones_kept() {
+    use std::str::FromStr;
+
+    let object_types = tmx::ObjectTypes::open("data/objecttypes.xml").unwrap();
+    assert_eq!(vec!["Enemy", "Door"], object_types.types().map(|t| t.name()).collect::<Vec<_>>());
+    let enemy = object_types.get("Enemy").unwrap();
+    assert_eq!(Some(&tmx::color::Color::argb(0xa0, 0xff, 0, 0)), enemy.color());
+    assert_eq!("10", enemy.property("hp").unwrap().value());
+    assert_eq!(tmx::property::PropertyType::Bool, enemy.property("boss").unwrap().property_type());
+
+    let mut map = tmx::Map::from_str(r#"<map width="1" height="1" tilewidth="16" tileheight="16">
+        <objectgroup>
+            <object id="1" type="Enemy"/>
+            <object id="2" type="Enemy">
+                <properties>
+                    <property name="hp" type="int" value="50"/>
+                    <property name="speed" type="float" value="1.5"/>
+                </properties>
+            </object>
+            <object id="3" class="Door"/>
+            <object id="4" type="Chest"/>
+        </objectgroup>
+    </map>"#).unwrap();
+    map.apply_object_types(&object_types);
+
+    let values = |id: u32| -> Vec<(String, String)> {
+        let (_, object) = map.find_object(id).unwrap();
+        object.properties().map(|p| (p.name().to_string(), p.value().to_string())).collect()
+    };
+    let pairs = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+        pairs.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect()
+    };
+    assert_eq!(pairs(&[("hp", "10"), ("boss", "false"), ("loot", "coin")]), values(1));
+    assert_eq!(pairs(&[("hp", "50"), ("speed", "1.5"), ("boss", "false"), ("loot", "coin")]), values(2));
+    assert_eq!(pairs(&[("locked", "true")]), values(3));
+    assert!(values(4).is_empty());
+
+    let err = tmx::ObjectTypes::open("data/no_such_objecttypes.xml").unwrap_err();
+    assert!(err.to_string().starts_with("data/no_such_objecttypes.xml: I/O error: "), "{}", err);
+}
+
+#[test]
+fn when_reading_from_reader_expect_same_result_as_from_file() {
+    use std::io::Cursor;
+
+    let bytes = std::fs::read("data/tiled_layout.tmx").unwrap();
+    let map = tmx::Map::from_reader(Cursor::new(bytes)).unwrap();
+    let expected = tmx::Map::open("data/tiled_layout.tmx").unwrap();
+    assert_eq!(expected.to_xml_string().unwrap(), map.to_xml_string().unwrap());
+
+    let bytes = std::fs::read("data/utf16le_bom.tmx").unwrap();
+    assert!(tmx::Map::from_reader(Cursor::new(bytes)).is_ok());
+
+    // External tilesets are left unloaded.
+    let bytes = std::fs::read("data/external_tileset_map.tmx").unwrap();
+    let map = tmx::Map::from_reader(Cursor::new(bytes)).unwrap();
+    assert!(map.tilesets().all(tmx::Tileset::is_external));
+
+    let bytes = std::fs::read("data/rich_tileset.tsx").unwrap();
+    let tileset = tmx::Tileset::from_reader(Cursor::new(bytes)).unwrap();
+    assert_eq!(tmx::Tileset::open("data/rich_tileset.tsx").unwrap().name(), tileset.name());
+
+    assert_matches!(tmx::Map::from_reader(Cursor::new(b"<map".to_vec())), Err(tmx::Error::Xml(..)));
+}
+
+#[test]
+fn when_stripping_unused_tilesets_expect_them_removed_and_gids_renumbered() {
+    use std::str::FromStr;
+
+    let mut map = tmx::Map::open("data/unused_tileset_map.tmx").unwrap();
+    let removed = map.strip_unused_tilesets().unwrap();
+    assert_eq!(vec!["unused"], removed.iter().map(tmx::Tileset::name).collect::<Vec<_>>());
+    assert_eq!(vec![("used", 1)], map.tilesets().map(|t| (t.name(), t.first_gid())).collect::<Vec<_>>());
+
+    let gids = map.layers().next().unwrap().data().unwrap().decode().unwrap().to_vec();
+    assert_eq!(vec![1, 0, 0x8000_0004, 1, 1, 0], gids);
+    let object = map.object_groups().next().unwrap().objects().next().unwrap();
+    assert_eq!(Some(2), object.gid());
+
+    // Every tile id still resolves once saved and read again.
+    let mut reread = tmx::Map::from_str(&map.to_xml_string().unwrap()).unwrap();
+    let layer = reread.layers().next().unwrap();
+    for gid in layer.data().unwrap().decode().unwrap().iter().cloned().filter(|&gid| gid != 0).chain(Some(2)) {
+        assert_eq!(Some("used"), reread.tileset_for_gid(gid).map(tmx::Tileset::name), "gid {}", gid);
+    }
+    assert!(reread.strip_unused_tilesets().unwrap().is_empty());
+}
+
+#[test]
+fn when_retaining_tiles_expect_extra_data_of_others_dropped() {
+    let map = tmx::Map::open("data/unused_tileset_map.tmx").unwrap();
+    let mut tileset = map.tilesets().nth(1).unwrap().clone();
+    tileset.retain_tiles(|tile| tile.id() != 0);
+    assert_eq!(vec![2], tileset.tiles().map(|tile| tile.id()).collect::<Vec<_>>());
+    assert!(tileset.tile_by_id(0).is_none());
+    assert!(tileset.tile_by_id(2).is_some());
+    assert_eq!(4, tileset.tile_count());
+}